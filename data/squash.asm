@@ -0,0 +1,115 @@
+; Squash, the demo run by `chip8 --demo`: keep the ball in play with the
+; paddle on the left (keypad 1 and 4, the 1 and Q keys), a point a return.
+; Written for this emulator and placed in the public domain.
+;
+; CowGod syntax, assembled by `chip8 asm` (see asm.rs) when the demo starts.
+; There are no labels: the address of every instruction is in its comment,
+; and jumps and calls name the address of their target.
+
+; paddle at x 2, score 0
+CLS                     ; 200 start
+LD VA, 2                ; 202
+LD VB, 13               ; 204
+LD V7, 0                ; 206
+CALL 0x292              ; 208
+LD I, 0x2A8             ; 20A
+DRW VA, VB, 6           ; 20C
+
+; the ball from the middle, heading left
+LD VC, 32               ; 20E serve
+RND VD, 0x0F            ; 210
+ADD VD, 8               ; 212
+LD V8, 0xFF             ; 214
+LD V9, 1                ; 216
+LD I, 0x2A8             ; 218
+DRW VC, VD, 1           ; 21A
+
+; a step every other frame
+LD V3, 2                ; 21C loop
+LD DT, V3               ; 21E
+LD V3, DT               ; 220 wait
+SE V3, 0                ; 222
+JP 0x220                ; 224
+
+; paddle: 1 up, 4 down
+LD I, 0x2A8             ; 226
+DRW VA, VB, 6           ; 228
+LD VE, 1                ; 22A
+SKNP VE                 ; 22C
+CALL 0x286              ; 22E
+LD VE, 4                ; 230
+SKNP VE                 ; 232
+CALL 0x28C              ; 234
+DRW VA, VB, 6           ; 236
+
+; ball: move, bounce off the walls
+DRW VC, VD, 1           ; 238
+ADD VC, V8              ; 23A
+ADD VD, V9              ; 23C
+SNE VD, 0               ; 23E
+LD V9, 1                ; 240
+SNE VD, 31              ; 242
+LD V9, 0xFF             ; 244
+SNE VC, 63              ; 246
+LD V8, 0xFF             ; 248
+SNE VC, 3               ; 24A
+CALL 0x264              ; 24C
+SNE VC, 0               ; 24E
+JP 0x258                ; 250
+LD I, 0x2A8             ; 252
+DRW VC, VD, 1           ; 254
+JP 0x21C                ; 256
+
+; missed: buzz, score back to 0
+LD V3, 15               ; 258 miss
+LD ST, V3               ; 25A
+CALL 0x292              ; 25C
+LD V7, 0                ; 25E
+CALL 0x292              ; 260
+JP 0x20E                ; 262
+
+; on the paddle when VB <= VD <= VB + 5
+SE V8, 0xFF             ; 264 hit
+RET                     ; 266
+LD V3, VD               ; 268
+SUB V3, VB              ; 26A
+SE VF, 1                ; 26C
+RET                     ; 26E
+LD V4, 5                ; 270
+SUB V4, V3              ; 272
+SE VF, 1                ; 274
+RET                     ; 276
+LD V8, 1                ; 278
+LD V3, 2                ; 27A
+LD ST, V3               ; 27C
+CALL 0x292              ; 27E
+ADD V7, 1               ; 280
+CALL 0x292              ; 282
+RET                     ; 284
+SE VB, 0                ; 286 up
+ADD VB, 0xFF            ; 288
+RET                     ; 28A
+SE VB, 26               ; 28C down
+ADD VB, 1               ; 28E
+RET                     ; 290
+
+; draw (or erase) the tens and units of V7 at the top
+LD I, 0xE00             ; 292 score
+LD B, V7                ; 294
+LD V2, [I]              ; 296
+LD V3, 28               ; 298
+LD V4, 1                ; 29A
+LD F, V1                ; 29C
+DRW V3, V4, 5           ; 29E
+ADD V3, 5               ; 2A0
+LD F, V2                ; 2A2
+DRW V3, V4, 5           ; 2A4
+RET                     ; 2A6
+
+; the paddle, its first row is the ball
+DB 0x80                 ; 2A8 sprite
+DB 0x80                 ; 2A9
+DB 0x80                 ; 2AA
+DB 0x80                 ; 2AB
+DB 0x80                 ; 2AC
+DB 0x80                 ; 2AD
@@ -0,0 +1,20 @@
+#!/bin/sh
+# Register chip8 as the application for .ch8 files, for the current user.
+# The chip8 binary must be on the PATH.
+set -e
+here=$(dirname "$0")
+data=${XDG_DATA_HOME:-$HOME/.local/share}
+
+mkdir -p "$data/applications" "$data/mime/packages"
+cp "$here/chip8.desktop" "$data/applications/"
+# the icon, drawn by the emulator
+for size in 64 128 256; do
+    icons="$data/icons/hicolor/${size}x${size}/apps"
+    mkdir -p "$icons"
+    chip8 icon --size $size "$icons/chip8.png"
+done
+gtk-update-icon-cache "$data/icons/hicolor" 2>/dev/null || true
+cp "$here/chip8-rom.xml" "$data/mime/packages/"
+update-mime-database "$data/mime"
+update-desktop-database "$data/applications" 2>/dev/null || true
+xdg-mime default chip8.desktop application/x-chip8-rom
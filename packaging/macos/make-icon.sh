@@ -0,0 +1,13 @@
+#!/bin/sh
+# Make chip8.icns, the icon of CHIP-8.app (Contents/Resources), from the
+# icon the emulator draws. The chip8 binary must be on the PATH.
+set -e
+set=$(mktemp -d)/chip8.iconset
+mkdir -p "$set"
+for size in 64 128 256 512; do
+    chip8 icon --size $size "$set/icon_${size}x${size}.png"
+done
+for size in 64 128 256; do
+    cp "$set/icon_$((size * 2))x$((size * 2)).png" "$set/icon_${size}x${size}@2x.png"
+done
+iconutil -c icns -o chip8.icns "$set"
@@ -0,0 +1,13 @@
+# Register chip8.exe as the application for .ch8 files, for the current
+# user. Run from the directory holding chip8.exe:
+#
+#     powershell -ExecutionPolicy Bypass -File install.ps1
+$exe = Join-Path (Get-Location) "chip8.exe"
+$classes = "HKCU:\Software\Classes"
+
+New-Item -Force "$classes\.ch8" | Out-Null
+Set-Item "$classes\.ch8" "chip8.rom"
+New-Item -Force "$classes\chip8.rom\shell\open\command" | Out-Null
+Set-Item "$classes\chip8.rom" "CHIP-8 ROM"
+# quoted, for paths with spaces
+Set-Item "$classes\chip8.rom\shell\open\command" "`"$exe`" `"%1`""
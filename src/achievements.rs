@@ -0,0 +1,199 @@
+// Achievements: named goals unlocked the first time a set of conditions on
+// the machine state holds. Rules are read from a text file, one per line:
+//
+//     # comment
+//     Century: [0x2F0] >= 100
+//     Perfect start: V3 == 0 && [0x300] > 5
+//
+// Operands are watched locations (see watch.rs) compared with a constant.
+
+use crate::watch::{parse_number, parse_operand, Operand};
+use crate::Machine;
+use std::fs;
+use std::io;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Cmp {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct Condition {
+    operand: Operand,
+    cmp: Cmp,
+    value: u16,
+}
+
+impl Condition {
+    fn holds(&self, m: &Machine) -> bool {
+        let v = self.operand.read(m);
+        match self.cmp {
+            Cmp::Eq => v == self.value,
+            Cmp::Ne => v != self.value,
+            Cmp::Lt => v < self.value,
+            Cmp::Le => v <= self.value,
+            Cmp::Gt => v > self.value,
+            Cmp::Ge => v >= self.value,
+        }
+    }
+}
+
+#[derive(Debug)]
+struct Achievement {
+    name: String,
+    conditions: Vec<Condition>,
+    unlocked: bool,
+}
+
+pub struct Achievements {
+    list: Vec<Achievement>,
+}
+
+fn parse_condition(s: &str) -> Result<Condition, String> {
+    let parts: Vec<&str> = s.split_whitespace().collect();
+    if parts.len() != 3 {
+        return Err(format!("expected `OPERAND OP VALUE`, got `{}`", s.trim()));
+    }
+    let cmp = match parts[1] {
+        "==" => Cmp::Eq,
+        "!=" => Cmp::Ne,
+        "<" => Cmp::Lt,
+        "<=" => Cmp::Le,
+        ">" => Cmp::Gt,
+        ">=" => Cmp::Ge,
+        op => return Err(format!("unknown comparison `{}`", op)),
+    };
+    let operand = parse_operand(parts[0])?;
+    let value = parse_number(parts[2]).ok_or(format!("invalid value `{}`", parts[2]))?;
+    if value > operand.max() {
+        return Err(format!("value `{}` is wider than `{}`", parts[2], parts[0]));
+    }
+    Ok(Condition {
+        operand,
+        cmp,
+        value,
+    })
+}
+
+impl Achievements {
+    pub fn parse(text: &str) -> Result<Achievements, String> {
+        let mut list = Vec::new();
+        for (n, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let err = |e: String| format!("line {}: {}", n + 1, e);
+            let (name, rule) = line
+                .rsplit_once(':')
+                .ok_or_else(|| err(String::from("expected `NAME: CONDITION`")))?;
+            let conditions = rule
+                .split("&&")
+                .map(parse_condition)
+                .collect::<Result<Vec<_>, _>>()
+                .map_err(err)?;
+            list.push(Achievement {
+                name: name.trim().to_string(),
+                conditions,
+                unlocked: false,
+            });
+        }
+        Ok(Achievements { list })
+    }
+
+    pub fn load(path: &str) -> io::Result<Achievements> {
+        let text = fs::read_to_string(path)?;
+        Achievements::parse(&text).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+    }
+
+    // evaluate the rules against the current state, returning the names of
+    // the achievements unlocked by it
+    pub fn update(&mut self, m: &Machine) -> Vec<String> {
+        let mut unlocked = Vec::new();
+        for a in self.list.iter_mut().filter(|a| !a.unlocked) {
+            if a.conditions.iter().all(|c| c.holds(m)) {
+                a.unlocked = true;
+                unlocked.push(a.name.clone());
+            }
+        }
+        unlocked
+    }
+
+    pub fn unlocked(&self) -> usize {
+        self.list.iter().filter(|a| a.unlocked).count()
+    }
+
+    pub fn len(&self) -> usize {
+        self.list.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.list.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn achievements_parse() {
+        let a = Achievements::parse(
+            "# rules\n\
+             \n\
+             Century: [0x2F0] >= 100\n\
+             Combo: V3 == 0 && [512] != 0x10\n",
+        )
+        .unwrap();
+        assert_eq!(2, a.len());
+        assert_eq!("Century", a.list[0].name);
+        assert_eq!(
+            vec![
+                Condition {
+                    operand: Operand::Register(3),
+                    cmp: Cmp::Eq,
+                    value: 0
+                },
+                Condition {
+                    operand: Operand::Memory(0x200),
+                    cmp: Cmp::Ne,
+                    value: 0x10
+                },
+            ],
+            a.list[1].conditions
+        );
+    }
+
+    #[test]
+    fn achievements_parse_errors() {
+        assert!(Achievements::parse("no rule here").is_err());
+        assert!(Achievements::parse("A: [0x10000] > 1").is_err());
+        assert!(Achievements::parse("A: VG > 1").is_err());
+        assert!(Achievements::parse("A: V1 => 1").is_err());
+        assert!(Achievements::parse("A: [0x300] == 300").is_err());
+        assert!(Achievements::parse("A: V0 < 0x100").is_err());
+        assert!(Achievements::parse("A: V0 <= 0xFF").is_ok());
+        let e = Achievements::parse("ok: V1 > 1\nbad: V1 >").err().unwrap();
+        assert!(e.starts_with("line 2"));
+    }
+
+    #[test]
+    fn achievements_unlock_once() {
+        let mut m = Machine::new();
+        m.init();
+        let mut a = Achievements::parse("Ten: V0 >= 10\nBig: [0x300] == 0xFF && V0 > 0").unwrap();
+
+        assert!(a.update(&m).is_empty());
+        m.registers[0] = 10;
+        assert_eq!(vec![String::from("Ten")], a.update(&m));
+        assert!(a.update(&m).is_empty());
+        m.memory[0x300] = 0xFF;
+        assert_eq!(vec![String::from("Big")], a.update(&m));
+        assert_eq!(2, a.unlocked());
+    }
+}
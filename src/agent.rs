@@ -0,0 +1,141 @@
+// Input agents: anything that looks at the screen (and the annotated game
+// values) once per frame and decides which keypad keys are held, e.g. a
+// scripted player, a reinforcement learning policy or random fuzzing.
+
+use crate::annotations::Annotations;
+use crate::Machine;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+
+// held keys, bit N for key N
+pub type KeyMask = u16;
+
+pub struct Observation<'a> {
+    pub frame: u64,
+    // width x height pixels, row major, bit N set when lit on plane N
+    pub gfx: &'a [u8],
+    pub width: usize,
+    pub height: usize,
+    // annotated values, in annotation order
+    pub values: Vec<(&'a str, u32)>,
+}
+
+impl<'a> Observation<'a> {
+    pub fn new(
+        frame: u64,
+        m: &'a Machine,
+        annotations: Option<&'a Annotations>,
+    ) -> Observation<'a> {
+        Observation {
+            frame,
+            gfx: m.gfx.pixels(),
+            width: m.gfx.width(),
+            height: m.gfx.height(),
+            values: annotations.map_or(Vec::new(), |a| a.values(m)),
+        }
+    }
+
+    pub fn value(&self, name: &str) -> Option<u32> {
+        self.values
+            .iter()
+            .find(|(n, _)| *n == name)
+            .map(|(_, v)| *v)
+    }
+}
+
+pub trait Agent {
+    // keys to hold during the next frame
+    fn act(&mut self, obs: &Observation) -> KeyMask;
+}
+
+impl Machine {
+    pub fn set_keypad(&mut self, keys: KeyMask) {
+        for k in 0..16 {
+            self.keys.insert(k, u8::from(keys & (1 << k) != 0));
+        }
+    }
+
+    pub fn keypad(&self) -> KeyMask {
+        (0..16)
+            .filter(|k| self.keys.get(k).is_some_and(|v| *v > 0))
+            .fold(0, |mask, k| mask | (1 << k))
+    }
+}
+
+// toggles random keys, `chance` is the probability of a toggle per frame
+pub struct RandomAgent {
+    rng: StdRng,
+    chance: f64,
+    keys: KeyMask,
+}
+
+impl RandomAgent {
+    pub fn new(seed: u64, chance: f64) -> RandomAgent {
+        RandomAgent {
+            rng: StdRng::seed_from_u64(seed),
+            chance,
+            keys: 0,
+        }
+    }
+}
+
+impl Agent for RandomAgent {
+    fn act(&mut self, _obs: &Observation) -> KeyMask {
+        if self.rng.gen_bool(self.chance) {
+            self.keys ^= 1 << self.rng.gen_range(0..16);
+        }
+        self.keys
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // holds key 5 while the score is below 3
+    struct Scripted;
+
+    impl Agent for Scripted {
+        fn act(&mut self, obs: &Observation) -> KeyMask {
+            if obs.value("score").unwrap_or(0) < 3 {
+                1 << 5
+            } else {
+                0
+            }
+        }
+    }
+
+    #[test]
+    fn agent_drives_keypad() {
+        let mut m = Machine::new();
+        m.init();
+        m.load_program(vec![
+            0x60, 0x05, // V0 = 5
+            0xE0, 0xA1, // skip if key V0 not pressed
+            0x71, 0x01, // V1 += 1
+            0x12, 0x02, // jump 0x202
+        ])
+        .unwrap();
+        let a = Annotations::parse("score: V1").unwrap();
+        let mut agent = Scripted;
+
+        for frame in 0..20 {
+            let keys = agent.act(&Observation::new(frame, &m, Some(&a)));
+            m.set_keypad(keys);
+            for _ in 0..3 {
+                m.exec_single().unwrap();
+            }
+        }
+        assert_eq!(3, m.registers[1]);
+        assert_eq!(0, m.keypad());
+    }
+
+    #[test]
+    fn keypad_mask_roundtrip() {
+        let mut m = Machine::new();
+        m.set_keypad(0b1000_0000_0010_0001);
+        assert_eq!(0b1000_0000_0010_0001, m.keypad());
+        assert_eq!(Some(&1), m.keys.get(&15));
+        assert_eq!(Some(&0), m.keys.get(&1));
+    }
+}
@@ -0,0 +1,210 @@
+// User names for the registers and memory locations of a ROM, with an
+// optional note, e.g. `V3 player_x  x of the left paddle`. They're kept per
+// ROM hash (so a renamed or pasted copy finds them) in
+// `$XDG_DATA_HOME/chip8/aliases/HASH.txt`, one alias per line:
+//
+//     V3 player_x x of the left paddle
+//     [0x2F0] score
+//
+// Names can be used in place of the location in debugger commands, and are
+// shown next to it in the debugger output.
+
+use crate::utils::data_dir;
+use crate::watch::{parse_operand, Operand};
+use crate::Machine;
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Alias {
+    pub operand: Operand,
+    pub name: String,
+    pub note: String,
+}
+
+#[derive(Debug, Default)]
+pub struct Aliases {
+    // where they're saved, none to keep them in memory only
+    path: Option<PathBuf>,
+    pub list: Vec<Alias>,
+}
+
+pub fn parse_alias(text: &str) -> Result<Alias, String> {
+    let mut parts = text.trim().splitn(3, char::is_whitespace);
+    let operand = parse_operand(parts.next().unwrap_or(""))?;
+    let name = parts.next().unwrap_or("");
+    if name.is_empty() {
+        return Err(String::from("expected `LOCATION NAME [NOTE]`"));
+    }
+    if parse_operand(name).is_ok() || !name.chars().all(|c| c.is_alphanumeric() || c == '_') {
+        return Err(format!("invalid name `{}`", name));
+    }
+    Ok(Alias {
+        operand,
+        name: name.to_string(),
+        note: parts.next().unwrap_or("").trim().to_string(),
+    })
+}
+
+fn operand_text(operand: &Operand) -> String {
+    match operand {
+        Operand::Memory(a) => format!("[{:#05X}]", a),
+        Operand::Register(r) => format!("V{:X}", r),
+    }
+}
+
+impl Aliases {
+    // the file of the ROM with that hash, none without a home directory
+    pub fn path_for(program_hash: u64) -> Option<PathBuf> {
+        data_dir().map(|dir| {
+            dir.join("chip8")
+                .join("aliases")
+                .join(format!("{:016x}.txt", program_hash))
+        })
+    }
+
+    // a missing file has no aliases
+    pub fn load(path: PathBuf) -> io::Result<Aliases> {
+        let text = match fs::read_to_string(&path) {
+            Ok(text) => text,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => String::new(),
+            Err(e) => return Err(e),
+        };
+        let mut aliases = Aliases {
+            path: Some(path),
+            list: Vec::new(),
+        };
+        for (n, line) in text.lines().enumerate() {
+            if line.trim().is_empty() {
+                continue;
+            }
+            let alias = parse_alias(line).map_err(|e| {
+                io::Error::new(io::ErrorKind::InvalidData, format!("line {}: {}", n + 1, e))
+            })?;
+            aliases.list.push(alias);
+        }
+        Ok(aliases)
+    }
+
+    pub fn save(&self) -> io::Result<()> {
+        let path = match &self.path {
+            Some(p) => p,
+            None => return Ok(()),
+        };
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        let text: String = self
+            .list
+            .iter()
+            .map(|a| {
+                let line = format!("{} {} {}", operand_text(&a.operand), a.name, a.note);
+                format!("{}\n", line.trim_end())
+            })
+            .collect();
+        fs::write(path, text)
+    }
+
+    // add or rename, a location has one name and a name one location
+    pub fn set(&mut self, alias: Alias) {
+        self.list
+            .retain(|a| a.operand != alias.operand && a.name != alias.name);
+        self.list.push(alias);
+    }
+
+    pub fn name_of(&self, operand: &Operand) -> Option<&str> {
+        self.list
+            .iter()
+            .find(|a| a.operand == *operand)
+            .map(|a| a.name.as_str())
+    }
+
+    // a location given by its name or as in watch.rs
+    pub fn parse_operand(&self, s: &str) -> Result<Operand, String> {
+        match self.list.iter().find(|a| a.name == s) {
+            Some(a) => Ok(a.operand),
+            None => parse_operand(s),
+        }
+    }
+
+    // e.g. `player_x (V3)`, or `V3` without a name
+    pub fn describe(&self, operand: &Operand) -> String {
+        match self.name_of(operand) {
+            Some(name) => format!("{} ({})", name, operand_text(operand)),
+            None => operand_text(operand),
+        }
+    }
+
+    // the named values, one per line
+    pub fn summary(&self, m: &Machine) -> String {
+        let mut text = String::new();
+        for a in &self.list {
+            text.push_str(&format!(
+                "{} = {:#04X}",
+                self.describe(&a.operand),
+                a.operand.read(m)
+            ));
+            if !a.note.is_empty() {
+                text.push_str(&format!("  # {}", a.note));
+            }
+            text.push('\n');
+        }
+        text
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn aliases_parse() {
+        let a = parse_alias("V3 player_x  x of the left paddle").unwrap();
+        assert_eq!(Operand::Register(3), a.operand);
+        assert_eq!("player_x", a.name);
+        assert_eq!("x of the left paddle", a.note);
+        assert_eq!("", parse_alias("[0x2F0] score").unwrap().note);
+
+        assert!(parse_alias("V3").is_err());
+        assert!(parse_alias("V3 V4").is_err());
+        assert!(parse_alias("V3 player-x").is_err());
+        assert!(parse_alias("VG player_x").is_err());
+    }
+
+    #[test]
+    fn aliases_names() {
+        let mut aliases = Aliases::default();
+        aliases.set(parse_alias("V3 x").unwrap());
+        aliases.set(parse_alias("V4 y").unwrap());
+        // renamed
+        aliases.set(parse_alias("V3 player_x").unwrap());
+        // moved
+        aliases.set(parse_alias("V5 y").unwrap());
+        assert_eq!(2, aliases.list.len());
+
+        assert_eq!(Some("player_x"), aliases.name_of(&Operand::Register(3)));
+        assert_eq!(None, aliases.name_of(&Operand::Register(4)));
+        assert_eq!(Ok(Operand::Register(5)), aliases.parse_operand("y"));
+        assert_eq!(Ok(Operand::Memory(0x200)), aliases.parse_operand("[0x200]"));
+        assert!(aliases.parse_operand("z").is_err());
+        assert_eq!("player_x (V3)", aliases.describe(&Operand::Register(3)));
+        assert_eq!("[0x2F0]", aliases.describe(&Operand::Memory(0x2F0)));
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore)]
+    fn aliases_save_load() {
+        let dir = std::env::temp_dir().join(format!("chip8-aliases-{}", std::process::id()));
+        let path = dir.join("aliases").join("0123456789abcdef.txt");
+        let mut aliases = Aliases::load(path.clone()).unwrap();
+        assert!(aliases.list.is_empty());
+        aliases.set(parse_alias("V3 player_x x of the left paddle").unwrap());
+        aliases.set(parse_alias("[0x2F0] score").unwrap());
+        aliases.save().unwrap();
+
+        let loaded = Aliases::load(path).unwrap();
+        assert_eq!(aliases.list, loaded.list);
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,95 @@
+// Allocation audit: a global allocator counting heap allocations, to check
+// that the interpreter stops allocating once a program runs (the embedded
+// targets have little heap, or none). The binary installs it with the
+// `alloc-audit` feature, and `--headless` then reports the allocations per
+// frame; tests/allocations.rs installs it to fail when the hot path
+// allocates.
+
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::fmt;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+static ALLOCATIONS: AtomicU64 = AtomicU64::new(0);
+
+// the system allocator, counting allocations and reallocations
+pub struct CountingAllocator;
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        System.alloc(layout)
+    }
+
+    unsafe fn alloc_zeroed(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        System.alloc_zeroed(layout)
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        System.realloc(ptr, layout, new_size)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+}
+
+// allocations so far by all threads, always 0 without the CountingAllocator
+pub fn allocations() -> u64 {
+    ALLOCATIONS.load(Ordering::Relaxed)
+}
+
+// allocations per frame of a run
+#[derive(Debug, Default, PartialEq)]
+pub struct Audit {
+    frames: u64,
+    total: u64,
+    // most allocations in one frame, and the first frame with that many
+    worst: (u64, u64),
+}
+
+impl Audit {
+    pub fn frame(&mut self, allocations: u64) {
+        if allocations > self.worst.0 {
+            self.worst = (allocations, self.frames);
+        }
+        self.frames += 1;
+        self.total += allocations;
+    }
+
+    pub fn total(&self) -> u64 {
+        self.total
+    }
+}
+
+impl fmt::Display for Audit {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} allocations in {} frames", self.total, self.frames)?;
+        if self.total > 0 {
+            write!(f, ", at most {} in frame {}", self.worst.0, self.worst.1)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn alloc_audit_frames() {
+        let mut audit = Audit::default();
+        audit.frame(0);
+        audit.frame(0);
+        assert_eq!("0 allocations in 2 frames", audit.to_string());
+        audit.frame(3);
+        audit.frame(1);
+        audit.frame(3);
+        assert_eq!(7, audit.total());
+        assert_eq!(
+            "7 allocations in 5 frames, at most 3 in frame 2",
+            audit.to_string()
+        );
+    }
+}
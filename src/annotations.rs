@@ -0,0 +1,175 @@
+// Per-ROM memory annotations: names for the game values a program keeps in
+// memory or registers, so they can be shown while it runs. One per line:
+//
+//     # pong.ann
+//     left score: [0x2F0] bcd
+//     ball x: V6
+//
+// A memory value is read as a byte (`u8`, the default), a big endian word
+// (`u16`) or the three BCD digits stored by FX33 (`bcd`). The window shows
+// the values in a panel beside the screen, captured frames carry them (see
+// capture.rs) and the playground streams them to its players (server/).
+
+use crate::watch::{parse_operand, Operand};
+use crate::{Machine, MEMORY_SIZE};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+// extension of the annotation file looked up next to a ROM
+pub const EXTENSION: &str = "ann";
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Format {
+    U8,
+    U16,
+    Bcd,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Annotation {
+    pub name: String,
+    operand: Operand,
+    format: Format,
+}
+
+impl Annotation {
+    pub fn read(&self, m: &Machine) -> u32 {
+        match (self.operand, self.format) {
+            (Operand::Memory(a), Format::U16) => {
+                u32::from(m.memory[a]) << 8 | u32::from(m.memory[a + 1])
+            }
+            (Operand::Memory(a), Format::Bcd) => m.memory[a..a + 3]
+                .iter()
+                .fold(0, |v, d| v * 10 + u32::from(*d)),
+            (operand, _) => u32::from(operand.read(m)),
+        }
+    }
+}
+
+pub struct Annotations {
+    pub list: Vec<Annotation>,
+}
+
+fn parse_line(line: &str) -> Result<Annotation, String> {
+    let (name, location) = line
+        .rsplit_once(':')
+        .ok_or_else(|| String::from("expected `NAME: LOCATION [FORMAT]`"))?;
+    let mut parts = location.split_whitespace();
+    let operand = parse_operand(parts.next().unwrap_or(""))?;
+    let format = match parts.next() {
+        None | Some("u8") => Format::U8,
+        Some("u16") => Format::U16,
+        Some("bcd") => Format::Bcd,
+        Some(f) => return Err(format!("unknown format `{}`", f)),
+    };
+    if let Some(extra) = parts.next() {
+        return Err(format!("unexpected `{}`", extra));
+    }
+    let size = match format {
+        Format::U8 => 1,
+        Format::U16 => 2,
+        Format::Bcd => 3,
+    };
+    match operand {
+        Operand::Register(_) if format != Format::U8 => {
+            return Err(String::from("registers can only be read as u8"))
+        }
+        Operand::Memory(a) if a + size > MEMORY_SIZE => {
+            return Err(format!("value at {:#05X} ends out of memory", a))
+        }
+        _ => {}
+    }
+    Ok(Annotation {
+        name: name.trim().to_string(),
+        operand,
+        format,
+    })
+}
+
+impl Annotations {
+    pub fn parse(text: &str) -> Result<Annotations, String> {
+        let mut list = Vec::new();
+        for (n, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            list.push(parse_line(line).map_err(|e| format!("line {}: {}", n + 1, e))?);
+        }
+        Ok(Annotations { list })
+    }
+
+    pub fn load(path: &Path) -> io::Result<Annotations> {
+        let text = fs::read_to_string(path)?;
+        Annotations::parse(&text).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+    }
+
+    // `game.rom` is annotated by `game.ann` in the same directory
+    pub fn sidecar_path(program_file: &str) -> PathBuf {
+        Path::new(program_file).with_extension(EXTENSION)
+    }
+
+    pub fn values(&self, m: &Machine) -> Vec<(&str, u32)> {
+        self.list
+            .iter()
+            .map(|a| (a.name.as_str(), a.read(m)))
+            .collect()
+    }
+
+    // a line per value, e.g. "score: 12\nlives: 3"
+    pub fn summary(&self, m: &Machine) -> String {
+        self.values(m)
+            .iter()
+            .map(|(name, v)| format!("{}: {}", name, v))
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn annotations_parse() {
+        let a = Annotations::parse(
+            "# pong\n\
+             left score: [0x2F0] bcd\n\
+             hi: [0x300] u16\n\
+             ball x: V6\n",
+        )
+        .unwrap();
+        assert_eq!(3, a.list.len());
+        assert_eq!("left score", a.list[0].name);
+        assert_eq!(Format::Bcd, a.list[0].format);
+        assert_eq!(Operand::Register(6), a.list[2].operand);
+        assert_eq!(Format::U8, a.list[2].format);
+
+        assert!(Annotations::parse("x: V1 u16").is_err());
+        assert!(Annotations::parse("x: [0xFFFF] bcd").is_err());
+        assert!(Annotations::parse("x: [0x200] f32").is_err());
+        assert!(Annotations::parse("x: [0x200] u8 u8").is_err());
+        assert!(Annotations::parse("x [0x200]").is_err());
+    }
+
+    #[test]
+    fn annotations_values() {
+        let mut m = Machine::new();
+        m.init();
+        let a = Annotations::parse("score: [0x300] bcd\nhi: [0x303] u16\nx: V6").unwrap();
+        m.memory[0x300..0x305].copy_from_slice(&[1, 2, 7, 0x01, 0x02]);
+        m.registers[6] = 42;
+
+        assert_eq!(vec![("score", 127), ("hi", 258), ("x", 42)], a.values(&m));
+        assert_eq!("score: 127\nhi: 258\nx: 42", a.summary(&m));
+    }
+
+    #[test]
+    fn annotations_sidecar_path() {
+        assert_eq!(
+            Path::new("data/pong.ann"),
+            Annotations::sidecar_path("data/pong.rom")
+        );
+    }
+}
@@ -0,0 +1,247 @@
+// Assembler for the syntaxes written by the disassembler (see disasm.rs),
+// so a disassembled ROM can be edited and built again.
+//
+//     chip8 asm [--syntax cowgod|octo|raw] SOURCE OUT
+//
+// Every line is an instruction matching one of the templates of its
+// syntax, data (`DW`/`DB` in CowGod syntax, byte literals in Octo, hex
+// words in raw listings) or a comment. The program is assembled from
+// 0x200 on, line after line; there are no labels.
+
+use crate::disasm::{Syntax, FORMS, LONG_INDEX};
+use crate::utils;
+use crate::watch::parse_number;
+use std::fs;
+use std::io;
+
+// `line` matched against `template`: the template's pattern bits are left
+// out, the operands are where the instruction has them
+fn operands(template: &str, line: &str) -> Option<u16> {
+    let mut word = 0;
+    let mut t = template;
+    let mut rest = line;
+    while let Some(c) = t.chars().next() {
+        if let Some(field) = t.strip_prefix('{') {
+            let end = field.find('}')?;
+            let kind = &field[..end];
+            t = &field[end + 1..];
+
+            let len = match kind {
+                "x" | "y" => 1,
+                _ => rest
+                    .find(|c: char| !c.is_ascii_alphanumeric())
+                    .unwrap_or(rest.len()),
+            };
+            let token = rest.get(..len).filter(|t| !t.is_empty())?;
+            rest = &rest[len..];
+            let (value, max) = match kind {
+                "x" | "y" => (u16::from_str_radix(token, 16).ok()?, 0xF),
+                "n" | "p" => (parse_number(token)?, 0xF),
+                "nn" => (parse_number(token)?, 0xFF),
+                "nnnn" => (parse_number(token)?, 0xFFFF),
+                _ => (parse_number(token)?, 0xFFF),
+            };
+            if value > max {
+                return None;
+            }
+            word |= match kind {
+                "x" | "p" => value << 8,
+                "y" => value << 4,
+                _ => value,
+            };
+        } else {
+            let l = rest.chars().next()?;
+            if !l.eq_ignore_ascii_case(&c) {
+                return None;
+            }
+            t = &t[c.len_utf8()..];
+            rest = &rest[l.len_utf8()..];
+        }
+    }
+    Some(word).filter(|_| rest.is_empty())
+}
+
+fn instruction(line: &str, syntax: Syntax) -> Option<Vec<u8>> {
+    if let Some(address) = LONG_INDEX.template(syntax).and_then(|t| operands(t, line)) {
+        return Some([LONG_INDEX.pattern.to_be_bytes(), address.to_be_bytes()].concat());
+    }
+    FORMS
+        .iter()
+        .find_map(|f| {
+            let template = f.template(syntax)?;
+            operands(template, line).map(|o| f.pattern | o)
+        })
+        .map(|w| w.to_be_bytes().to_vec())
+}
+
+fn data(line: &str, syntax: Syntax) -> Option<Vec<u8>> {
+    let byte = |t: &str| parse_number(t).filter(|v| *v <= 0xFF).map(|v| v as u8);
+    match syntax {
+        Syntax::CowGod => {
+            let (directive, value) = line.split_once(' ')?;
+            let value = parse_number(value)?;
+            match directive.to_ascii_uppercase().as_str() {
+                "DW" => Some(value.to_be_bytes().to_vec()),
+                "DB" if value <= 0xFF => Some(vec![value as u8]),
+                _ => None,
+            }
+        }
+        Syntax::Octo => line.split(' ').map(byte).collect(),
+        Syntax::Raw => utils::parse_hex(line).ok(),
+    }
+}
+
+pub fn assemble(source: &str, syntax: Syntax) -> Result<Vec<u8>, String> {
+    let mut program = Vec::new();
+    for (n, line) in source.lines().enumerate() {
+        let line = match line.find(syntax.comment()) {
+            Some(i) => &line[..i],
+            None => line,
+        };
+        // one space between words, none before a comma
+        let line = line
+            .replace(',', ", ")
+            .split_whitespace()
+            .collect::<Vec<_>>()
+            .join(" ")
+            .replace(" ,", ",");
+        if line.is_empty() {
+            continue;
+        }
+
+        let bytes = match syntax {
+            Syntax::Raw => data(&line, syntax),
+            _ => instruction(&line, syntax).or_else(|| data(&line, syntax)),
+        };
+        match bytes {
+            Some(b) => program.extend(b),
+            None => return Err(format!("line {}: cannot assemble `{}`", n + 1, line)),
+        }
+    }
+    Ok(program)
+}
+
+pub fn run(args: &[String]) -> io::Result<()> {
+    let invalid = |e: String| io::Error::new(io::ErrorKind::InvalidInput, e);
+    let mut syntax = Syntax::CowGod;
+    let mut files = Vec::new();
+    let mut it = args.iter();
+    while let Some(arg) = it.next() {
+        match arg.as_str() {
+            "--syntax" => {
+                let name = it
+                    .next()
+                    .ok_or_else(|| invalid(String::from("missing value for --syntax")))?;
+                syntax = Syntax::parse(name).map_err(invalid)?;
+            }
+            s if s.starts_with("--") => return Err(invalid(format!("unknown option `{}`", s))),
+            s => files.push(s.to_string()),
+        }
+    }
+    let (source, out) = match files.as_slice() {
+        [source, out] => (source, out),
+        _ => {
+            return Err(invalid(String::from(
+                "usage: chip8 asm [--syntax cowgod|octo|raw] SOURCE OUT",
+            )))
+        }
+    };
+
+    let text = fs::read_to_string(source)?;
+    let program = assemble(&text, syntax)
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, format!("{}: {}", source, e)))?;
+    fs::write(out, &program)?;
+    println!("{}: {} bytes", out, program.len());
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::aliases::Aliases;
+    use crate::disasm::disassemble;
+
+    const SYNTAXES: [Syntax; 3] = [Syntax::CowGod, Syntax::Octo, Syntax::Raw];
+
+    #[test]
+    fn asm_instructions() {
+        let source = "
+            ; setup
+            LD VA, 0x02
+            ld vb,12        ; spacing and case don't matter
+            DRW VA , VB, 6
+            SHR V1, V2
+            DW 0xFFFF
+            DB 0xAB
+        ";
+        assert_eq!(
+            Ok(vec![
+                0x6A, 0x02, 0x6B, 0x0C, 0xDA, 0xB6, 0x81, 0x26, 0xFF, 0xFF, 0xAB
+            ]),
+            assemble(source, Syntax::CowGod)
+        );
+
+        let source = "va := 0x02\nif va != vb then\n0x12 0x34 # data\n";
+        assert_eq!(
+            Ok(vec![0x6A, 0x02, 0x5A, 0xB0, 0x12, 0x34]),
+            assemble(source, Syntax::Octo)
+        );
+
+        // XO-CHIP, the long index load is two words
+        let source = "i := long 0x1234\nplane 3\nsave v2 - v5\n";
+        assert_eq!(
+            Ok(vec![0xF0, 0x00, 0x12, 0x34, 0xF3, 0x01, 0x52, 0x52]),
+            assemble(source, Syntax::Octo)
+        );
+        assert!(assemble("LD I, LONG 0x10000", Syntax::CowGod).is_err());
+
+        assert!(assemble("LD VA, 0x100", Syntax::CowGod).is_err());
+        assert!(assemble("DRW VA, VB, 16", Syntax::CowGod).is_err());
+        assert!(assemble("LD VA", Syntax::CowGod).is_err());
+        let e = assemble("CLS\nJUMP 0x200", Syntax::CowGod).unwrap_err();
+        assert!(e.starts_with("line 2:"), "{}", e);
+    }
+
+    // every word, instruction or data, comes back from its disassembly
+    #[test]
+    #[cfg_attr(miri, ignore)]
+    fn asm_round_trip_every_word() {
+        let aliases = Aliases::default();
+        for syntax in SYNTAXES {
+            let program: Vec<u8> = (0..=u16::MAX).flat_map(u16::to_be_bytes).collect();
+            let text = disassemble(&program, syntax, &aliases);
+            assert_eq!(
+                Ok(&program),
+                assemble(&text, syntax).as_ref(),
+                "{:?}",
+                syntax
+            );
+        }
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore)]
+    fn asm_round_trip_bundled_roms() {
+        let aliases = Aliases::default();
+        let mut roms = 0;
+        for entry in fs::read_dir("data").unwrap() {
+            let path = entry.unwrap().path();
+            if path.extension().is_none_or(|e| e != "rom" && e != "ch8") {
+                continue;
+            }
+            roms += 1;
+            let program = fs::read(&path).unwrap();
+            for syntax in SYNTAXES {
+                let text = disassemble(&program, syntax, &aliases);
+                assert_eq!(
+                    Ok(&program),
+                    assemble(&text, syntax).as_ref(),
+                    "{} in {:?} syntax",
+                    path.display(),
+                    syntax
+                );
+            }
+        }
+        assert!(roms > 0);
+    }
+}
@@ -0,0 +1,474 @@
+// XO-CHIP style audio: while the sound timer runs, the 128 bits of the
+// pattern buffer (loaded by F002) are played in a loop, at 4000 bits per
+// second for the default pitch (64) and an octave higher every 48 steps
+// (FX3A).
+//
+// The `audio` command runs a ROM headless and records what it plays as
+// events stamped with the instruction (cycle) they happened on, so music
+// can be captured exactly, and optionally renders them to a WAV file:
+//
+//     chip8 audio [--cycles N] [--wav FILE] [--filter box|nearest] ROM EVENTS
+//
+// The event file has one event per line: `CYCLE on`, `CYCLE off`,
+// `CYCLE pattern HEX` (the 16 bytes of the new pattern) or `CYCLE pitch N`.
+//
+// Playing the pattern at the host sample rate is a resampling: `nearest`
+// takes the bit under each sample, which aliases at high pitches, `box`
+// (the default) averages the bits each sample covers.
+//
+// It's all integers, so a recording renders to the same samples on every
+// platform: rates are in millihertz, and the position in the pattern is
+// counted in steps of a sample's worth of a millihertz, exactly.
+
+use crate::exit;
+use crate::scheduler::TimerClock;
+use crate::{Machine, CPU_IPS};
+use std::fs;
+use std::io;
+
+pub const PATTERN_SIZE: usize = 16;
+const PATTERN_BITS: u64 = (PATTERN_SIZE * 8) as u64;
+// bits played per second at the default pitch
+pub const PATTERN_RATE: u32 = 4000;
+pub const DEFAULT_PITCH: u8 = 64;
+// a 500 Hz square wave until the program loads its own pattern
+pub const DEFAULT_PATTERN: [u8; PATTERN_SIZE] = [0xF0; PATTERN_SIZE];
+pub const DEFAULT_TONE: u32 = 500;
+// 2 ^ (i / 48) in 16.16 fixed point, the pitch steps of an octave
+const PITCH_STEPS: [u64; 48] = [
+    65536, 66489, 67456, 68438, 69433, 70443, 71468, 72507, 73562, 74632, 75717, 76819, 77936,
+    79069, 80220, 81386, 82570, 83771, 84990, 86226, 87480, 88752, 90043, 91353, 92682, 94030,
+    95398, 96785, 98193, 99621, 101070, 102540, 104032, 105545, 107080, 108638, 110218, 111821,
+    113448, 115098, 116772, 118470, 120194, 121942, 123715, 125515, 127341, 129193,
+];
+
+pub const SAMPLE_RATE: u32 = 44_100;
+const AMPLITUDE: i16 = 8_000;
+// a voice's samples go from -FULL_SCALE to FULL_SCALE
+pub const FULL_SCALE: i16 = i16::MAX;
+// one minute
+const DEFAULT_CYCLES: u64 = CPU_IPS as u64 * 60;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Event {
+    On,
+    Off,
+    Pattern([u8; PATTERN_SIZE]),
+    Pitch(u8),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Filter {
+    Nearest,
+    Box,
+}
+
+impl Filter {
+    pub fn parse(name: &str) -> Result<Filter, String> {
+        match name {
+            "nearest" => Ok(Filter::Nearest),
+            "box" => Ok(Filter::Box),
+            _ => Err(format!("unknown filter `{}`", name)),
+        }
+    }
+}
+
+// bits played per second at `pitch`, in millihertz
+pub fn playback_rate(pitch: u8) -> u32 {
+    let steps = i32::from(pitch) - i32::from(DEFAULT_PITCH);
+    let (octaves, step) = (steps.div_euclid(48), steps.rem_euclid(48) as usize);
+    let rate = (u64::from(PATTERN_RATE) * 1000 * PITCH_STEPS[step]) >> 16;
+    let rate = if octaves >= 0 {
+        rate << octaves
+    } else {
+        rate >> -octaves
+    };
+    rate as u32
+}
+
+fn level(pattern: &[u8; PATTERN_SIZE], bit: u64) -> i64 {
+    let bit = (bit % PATTERN_BITS) as usize;
+    if pattern[bit / 8] & (0x80 >> (bit % 8)) != 0 {
+        1
+    } else {
+        -1
+    }
+}
+
+// the pattern played at a sample rate
+pub struct Voice {
+    pub pattern: [u8; PATTERN_SIZE],
+    pub pitch: u8,
+    // frequency of the default pattern, in Hz: the plain buzzer of the
+    // programs that don't load their own
+    pub tone: u32,
+    filter: Filter,
+    sample_rate: u32,
+    // position in the pattern, in 1 / (1000 * sample_rate) of a bit: a
+    // sample moves it by the rate in millihertz
+    phase: u64,
+}
+
+impl Voice {
+    pub fn new(filter: Filter, sample_rate: u32) -> Voice {
+        Voice {
+            pattern: DEFAULT_PATTERN,
+            pitch: DEFAULT_PITCH,
+            tone: DEFAULT_TONE,
+            filter,
+            sample_rate,
+            phase: 0,
+        }
+    }
+
+    // the next sample, between -FULL_SCALE and FULL_SCALE
+    pub fn next_sample(&mut self) -> i16 {
+        let mut step = u64::from(playback_rate(self.pitch));
+        if self.pattern == DEFAULT_PATTERN {
+            step = step * u64::from(self.tone) / u64::from(DEFAULT_TONE);
+        }
+        let bit = 1000 * u64::from(self.sample_rate);
+        let start = self.phase;
+        self.phase = (start + step) % (PATTERN_BITS * bit);
+        let full = i64::from(FULL_SCALE);
+        match self.filter {
+            Filter::Nearest => (level(&self.pattern, start / bit) * full) as i16,
+            Filter::Box => {
+                // integrate the levels over [start, start + step)
+                let end = start + step;
+                let mut sum = 0;
+                let mut pos = start;
+                while pos < end {
+                    let next = ((pos / bit + 1) * bit).min(end);
+                    sum += level(&self.pattern, pos / bit) * (next - pos) as i64;
+                    pos = next;
+                }
+                (sum * full / step as i64) as i16
+            }
+        }
+    }
+
+    // back to the start of the pattern
+    pub fn restart(&mut self) {
+        self.phase = 0;
+    }
+}
+
+// watches a machine and records its audio events
+pub struct Recorder {
+    cycle: u64,
+    playing: bool,
+    pattern: [u8; PATTERN_SIZE],
+    pitch: u8,
+    pub events: Vec<(u64, Event)>,
+}
+
+impl Default for Recorder {
+    fn default() -> Recorder {
+        Recorder::new()
+    }
+}
+
+impl Recorder {
+    pub fn new() -> Recorder {
+        Recorder {
+            cycle: 0,
+            playing: false,
+            pattern: DEFAULT_PATTERN,
+            pitch: DEFAULT_PITCH,
+            events: Vec::new(),
+        }
+    }
+
+    pub fn cycles(&self) -> u64 {
+        self.cycle
+    }
+
+    // call once per instruction, before running it
+    pub fn update(&mut self, m: &Machine) {
+        if m.audio_pattern != self.pattern {
+            self.pattern = m.audio_pattern;
+            self.events.push((self.cycle, Event::Pattern(self.pattern)));
+        }
+        if m.audio_pitch != self.pitch {
+            self.pitch = m.audio_pitch;
+            self.events.push((self.cycle, Event::Pitch(self.pitch)));
+        }
+        let playing = m.sound_timer > 0;
+        if playing != self.playing {
+            self.playing = playing;
+            let event = if playing { Event::On } else { Event::Off };
+            self.events.push((self.cycle, event));
+        }
+        self.cycle += 1;
+    }
+
+    pub fn to_text(&self) -> String {
+        let mut text = format!("# {} cycles per second\n", CPU_IPS);
+        for (cycle, event) in &self.events {
+            let line = match event {
+                Event::On => format!("{} on", cycle),
+                Event::Off => format!("{} off", cycle),
+                Event::Pattern(p) => {
+                    let hex: String = p.iter().map(|b| format!("{:02X}", b)).collect();
+                    format!("{} pattern {}", cycle, hex)
+                }
+                Event::Pitch(p) => format!("{} pitch {}", cycle, p),
+            };
+            text.push_str(&line);
+            text.push('\n');
+        }
+        text
+    }
+}
+
+// the events played back as 16 bit mono PCM samples, `cycles` long
+pub fn render(events: &[(u64, Event)], cycles: u64, filter: Filter) -> Vec<i16> {
+    let samples = cycles * u64::from(SAMPLE_RATE) / u64::from(CPU_IPS);
+    let mut out = Vec::with_capacity(samples as usize);
+    let mut next = events.iter().peekable();
+    let mut playing = false;
+    let mut voice = Voice::new(filter, SAMPLE_RATE);
+    for s in 0..samples {
+        let cycle = s * u64::from(CPU_IPS) / u64::from(SAMPLE_RATE);
+        while let Some((_, event)) = next.next_if(|(c, _)| *c <= cycle) {
+            match event {
+                Event::On => {
+                    playing = true;
+                    voice.restart();
+                }
+                Event::Off => playing = false,
+                Event::Pattern(p) => voice.pattern = *p,
+                Event::Pitch(p) => voice.pitch = *p,
+            }
+        }
+        let sample = if playing {
+            (i32::from(voice.next_sample()) * i32::from(AMPLITUDE) / i32::from(FULL_SCALE)) as i16
+        } else {
+            0
+        };
+        out.push(sample);
+    }
+    out
+}
+
+// runs a loaded program for `cycles` instructions (or up to an invalid
+// opcode, then returned) with the timers at their pace, recording what it
+// plays
+pub fn record(m: &mut Machine, cycles: u64) -> (Recorder, Option<(usize, u16)>) {
+    let mut recorder = Recorder::new();
+    let mut clock = TimerClock::new(CPU_IPS);
+    while recorder.cycles() < cycles {
+        recorder.update(m);
+        if m.exec_single().is_err() {
+            return (recorder, m.invalid_opcode());
+        }
+        for _ in 0..clock.advance(1) {
+            m.tick_timers();
+        }
+    }
+    (recorder, None)
+}
+
+pub fn wav(samples: &[i16]) -> Vec<u8> {
+    let data_size = (samples.len() * 2) as u32;
+    let mut out = Vec::with_capacity(44 + samples.len() * 2);
+    out.extend_from_slice(b"RIFF");
+    out.extend_from_slice(&(36 + data_size).to_le_bytes());
+    out.extend_from_slice(b"WAVEfmt ");
+    out.extend_from_slice(&16u32.to_le_bytes());
+    // PCM, mono
+    out.extend_from_slice(&1u16.to_le_bytes());
+    out.extend_from_slice(&1u16.to_le_bytes());
+    out.extend_from_slice(&SAMPLE_RATE.to_le_bytes());
+    out.extend_from_slice(&(SAMPLE_RATE * 2).to_le_bytes());
+    out.extend_from_slice(&2u16.to_le_bytes());
+    out.extend_from_slice(&16u16.to_le_bytes());
+    out.extend_from_slice(b"data");
+    out.extend_from_slice(&data_size.to_le_bytes());
+    for s in samples {
+        out.extend_from_slice(&s.to_le_bytes());
+    }
+    out
+}
+
+pub fn run(args: &[String]) -> io::Result<()> {
+    let invalid = |e: String| io::Error::new(io::ErrorKind::InvalidInput, e);
+    let mut cycles = DEFAULT_CYCLES;
+    let mut wav_file = None;
+    let mut filter = Filter::Box;
+    let mut files = Vec::new();
+    let mut it = args.iter();
+    while let Some(arg) = it.next() {
+        let mut value = |name: &str| {
+            it.next()
+                .ok_or_else(|| invalid(format!("missing value for {}", name)))
+        };
+        match arg.as_str() {
+            "--cycles" => {
+                let v = value(arg)?;
+                cycles = v
+                    .parse()
+                    .map_err(|e| invalid(format!("invalid value `{}` for --cycles: {}", v, e)))?;
+            }
+            "--wav" => wav_file = Some(value(arg)?.clone()),
+            "--filter" => filter = Filter::parse(value(arg)?).map_err(invalid)?,
+            s if s.starts_with("--") => return Err(invalid(format!("unknown option `{}`", s))),
+            s => files.push(s.to_string()),
+        }
+    }
+    let (rom, events_file) =
+        match files.as_slice() {
+            [rom, events] => (rom, events),
+            _ => return Err(invalid(String::from(
+                "usage: chip8 audio [--cycles N] [--wav FILE] [--filter box|nearest] ROM EVENTS",
+            ))),
+        };
+
+    let mut m = Machine::new();
+    m.init();
+    m.seed_rng(0);
+    m.load_program_file(rom)
+        .map_err(|e| exit::rom_load(rom, e))?;
+    let (recorder, invalid) = record(&mut m, cycles);
+
+    fs::write(events_file, recorder.to_text())?;
+    println!(
+        "{}: {} events in {} cycles",
+        events_file,
+        recorder.events.len(),
+        recorder.cycles()
+    );
+    if let Some(path) = wav_file {
+        let samples = render(&recorder.events, recorder.cycles(), filter);
+        fs::write(&path, wav(&samples))?;
+        println!("{}: rendered", path);
+    }
+    // what was recorded up to it is still written
+    match invalid {
+        Some((pc, opcode)) => Err(exit::invalid_opcode(pc, opcode)),
+        None => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn audio_records_events() {
+        let mut m = Machine::new();
+        m.init();
+        m.sound_timer = 0;
+        // I = pattern; load it; V0 = 3; ST = V0; loop
+        m.load_program_hex("A20A F002 6003 F018 1208 00FF 00FF 00FF 00FF 00FF 00FF 00FF 00FF")
+            .unwrap();
+        let mut recorder = Recorder::new();
+        let mut clock = TimerClock::new(CPU_IPS);
+        for _ in 0..48 {
+            recorder.update(&m);
+            m.exec_single().unwrap();
+            for _ in 0..clock.advance(1) {
+                m.tick_timers();
+            }
+        }
+        let mut pattern = [0xFF; PATTERN_SIZE];
+        for b in pattern.iter_mut().step_by(2) {
+            *b = 0;
+        }
+        assert_eq!(
+            vec![
+                (2, Event::Pattern(pattern)),
+                (4, Event::On),
+                (35, Event::Off),
+            ],
+            recorder.events
+        );
+        let text = recorder.to_text();
+        assert!(text.contains("\n2 pattern 00FF00FF"), "{}", text);
+        assert!(text.ends_with("4 on\n35 off\n"), "{}", text);
+    }
+
+    #[test]
+    fn audio_render() {
+        let events = [(0, Event::On), (CPU_IPS as u64 / 2, Event::Off)];
+        let samples = render(&events, CPU_IPS as u64, Filter::Nearest);
+        assert_eq!(SAMPLE_RATE as usize, samples.len());
+        // half a second of the default square wave, then silence
+        let half = samples.len() / 2;
+        assert!(samples[..half].iter().all(|s| s.abs() == AMPLITUDE));
+        assert!(samples[half + 1..].iter().all(|s| *s == 0));
+        // 500 Hz: 500 changes of sign in half a second
+        let changes = samples[..half].windows(2).filter(|w| w[0] != w[1]).count();
+        assert!((498..=500).contains(&changes), "{}", changes);
+
+        let file = wav(&samples[..4]);
+        assert_eq!(b"RIFF", &file[..4]);
+        assert_eq!(44 + 8, file.len());
+    }
+
+    #[test]
+    fn audio_pitch() {
+        assert_eq!(4_000_000, playback_rate(DEFAULT_PITCH));
+        assert_eq!(8_000_000, playback_rate(112));
+        assert_eq!(2_000_000, playback_rate(16));
+        // half an octave up, 4000 * sqrt(2) Hz
+        let rate = playback_rate(88);
+        assert!((5_656_850..5_656_870).contains(&rate), "{}", rate);
+        assert_eq!(1_587_402, playback_rate(0));
+        assert_eq!(63_082_512, playback_rate(255));
+
+        let mut m = Machine::new();
+        m.init();
+        m.load_program_hex("6070 F03A").unwrap();
+        let mut recorder = Recorder::new();
+        for _ in 0..2 {
+            recorder.update(&m);
+            m.exec_single().unwrap();
+        }
+        recorder.update(&m);
+        assert_eq!(Some(&(2, Event::Pitch(0x70))), recorder.events.last());
+        assert!(recorder.to_text().ends_with("2 pitch 112\n"));
+    }
+
+    #[test]
+    fn audio_filters() {
+        // an octave up: 1000 Hz, 1000 changes of sign in half a second
+        let events = [(0, Event::Pitch(112)), (0, Event::On)];
+        let samples = render(&events, CPU_IPS as u64 / 2, Filter::Nearest);
+        let changes = samples.windows(2).filter(|w| w[0] != w[1]).count();
+        assert!((998..=1000).contains(&changes), "{}", changes);
+
+        // the box filter smooths the edges falling inside a sample
+        let samples = render(&events, CPU_IPS as u64 / 2, Filter::Box);
+        assert!(samples.iter().all(|s| s.abs() <= AMPLITUDE));
+        assert!(samples.iter().any(|s| s.abs() < AMPLITUDE));
+        let sum: i64 = samples.iter().map(|s| i64::from(*s)).sum();
+        let mean = sum / samples.len() as i64;
+        assert!(mean.abs() < 10, "{}", mean);
+
+        let mut voice = Voice::new(Filter::Box, SAMPLE_RATE);
+        voice.pattern = [0xFF; PATTERN_SIZE];
+        voice.pitch = 255;
+        assert!((0..1000).all(|_| voice.next_sample() == FULL_SCALE));
+    }
+
+    #[test]
+    fn audio_tone() {
+        // changes of sign in a second
+        let changes = |voice: &mut Voice| {
+            let samples: Vec<i16> = (0..SAMPLE_RATE).map(|_| voice.next_sample()).collect();
+            samples.windows(2).filter(|w| w[0] != w[1]).count()
+        };
+        let mut voice = Voice::new(Filter::Nearest, SAMPLE_RATE);
+        assert!((998..=1000).contains(&changes(&mut voice)));
+        voice.tone = 880;
+        let n = changes(&mut voice);
+        assert!((1758..=1760).contains(&n), "{}", n);
+
+        // a program's own pattern plays as it is
+        voice.pattern = [0xCC; PATTERN_SIZE];
+        let n = changes(&mut voice);
+        assert!((1998..=2000).contains(&n), "{}", n);
+    }
+}
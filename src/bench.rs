@@ -0,0 +1,210 @@
+// The execution paths side by side on one workload:
+//
+//     chip8 bench [--cycles N] [--runs N] [ROM]
+//
+// runs ROM (the demo without one) for N instructions through every backend,
+// the best of --runs each, and prints a table of their speeds against the
+// first one, so a change slowing one of them down shows. The backends are
+// the ways the interpreter runs: as is, with the memory protection, with a
+// pre-execution hook (the debugger's and the plugins' path). A decode cache
+// or a JIT goes in BACKENDS when there's one. A program that halts is
+// loaded again and goes on; the backends must end in the same state, the
+// table marks the ones that don't.
+
+use crate::demo;
+use crate::utils::{self, FNV_OFFSET};
+use crate::{HookAction, Machine};
+use std::fs;
+use std::io;
+use std::time::{Duration, Instant};
+
+const DEFAULT_CYCLES: u64 = 2_000_000;
+const DEFAULT_RUNS: usize = 3;
+const SEED: u64 = 1;
+
+// a way to run the machine: what it needs set up
+pub struct Backend {
+    pub name: &'static str,
+    setup: fn(&mut Machine),
+}
+
+pub const BACKENDS: [Backend; 3] = [
+    Backend {
+        name: "interpreter",
+        setup: |_| {},
+    },
+    Backend {
+        name: "protected",
+        setup: |m| m.enable_protection(),
+    },
+    Backend {
+        name: "hooked",
+        setup: |m| m.set_pre_exec_hook(|_, _| HookAction::Allow),
+    },
+];
+
+#[derive(Debug)]
+pub struct Timing {
+    pub backend: &'static str,
+    // the best run
+    pub elapsed: Duration,
+    // of the state it ended in
+    pub checksum: u64,
+}
+
+fn load(m: &mut Machine, program: &[u8], backend: &Backend) -> Result<(), String> {
+    m.init();
+    m.seed_rng(SEED);
+    m.load_program(program.to_vec())
+        .map_err(|e| e.to_string())?;
+    (backend.setup)(m);
+    Ok(())
+}
+
+// `cycles` instructions of `program` through `backend`, loading it again
+// when it halts, and the checksum of where it ends
+fn run_once(program: &[u8], cycles: u64, backend: &Backend) -> Result<(Duration, u64), String> {
+    let mut m = Machine::new();
+    load(&mut m, program, backend)?;
+    let start = Instant::now();
+    let mut since_load = 0;
+    for _ in 0..cycles {
+        if m.run_for(1).is_err() {
+            if since_load == 0 {
+                return Err(format!("the program halts at once with {}", backend.name));
+            }
+            load(&mut m, program, backend)?;
+            since_load = 0;
+            continue;
+        }
+        since_load += 1;
+    }
+    let elapsed = start.elapsed();
+    let checksum = utils::fnv1a(FNV_OFFSET, &m.memory);
+    let checksum = utils::fnv1a(checksum, &m.registers);
+    Ok((
+        elapsed,
+        utils::fnv1a(checksum, &(m.pc as u32).to_le_bytes()),
+    ))
+}
+
+pub fn measure(program: &[u8], cycles: u64, runs: usize) -> Result<Vec<Timing>, String> {
+    let mut timings = Vec::new();
+    for backend in BACKENDS.iter() {
+        let mut best: Option<(Duration, u64)> = None;
+        for _ in 0..runs.max(1) {
+            let run = run_once(program, cycles, backend)?;
+            if best.is_none_or(|b| run.0 < b.0) {
+                best = Some(run);
+            }
+        }
+        if let Some((elapsed, checksum)) = best {
+            timings.push(Timing {
+                backend: backend.name,
+                elapsed,
+                checksum,
+            });
+        }
+    }
+    Ok(timings)
+}
+
+// the timings as a table, each against the first
+#[allow(clippy::float_arithmetic)]
+pub fn table(timings: &[Timing], cycles: u64) -> String {
+    let mut text = format!(
+        "{:<12} {:>10} {:>8} {:>9}\n",
+        "backend", "time", "MIPS", "relative"
+    );
+    let first = match timings.first() {
+        Some(first) => first,
+        None => return text,
+    };
+    for t in timings {
+        let seconds = t.elapsed.as_secs_f64().max(1e-9);
+        text.push_str(&format!(
+            "{:<12} {:>7.1} ms {:>8.1} {:>8.2}x",
+            t.backend,
+            seconds * 1000.0,
+            cycles as f64 / seconds / 1e6,
+            first.elapsed.as_secs_f64().max(1e-9) / seconds
+        ));
+        if t.checksum != first.checksum {
+            text.push_str("  (ends in another state)");
+        }
+        text.push('\n');
+    }
+    text
+}
+
+pub fn run(args: &[String]) -> io::Result<()> {
+    let invalid = |e: String| io::Error::new(io::ErrorKind::InvalidInput, e);
+    let mut cycles = DEFAULT_CYCLES;
+    let mut runs = DEFAULT_RUNS;
+    let mut rom = None;
+    let mut it = args.iter();
+    while let Some(arg) = it.next() {
+        let mut value = |name: &str| -> Result<u64, String> {
+            let v = it.next().ok_or(format!("missing value for {}", name))?;
+            v.parse::<u64>()
+                .map_err(|e| format!("invalid value `{}` for {}: {}", v, name, e))
+        };
+        match arg.as_str() {
+            "--cycles" => cycles = value(arg).map_err(invalid)?,
+            "--runs" => runs = value(arg).map_err(invalid)? as usize,
+            s if s.starts_with("--") => return Err(invalid(format!("unknown option `{}`", s))),
+            s if rom.is_none() => rom = Some(s.to_string()),
+            _ => {
+                return Err(invalid(String::from(
+                    "usage: chip8 bench [--cycles N] [--runs N] [ROM]",
+                )))
+            }
+        }
+    }
+
+    let (name, program) = match &rom {
+        Some(rom) => (rom.as_str(), fs::read(rom)?),
+        None => (demo::NAME, demo::program()),
+    };
+    println!("{}: {} instructions, best of {}", name, cycles, runs.max(1));
+    let timings = measure(&program, cycles, runs).map_err(invalid)?;
+    print!("{}", table(&timings, cycles));
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bench_measure() {
+        let timings = measure(&demo::program(), 20_000, 1).unwrap();
+        let names: Vec<&str> = timings.iter().map(|t| t.backend).collect();
+        assert_eq!(vec!["interpreter", "protected", "hooked"], names);
+        // the same work whatever runs it
+        assert!(timings.iter().all(|t| t.checksum == timings[0].checksum));
+
+        // halting programs start again, unless they can't run at all
+        assert!(measure(&[0x60, 0x05, 0x00, 0xEE], 1000, 1).is_ok());
+        assert!(measure(&[0x00, 0xEE], 1000, 1).is_err());
+    }
+
+    #[test]
+    fn bench_table() {
+        let timing = |backend, ms, checksum| Timing {
+            backend,
+            elapsed: Duration::from_millis(ms),
+            checksum,
+        };
+        let text = table(
+            &[timing("interpreter", 10, 1), timing("hooked", 20, 2)],
+            1_000_000,
+        );
+        let lines: Vec<&str> = text.lines().collect();
+        assert_eq!("interpreter     10.0 ms    100.0     1.00x", lines[1]);
+        assert_eq!(
+            "hooked          20.0 ms     50.0     0.50x  (ends in another state)",
+            lines[2]
+        );
+    }
+}
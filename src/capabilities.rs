@@ -0,0 +1,152 @@
+// What each interpreter profile (the `--quirks` presets) runs: the later
+// instruction sets on top of CHIP-8 and the quirks it has, for tools that
+// check a ROM against the interpreter it's meant for. `chip8 info
+// --capabilities` prints the matrix, `chip8 info --variant NAME` warns of
+// the instructions a ROM uses that NAME lacks.
+//
+// XO-CHIP runs the SUPER-CHIP instructions as well; the VIP is CHIP-8 with
+// more of its quirks, so the same instructions.
+
+use crate::info::{self, Hint, Variant};
+use crate::quirks::Quirks;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Capabilities {
+    pub profile: &'static str,
+    // the instruction sets past CHIP-8
+    pub sets: &'static [Variant],
+    pub quirks: Quirks,
+}
+
+fn sets(profile: &str) -> &'static [Variant] {
+    match profile {
+        "schip" => &[Variant::SuperChip],
+        "xochip" => &[Variant::SuperChip, Variant::XoChip],
+        _ => &[],
+    }
+}
+
+impl Capabilities {
+    // the capabilities of the profile with that name
+    pub fn of(profile: &str) -> Option<Capabilities> {
+        let profile = Quirks::profile_names()
+            .into_iter()
+            .find(|name| *name == profile)?;
+        Some(Capabilities {
+            profile,
+            sets: sets(profile),
+            quirks: Quirks::profile(profile)?,
+        })
+    }
+
+    pub fn supports(&self, variant: Variant) -> bool {
+        self.sets.contains(&variant)
+    }
+
+    // whether it runs the instruction of that name (CHIP-8 ones included)
+    pub fn runs(&self, instruction: &str) -> bool {
+        [Variant::SuperChip, Variant::XoChip]
+            .iter()
+            .all(|v| self.supports(*v) || !info::instructions(*v).contains(&instruction))
+    }
+
+    // the instructions found in a ROM that it lacks
+    pub fn missing<'a>(&self, hints: &'a [Hint]) -> Vec<&'a Hint> {
+        hints.iter().filter(|h| !self.supports(h.variant)).collect()
+    }
+}
+
+// the capabilities of every profile
+pub fn matrix() -> Vec<Capabilities> {
+    Quirks::profile_names()
+        .into_iter()
+        .filter_map(Capabilities::of)
+        .collect()
+}
+
+// the matrix as text: a row per later instruction and per quirk, a column
+// per profile
+pub fn table() -> String {
+    let matrix = matrix();
+    let mut rows: Vec<(String, Vec<bool>)> = Vec::new();
+    for variant in [Variant::SuperChip, Variant::XoChip].iter() {
+        for name in info::instructions(*variant) {
+            let cells = matrix.iter().map(|c| c.runs(name)).collect();
+            rows.push((format!("{} ({})", name, variant.name()), cells));
+        }
+    }
+    for (i, (name, _)) in Quirks::default().flags().iter().enumerate() {
+        let cells = matrix.iter().map(|c| c.quirks.flags()[i].1).collect();
+        rows.push((format!("quirk {}", name), cells));
+    }
+
+    let width = rows.iter().map(|(name, _)| name.len()).max().unwrap_or(0);
+    let mut text = format!("{:<w$}", "", w = width);
+    for c in &matrix {
+        text.push_str(&format!("  {:>6}", c.profile));
+    }
+    text.push('\n');
+    for (name, cells) in rows {
+        text.push_str(&format!("{:<w$}", name, w = width));
+        for on in cells {
+            text.push_str(&format!("  {:>6}", if on { "x" } else { "-" }));
+        }
+        text.push('\n');
+    }
+    text
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn capabilities_of() {
+        let chip8 = Capabilities::of("chip8").unwrap();
+        assert!(!chip8.supports(Variant::SuperChip) && !chip8.supports(Variant::XoChip));
+        assert!(chip8.runs("8XY6") && !chip8.runs("00FF"));
+        let schip = Capabilities::of("schip").unwrap();
+        assert!(schip.runs("00FF") && schip.runs("DXY0") && !schip.runs("F000"));
+        assert!(schip.quirks.jump_vx);
+        let xochip = Capabilities::of("xochip").unwrap();
+        assert!(xochip.runs("00FF") && xochip.runs("F000") && xochip.runs("5XY2"));
+        assert_eq!(
+            Capabilities::of("chip8").unwrap().sets,
+            Capabilities::of("vip").unwrap().sets
+        );
+        assert_eq!(None, Capabilities::of("jump-vx"));
+        assert_eq!(4, matrix().len());
+
+        // HIGH, then LD I, LONG
+        let hints = info::hints(&[0x00, 0xFF, 0xF0, 0x00, 0x03, 0x00]);
+        let names =
+            |c: &Capabilities| -> Vec<&str> { c.missing(&hints).iter().map(|h| h.name).collect() };
+        assert_eq!(vec!["00FF", "F000"], names(&chip8));
+        assert_eq!(vec!["F000"], names(&schip));
+        assert!(names(&xochip).is_empty());
+    }
+
+    #[test]
+    fn capabilities_table() {
+        let text = table();
+        let lines: Vec<&str> = text.lines().collect();
+        assert!(
+            lines[0].ends_with("   chip8   schip  xochip     vip"),
+            "{}",
+            text
+        );
+        let row = |prefix: &str| {
+            lines
+                .iter()
+                .find(|l| l.starts_with(prefix))
+                .unwrap()
+                .to_string()
+        };
+        assert!(row("00FF (SUPER-CHIP)").ends_with("-       x       x       -"));
+        assert!(row("F000 (XO-CHIP)").ends_with("-       -       x       -"));
+        assert!(row("quirk jump-vx").ends_with("-       x       -       -"));
+        assert!(row("quirk vip-font").ends_with("-       -       -       x"));
+        assert!(row("quirk memory-4k").ends_with("x       x       -       x"));
+        assert_eq!(1 + 17 + 11, lines.len());
+    }
+}
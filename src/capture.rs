@@ -0,0 +1,204 @@
+// Frame capture: bursts of consecutive frames saved as numbered PNGs, to
+// make animations of a game or look at a glitch frame by frame. A burst of
+// `--capture-frames N` frames (60) starts on F12 in the window, or on a
+// trigger given with `--capture-at`:
+//
+//     cycle:N    the first frame once N instructions have run
+//     break      each time the debugger stops on a breakpoint
+//
+// Frames are numbered by the 60 Hz timer ticks since the program started,
+// which come at the same cycles on every run, so the same frame of a
+// replayed run gets the same name. Paths are a template (`--capture-path`)
+// where `{rom}` is the ROM's file name without its extension, `{frame}` the
+// frame number on 6 digits and `{cycle}` the instructions run, by default
+//
+//     {rom}/frame_{frame}.png     pong/frame_000123.png
+//
+// The game values of an annotated ROM (see annotations.rs) are the PNG's
+// comment, a `name: value` per line.
+
+use crate::framebuffer::FrameBuffer;
+use crate::png;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+pub const DEFAULT_FRAMES: u32 = 60;
+pub const DEFAULT_TEMPLATE: &str = "{rom}/frame_{frame}.png";
+// the colours of the frames when there's no window to take them from
+pub const DEFAULT_PALETTE: [[u8; 4]; 4] = [
+    [0, 0, 0, 255],
+    [255, 255, 255, 255],
+    [170, 170, 170, 255],
+    [85, 85, 85, 255],
+];
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Trigger {
+    Cycle(u64),
+    Breakpoint,
+}
+
+impl Trigger {
+    pub fn parse(text: &str) -> Result<Trigger, String> {
+        match text.split_once(':') {
+            Some(("cycle", n)) => n
+                .parse()
+                .map(Trigger::Cycle)
+                .map_err(|_| format!("invalid cycle `{}`", n)),
+            None if text == "break" => Ok(Trigger::Breakpoint),
+            _ => Err(format!(
+                "invalid capture trigger `{}` (cycle:N or break)",
+                text
+            )),
+        }
+    }
+}
+
+// the name {rom} stands for
+pub fn rom_name(path: &str) -> String {
+    Path::new(path)
+        .file_stem()
+        .map_or_else(|| String::from("rom"), |s| s.to_string_lossy().into_owned())
+}
+
+#[derive(Debug, Clone)]
+pub struct Capture {
+    template: String,
+    rom: String,
+    // frames per burst
+    frames: u32,
+    // cleared once a cycle trigger has fired
+    trigger: Option<Trigger>,
+    // frames left in the burst being taken
+    remaining: u32,
+    // the last frame saved, one that didn't move on isn't saved again
+    last: Option<u64>,
+}
+
+impl Capture {
+    pub fn new(template: &str, rom: &str, frames: u32, trigger: Option<Trigger>) -> Capture {
+        Capture {
+            template: String::from(template),
+            rom: String::from(rom),
+            frames,
+            trigger,
+            remaining: 0,
+            last: None,
+        }
+    }
+
+    // a burst from the next frame on, a running one starts over
+    pub fn start(&mut self) {
+        self.remaining = self.frames;
+    }
+
+    pub fn stop(&mut self) {
+        self.remaining = 0;
+    }
+
+    pub fn capturing(&self) -> bool {
+        self.remaining > 0
+    }
+
+    // the debugger stopped on a breakpoint
+    pub fn breakpoint(&mut self) {
+        if self.trigger == Some(Trigger::Breakpoint) {
+            self.start();
+        }
+    }
+
+    pub fn path(&self, frame: u64, cycle: u64) -> PathBuf {
+        PathBuf::from(
+            self.template
+                .replace("{rom}", &self.rom)
+                .replace("{frame}", &format!("{:06}", frame))
+                .replace("{cycle}", &cycle.to_string()),
+        )
+    }
+
+    // call on each frame: saves it when a burst is being taken, with the
+    // annotated game values (empty without annotations), returns where
+    pub fn frame(
+        &mut self,
+        frame: u64,
+        cycle: u64,
+        gfx: &FrameBuffer,
+        palette: &[[u8; 4]; 4],
+        values: &str,
+    ) -> io::Result<Option<PathBuf>> {
+        if let Some(Trigger::Cycle(n)) = self.trigger {
+            if cycle >= n {
+                self.trigger = None;
+                self.start();
+            }
+        }
+        if self.remaining == 0 || self.last == Some(frame) {
+            return Ok(None);
+        }
+        self.remaining -= 1;
+        self.last = Some(frame);
+        let path = self.path(frame, cycle);
+        let mut rgba = vec![0; gfx.pixels().len() * 4];
+        gfx.to_rgba(palette, &mut rgba);
+        if let Some(dir) = path.parent().filter(|d| !d.as_os_str().is_empty()) {
+            fs::create_dir_all(dir)?;
+        }
+        let png = png::encode_commented(&rgba, gfx.width(), gfx.height(), values);
+        fs::write(&path, png)?;
+        Ok(Some(path))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Machine;
+
+    #[test]
+    fn capture_triggers() {
+        assert_eq!(Ok(Trigger::Cycle(5000)), Trigger::parse("cycle:5000"));
+        assert_eq!(Ok(Trigger::Breakpoint), Trigger::parse("break"));
+        assert!(Trigger::parse("cycle:").is_err());
+        assert!(Trigger::parse("frame:3").is_err());
+        assert_eq!("pong", rom_name("roms/pong.ch8"));
+
+        let c = Capture::new("{rom}-{cycle}/f{frame}.png", "pong", 2, None);
+        assert_eq!(PathBuf::from("pong-700/f000123.png"), c.path(123, 700));
+    }
+
+    #[test]
+    fn capture_bursts() {
+        let dir = std::env::temp_dir().join(format!("chip8-capture-{}", std::process::id()));
+        let template = format!("{}/{{rom}}/frame_{{frame}}.png", dir.display());
+        let mut m = Machine::new();
+        m.init();
+        let gfx = m.framebuffer();
+
+        let mut c = Capture::new(&template, "pong", 2, Some(Trigger::Cycle(100)));
+        assert_eq!(None, c.frame(1, 50, gfx, &DEFAULT_PALETTE, "").unwrap());
+        let first = c.frame(2, 100, gfx, &DEFAULT_PALETTE, "").unwrap().unwrap();
+        assert_eq!(dir.join("pong/frame_000002.png"), first);
+        assert_eq!(None, c.frame(2, 120, gfx, &DEFAULT_PALETTE, "").unwrap());
+        let second = c.frame(3, 150, gfx, &DEFAULT_PALETTE, "score: 7").unwrap();
+        // a burst is over, the trigger doesn't fire again
+        assert!(!c.capturing());
+        assert_eq!(None, c.frame(4, 200, gfx, &DEFAULT_PALETTE, "").unwrap());
+        let png = fs::read(&first).unwrap();
+        let black = [0, 0, 0, 255].repeat(64 * 32);
+        assert_eq!(png::encode(&black, 64, 32), png);
+        // annotated values go in the frame's comment
+        assert_eq!(
+            png::encode_commented(&black, 64, 32, "score: 7"),
+            fs::read(second.unwrap()).unwrap()
+        );
+
+        // breakpoints start one each time
+        let mut c = Capture::new(&template, "pong", 1, Some(Trigger::Breakpoint));
+        c.breakpoint();
+        assert!(c.capturing());
+        c.stop();
+        assert_eq!(None, c.frame(5, 250, gfx, &DEFAULT_PALETTE, "").unwrap());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
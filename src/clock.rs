@@ -0,0 +1,115 @@
+// Time, for what runs at the pace of the real world: the frontend loops
+// (the window, `--tui`, the playground sessions) and the limits' timeout.
+// They ask a `Clock` for the time and sleep through it, so tests can hand
+// them a `VirtualClock` and move time forward themselves: the 60 Hz frames
+// and timer ticks come exactly when expected, without waiting for them.
+
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+pub trait Clock: Send + Sync {
+    fn now(&self) -> Instant;
+    fn sleep(&self, duration: Duration);
+}
+
+// the real time
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> Instant {
+        Instant::now()
+    }
+
+    fn sleep(&self, duration: Duration) {
+        thread::sleep(duration);
+    }
+}
+
+// time that only moves when it's advanced, or slept through (which returns
+// at once); clones share the same time
+#[derive(Debug, Clone)]
+pub struct VirtualClock {
+    start: Instant,
+    elapsed: Arc<Mutex<Duration>>,
+}
+
+impl Default for VirtualClock {
+    fn default() -> VirtualClock {
+        VirtualClock::new()
+    }
+}
+
+impl VirtualClock {
+    pub fn new() -> VirtualClock {
+        VirtualClock {
+            start: Instant::now(),
+            elapsed: Arc::new(Mutex::new(Duration::ZERO)),
+        }
+    }
+
+    pub fn advance(&self, duration: Duration) {
+        *self.elapsed.lock().unwrap() += duration;
+    }
+
+    // the time advanced since it was made
+    pub fn elapsed(&self) -> Duration {
+        *self.elapsed.lock().unwrap()
+    }
+}
+
+impl Clock for VirtualClock {
+    fn now(&self) -> Instant {
+        self.start + self.elapsed()
+    }
+
+    fn sleep(&self, duration: Duration) {
+        self.advance(duration);
+    }
+}
+
+// the clock of everything that isn't told otherwise
+pub fn system() -> Arc<dyn Clock> {
+    Arc::new(SystemClock)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::scheduler::Scheduler;
+    use crate::{Machine, Timer, CPU_IPS};
+
+    #[test]
+    fn clock_virtual_time() {
+        let clock = VirtualClock::new();
+        let start = clock.now();
+        assert_eq!(start, clock.now());
+        clock.advance(Duration::from_millis(250));
+        // a clone moves with it
+        let other = clock.clone();
+        other.sleep(Duration::from_millis(750));
+        assert_eq!(Duration::from_secs(1), clock.now() - start);
+        assert_eq!(Duration::from_secs(1), clock.elapsed());
+    }
+
+    #[test]
+    fn clock_drives_the_timers() {
+        // a second of frames, as the frontend loops run them: the delay
+        // timer set to 60 runs out on time
+        let clock = VirtualClock::new();
+        let frame = Duration::from_nanos(1_000_000_000 / 60);
+        let mut scheduler = Scheduler::new(CPU_IPS, frame, clock.now());
+        let mut m = Machine::new();
+        m.init();
+        m.load_program_hex("603C F015 1204").unwrap();
+        let mut frames = 0;
+        while frames == 0 || m.get_timer(Timer::Delay) > 0 {
+            clock.sleep(scheduler.sleep_time(clock.now()));
+            m.run_for(scheduler.batch(clock.now()) as u64).unwrap();
+            frames += 1;
+        }
+        assert!((60..=62).contains(&frames), "{}", frames);
+        assert!(clock.elapsed() >= Duration::from_secs(1));
+    }
+}
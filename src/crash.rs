@@ -0,0 +1,186 @@
+// Crash dumps: a panic in the window or in a headless run leaves the
+// machine on disk, for a bug report that can be reproduced. The frontends
+// publish the machine as they go (the window every frame, headless runs
+// every emulated second), and the panic hook, installed in front of the
+// default one, writes the last machine published:
+//
+//     ~/.local/share/chip8/crashes/HASH-TIME.state   the save state
+//     ~/.local/share/chip8/crashes/HASH-TIME.txt     the panic and the report
+//
+// HASH the ROM's, TIME the Unix time of the panic. The report is the one
+// the debugger prints: registers, stack and the last instructions run. The
+// state loads with `--load-state`. A panic in the interpreter itself is
+// dumped again with the machine as it was when it panicked.
+
+use crate::quirks::Quirks;
+use crate::utils::data_dir;
+use crate::Machine;
+use std::collections::VecDeque;
+use std::fs;
+use std::io;
+use std::panic;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, TryLockError};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+// what's needed of a machine to save it and report on it
+struct Published {
+    snapshot: Vec<u8>,
+    quirks: Quirks,
+    cpu_hz: u32,
+    program_hash: u64,
+    recent: VecDeque<(usize, u16)>,
+}
+
+#[derive(Default)]
+struct Shared {
+    published: Option<Published>,
+    // the last dump: the panic message, and its files without extension
+    dumped: Option<(String, PathBuf)>,
+}
+
+#[derive(Clone)]
+pub struct CrashDump {
+    shared: Arc<Mutex<Shared>>,
+    dir: Option<PathBuf>,
+}
+
+impl CrashDump {
+    pub fn new(dir: Option<PathBuf>) -> CrashDump {
+        CrashDump {
+            shared: Arc::default(),
+            dir,
+        }
+    }
+
+    pub fn default_dir() -> Option<PathBuf> {
+        data_dir().map(|dir| dir.join("chip8").join("crashes"))
+    }
+
+    // a dump in the default directory on panic, from now on
+    pub fn install() -> CrashDump {
+        let dump = CrashDump::new(CrashDump::default_dir());
+        let hooked = dump.clone();
+        let previous = panic::take_hook();
+        panic::set_hook(Box::new(move |info| {
+            previous(info);
+            let secs = SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map_or(0, |d| d.as_secs());
+            hooked.report(hooked.dump(&info.to_string(), secs));
+        }));
+        dump
+    }
+
+    pub fn publish(&self, m: &Machine, cpu_hz: u32) {
+        let published = Published {
+            snapshot: m.snapshot(),
+            quirks: m.quirks(),
+            cpu_hz,
+            program_hash: m.program_hash(),
+            recent: m.recent.clone(),
+        };
+        if let Ok(mut shared) = self.shared.lock() {
+            shared.published = Some(published);
+        }
+    }
+
+    // the machine published written out, on a panic with `message` at
+    // `secs`: the path of the files, without extension, none when there's
+    // nothing to write or nowhere to write it
+    pub fn dump(&self, message: &str, secs: u64) -> io::Result<Option<PathBuf>> {
+        // a panic while publishing leaves the lock poisoned, not the data
+        let mut shared = match self.shared.try_lock() {
+            Ok(shared) => shared,
+            Err(TryLockError::Poisoned(poisoned)) => poisoned.into_inner(),
+            Err(TryLockError::WouldBlock) => return Ok(None),
+        };
+        let (dir, published) = match (&self.dir, &shared.published) {
+            (Some(dir), Some(published)) => (dir, published),
+            _ => return Ok(None),
+        };
+        let path = dir.join(format!("{:016x}-{}", published.program_hash, secs));
+        write(published, message, &path)?;
+        shared.dumped = Some((message.to_string(), path.clone()));
+        Ok(Some(path))
+    }
+
+    // the last dump written again with the machine published since
+    pub fn redump(&self) -> io::Result<Option<PathBuf>> {
+        let shared = self.shared.lock().unwrap_or_else(|p| p.into_inner());
+        match (&shared.dumped, &shared.published) {
+            (Some((message, path)), Some(published)) => {
+                write(published, message, path)?;
+                Ok(Some(path.clone()))
+            }
+            _ => Ok(None),
+        }
+    }
+
+    // where a dump went, on the standard error as the panic
+    pub fn report(&self, dumped: io::Result<Option<PathBuf>>) {
+        match dumped {
+            Ok(Some(path)) => eprintln!("crash dump: {}.state and .txt", path.display()),
+            Ok(None) => {}
+            Err(e) => eprintln!("cannot write the crash dump: {}", e),
+        }
+    }
+}
+
+fn write(published: &Published, message: &str, path: &Path) -> io::Result<()> {
+    let mut m = Machine::new();
+    m.set_quirks(published.quirks);
+    m.restore(&published.snapshot)
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e.to_string()))?;
+    m.program_hash = published.program_hash;
+    m.recent = published.recent.clone();
+    m.save_state(&path.with_extension("state"), published.cpu_hz)?;
+    let text = format!("panic: {}\n\n{}", message, m.report());
+    fs::write(path.with_extension("txt"), text)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+
+    #[test]
+    fn crash_dump() {
+        let dir = env::temp_dir().join(format!("chip8-crash-{}", std::process::id()));
+        let dump = CrashDump::new(Some(dir.clone()));
+        // nothing published yet
+        assert!(dump.dump("boom", 1).unwrap().is_none());
+
+        let mut m = Machine::new();
+        m.set_quirks(Quirks::parse("schip").unwrap());
+        m.init();
+        m.load_program_hex("6A2B 6B01 1204").unwrap();
+        m.run_for(3).unwrap();
+        dump.publish(&m, 900);
+        let path = dump.dump("boom at main.rs:1", 1700000000).unwrap().unwrap();
+        assert_eq!(
+            dir.join(format!("{:016x}-1700000000", m.program_hash())),
+            path
+        );
+        let text = fs::read_to_string(path.with_extension("txt")).unwrap();
+        assert!(text.starts_with("panic: boom at main.rs:1\n\n"), "{}", text);
+        assert!(text.contains("VA: 2B  VB: 01"), "{}", text);
+        assert!(text.contains("  0x204: 1204"), "{}", text);
+
+        let mut loaded = Machine::new();
+        loaded.init();
+        let config = loaded.load_state(&path.with_extension("state")).unwrap();
+        assert_eq!(900, config.cpu_hz);
+        assert_eq!(m.quirks(), config.quirks);
+        assert_eq!(m.state_hash(), loaded.state_hash());
+
+        // again, with the machine further on
+        m.run_for(1).unwrap();
+        m.registers[0] = 7;
+        dump.publish(&m, 900);
+        assert_eq!(Some(path.clone()), dump.redump().unwrap());
+        loaded.load_state(&path.with_extension("state")).unwrap();
+        assert_eq!(7, loaded.registers[0]);
+        let _ = fs::remove_dir_all(dir);
+    }
+}
@@ -0,0 +1,468 @@
+// Debugger: breakpoints and watches on the running program. They're set up
+// by a script of commands loaded on startup (`--debug-script FILE`), so the
+// setup for a ROM bug can be reproduced in one command. One per line:
+//
+//     # pong.dbg
+//     break 0x2A4
+//     watch [0x2F0]
+//     watch V6
+//     alias V6 ball_x
+//     run
+//
+// `break ADDR` pauses before the instruction at ADDR, `watch LOCATION`
+// prints the value (see watch.rs) whenever it changes, `alias LOCATION
+// NAME [NOTE]` names a location for this ROM (see aliases.rs), and `run`
+// starts the program, which is otherwise paused until resumed (F5).
+//
+// The same commands are typed on the console while the program runs
+// (`--debug`), along with the ones to look around a paused program:
+//
+//     delete ADDR      remove the breakpoint at ADDR
+//     pause            pause before the next instruction (F6)
+//     step [N]         run N instructions (1) and pause again (F10)
+//     regs             PC, I, the timers and the registers
+//     stack            the addresses of the calls, innermost last
+//     mem ADDR [LEN]   hex dump of LEN bytes (16) from ADDR
+//     search start     look for a value in memory (see search.rs), then
+//     search STEP      narrow it down: eq N, inc, dec, same, changed
+//     search           the candidates left
+
+use crate::aliases::{parse_alias, Alias, Aliases};
+use crate::search::{Search, Step};
+use crate::trace;
+use crate::watch::{parse_number, Operand};
+use crate::{Machine, MEMORY_SIZE};
+use std::fmt::Write;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Command {
+    Break(usize),
+    Watch(Operand),
+    Alias(Alias),
+    Run,
+    Delete(usize),
+    Pause,
+    Step(u32),
+    Registers,
+    Stack,
+    Memory(usize, usize),
+    SearchStart,
+    Search(Step),
+    SearchList,
+}
+
+// bytes per line of a memory dump
+const DUMP_WIDTH: usize = 16;
+
+fn parse_address(s: &str) -> Result<usize, String> {
+    match parse_number(s) {
+        Some(a) if usize::from(a) < MEMORY_SIZE => Ok(usize::from(a)),
+        _ => Err(format!("invalid address `{}`", s)),
+    }
+}
+
+fn parse_count(s: &str) -> Result<u16, String> {
+    match parse_number(s) {
+        Some(n) if n > 0 => Ok(n),
+        _ => Err(format!("invalid count `{}`", s)),
+    }
+}
+
+// locations can be given by their alias
+pub fn parse_command(line: &str, aliases: &Aliases) -> Result<Command, String> {
+    if let Some(alias) = line.strip_prefix("alias ") {
+        return Ok(Command::Alias(parse_alias(alias)?));
+    }
+    let parts: Vec<&str> = line.split_whitespace().collect();
+    let name = parts.first().copied().unwrap_or("");
+    let args = parts.get(1..).unwrap_or(&[]);
+    let command = match (name, args) {
+        ("break", [a]) => Command::Break(parse_address(a)?),
+        ("delete", [a]) => Command::Delete(parse_address(a)?),
+        ("watch", [w]) => Command::Watch(aliases.parse_operand(w)?),
+        ("run", []) => Command::Run,
+        ("pause", []) => Command::Pause,
+        ("step", []) => Command::Step(1),
+        ("step", [n]) => Command::Step(u32::from(parse_count(n)?)),
+        ("regs", []) => Command::Registers,
+        ("stack", []) => Command::Stack,
+        ("mem", [a]) => Command::Memory(parse_address(a)?, DUMP_WIDTH),
+        ("mem", [a, n]) => Command::Memory(parse_address(a)?, usize::from(parse_count(n)?)),
+        ("search", []) => Command::SearchList,
+        ("search", ["start"]) => Command::SearchStart,
+        ("search", step) => Command::Search(Step::parse(step)?),
+        ("break" | "delete" | "watch" | "alias" | "mem", []) => {
+            return Err(format!("missing argument for `{}`", name))
+        }
+        ("break" | "delete" | "watch" | "run" | "pause" | "step" | "regs" | "stack" | "mem", _) => {
+            return Err(format!("unexpected `{}`", args[args.len() - 1]))
+        }
+        _ => return Err(format!("unknown command `{}`", name)),
+    };
+    Ok(command)
+}
+
+fn registers(m: &Machine) -> String {
+    let mut out = format!(
+        "PC: {:#05X}  I: {:#05X}  DT: {}  ST: {}\n",
+        m.pc, m.index_register, m.delay_timer, m.sound_timer
+    );
+    for (i, values) in m.registers.chunks(8).enumerate() {
+        let cells: Vec<String> = values
+            .iter()
+            .enumerate()
+            .map(|(j, v)| format!("V{:X}: {:02X}", i * 8 + j, v))
+            .collect();
+        let _ = writeln!(out, "{}", cells.join("  "));
+    }
+    out
+}
+
+fn stack(m: &Machine) -> String {
+    let stack: Vec<String> = m.stack.iter().map(|a| format!("{:#05X}", a)).collect();
+    format!("stack: [{}]\n", stack.join(", "))
+}
+
+// LEN bytes from ADDR, stopping at the end of memory
+fn memory(m: &Machine, address: usize, len: usize) -> String {
+    let end = (address + len).min(MEMORY_SIZE);
+    let mut out = String::new();
+    for (i, line) in m.memory[address..end].chunks(DUMP_WIDTH).enumerate() {
+        let bytes: Vec<String> = line.iter().map(|b| format!("{:02X}", b)).collect();
+        let _ = writeln!(
+            out,
+            "{:#05X}: {}",
+            address + i * DUMP_WIDTH,
+            bytes.join(" ")
+        );
+    }
+    out
+}
+
+#[derive(Debug, Default)]
+pub struct Debugger {
+    breakpoints: Vec<usize>,
+    // watched locations with their last value
+    watches: Vec<(Operand, u16)>,
+    pub aliases: Aliases,
+    pub paused: bool,
+    // breakpoint to step over when resuming on it
+    resumed_at: Option<usize>,
+    // instructions left to run before pausing again
+    steps: Option<u32>,
+    search: Option<Search>,
+}
+
+impl Debugger {
+    // the text printed by the command, if any
+    pub fn execute(&mut self, command: Command, m: &Machine) -> io::Result<String> {
+        match command {
+            Command::Break(a) => {
+                if !self.breakpoints.contains(&a) {
+                    self.breakpoints.push(a);
+                }
+            }
+            Command::Delete(a) => self.breakpoints.retain(|b| *b != a),
+            Command::Watch(w) => self.watches.push((w, w.read(m))),
+            Command::Alias(a) => {
+                self.aliases.set(a);
+                self.aliases.save()?;
+            }
+            Command::Run => self.resume(),
+            Command::Pause => self.pause(),
+            Command::Step(n) => self.step(n),
+            Command::Registers => return Ok(registers(m)),
+            Command::Stack => return Ok(stack(m)),
+            Command::Memory(a, n) => return Ok(memory(m, a, n)),
+            Command::SearchStart => {
+                let search = self.search.insert(Search::new(m));
+                return Ok(search.summary());
+            }
+            Command::Search(step) => {
+                // a value can be looked for right away, a change needs a start
+                let search = match (&mut self.search, step) {
+                    (Some(search), _) => search,
+                    (None, Step::Equals(_)) => self.search.insert(Search::new(m)),
+                    (None, _) => return Ok(String::from("no search, `search start` first\n")),
+                };
+                search.narrow(m, step);
+                return Ok(search.summary());
+            }
+            Command::SearchList => {
+                return Ok(match &self.search {
+                    Some(search) => search.summary(),
+                    None => String::from("no search, `search start` first\n"),
+                })
+            }
+        }
+        Ok(String::new())
+    }
+
+    // a debugger set up by a script, paused unless the script runs
+    pub fn from_script(text: &str, m: &Machine, aliases: Aliases) -> io::Result<Debugger> {
+        let mut debugger = Debugger {
+            aliases,
+            paused: true,
+            ..Debugger::default()
+        };
+        for (n, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let command = parse_command(line, &debugger.aliases).map_err(|e| {
+                io::Error::new(io::ErrorKind::InvalidData, format!("line {}: {}", n + 1, e))
+            })?;
+            debugger.execute(command, m)?;
+        }
+        Ok(debugger)
+    }
+
+    pub fn load_script(path: &Path, m: &Machine, aliases: Aliases) -> io::Result<Debugger> {
+        let text = fs::read_to_string(path)?;
+        Debugger::from_script(&text, m, aliases)
+    }
+
+    pub fn resume(&mut self) {
+        self.paused = false;
+        self.steps = None;
+    }
+
+    pub fn pause(&mut self) {
+        self.paused = true;
+        self.steps = None;
+    }
+
+    // run `n` instructions, then pause
+    pub fn step(&mut self, n: u32) {
+        self.paused = false;
+        self.steps = Some(n);
+    }
+
+    // call before every instruction: what changed since the previous one,
+    // one line each. Pauses on a breakpoint
+    pub fn check(&mut self, m: &Machine) -> Vec<String> {
+        let mut notes = Vec::new();
+        for (w, last) in self.watches.iter_mut() {
+            let v = w.read(m);
+            if v != *last {
+                notes.push(format!(
+                    "watch {}: {:#04X} -> {:#04X}",
+                    self.aliases.describe(w),
+                    last,
+                    v
+                ));
+                *last = v;
+            }
+        }
+
+        if self.paused {
+            return notes;
+        }
+        if self.resumed_at.is_some_and(|a| a != m.pc) {
+            self.resumed_at = None;
+        }
+        if self.breakpoints.contains(&m.pc) && self.resumed_at.is_none() {
+            self.pause();
+            self.resumed_at = Some(m.pc);
+            notes.push(format!("breakpoint at {:#05X}", m.pc));
+            return notes;
+        }
+        match self.steps {
+            Some(0) => {
+                self.pause();
+                // resuming doesn't stop on a breakpoint set here
+                self.resumed_at = Some(m.pc);
+                notes.push(trace::instruction(m));
+            }
+            Some(n) => self.steps = Some(n - 1),
+            None => {}
+        }
+        notes
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn machine(program: &str) -> Machine {
+        let mut m = Machine::new();
+        m.init();
+        m.load_program_hex(program).unwrap();
+        m
+    }
+
+    #[test]
+    fn debugger_parse_command() {
+        let mut a = Aliases::default();
+        assert_eq!(Ok(Command::Break(0x2A4)), parse_command("break 0x2A4", &a));
+        assert_eq!(
+            Ok(Command::Watch(Operand::Register(6))),
+            parse_command("watch  V6", &a)
+        );
+        assert_eq!(Ok(Command::Run), parse_command("run", &a));
+        assert!(parse_command("break", &a).is_err());
+        assert!(parse_command("break 0x10000", &a).is_err());
+        assert!(parse_command("watch V6 V7", &a).is_err());
+        assert!(parse_command("run now", &a).is_err());
+        assert!(parse_command("jump 0x200", &a).is_err());
+
+        let alias = parse_command("alias V6 ball_x  x of the ball", &a).unwrap();
+        assert_eq!(
+            Command::Alias(parse_alias("V6 ball_x x of the ball").unwrap()),
+            alias
+        );
+        assert!(parse_command("alias V6", &a).is_err());
+        assert!(parse_command("watch ball_x", &a).is_err());
+        a.set(parse_alias("V6 ball_x").unwrap());
+        assert_eq!(
+            Ok(Command::Watch(Operand::Register(6))),
+            parse_command("watch ball_x", &a)
+        );
+    }
+
+    #[test]
+    fn debugger_script() {
+        let m = machine("6001");
+        let d = Debugger::from_script("# setup\nbreak 0x204\n\nwatch V1\n", &m, Aliases::default())
+            .unwrap();
+        assert!(d.paused);
+        assert_eq!(vec![0x204], d.breakpoints);
+        let d = Debugger::from_script("break 0x204\nrun\n", &m, Aliases::default()).unwrap();
+        assert!(!d.paused);
+
+        let e =
+            Debugger::from_script("break 0x204\nwatch V16\n", &m, Aliases::default()).unwrap_err();
+        assert!(e.to_string().starts_with("line 2:"), "{}", e);
+    }
+
+    #[test]
+    fn debugger_breaks_and_watches() {
+        // V1 = 1; V1 += 1; jump back to V1 += 1
+        let mut m = machine("6101 7101 1202");
+        let script = "break 0x202\nwatch V1\nalias V1 counter\nrun";
+        let mut d = Debugger::from_script(script, &m, Aliases::default()).unwrap();
+
+        assert!(d.check(&m).is_empty());
+        m.exec_single().unwrap();
+        assert_eq!(
+            vec!["watch counter (V1): 0x00 -> 0x01", "breakpoint at 0x202"],
+            d.check(&m)
+        );
+        assert!(d.paused);
+
+        // resuming steps over the breakpoint, and stops there next time
+        d.resume();
+        assert!(d.check(&m).is_empty());
+        m.exec_single().unwrap();
+        assert_eq!(vec!["watch counter (V1): 0x01 -> 0x02"], d.check(&m));
+        m.exec_single().unwrap();
+        assert_eq!(vec!["breakpoint at 0x202"], d.check(&m));
+        assert_eq!("counter (V1) = 0x02\n", d.aliases.summary(&m));
+    }
+
+    #[test]
+    fn debugger_parse_console_commands() {
+        let a = Aliases::default();
+        assert_eq!(
+            Ok(Command::Delete(0x2A4)),
+            parse_command("delete 0x2A4", &a)
+        );
+        assert_eq!(Ok(Command::Pause), parse_command("pause", &a));
+        assert_eq!(Ok(Command::Step(1)), parse_command("step", &a));
+        assert_eq!(Ok(Command::Step(10)), parse_command("step 10", &a));
+        assert_eq!(Ok(Command::Registers), parse_command("regs", &a));
+        assert_eq!(Ok(Command::Stack), parse_command("stack", &a));
+        assert_eq!(
+            Ok(Command::Memory(0x300, 16)),
+            parse_command("mem 0x300", &a)
+        );
+        assert_eq!(
+            Ok(Command::Memory(0x300, 4)),
+            parse_command("mem 0x300 4", &a)
+        );
+        assert!(parse_command("step 0", &a).is_err());
+        assert!(parse_command("mem", &a).is_err());
+        assert!(parse_command("mem 0x300 4 5", &a).is_err());
+        assert!(parse_command("regs V1", &a).is_err());
+        assert!(parse_command("delete", &a).is_err());
+        assert_eq!(Ok(Command::SearchStart), parse_command("search start", &a));
+        assert_eq!(
+            Ok(Command::Search(Step::Equals(3))),
+            parse_command("search eq 3", &a)
+        );
+        assert_eq!(
+            Ok(Command::Search(Step::Decreased)),
+            parse_command("search dec", &a)
+        );
+        assert_eq!(Ok(Command::SearchList), parse_command("search", &a));
+        assert!(parse_command("search down", &a).is_err());
+    }
+
+    #[test]
+    fn debugger_search() {
+        // [0x300] = 5, then 4
+        let mut m = machine("A300 6005 F055 6004 F055");
+        let mut d = Debugger::default();
+        let none = "no search, `search start` first\n";
+        assert_eq!(none, d.execute(Command::SearchList, &m).unwrap());
+        assert_eq!(
+            none,
+            d.execute(Command::Search(Step::Decreased), &m).unwrap()
+        );
+
+        m.run_for(3).unwrap();
+        let out = d.execute(Command::Search(Step::Equals(5)), &m).unwrap();
+        assert!(out.contains("0x300: 0x05\n"), "{}", out);
+        m.run_for(2).unwrap();
+        assert_eq!(
+            "1 candidate\n0x300: 0x04\n",
+            d.execute(Command::Search(Step::Decreased), &m).unwrap()
+        );
+        let out = d.execute(Command::SearchStart, &m).unwrap();
+        assert_eq!("65536 candidates, the whole memory\n", out);
+    }
+
+    #[test]
+    fn debugger_steps() {
+        // V1 = 1; V1 += 1; call 0x208; halt; return
+        let mut m = machine("6101 7101 2208 1206 00EE");
+        let mut d = Debugger::from_script("break 0x208", &m, Aliases::default()).unwrap();
+        assert!(d.paused);
+
+        d.step(1);
+        assert!(d.check(&m).is_empty());
+        m.exec_single().unwrap();
+        assert_eq!(vec!["0x202: 7101  ADD V1, 0x01"], d.check(&m));
+        assert!(d.paused);
+
+        // a breakpoint stops the steps
+        d.step(5);
+        for _ in 0..2 {
+            assert!(d.check(&m).is_empty());
+            m.exec_single().unwrap();
+        }
+        assert_eq!(vec!["breakpoint at 0x208"], d.check(&m));
+
+        let out = d.execute(Command::Registers, &m).unwrap();
+        assert!(out.starts_with("PC: 0x208  I: 0x000  DT: "), "{}", out);
+        assert!(out.contains("V1: 02"), "{}", out);
+        assert_eq!("stack: [0x204]\n", d.execute(Command::Stack, &m).unwrap());
+        assert_eq!(
+            "0x200: 61 01 71 01 22 08\n",
+            d.execute(Command::Memory(0x200, 6), &m).unwrap()
+        );
+        let out = d.execute(Command::Memory(0xFFF8, 32), &m).unwrap();
+        assert_eq!(1, out.lines().count());
+
+        d.execute(Command::Delete(0x208), &m).unwrap();
+        d.resume();
+        assert!(d.check(&m).is_empty());
+        d.execute(Command::Pause, &m).unwrap();
+        assert!(d.paused);
+    }
+}
@@ -0,0 +1,50 @@
+// The demo: a small game kept as source (data/squash.asm) and built with
+// the assembler when it starts, so `chip8 --demo` has something to play
+// without a ROM around.
+
+use crate::asm;
+use crate::disasm::Syntax;
+
+// what the window title and messages call it
+pub const NAME: &str = "squash (demo)";
+
+const SOURCE: &str = include_str!("../data/squash.asm");
+
+pub fn program() -> Vec<u8> {
+    // checked by the tests, it can't fail at runtime
+    asm::assemble(SOURCE, Syntax::CowGod).expect("the demo doesn't assemble")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Machine, CPU_IPS};
+
+    #[test]
+    fn demo_plays() {
+        let mut m = Machine::new();
+        m.init();
+        m.seed_rng(7);
+        m.load_program(program()).unwrap();
+        // the paddle follows the ball: every return scores
+        let mut best = 0;
+        for _ in 0..20 * 60 {
+            let (paddle, ball) = (m.registers[0xB], m.registers[0xD]);
+            m.set_key_state(1, ball < paddle + 2);
+            m.set_key_state(4, ball > paddle + 3);
+            m.run_for(u64::from(CPU_IPS / 60)).unwrap();
+            best = best.max(m.registers[7]);
+        }
+        assert!(best >= 3, "{}", best);
+
+        // left alone, it keeps serving
+        m.init();
+        m.load_program(program()).unwrap();
+        let mut balls = std::collections::HashSet::new();
+        for _ in 0..20 {
+            m.run_for(u64::from(CPU_IPS)).unwrap();
+            balls.insert((m.registers[0xC], m.registers[0xD]));
+        }
+        assert!(balls.len() > 10, "{:?}", balls);
+    }
+}
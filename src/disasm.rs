@@ -0,0 +1,386 @@
+// Disassembler: a ROM as source text, in one of three syntaxes.
+//
+//     chip8 disasm [--syntax cowgod|octo|raw] ROM
+//
+// - `cowgod`: the mnemonics of Cowgod's Chip-8 technical reference,
+//   e.g. `LD VA, 0x02`
+// - `octo`: Octo statements, that Octo compiles back to the same ROM,
+//   e.g. `va := 0x02`
+// - `raw`: the address and word of every instruction, annotated with its
+//   CowGod mnemonic
+//
+// The ROM is read as 2-byte instructions from 0x200, but for the XO-CHIP
+// `F000 NNNN` that takes the word after it: words that aren't an
+// instruction (or have no equivalent in the syntax) are written as data.
+// The names given to registers and addresses with the debugger (see
+// aliases.rs) are noted next to the instructions using them.
+
+use crate::aliases::Aliases;
+use crate::exit;
+use crate::watch::Operand;
+use crate::{utils, PROGRAM_START_ADDRESS};
+use std::fs;
+use std::io;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Syntax {
+    CowGod,
+    Octo,
+    Raw,
+}
+
+impl Syntax {
+    pub fn parse(name: &str) -> Result<Syntax, String> {
+        match name {
+            "cowgod" => Ok(Syntax::CowGod),
+            "octo" => Ok(Syntax::Octo),
+            "raw" => Ok(Syntax::Raw),
+            _ => Err(format!("unknown syntax `{}`", name)),
+        }
+    }
+
+    // start of a comment
+    pub fn comment(self) -> &'static str {
+        match self {
+            Syntax::Octo => "#",
+            Syntax::CowGod | Syntax::Raw => ";",
+        }
+    }
+}
+
+// An instruction: the bits of `mask` are `pattern`, the others are the
+// operands written in place of `{x}`, `{y}` (registers), `{n}` (4 bits),
+// `{nn}` (8 bits), `{nnn}` (an address) and `{p}` (the X digit as a
+// plane mask) in the templates
+pub struct Form {
+    pub mask: u16,
+    pub pattern: u16,
+    pub cowgod: &'static str,
+    // none when Octo has no statement for it
+    pub octo: Option<&'static str>,
+}
+
+const fn form(mask: u16, pattern: u16, cowgod: &'static str, octo: &'static str) -> Form {
+    Form {
+        mask,
+        pattern,
+        cowgod,
+        octo: Some(octo),
+    }
+}
+
+// the first match wins, so specific forms come before general ones
+pub const FORMS: &[Form] = &[
+    form(0xFFFF, 0x00E0, "CLS", "clear"),
+    form(0xFFFF, 0x00EE, "RET", "return"),
+    form(0xFFF0, 0x00C0, "SCD {n}", "scroll-down {n}"),
+    form(0xFFF0, 0x00D0, "SCU {n}", "scroll-up {n}"),
+    form(0xFFFF, 0x00FB, "SCR", "scroll-right"),
+    form(0xFFFF, 0x00FC, "SCL", "scroll-left"),
+    form(0xFFFF, 0x00FD, "EXIT", "exit"),
+    form(0xFFFF, 0x00FE, "LOW", "lores"),
+    form(0xFFFF, 0x00FF, "HIGH", "hires"),
+    Form {
+        mask: 0xF000,
+        pattern: 0x0000,
+        cowgod: "SYS {nnn}",
+        octo: None,
+    },
+    form(0xF000, 0x1000, "JP {nnn}", "jump {nnn}"),
+    form(0xF000, 0x2000, "CALL {nnn}", ":call {nnn}"),
+    form(0xF000, 0x3000, "SE V{x}, {nn}", "if v{x} != {nn} then"),
+    form(0xF000, 0x4000, "SNE V{x}, {nn}", "if v{x} == {nn} then"),
+    form(0xF00F, 0x5000, "SE V{x}, V{y}", "if v{x} != v{y} then"),
+    form(0xF00F, 0x5002, "LD [I], V{x}-V{y}", "save v{x} - v{y}"),
+    form(0xF00F, 0x5003, "LD V{x}-V{y}, [I]", "load v{x} - v{y}"),
+    form(0xF000, 0x6000, "LD V{x}, {nn}", "v{x} := {nn}"),
+    form(0xF000, 0x7000, "ADD V{x}, {nn}", "v{x} += {nn}"),
+    form(0xF00F, 0x8000, "LD V{x}, V{y}", "v{x} := v{y}"),
+    form(0xF00F, 0x8001, "OR V{x}, V{y}", "v{x} |= v{y}"),
+    form(0xF00F, 0x8002, "AND V{x}, V{y}", "v{x} &= v{y}"),
+    form(0xF00F, 0x8003, "XOR V{x}, V{y}", "v{x} ^= v{y}"),
+    form(0xF00F, 0x8004, "ADD V{x}, V{y}", "v{x} += v{y}"),
+    form(0xF00F, 0x8005, "SUB V{x}, V{y}", "v{x} -= v{y}"),
+    form(0xF00F, 0x8006, "SHR V{x}, V{y}", "v{x} >>= v{y}"),
+    form(0xF00F, 0x8007, "SUBN V{x}, V{y}", "v{x} =- v{y}"),
+    form(0xF00F, 0x800E, "SHL V{x}, V{y}", "v{x} <<= v{y}"),
+    form(0xF00F, 0x9000, "SNE V{x}, V{y}", "if v{x} == v{y} then"),
+    form(0xF000, 0xA000, "LD I, {nnn}", "i := {nnn}"),
+    form(0xF000, 0xB000, "JP V0, {nnn}", "jump0 {nnn}"),
+    form(0xF000, 0xC000, "RND V{x}, {nn}", "v{x} := random {nn}"),
+    form(
+        0xF000,
+        0xD000,
+        "DRW V{x}, V{y}, {n}",
+        "sprite v{x} v{y} {n}",
+    ),
+    form(0xF0FF, 0xE09E, "SKP V{x}", "if v{x} -key then"),
+    form(0xF0FF, 0xE0A1, "SKNP V{x}", "if v{x} key then"),
+    form(0xF0FF, 0xF001, "PLANE {p}", "plane {p}"),
+    form(0xFFFF, 0xF002, "AUDIO", "audio"),
+    form(0xF0FF, 0xF007, "LD V{x}, DT", "v{x} := delay"),
+    form(0xF0FF, 0xF00A, "LD V{x}, K", "v{x} := key"),
+    form(0xF0FF, 0xF015, "LD DT, V{x}", "delay := v{x}"),
+    form(0xF0FF, 0xF018, "LD ST, V{x}", "buzzer := v{x}"),
+    form(0xF0FF, 0xF01E, "ADD I, V{x}", "i += v{x}"),
+    form(0xF0FF, 0xF029, "LD F, V{x}", "i := hex v{x}"),
+    form(0xF0FF, 0xF030, "LD HF, V{x}", "i := bighex v{x}"),
+    form(0xF0FF, 0xF033, "LD B, V{x}", "bcd v{x}"),
+    form(0xF0FF, 0xF03A, "LD PITCH, V{x}", "pitch := v{x}"),
+    form(0xF0FF, 0xF055, "LD [I], V{x}", "save v{x}"),
+    form(0xF0FF, 0xF065, "LD V{x}, [I]", "load v{x}"),
+    form(0xF0FF, 0xF075, "LD R, V{x}", "saveflags v{x}"),
+    form(0xF0FF, 0xF085, "LD V{x}, R", "loadflags v{x}"),
+];
+
+// F000 NNNN, formatted with the word after it: `{nnnn}` is its address
+pub const LONG_INDEX: Form = form(0xFFFF, 0xF000, "LD I, LONG {nnnn}", "i := long {nnnn}");
+
+pub fn decode(word: u16) -> Option<&'static Form> {
+    FORMS.iter().find(|f| word & f.mask == f.pattern)
+}
+
+impl Form {
+    pub fn template(&self, syntax: Syntax) -> Option<&'static str> {
+        match syntax {
+            Syntax::Octo => self.octo,
+            Syntax::CowGod | Syntax::Raw => Some(self.cowgod),
+        }
+    }
+
+    // the template filled in with the operands of `word`
+    pub fn format(&self, word: u16, syntax: Syntax) -> Option<String> {
+        let register = |r: u16| match syntax {
+            Syntax::Octo => format!("{:x}", r),
+            Syntax::CowGod | Syntax::Raw => format!("{:X}", r),
+        };
+        let text = self
+            .template(syntax)?
+            .replace("{x}", &register(word >> 8 & 0xF))
+            .replace("{y}", &register(word >> 4 & 0xF))
+            .replace("{p}", &(word >> 8 & 0xF).to_string())
+            .replace("{nnnn}", &format!("{:#06X}", word))
+            .replace("{nnn}", &format!("{:#05X}", word & 0xFFF))
+            .replace("{nn}", &format!("{:#04X}", word & 0xFF))
+            .replace("{n}", &(word & 0xF).to_string());
+        Some(text)
+    }
+
+    // the registers and address used by `word`
+    fn operands(&self, word: u16) -> Vec<Operand> {
+        let mut operands = Vec::new();
+        if self.cowgod.contains("{x}") {
+            operands.push(Operand::Register(usize::from(word >> 8 & 0xF)));
+        }
+        if self.cowgod.contains("{y}") {
+            operands.push(Operand::Register(usize::from(word >> 4 & 0xF)));
+        }
+        if self.cowgod.contains("{nnn}") {
+            operands.push(Operand::Memory(usize::from(word & 0xFFF)));
+        }
+        if self.cowgod.contains("{nnnn}") {
+            operands.push(Operand::Memory(usize::from(word)));
+        }
+        operands
+    }
+}
+
+fn data(bytes: &[u8], syntax: Syntax) -> String {
+    match (syntax, bytes) {
+        (Syntax::CowGod, [hi, lo]) => format!("DW {:#06X}", u16::from(*hi) << 8 | u16::from(*lo)),
+        (Syntax::Raw, _) => String::from("data"),
+        (Syntax::CowGod, _) => bytes
+            .iter()
+            .map(|b| format!("DB {:#04X}", b))
+            .collect::<Vec<_>>()
+            .join("\n"),
+        (Syntax::Octo, _) => bytes
+            .iter()
+            .map(|b| format!("{:#04X}", b))
+            .collect::<Vec<_>>()
+            .join(" "),
+    }
+}
+
+pub fn disassemble(program: &[u8], syntax: Syntax, aliases: &Aliases) -> String {
+    let mut text = String::new();
+    let mut offset = 0;
+    while offset < program.len() {
+        let address = PROGRAM_START_ADDRESS + offset;
+        let rest = &program[offset..];
+        let (bytes, form, word) = match *rest {
+            [0xF0, 0x00, hi, lo, ..] => (
+                &rest[..4],
+                Some(&LONG_INDEX),
+                Some(u16::from(hi) << 8 | u16::from(lo)),
+            ),
+            [hi, lo, ..] => {
+                let word = u16::from(hi) << 8 | u16::from(lo);
+                (&rest[..2], decode(word), Some(word))
+            }
+            _ => (rest, None, None),
+        };
+        offset += bytes.len();
+        let code = match (form, word) {
+            (Some(f), Some(w)) => f.format(w, syntax),
+            _ => None,
+        };
+
+        let mut notes: Vec<String> = match (form, word) {
+            (Some(f), Some(w)) => f
+                .operands(w)
+                .iter()
+                .filter_map(|o| aliases.name_of(o).map(|n| format!("{}={}", n, describe(o))))
+                .collect(),
+            _ => Vec::new(),
+        };
+        notes.dedup();
+        let notes = if notes.is_empty() {
+            String::new()
+        } else {
+            format!(" {}", notes.join(", "))
+        };
+
+        let line = match syntax {
+            Syntax::Raw => {
+                let hex: String = bytes.iter().map(|b| format!("{:02X}", b)).collect();
+                let mnemonic = code.unwrap_or_else(|| data(bytes, syntax));
+                format!(
+                    "{:03X}: {:<4}  {} {}{}",
+                    address,
+                    hex,
+                    syntax.comment(),
+                    mnemonic,
+                    notes
+                )
+            }
+            _ => {
+                let code = code.unwrap_or_else(|| data(bytes, syntax));
+                format!("{:<24}{} {:03X}{}", code, syntax.comment(), address, notes)
+            }
+        };
+        text.push_str(&line);
+        text.push('\n');
+    }
+    text
+}
+
+fn describe(operand: &Operand) -> String {
+    match operand {
+        Operand::Memory(a) => format!("[{:#05X}]", a),
+        Operand::Register(r) => format!("V{:X}", r),
+    }
+}
+
+pub fn run(args: &[String]) -> io::Result<()> {
+    let invalid = |e: String| io::Error::new(io::ErrorKind::InvalidInput, e);
+    let mut syntax = Syntax::CowGod;
+    let mut rom = None;
+    let mut it = args.iter();
+    while let Some(arg) = it.next() {
+        match arg.as_str() {
+            "--syntax" => {
+                let name = it
+                    .next()
+                    .ok_or_else(|| invalid(String::from("missing value for --syntax")))?;
+                syntax = Syntax::parse(name).map_err(invalid)?;
+            }
+            s if s.starts_with("--") => return Err(invalid(format!("unknown option `{}`", s))),
+            s if rom.is_none() => rom = Some(s.to_string()),
+            s => return Err(invalid(format!("unexpected argument `{}`", s))),
+        }
+    }
+    let rom = rom.ok_or_else(|| {
+        invalid(String::from(
+            "usage: chip8 disasm [--syntax cowgod|octo|raw] ROM",
+        ))
+    })?;
+
+    let program = fs::read(&rom).map_err(|e| exit::rom_load(&rom, e))?;
+    let hash = utils::fnv1a(utils::FNV_OFFSET, &program);
+    let aliases = match Aliases::path_for(hash) {
+        Some(path) => Aliases::load(path)?,
+        None => Aliases::default(),
+    };
+    print!("{}", disassemble(&program, syntax, &aliases));
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::aliases::parse_alias;
+
+    #[test]
+    fn disasm_forms() {
+        let cases: &[(u16, &str, Option<&str>)] = &[
+            (0x00E0, "CLS", Some("clear")),
+            (0x00C4, "SCD 4", Some("scroll-down 4")),
+            (0x0230, "SYS 0x230", None),
+            (0x12A4, "JP 0x2A4", Some("jump 0x2A4")),
+            (0x3A05, "SE VA, 0x05", Some("if va != 0x05 then")),
+            (0x8AB6, "SHR VA, VB", Some("va >>= vb")),
+            (0x8AB7, "SUBN VA, VB", Some("va =- vb")),
+            (0xC3FF, "RND V3, 0xFF", Some("v3 := random 0xFF")),
+            (0xD01F, "DRW V0, V1, 15", Some("sprite v0 v1 15")),
+            (0xE59E, "SKP V5", Some("if v5 -key then")),
+            (0xF265, "LD V2, [I]", Some("load v2")),
+            (0x5A32, "LD [I], VA-V3", Some("save va - v3")),
+            (0xF201, "PLANE 2", Some("plane 2")),
+        ];
+        for (word, cowgod, octo) in cases {
+            let form = decode(*word).unwrap();
+            assert_eq!(Some(cowgod.to_string()), form.format(*word, Syntax::CowGod));
+            assert_eq!(octo.map(String::from), form.format(*word, Syntax::Octo));
+        }
+        assert!(decode(0x5001).is_none());
+        assert!(decode(0x8008).is_none());
+        assert!(decode(0xF0FF).is_none());
+
+        // the address of F000 is the next word, without one it's data
+        let text = disassemble(
+            &[0xF0, 0x00, 0x12, 0x34, 0xF0, 0x00],
+            Syntax::CowGod,
+            &Aliases::default(),
+        );
+        let lines: Vec<&str> = text.lines().collect();
+        assert_eq!("LD I, LONG 0x1234       ; 200", lines[0]);
+        assert_eq!("DW 0xF000               ; 204", lines[1]);
+    }
+
+    #[test]
+    fn disasm_syntaxes() {
+        let program = [0x6A, 0x02, 0x02, 0x30, 0xFF, 0xFF, 0xAB];
+        let aliases = Aliases::default();
+
+        let text = disassemble(&program, Syntax::CowGod, &aliases);
+        let lines: Vec<&str> = text.lines().collect();
+        assert_eq!("LD VA, 0x02             ; 200", lines[0]);
+        assert_eq!("SYS 0x230               ; 202", lines[1]);
+        assert_eq!("DW 0xFFFF               ; 204", lines[2]);
+        assert_eq!("DB 0xAB                 ; 206", lines[3]);
+
+        let text = disassemble(&program, Syntax::Octo, &aliases);
+        let lines: Vec<&str> = text.lines().collect();
+        assert_eq!("va := 0x02              # 200", lines[0]);
+        assert_eq!("0x02 0x30               # 202", lines[1]);
+        assert_eq!("0xAB                    # 206", lines[3]);
+
+        let text = disassemble(&program, Syntax::Raw, &aliases);
+        let lines: Vec<&str> = text.lines().collect();
+        assert_eq!("200: 6A02  ; LD VA, 0x02", lines[0]);
+        assert_eq!("204: FFFF  ; data", lines[2]);
+        assert_eq!("206: AB    ; data", lines[3]);
+    }
+
+    #[test]
+    fn disasm_notes_aliases() {
+        let mut aliases = Aliases::default();
+        aliases.set(parse_alias("VA player_x").unwrap());
+        aliases.set(parse_alias("[0x2F0] score").unwrap());
+        let text = disassemble(&[0x8A, 0xA4, 0xA2, 0xF0], Syntax::CowGod, &aliases);
+        let lines: Vec<&str> = text.lines().collect();
+        assert_eq!("ADD VA, VA              ; 200 player_x=VA", lines[0]);
+        assert_eq!("LD I, 0x2F0             ; 202 score=[0x2F0]", lines[1]);
+    }
+}
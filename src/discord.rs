@@ -0,0 +1,270 @@
+// Discord Rich Presence: the ROM being played, and since when, shown on the
+// player's Discord profile. The Discord client listens on a local socket,
+// `discord-ipc-0` (to 9) in $XDG_RUNTIME_DIR, $TMPDIR or /tmp, a named pipe
+// `\\.\pipe\discord-ipc-0` on Windows, carrying frames of an opcode and a
+// length, both u32 little endian, then that many bytes of JSON. A client
+// sends a handshake with the id of its Discord application, then a
+// SET_ACTIVITY command each time what it shows changes.
+//
+// Connecting needs the `discord` feature, and the id of an application
+// registered with Discord in CHIP8_DISCORD_CLIENT_ID: the window publishes
+// nothing without it. The title is the ROM's entry in the ROM database (see
+// info.rs) up to its first `(` or `,`, else the ROM's file name. Discord is
+// talked to on a thread of its own, so a client that doesn't answer can't
+// hold the window; if it can't be reached, presence is off until restart.
+
+use crate::info;
+use std::io::{self, Read, Write};
+use std::sync::mpsc;
+use std::thread;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+pub const CLIENT_ID_VARIABLE: &str = "CHIP8_DISCORD_CLIENT_ID";
+// the frame opcodes
+const HANDSHAKE: u32 = 0;
+const FRAME: u32 = 1;
+const CLOSE: u32 = 2;
+// the largest answer read, Discord's are a few K
+const MAX_FRAME: usize = 1 << 16;
+
+pub fn write_frame(out: &mut impl Write, op: u32, json: &str) -> io::Result<()> {
+    let mut frame = Vec::with_capacity(8 + json.len());
+    frame.extend_from_slice(&op.to_le_bytes());
+    frame.extend_from_slice(&(json.len() as u32).to_le_bytes());
+    frame.extend_from_slice(json.as_bytes());
+    out.write_all(&frame)?;
+    out.flush()
+}
+
+pub fn read_frame(input: &mut impl Read) -> io::Result<(u32, String)> {
+    let mut head = [0; 8];
+    input.read_exact(&mut head)?;
+    let op = u32::from_le_bytes([head[0], head[1], head[2], head[3]]);
+    let len = u32::from_le_bytes([head[4], head[5], head[6], head[7]]) as usize;
+    if len > MAX_FRAME {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("a {} bytes frame", len),
+        ));
+    }
+    let mut json = vec![0; len];
+    input.read_exact(&mut json)?;
+    Ok((op, String::from_utf8_lossy(&json).into_owned()))
+}
+
+// a JSON string
+fn quote(text: &str) -> String {
+    let mut quoted = String::from("\"");
+    for c in text.chars() {
+        match c {
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            c if u32::from(c) < 0x20 => quoted.push_str(&format!("\\u{:04x}", u32::from(c))),
+            c => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
+// what the profile shows of a ROM with that hash: its title in the
+// database, else its name
+pub fn title(database: Option<&str>, hash: u64, name: &str) -> String {
+    let entry = database.and_then(|db| info::lookup(db, hash));
+    let title = entry.map(|e| e.split(&['(', ','][..]).next().unwrap_or(e).trim());
+    title.filter(|t| !t.is_empty()).unwrap_or(name).to_string()
+}
+
+// a connection to the Discord client
+pub struct Session<P> {
+    pipe: P,
+    nonce: u64,
+}
+
+impl<P: Read + Write> Session<P> {
+    // introduce the application, Discord closes the connection of one it
+    // doesn't know
+    pub fn handshake(mut pipe: P, client_id: &str) -> io::Result<Session<P>> {
+        let json = format!("{{\"v\":1,\"client_id\":{}}}", quote(client_id));
+        write_frame(&mut pipe, HANDSHAKE, &json)?;
+        match read_frame(&mut pipe)? {
+            (FRAME, _) => Ok(Session { pipe, nonce: 0 }),
+            (_, reply) => Err(io::Error::new(
+                io::ErrorKind::ConnectionRefused,
+                format!("handshake refused: {}", reply),
+            )),
+        }
+    }
+
+    // `title` played since `start` (seconds since the epoch), as this
+    // process
+    pub fn set_activity(&mut self, title: &str, start: u64) -> io::Result<()> {
+        self.nonce += 1;
+        let json = format!(
+            "{{\"cmd\":\"SET_ACTIVITY\",\"args\":{{\"pid\":{},\"activity\":{{\
+             \"details\":{},\"state\":\"CHIP-8\",\"timestamps\":{{\"start\":{}}}}}}},\
+             \"nonce\":\"{}\"}}",
+            std::process::id(),
+            quote(title),
+            start,
+            self.nonce
+        );
+        write_frame(&mut self.pipe, FRAME, &json)?;
+        match read_frame(&mut self.pipe)? {
+            (FRAME, reply) if !reply.contains("\"evt\":\"ERROR\"") => Ok(()),
+            (CLOSE, reply) => Err(io::Error::new(
+                io::ErrorKind::ConnectionAborted,
+                format!("closed: {}", reply),
+            )),
+            (_, reply) => Err(io::Error::other(format!("refused: {}", reply))),
+        }
+    }
+}
+
+pub trait Pipe: Read + Write + Send {}
+
+impl<T: Read + Write + Send> Pipe for T {}
+
+#[cfg(all(feature = "discord", unix))]
+fn open() -> io::Result<Box<dyn Pipe>> {
+    use std::os::unix::net::UnixStream;
+    use std::path::PathBuf;
+
+    let dir = ["XDG_RUNTIME_DIR", "TMPDIR", "TMP", "TEMP"]
+        .iter()
+        .find_map(std::env::var_os)
+        .map_or_else(|| PathBuf::from("/tmp"), PathBuf::from);
+    (0..10)
+        .find_map(|n| UnixStream::connect(dir.join(format!("discord-ipc-{}", n))).ok())
+        .map(|s| Box::new(s) as Box<dyn Pipe>)
+        .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "Discord isn't running"))
+}
+
+#[cfg(all(feature = "discord", windows))]
+fn open() -> io::Result<Box<dyn Pipe>> {
+    (0..10)
+        .find_map(|n| {
+            std::fs::OpenOptions::new()
+                .read(true)
+                .write(true)
+                .open(format!(r"\\.\pipe\discord-ipc-{}", n))
+                .ok()
+        })
+        .map(|f| Box::new(f) as Box<dyn Pipe>)
+        .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "Discord isn't running"))
+}
+
+#[cfg(not(all(feature = "discord", any(unix, windows))))]
+fn open() -> io::Result<Box<dyn Pipe>> {
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        "Discord presence needs a build with the `discord` feature",
+    ))
+}
+
+// the thread publishing the ROMs played
+pub struct Presence {
+    sender: mpsc::Sender<(String, u64)>,
+}
+
+impl Presence {
+    // connects on the first ROM played
+    pub fn start(client_id: String) -> Presence {
+        let (sender, playing) = mpsc::channel::<(String, u64)>();
+        thread::spawn(move || {
+            let mut session = None;
+            for (title, start) in playing {
+                let connected = match session.take() {
+                    Some(s) => Ok(s),
+                    None => open().and_then(|pipe| Session::handshake(pipe, &client_id)),
+                };
+                match connected.and_then(|mut s| s.set_activity(&title, start).map(|_| s)) {
+                    Ok(s) => session = Some(s),
+                    Err(e) => {
+                        println!("Discord presence off: {}", e);
+                        return;
+                    }
+                }
+            }
+        });
+        Presence { sender }
+    }
+
+    // from the environment, when there's an application id
+    pub fn from_env() -> Option<Presence> {
+        std::env::var(CLIENT_ID_VARIABLE)
+            .ok()
+            .filter(|id| !id.is_empty())
+            .map(Presence::start)
+    }
+
+    // `title` starts now
+    pub fn playing(&self, title: &str) {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |d| d.as_secs());
+        // the thread is gone when presence is off
+        let _ = self.sender.send((title.to_string(), now));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    #[test]
+    fn discord_frames() {
+        let mut out = Vec::new();
+        write_frame(&mut out, FRAME, "{}").unwrap();
+        assert_eq!([1, 0, 0, 0, 2, 0, 0, 0, b'{', b'}'], out[..]);
+        assert_eq!(
+            (FRAME, String::from("{}")),
+            read_frame(&mut Cursor::new(out)).unwrap()
+        );
+        let huge = [1, 0, 0, 0, 0, 0, 0, 1];
+        assert!(read_frame(&mut Cursor::new(huge)).is_err());
+
+        assert_eq!(r#""a \"b\" \\ \u000a""#, quote("a \"b\" \\ \n"));
+
+        let db = "00000000000000ff Pong (1990, Paul Vervalin), keypad 1/4\n";
+        assert_eq!("Pong", title(Some(db), 0xFF, "pong"));
+        assert_eq!("pong2", title(Some(db), 0xFE, "pong2"));
+        assert_eq!("pong", title(None, 0xFF, "pong"));
+    }
+
+    #[test]
+    #[cfg(unix)]
+    #[cfg_attr(miri, ignore)]
+    fn discord_session() {
+        use std::os::unix::net::UnixStream;
+
+        // a Discord client, ready, taking an activity then refusing one
+        let (client, mut discord) = UnixStream::pair().unwrap();
+        let server = thread::spawn(move || {
+            let (op, hello) = read_frame(&mut discord).unwrap();
+            assert_eq!((HANDSHAKE, r#"{"v":1,"client_id":"42"}"#), (op, &hello[..]));
+            write_frame(&mut discord, FRAME, r#"{"cmd":"DISPATCH","evt":"READY"}"#).unwrap();
+            let (op, activity) = read_frame(&mut discord).unwrap();
+            write_frame(&mut discord, FRAME, r#"{"evt":null,"nonce":"1"}"#).unwrap();
+            read_frame(&mut discord).unwrap();
+            write_frame(&mut discord, FRAME, r#"{"evt":"ERROR","nonce":"2"}"#).unwrap();
+            (op, activity)
+        });
+
+        let mut session = Session::handshake(client, "42").unwrap();
+        session.set_activity("Pong", 1_700_000_000).unwrap();
+        assert!(session.set_activity("Pong", 1_700_000_000).is_err());
+        let (op, activity) = server.join().unwrap();
+        assert_eq!(FRAME, op);
+        assert!(activity.starts_with(r#"{"cmd":"SET_ACTIVITY","args":{"pid":"#));
+        assert!(activity.ends_with(
+            r#""activity":{"details":"Pong","state":"CHIP-8","timestamps":{"start":1700000000}}},"nonce":"1"}"#
+        ));
+
+        // Discord closes on an unknown application
+        let (client, mut discord) = UnixStream::pair().unwrap();
+        write_frame(&mut discord, CLOSE, r#"{"code":4000}"#).unwrap();
+        assert!(Session::handshake(client, "0").is_err());
+    }
+}
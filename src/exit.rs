@@ -0,0 +1,102 @@
+// Exit codes of the `chip8` process, so scripts and CI jobs can tell the
+// outcomes apart:
+//
+//     0  normal quit
+//     1  any other error (bad arguments, I/O...)
+//     2  the ROM couldn't be loaded
+//     3  the program halted on an invalid opcode
+//     4  a check failed: selftest ROMs, soak or fuzz sessions
+//     5  the window froze and the watchdog ended it
+//     6  the program reached an execution limit (see limits.rs)
+//
+// The errors carrying a code are io::Errors wrapping an `Exit`, made by
+// the functions below; the binary turns them into the process exit code.
+
+use std::error::Error;
+use std::fmt;
+use std::io;
+
+pub const ERROR: i32 = 1;
+pub const ROM_LOAD: i32 = 2;
+pub const INVALID_OPCODE: i32 = 3;
+pub const CHECK_FAILED: i32 = 4;
+pub const FROZEN: i32 = 5;
+pub const LIMIT_REACHED: i32 = 6;
+
+#[derive(Debug)]
+struct Exit {
+    code: i32,
+    message: String,
+}
+
+impl fmt::Display for Exit {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.message)
+    }
+}
+
+impl Error for Exit {}
+
+fn error(kind: io::ErrorKind, code: i32, message: String) -> io::Error {
+    io::Error::new(kind, Exit { code, message })
+}
+
+// reading the ROM at `path` failed with `e`
+pub fn rom_load(path: &str, e: io::Error) -> io::Error {
+    let message = format!("cannot load `{}`: {}", path, e);
+    error(e.kind(), ROM_LOAD, message)
+}
+
+pub fn invalid_opcode(pc: usize, opcode: u16) -> io::Error {
+    let message = format!("invalid opcode {:04X} at {:#05X}", opcode, pc);
+    error(io::ErrorKind::InvalidData, INVALID_OPCODE, message)
+}
+
+pub fn limit_reached(message: String) -> io::Error {
+    error(io::ErrorKind::Other, LIMIT_REACHED, message)
+}
+
+pub fn check_failed(message: String) -> io::Error {
+    error(io::ErrorKind::Other, CHECK_FAILED, message)
+}
+
+// the exit code for an error
+pub fn code(e: &io::Error) -> i32 {
+    e.get_ref()
+        .and_then(|inner| inner.downcast_ref::<Exit>())
+        .map_or(ERROR, |exit| exit.code)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn exit_codes() {
+        let e = rom_load("pong.ch8", io::Error::from(io::ErrorKind::NotFound));
+        assert_eq!(ROM_LOAD, code(&e));
+        assert_eq!(io::ErrorKind::NotFound, e.kind());
+        assert!(
+            e.to_string().starts_with("cannot load `pong.ch8`: "),
+            "{}",
+            e
+        );
+
+        let e = invalid_opcode(0x204, 0xF0FF);
+        assert_eq!(INVALID_OPCODE, code(&e));
+        assert_eq!("invalid opcode F0FF at 0x204", e.to_string());
+        assert_eq!(
+            CHECK_FAILED,
+            code(&check_failed(String::from("2 ROMs failed")))
+        );
+        assert_eq!(
+            LIMIT_REACHED,
+            code(&limit_reached(String::from(
+                "limit of 10 cycles reached at 0x200"
+            )))
+        );
+
+        assert_eq!(ERROR, code(&io::Error::other("usage")));
+        assert_eq!(ERROR, code(&io::Error::from(io::ErrorKind::NotFound)));
+    }
+}
@@ -0,0 +1,169 @@
+// Opcode extensions: handlers for opcodes the interpreter doesn't know, so
+// new instructions can be tried out on top of it (most of the 0NNN machine
+// code calls, 5XY1, 8XY8 to 8XYD, EXNN past EX9E/EXA1...). A handler is
+// registered for the opcodes matching a mask and a pattern, e.g.
+//
+//     m.register_opcode(0xF000, 0x0000, |ctx| { ... Next::Step })
+//
+// for 0NNN; the opcodes the interpreter knows still run as they always
+// did. It's handed the opcode and the state it may change, and says where
+// the program goes next. Handlers added first come first, and init keeps
+// them. Plugins add theirs through the same registry (see plugin.rs).
+
+use crate::framebuffer::FrameBuffer;
+use crate::{parse_opcode, Machine, OpCode};
+
+// what a handler can see and change
+pub struct Context<'a> {
+    pub opcode: u16,
+    // address of the opcode
+    pub pc: usize,
+    pub registers: &'a mut [u8; 16],
+    pub index: &'a mut u16,
+    pub memory: &'a mut [u8],
+    pub gfx: &'a mut FrameBuffer,
+}
+
+// where the program goes after the opcode
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Next {
+    // the next instruction
+    Step,
+    // the one after, as the 3XNN skips do
+    Skip,
+    Jump(usize),
+    // the handler doesn't take it after all, the program halts on it
+    Invalid,
+}
+
+pub type OpcodeHandler = dyn FnMut(&mut Context) -> Next + Send;
+
+pub(crate) struct Extension {
+    mask: u16,
+    pattern: u16,
+    handler: Box<OpcodeHandler>,
+}
+
+impl Machine {
+    // handle the unknown opcodes with `opcode & mask == pattern`; refused
+    // when the interpreter already knows all of them
+    pub fn register_opcode<F>(&mut self, mask: u16, pattern: u16, handler: F) -> Result<(), String>
+    where
+        F: FnMut(&mut Context) -> Next + Send + 'static,
+    {
+        if pattern & !mask != 0 {
+            return Err(format!(
+                "pattern {:04X} has bits outside of mask {:04X}",
+                pattern, mask
+            ));
+        }
+        let free = (0..=u16::MAX)
+            .filter(|op| op & mask == pattern)
+            .any(|op| parse_opcode(Some(op)) == OpCode::Invalid);
+        if !free {
+            return Err(format!(
+                "every opcode matching {:04X}/{:04X} is an instruction already",
+                pattern, mask
+            ));
+        }
+        self.extensions.push(Extension {
+            mask,
+            pattern,
+            handler: Box::new(handler),
+        });
+        Ok(())
+    }
+
+    pub fn clear_opcode_extensions(&mut self) {
+        self.extensions.clear();
+    }
+
+    // run an unknown opcode with its handler, false when there's none or it
+    // turned the opcode down
+    pub(crate) fn exec_extension(&mut self, opcode: u16) -> bool {
+        let extension = match self
+            .extensions
+            .iter_mut()
+            .find(|e| opcode & e.mask == e.pattern)
+        {
+            Some(e) => e,
+            None => return false,
+        };
+        let mut context = Context {
+            opcode,
+            pc: self.pc,
+            registers: &mut self.registers,
+            index: &mut self.index_register,
+            memory: &mut self.memory,
+            gfx: &mut self.gfx,
+        };
+        match (extension.handler)(&mut context) {
+            Next::Step => self.pc_inc(),
+            Next::Skip => {
+                self.skip_next();
+                self.pc_inc();
+            }
+            Next::Jump(address) => self.pc = address,
+            Next::Invalid => return false,
+        }
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Chip8Error;
+
+    #[test]
+    fn extensions_registry() {
+        let mut m = Machine::new();
+        // 0NNN: V0 = NN, skip when N is odd, 0FFF is turned down
+        m.register_opcode(0xF000, 0x0000, |ctx| {
+            if ctx.opcode == 0x0FFF {
+                return Next::Invalid;
+            }
+            ctx.registers[0] = ctx.opcode as u8;
+            if ctx.opcode & 1 == 1 {
+                Next::Skip
+            } else {
+                Next::Step
+            }
+        })
+        .unwrap();
+        // 8XYF: jump to V[X] * 2 + 0x200, with I pointing at the pc
+        m.register_opcode(0xF00F, 0x800F, |ctx| {
+            *ctx.index = ctx.pc as u16;
+            ctx.memory[0x300] = 0x12;
+            Next::Jump(usize::from(ctx.registers[usize::from(ctx.opcode >> 8 & 0xF)]) * 2 + 0x200)
+        })
+        .unwrap();
+        m.init();
+
+        // 00E0 is still the clear screen, 0043 skips 6104
+        m.load_program_hex("00E0 0042 0043 6104 6006 800F 0FFF")
+            .unwrap();
+        m.run_for(3).unwrap();
+        assert_eq!((0x43, 0x208), (m.registers[0], m.pc));
+        m.run_for(2).unwrap();
+        assert_eq!(0, m.registers[1]);
+        assert_eq!(
+            (0x20C, 0x20A, 0x12),
+            (m.pc, m.index_register, m.memory[0x300])
+        );
+        assert_eq!(
+            Err(Chip8Error::InvalidOpcode {
+                pc: 0x20C,
+                opcode: 0x0FFF
+            }),
+            m.exec_single()
+        );
+
+        assert!(m.register_opcode(0xF000, 0x1000, |_| Next::Step).is_err());
+        assert!(m.register_opcode(0xF000, 0x0001, |_| Next::Step).is_err());
+        m.clear_opcode_extensions();
+        m.init();
+        m.load_program_hex("0042").unwrap();
+        assert!(m.exec_single().is_err());
+    }
+}
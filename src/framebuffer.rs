@@ -0,0 +1,369 @@
+// The display. Up to 128x64 pixels (SCHIP hi-res) on up to two bit planes
+// (XO-CHIP): every pixel is one byte with bit N set when it's lit on plane
+// N. The pixels of the current resolution are stored row by row from the
+// start of the buffer, so the visible screen is always a plain slice.
+//
+// Drawing and scrolling only touch the selected planes, and every change
+// marks the buffer dirty until the frontend takes the flag.
+
+use crate::utils::convert_to_bits;
+use crate::{GFX_HEIGHT, GFX_WIDTH};
+use std::fmt;
+
+pub const MAX_WIDTH: usize = 128;
+pub const MAX_HEIGHT: usize = 64;
+pub const PLANES: usize = 2;
+// all planes selected
+pub const PLANE_MASK: u8 = (1 << PLANES) - 1;
+
+#[derive(Clone)]
+pub struct FrameBuffer {
+    width: usize,
+    height: usize,
+    pixels: [u8; MAX_WIDTH * MAX_HEIGHT],
+    // planes drawn to, bit N for plane N
+    selected: u8,
+    dirty: bool,
+}
+
+impl fmt::Debug for FrameBuffer {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "FrameBuffer({}x{}, planes {:#b})",
+            self.width, self.height, self.selected
+        )
+    }
+}
+
+impl Default for FrameBuffer {
+    fn default() -> FrameBuffer {
+        FrameBuffer::new()
+    }
+}
+
+impl FrameBuffer {
+    // 64x32, first plane selected
+    pub fn new() -> FrameBuffer {
+        FrameBuffer {
+            width: GFX_WIDTH,
+            height: GFX_HEIGHT,
+            pixels: [0; MAX_WIDTH * MAX_HEIGHT],
+            selected: 1,
+            dirty: false,
+        }
+    }
+
+    // rebuild from the parts returned by width/height/planes/pixels
+    pub fn from_parts(
+        width: usize,
+        height: usize,
+        selected: u8,
+        pixels: &[u8],
+    ) -> Option<FrameBuffer> {
+        let mut fb = FrameBuffer::new();
+        fb.set_resolution(width, height)?;
+        if selected & !PLANE_MASK != 0
+            || pixels.len() != width * height
+            || pixels.iter().any(|p| p & !PLANE_MASK != 0)
+        {
+            return None;
+        }
+        fb.selected = selected;
+        fb.pixels[..pixels.len()].copy_from_slice(pixels);
+        Some(fb)
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    // switch resolution, clearing the screen. None if it's too large
+    pub fn set_resolution(&mut self, width: usize, height: usize) -> Option<()> {
+        if width == 0 || height == 0 || width > MAX_WIDTH || height > MAX_HEIGHT {
+            return None;
+        }
+        self.width = width;
+        self.height = height;
+        self.pixels = [0; MAX_WIDTH * MAX_HEIGHT];
+        self.dirty = true;
+        Some(())
+    }
+
+    pub fn planes(&self) -> u8 {
+        self.selected
+    }
+
+    pub fn select_planes(&mut self, mask: u8) {
+        self.selected = mask & PLANE_MASK;
+    }
+
+    // the visible screen, row major
+    pub fn pixels(&self) -> &[u8] {
+        &self.pixels[..self.width * self.height]
+    }
+
+    pub fn pixel(&self, x: usize, y: usize) -> u8 {
+        if x >= self.width || y >= self.height {
+            return 0;
+        }
+        self.pixels[y * self.width + x]
+    }
+
+    // the visible screen as RGBA bytes into `out`, 4 per pixel, with the
+    // colour of each plane combination in `palette`. A straight table
+    // lookup per pixel, without branches, that the compiler vectorizes
+    pub fn to_rgba(&self, palette: &[[u8; 4]; 1 << PLANES], out: &mut [u8]) {
+        let colours = palette.map(u32::from_ne_bytes);
+        for (p, rgba) in self.pixels().iter().zip(out.chunks_exact_mut(4)) {
+            rgba.copy_from_slice(&colours[usize::from(p & PLANE_MASK)].to_ne_bytes());
+        }
+    }
+
+    // the lit pixels in horizontal runs of the same value, (value, x, y,
+    // length), so a row is drawn in a few rectangles
+    pub fn runs(&self) -> Vec<(u8, usize, usize, usize)> {
+        let mut runs = Vec::new();
+        for (y, row) in self.pixels().chunks(self.width).enumerate() {
+            let mut x = 0;
+            while x < row.len() {
+                let len = row[x..].iter().take_while(|p| **p == row[x]).count();
+                if row[x] != 0 {
+                    runs.push((row[x], x, y, len));
+                }
+                x += len;
+            }
+        }
+        runs
+    }
+
+    // true once after every change
+    pub fn take_dirty(&mut self) -> bool {
+        std::mem::replace(&mut self.dirty, false)
+    }
+
+    // clear the selected planes
+    pub fn clear(&mut self) {
+        let keep = !self.selected;
+        for p in self.pixels.iter_mut() {
+            *p &= keep;
+        }
+        self.dirty = true;
+    }
+
+    // XOR a sprite into `plane`, `width` pixels (8 or 16) per row, with
+    // `sprite` holding width / 8 bytes per row. The position wraps around,
+    // and so do the pixels past the edges unless `clip`. Returns true if a
+    // lit pixel was turned off
+    pub fn draw(
+        &mut self,
+        plane: usize,
+        x: usize,
+        y: usize,
+        sprite: &[u8],
+        width: usize,
+        clip: bool,
+    ) -> bool {
+        let bit = 1 << plane;
+        let bytes_per_row = width.div_ceil(8).max(1);
+        let (x, y) = (x % self.width, y % self.height);
+        let mut collision = false;
+        for (row, bytes) in sprite.chunks(bytes_per_row).enumerate() {
+            if clip && y + row >= self.height {
+                break;
+            }
+            let py = (y + row) % self.height;
+            let bits = bytes.iter().flat_map(|b| convert_to_bits(*b));
+            for (col, on) in bits.enumerate().take(width) {
+                if on == 0 || (clip && x + col >= self.width) {
+                    continue;
+                }
+                let px = (x + col) % self.width;
+                let p = &mut self.pixels[py * self.width + px];
+                collision |= *p & bit != 0;
+                *p ^= bit;
+            }
+        }
+        self.dirty = true;
+        collision
+    }
+
+    // move the selected planes by (dx, dy), filling with unlit pixels
+    fn scroll(&mut self, dx: isize, dy: isize) {
+        let (w, h) = (self.width as isize, self.height as isize);
+        let old = self.pixels;
+        let sel = self.selected;
+        for y in 0..h {
+            for x in 0..w {
+                let (sx, sy) = (x - dx, y - dy);
+                let moved = if sx >= 0 && sx < w && sy >= 0 && sy < h {
+                    old[(sy * w + sx) as usize] & sel
+                } else {
+                    0
+                };
+                let p = &mut self.pixels[(y * w + x) as usize];
+                *p = (*p & !sel) | moved;
+            }
+        }
+        self.dirty = true;
+    }
+
+    pub fn scroll_down(&mut self, n: usize) {
+        self.scroll(0, n.min(self.height) as isize);
+    }
+
+    pub fn scroll_up(&mut self, n: usize) {
+        self.scroll(0, -(n.min(self.height) as isize));
+    }
+
+    pub fn scroll_right(&mut self, n: usize) {
+        self.scroll(n.min(self.width) as isize, 0);
+    }
+
+    pub fn scroll_left(&mut self, n: usize) {
+        self.scroll(-(n.min(self.width) as isize), 0);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lit(fb: &FrameBuffer) -> Vec<(usize, usize)> {
+        let mut v = Vec::new();
+        for y in 0..fb.height() {
+            for x in 0..fb.width() {
+                if fb.pixel(x, y) != 0 {
+                    v.push((x, y));
+                }
+            }
+        }
+        v
+    }
+
+    #[test]
+    fn framebuffer_draw_collides_and_wraps() {
+        let mut fb = FrameBuffer::new();
+        assert!(!fb.take_dirty());
+        assert!(!fb.draw(0, 62, 31, &[0b1010_0000, 0b1000_0000], 8, false));
+        assert!(fb.take_dirty());
+        assert!(!fb.take_dirty());
+        assert_eq!(vec![(62, 0), (0, 31), (62, 31)], lit(&fb));
+
+        assert!(fb.draw(0, 62, 0, &[0b1000_0000], 8, false));
+        assert_eq!(vec![(0, 31), (62, 31)], lit(&fb));
+    }
+
+    #[test]
+    fn framebuffer_draw_clips() {
+        let mut fb = FrameBuffer::new();
+        assert!(!fb.draw(0, 62, 31, &[0b1010_0000, 0b1000_0000], 8, true));
+        assert_eq!(vec![(62, 31)], lit(&fb));
+        // the position itself wraps
+        fb.draw(0, 64 + 1, 32 + 2, &[0b1000_0000], 8, true);
+        assert_eq!(vec![(1, 2), (62, 31)], lit(&fb));
+    }
+
+    #[test]
+    fn framebuffer_wide_sprites_and_planes() {
+        let mut fb = FrameBuffer::new();
+        fb.set_resolution(128, 64).unwrap();
+        fb.draw(1, 0, 0, &[0x00, 0x01], 16, false);
+        assert_eq!(2, fb.pixel(15, 0));
+        assert_eq!(128 * 64, fb.pixels().len());
+
+        fb.draw(0, 15, 0, &[0x80], 8, false);
+        assert_eq!(3, fb.pixel(15, 0));
+
+        // only the selected plane is cleared
+        fb.select_planes(0b10);
+        fb.clear();
+        assert_eq!(1, fb.pixel(15, 0));
+        fb.select_planes(0xFF);
+        assert_eq!(PLANE_MASK, fb.planes());
+    }
+
+    #[test]
+    fn framebuffer_to_rgba() {
+        let palette = [
+            [0, 0, 0, 255],
+            [255, 255, 255, 255],
+            [1, 2, 3, 255],
+            [4, 5, 6, 255],
+        ];
+        let mut fb = FrameBuffer::new();
+        fb.draw(0, 1, 0, &[0b1000_0000], 8, false);
+        fb.draw(1, 2, 0, &[0b1000_0000], 8, false);
+        let mut rgba = vec![0; fb.pixels().len() * 4];
+        fb.to_rgba(&palette, &mut rgba);
+        assert_eq!(
+            [0, 0, 0, 255, 255, 255, 255, 255, 1, 2, 3, 255, 0, 0, 0, 255],
+            rgba[..16]
+        );
+        assert!(rgba[16..].chunks(4).all(|p| p == [0, 0, 0, 255]));
+    }
+
+    #[test]
+    fn framebuffer_runs() {
+        let mut fb = FrameBuffer::new();
+        assert!(fb.runs().is_empty());
+        fb.draw(0, 0, 0, &[0b1110_1100, 0b0000_0001], 8, false);
+        fb.draw(1, 1, 0, &[0b1000_0000], 8, false);
+        fb.draw(0, 60, 1, &[0b1111_0000], 8, false);
+        assert_eq!(
+            vec![
+                (1, 0, 0, 1),
+                (3, 1, 0, 1),
+                (1, 2, 0, 1),
+                (1, 4, 0, 2),
+                (1, 7, 1, 1),
+                (1, 60, 1, 4)
+            ],
+            fb.runs()
+        );
+    }
+
+    #[test]
+    fn framebuffer_scroll() {
+        let mut fb = FrameBuffer::new();
+        fb.draw(0, 0, 0, &[0x80], 8, false);
+        fb.scroll_down(2);
+        assert_eq!(vec![(0, 2)], lit(&fb));
+        fb.scroll_right(4);
+        assert_eq!(vec![(4, 2)], lit(&fb));
+        fb.scroll_left(1);
+        fb.scroll_up(1);
+        assert_eq!(vec![(3, 1)], lit(&fb));
+        fb.scroll_left(100);
+        assert!(lit(&fb).is_empty());
+
+        // other planes stay put
+        fb.draw(0, 5, 5, &[0x80], 8, false);
+        fb.select_planes(0b10);
+        fb.scroll_down(1);
+        assert_eq!(vec![(5, 5)], lit(&fb));
+    }
+
+    #[test]
+    fn framebuffer_resolution_and_parts() {
+        let mut fb = FrameBuffer::new();
+        fb.draw(0, 0, 0, &[0xFF], 8, false);
+        assert_eq!(None, fb.set_resolution(256, 64));
+        fb.set_resolution(64, 64).unwrap();
+        assert!(lit(&fb).is_empty());
+        fb.draw(0, 0, 40, &[0x80], 8, false);
+
+        let copy = FrameBuffer::from_parts(64, 64, fb.planes(), fb.pixels()).unwrap();
+        assert_eq!(fb.pixels(), copy.pixels());
+        assert_eq!(1, copy.pixel(0, 40));
+        assert_eq!(0, copy.pixel(64, 40));
+
+        assert!(FrameBuffer::from_parts(64, 32, 1, fb.pixels()).is_none());
+        assert!(FrameBuffer::from_parts(64, 64, 4, fb.pixels()).is_none());
+        assert!(FrameBuffer::from_parts(2, 1, 1, &[0, 4]).is_none());
+    }
+}
@@ -0,0 +1,328 @@
+// Command line options of the SDL frontend.
+
+use super::config::MAX_VOLUME;
+use super::power;
+use crate::capture::{self, Trigger};
+use crate::quirks::{self, Quirks};
+use crate::trace;
+use crate::{utils, CPU_IPS};
+
+// fastest emulation speed, in instructions per second
+const MAX_CPU_HZ: u32 = 100_000;
+
+pub struct Options {
+    // none when not given, then it's picked in a file dialog
+    pub program_file: Option<String>,
+    // play the built-in demo game instead of a ROM
+    pub demo: bool,
+    // run the first run setup again
+    pub setup: bool,
+    // data loaded over or after the ROM, at their address
+    pub overlays: Vec<(usize, String)>,
+    // plugin libraries to load, see plugin.rs
+    pub plugins: Vec<String>,
+    // run with the standard memory protection map
+    pub protect: bool,
+    // achievements rules file
+    pub achievements: Option<String>,
+    // memory annotations file (defaults to the one next to the ROM)
+    pub annotations: Option<String>,
+    // interpreter quirks to enable
+    pub quirks: Quirks,
+    // instructions per second
+    pub cpu_hz: u32,
+    // start paused, with debugger commands read from the console
+    pub debug: bool,
+    // debugger commands run on startup
+    pub debug_script: Option<String>,
+    // SDL audio playback device, saved in the config
+    pub audio_device: Option<String>,
+    // volume in percent, saved in the config
+    pub volume: Option<u8>,
+    // frequency of the buzzer, in Hz
+    pub tone: Option<u32>,
+    // handheld preset: fullscreen and the state saved for the next run
+    pub handheld: bool,
+    // the second player's keys on, whatever the ROM
+    pub two_player: bool,
+    // show the keypad on the screen (always on Android)
+    pub touch_keypad: bool,
+    // cross-fade between screens, see smoothing.rs
+    pub smooth: bool,
+    // save state restored on startup, and used by the state hotkeys
+    pub load_state: Option<String>,
+    // open a window even when an instance is running
+    pub new_instance: bool,
+    // file logging every executed instruction
+    pub trace: Option<String>,
+    // the instructions traced
+    pub trace_filter: Option<trace::Filter>,
+    // bursts of frames saved as PNGs, on F12 or a trigger
+    pub capture_at: Option<Trigger>,
+    pub capture_frames: u32,
+    pub capture_path: String,
+    // file the keypad inputs are recorded in, or played back from
+    pub record: Option<String>,
+    pub replay: Option<String>,
+    // when to draw fewer frames, see power.rs
+    pub power_save: power::Mode,
+}
+
+pub fn parse_args(args: &[String]) -> Result<Options, String> {
+    let mut opts = Options {
+        program_file: None,
+        demo: false,
+        setup: false,
+        overlays: Vec::new(),
+        plugins: Vec::new(),
+        protect: false,
+        achievements: None,
+        annotations: None,
+        quirks: Quirks::default(),
+        cpu_hz: CPU_IPS,
+        debug: false,
+        debug_script: None,
+        audio_device: None,
+        volume: None,
+        tone: None,
+        handheld: false,
+        two_player: false,
+        touch_keypad: false,
+        smooth: false,
+        load_state: None,
+        new_instance: false,
+        trace: None,
+        trace_filter: None,
+        capture_at: None,
+        capture_frames: capture::DEFAULT_FRAMES,
+        capture_path: String::from(capture::DEFAULT_TEMPLATE),
+        record: None,
+        replay: None,
+        power_save: power::Mode::Auto,
+    };
+
+    let mut it = args.iter();
+    while let Some(arg) = it.next() {
+        let mut value = |name: &str| {
+            it.next()
+                .cloned()
+                .ok_or(format!("missing value for {}", name))
+        };
+        match arg.as_str() {
+            "--demo" => opts.demo = true,
+            "--setup" => opts.setup = true,
+            "--protect" => opts.protect = true,
+            "--debug" => opts.debug = true,
+            "--handheld" => opts.handheld = true,
+            "--two-player" => opts.two_player = true,
+            "--touch-keypad" => opts.touch_keypad = true,
+            "--smooth" => opts.smooth = true,
+            "--new-instance" => opts.new_instance = true,
+            "--achievements" => opts.achievements = Some(value(arg)?),
+            "--annotations" => opts.annotations = Some(value(arg)?),
+            "--quirks" => opts.quirks = Quirks::parse(&value(arg)?)?,
+            "--cosmac" => {
+                opts.quirks = Quirks::parse("vip")?;
+                opts.cpu_hz = quirks::VIP_CPU_HZ;
+            }
+            "--overlay" => opts.overlays.push(utils::parse_overlay(&value(arg)?)?),
+            "--plugin" => opts.plugins.push(value(arg)?),
+            "--debug-script" => opts.debug_script = Some(value(arg)?),
+            "--audio-device" => opts.audio_device = Some(value(arg)?),
+            "--load-state" => opts.load_state = Some(value(arg)?),
+            "--trace" => opts.trace = Some(value(arg)?),
+            "--capture-at" => opts.capture_at = Some(Trigger::parse(&value(arg)?)?),
+            "--capture-frames" => {
+                let v = value(arg)?;
+                let n = v.parse().ok().filter(|n| *n > 0);
+                opts.capture_frames = n.ok_or(format!("invalid frame count `{}`", v))?;
+            }
+            "--capture-path" => opts.capture_path = value(arg)?,
+            "--trace-filter" => opts.trace_filter = Some(trace::Filter::parse(&value(arg)?)?),
+            "--record" => opts.record = Some(value(arg)?),
+            "--replay" => opts.replay = Some(value(arg)?),
+            "--power-save" => opts.power_save = power::Mode::parse(&value(arg)?)?,
+            "--cpu-hz" => {
+                let v = value(arg)?;
+                let hz = v.parse().ok().filter(|hz| (1..=MAX_CPU_HZ).contains(hz));
+                opts.cpu_hz = hz.ok_or(format!("invalid speed `{}` (1 to {})", v, MAX_CPU_HZ))?;
+            }
+            "--volume" => {
+                let v = value(arg)?;
+                let volume = v.parse().ok().filter(|v| *v <= MAX_VOLUME);
+                opts.volume = Some(volume.ok_or(format!("invalid volume `{}`", v))?);
+            }
+            "--tone" => {
+                let v = value(arg)?;
+                let tone = v.parse().ok().filter(|t| (20..=20_000).contains(t));
+                opts.tone = Some(tone.ok_or(format!("invalid tone `{}` (20 to 20000 Hz)", v))?);
+            }
+            // the process serial number older macOS versions pass to apps
+            // started from the Finder
+            s if s.starts_with("-psn_") => {}
+            s if s.starts_with("--") => return Err(format!("unknown option `{}`", s)),
+            s => {
+                if opts.program_file.is_some() {
+                    return Err(format!("unexpected argument `{}`", s));
+                }
+                opts.program_file = Some(s.to_string());
+            }
+        }
+    }
+    if opts.demo && opts.program_file.is_some() {
+        return Err(String::from("--demo runs its own program, not a ROM"));
+    }
+    if opts.capture_at == Some(Trigger::Breakpoint) && !opts.debug && opts.debug_script.is_none() {
+        return Err(String::from(
+            "--capture-at break needs the debugger (--debug or --debug-script)",
+        ));
+    }
+    if opts.trace_filter.is_some() && opts.trace.is_none() {
+        return Err(String::from("--trace-filter needs --trace"));
+    }
+    // recordings start from a reset machine
+    if opts.record.is_some() && opts.replay.is_some() {
+        return Err(String::from("--record and --replay can't be used together"));
+    }
+    if (opts.record.is_some() || opts.replay.is_some()) && opts.load_state.is_some() {
+        return Err(String::from(
+            "--load-state can't be used with --record or --replay",
+        ));
+    }
+    Ok(opts)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(a: &[&str]) -> Vec<String> {
+        a.iter().map(|s| s.to_string()).collect()
+    }
+
+    #[test]
+    fn parse_args_defaults() {
+        let opts = parse_args(&[]).unwrap();
+        assert_eq!(None, opts.program_file);
+        assert!(!opts.protect);
+        assert!(!opts.handheld);
+        assert_eq!(CPU_IPS, opts.cpu_hz);
+        assert_eq!(None, opts.achievements);
+    }
+
+    #[test]
+    fn parse_args_options() {
+        let opts =
+            parse_args(&args(&["--protect", "pong.rom", "--achievements", "a.txt"])).unwrap();
+        assert_eq!(Some(String::from("pong.rom")), opts.program_file);
+        assert!(opts.protect);
+        assert_eq!(Some(String::from("a.txt")), opts.achievements);
+
+        assert!(parse_args(&args(&["--handheld"])).unwrap().handheld);
+        assert!(parse_args(&args(&["--demo"])).unwrap().demo);
+        assert!(parse_args(&args(&["--demo", "pong.rom"])).is_err());
+        assert!(parse_args(&args(&["--setup"])).unwrap().setup);
+        let opts = parse_args(&args(&["--overlay", "0x600:levels.bin", "game.ch8"])).unwrap();
+        assert_eq!(vec![(0x600, String::from("levels.bin"))], opts.overlays);
+        let opts = parse_args(&args(&["--plugin", "a.so", "--plugin", "b.so"])).unwrap();
+        assert_eq!(
+            vec![String::from("a.so"), String::from("b.so")],
+            opts.plugins
+        );
+        assert!(parse_args(&args(&["--touch-keypad"])).unwrap().touch_keypad);
+        assert!(parse_args(&args(&["--smooth"])).unwrap().smooth);
+        assert!(parse_args(&args(&["--new-instance"])).unwrap().new_instance);
+
+        // from a file manager
+        let opts = parse_args(&args(&[
+            "-psn_0_1234",
+            "/home/me/ROMs/Space Invaders é.ch8",
+        ]));
+        assert_eq!(
+            Some(String::from("/home/me/ROMs/Space Invaders é.ch8")),
+            opts.unwrap().program_file
+        );
+
+        let opts = parse_args(&args(&["--quirks", "index-overflow"])).unwrap();
+        assert!(opts.quirks.index_overflow);
+        // the preset, then a speed of its own
+        let opts = parse_args(&args(&["--cosmac", "--cpu-hz", "1000"])).unwrap();
+        assert!(opts.quirks.display_wait && opts.quirks.vip_font);
+        assert_eq!(1000, opts.cpu_hz);
+
+        let opts = parse_args(&args(&["--debug-script", "pong.dbg"])).unwrap();
+        assert_eq!(Some(String::from("pong.dbg")), opts.debug_script);
+        assert!(!opts.debug);
+        assert!(parse_args(&args(&["--debug"])).unwrap().debug);
+
+        let opts = parse_args(&args(&["--audio-device", "USB Audio"])).unwrap();
+        assert_eq!(Some(String::from("USB Audio")), opts.audio_device);
+
+        assert_eq!(
+            1000,
+            parse_args(&args(&["--cpu-hz", "1000"])).unwrap().cpu_hz
+        );
+        assert!(parse_args(&args(&["--cpu-hz", "0"])).is_err());
+
+        let opts = parse_args(&args(&["--volume", "80", "--tone", "440"])).unwrap();
+        assert_eq!(Some(80), opts.volume);
+        assert_eq!(Some(440), opts.tone);
+        assert!(parse_args(&args(&["--volume", "101"])).is_err());
+        assert!(parse_args(&args(&["--tone", "5"])).is_err());
+
+        assert!(parse_args(&args(&["--two-player"])).unwrap().two_player);
+
+        let opts = parse_args(&args(&["--load-state", "pong.state"])).unwrap();
+        assert_eq!(Some(String::from("pong.state")), opts.load_state);
+
+        let opts = parse_args(&args(&["--trace", "pong.trace", "pong.ch8"])).unwrap();
+        assert_eq!(Some(String::from("pong.trace")), opts.trace);
+        let opts = parse_args(&args(&[
+            "--trace",
+            "pong.trace",
+            "--trace-filter",
+            "0x200-0x2FF,draw",
+        ]))
+        .unwrap();
+        assert_eq!(
+            Some(trace::Filter::parse("0x200-0x2FF,draw").unwrap()),
+            opts.trace_filter
+        );
+        assert!(parse_args(&args(&["--trace-filter", "draw"])).is_err());
+
+        let opts = parse_args(&args(&[
+            "--capture-at",
+            "cycle:500",
+            "--capture-path",
+            "f.png",
+        ]))
+        .unwrap();
+        assert_eq!(Some(Trigger::Cycle(500)), opts.capture_at);
+        assert_eq!(
+            (capture::DEFAULT_FRAMES, "f.png"),
+            (opts.capture_frames, &opts.capture_path[..])
+        );
+        assert!(parse_args(&args(&["--capture-at", "break"])).is_err());
+        assert!(parse_args(&args(&["--debug", "--capture-at", "break"])).is_ok());
+        assert!(parse_args(&args(&["--capture-frames", "none"])).is_err());
+        assert!(parse_args(&args(&["--trace", "t", "--trace-filter", "jumps"])).is_err());
+
+        let opts = parse_args(&args(&["--record", "pong.c8r"])).unwrap();
+        assert_eq!(Some(String::from("pong.c8r")), opts.record);
+        assert_eq!(None, opts.replay);
+        let opts = parse_args(&args(&["--replay", "pong.c8r"])).unwrap();
+        assert_eq!(Some(String::from("pong.c8r")), opts.replay);
+        assert!(parse_args(&args(&["--record", "a.c8r", "--replay", "b.c8r"])).is_err());
+
+        assert_eq!(power::Mode::Auto, parse_args(&[]).unwrap().power_save);
+        let opts = parse_args(&args(&["--power-save", "on"])).unwrap();
+        assert_eq!(power::Mode::On, opts.power_save);
+        assert!(parse_args(&args(&["--power-save", "max"])).is_err());
+        assert!(parse_args(&args(&["--replay", "a.c8r", "--load-state", "a.state"])).is_err());
+
+        assert!(parse_args(&args(&["--achievements"])).is_err());
+        assert!(parse_args(&args(&["--quirks", "nope"])).is_err());
+        assert!(parse_args(&args(&["--nope"])).is_err());
+        assert!(parse_args(&args(&["a.rom", "b.rom"])).is_err());
+    }
+}
@@ -0,0 +1,404 @@
+// Frontend settings kept between runs, in `$XDG_CONFIG_HOME/chip8/config`
+// (`~/.config/chip8/config` by default), one `key = value` per line:
+//
+//     # sound
+//     volume = 60
+//     muted = false
+//     audio-device = USB Audio
+//     # gamepad, see hotkeys.rs
+//     hotkey-reset = back+a
+//     # keypad, see keymap.rs
+//     layout = azerty
+//     key-5 = w, up
+//     pad-6 = a
+//     key2-5 = i
+//     # ROMs played by two on the keyboard, by file name
+//     two-player = pong, tank
+//     # display
+//     scale = 10
+//     palette = amber
+//     # where the file dialog opens
+//     rom-dir = /home/me/roms
+//     # scrolled without a ROM, see marquee.rs
+//     idle = C8
+//
+// They're changed from the emulator (volume and mute keys), the command
+// line or the first run setup (see setup.rs), and saved right away.
+
+use super::hotkeys::{self, Hotkeys};
+use super::keymap::{self, Keymap};
+use crate::{capture, marquee};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+pub const MAX_VOLUME: u8 = 100;
+const DEFAULT_VOLUME: u8 = 50;
+// window pixels per CHIP-8 pixel
+pub const DEFAULT_SCALE: u8 = 10;
+pub const MAX_SCALE: u8 = 30;
+
+// RGBA colour of each plane combination, per palette
+pub const PALETTES: [(&str, [[u8; 4]; 4]); 4] = [
+    (
+        "grey",
+        [
+            [0, 0, 0, 255],
+            [255, 255, 255, 255],
+            [170, 170, 170, 255],
+            [85, 85, 85, 255],
+        ],
+    ),
+    (
+        "amber",
+        [
+            [26, 14, 0, 255],
+            [255, 176, 0, 255],
+            [204, 112, 0, 255],
+            [110, 60, 0, 255],
+        ],
+    ),
+    (
+        "green",
+        [
+            [8, 24, 8, 255],
+            [51, 255, 102, 255],
+            [30, 170, 60, 255],
+            [20, 90, 35, 255],
+        ],
+    ),
+    // Octo's default colours
+    (
+        "octo",
+        [
+            [153, 102, 0, 255],
+            [255, 204, 0, 255],
+            [255, 102, 0, 255],
+            [102, 34, 0, 255],
+        ],
+    ),
+];
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Config {
+    // where it's saved, none to keep it in memory only
+    path: Option<PathBuf>,
+    // percent
+    pub volume: u8,
+    pub muted: bool,
+    // SDL playback device, the system default when none
+    pub audio_device: Option<String>,
+    // gamepad chords of the emulator actions
+    pub hotkeys: Hotkeys,
+    // keys and gamepad buttons of the keypad
+    pub keymap: Keymap,
+    // ROMs the second player's keys are on for, by name without extension
+    pub two_player: Vec<String>,
+    // window pixels per CHIP-8 pixel, 1 to MAX_SCALE
+    pub scale: u8,
+    // index in PALETTES
+    pub palette: usize,
+    // the folder the file dialog opens in, the current one when none
+    pub rom_dir: Option<PathBuf>,
+    // the hex digits scrolling when there's no ROM
+    pub idle: Vec<u8>,
+}
+
+impl Default for Config {
+    fn default() -> Config {
+        Config {
+            path: None,
+            volume: DEFAULT_VOLUME,
+            muted: false,
+            audio_device: None,
+            hotkeys: Hotkeys::default(),
+            keymap: Keymap::default(),
+            two_player: Vec::new(),
+            scale: DEFAULT_SCALE,
+            palette: 0,
+            rom_dir: None,
+            idle: marquee::parse(marquee::DEFAULT_DIGITS).unwrap_or_default(),
+        }
+    }
+}
+
+fn config_dir() -> Option<PathBuf> {
+    match std::env::var_os("XDG_CONFIG_HOME") {
+        Some(dir) if !dir.is_empty() => Some(PathBuf::from(dir)),
+        _ => std::env::var_os("HOME").map(|home| Path::new(&home).join(".config")),
+    }
+}
+
+impl Config {
+    // none without a home directory
+    pub fn default_path() -> Option<PathBuf> {
+        config_dir().map(|dir| dir.join("chip8").join("config"))
+    }
+
+    pub fn parse(text: &str) -> Result<Config, String> {
+        let mut config = Config::default();
+        for (n, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let (key, value) = line
+                .split_once('=')
+                .map(|(k, v)| (k.trim(), v.trim()))
+                .ok_or_else(|| format!("line {}: expected `KEY = VALUE`", n + 1))?;
+            let invalid = || format!("line {}: invalid value `{}` for {}", n + 1, value, key);
+            match key {
+                "volume" => {
+                    config.volume = value
+                        .parse()
+                        .ok()
+                        .filter(|v| *v <= MAX_VOLUME)
+                        .ok_or_else(invalid)?
+                }
+                "muted" => config.muted = value.parse().map_err(|_| invalid())?,
+                "audio-device" => {
+                    config.audio_device = Some(value.to_string()).filter(|d| !d.is_empty())
+                }
+                "layout" => config.keymap.set_layout(value).map_err(|_| invalid())?,
+                "two-player" => {
+                    config.two_player = value
+                        .split(',')
+                        .map(str::trim)
+                        .filter(|s| !s.is_empty())
+                        .map(String::from)
+                        .collect()
+                }
+                "scale" => {
+                    config.scale = value
+                        .parse()
+                        .ok()
+                        .filter(|s| (1..=MAX_SCALE).contains(s))
+                        .ok_or_else(invalid)?
+                }
+                "palette" => {
+                    config.palette = PALETTES
+                        .iter()
+                        .position(|p| p.0.eq_ignore_ascii_case(value))
+                        .ok_or_else(invalid)?
+                }
+                "rom-dir" => {
+                    config.rom_dir = Some(PathBuf::from(value)).filter(|_| !value.is_empty())
+                }
+                "idle" => {
+                    config.idle =
+                        marquee::parse(value).map_err(|e| format!("line {}: {}", n + 1, e))?
+                }
+                _ => match keymap::parse_setting(key) {
+                    Some(("key", k)) => config.keymap.bind_keys(k, value),
+                    Some(("key2", k)) => config.keymap.bind_keys2(k, value),
+                    Some((_, k)) => config
+                        .keymap
+                        .bind_pad(k, value)
+                        .map_err(|e| format!("line {}: {}", n + 1, e))?,
+                    None => {
+                        let action = key
+                            .strip_prefix("hotkey-")
+                            .and_then(|name| hotkeys::ACTIONS.iter().find(|a| a.1 == name))
+                            .ok_or_else(|| format!("line {}: unknown setting `{}`", n + 1, key))?;
+                        let chord = hotkeys::parse_chord(value)
+                            .map_err(|e| format!("line {}: {}", n + 1, e))?;
+                        config.hotkeys.bind(action.0, chord);
+                    }
+                },
+            }
+        }
+        Ok(config)
+    }
+
+    // a missing file is the default configuration
+    pub fn load(path: PathBuf) -> io::Result<Config> {
+        let text = match fs::read_to_string(&path) {
+            Ok(text) => text,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => String::new(),
+            Err(e) => return Err(e),
+        };
+        let mut config =
+            Config::parse(&text).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        config.path = Some(path);
+        Ok(config)
+    }
+
+    pub fn to_text(&self) -> String {
+        let mut text = format!("volume = {}\nmuted = {}\n", self.volume, self.muted);
+        if let Some(device) = &self.audio_device {
+            text.push_str(&format!("audio-device = {}\n", device));
+        }
+        for (action, chord) in &self.hotkeys.bindings {
+            text.push_str(&format!(
+                "hotkey-{} = {}\n",
+                hotkeys::action_name(*action),
+                chord.join("+")
+            ));
+        }
+        text.push_str(&self.keymap.to_text());
+        if !self.two_player.is_empty() {
+            text.push_str(&format!("two-player = {}\n", self.two_player.join(", ")));
+        }
+        text.push_str(&format!(
+            "scale = {}\npalette = {}\n",
+            self.scale,
+            self.palette_name()
+        ));
+        if let Some(dir) = &self.rom_dir {
+            text.push_str(&format!("rom-dir = {}\n", dir.display()));
+        }
+        let idle: String = self.idle.iter().map(|d| format!("{:X}", d)).collect();
+        if idle != marquee::DEFAULT_DIGITS {
+            text.push_str(&format!("idle = {}\n", idle));
+        }
+        text
+    }
+
+    // whether it was never saved: the first run, when there's somewhere
+    // to save it
+    pub fn is_new(&self) -> bool {
+        self.path.as_ref().is_some_and(|p| !p.exists())
+    }
+
+    // whether the program at `path` is listed for two players
+    pub fn is_two_player(&self, path: &str) -> bool {
+        let name = capture::rom_name(path);
+        self.two_player
+            .iter()
+            .any(|r| r.eq_ignore_ascii_case(&name))
+    }
+
+    pub fn palette_name(&self) -> &'static str {
+        PALETTES[self.palette].0
+    }
+
+    pub fn colours(&self) -> &'static [[u8; 4]; 4] {
+        &PALETTES[self.palette].1
+    }
+
+    pub fn save(&self) -> io::Result<()> {
+        let path = match &self.path {
+            Some(p) => p,
+            None => return Ok(()),
+        };
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        fs::write(path, self.to_text())
+    }
+
+    // volume up or down by `step` percent
+    pub fn change_volume(&mut self, step: i8) {
+        let v = i16::from(self.volume) + i16::from(step);
+        self.volume = v.clamp(0, i16::from(MAX_VOLUME)) as u8;
+        self.muted = false;
+    }
+
+    // the gain to play at, 0 to 1
+    pub fn gain(&self) -> f32 {
+        if self.muted {
+            0.0
+        } else {
+            f32::from(self.volume) / f32::from(MAX_VOLUME)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn config_parse() {
+        let config =
+            Config::parse("# sound\nvolume = 60\nmuted=true\naudio-device = USB Audio\n").unwrap();
+        assert_eq!(60, config.volume);
+        assert!(config.muted);
+        assert_eq!(Some(String::from("USB Audio")), config.audio_device);
+        assert_eq!(config, Config::parse(&config.to_text()).unwrap());
+
+        let config = Config::parse("hotkey-reset = back+y\nhotkey-quit =\n").unwrap();
+        assert_eq!(
+            "reset back+y, save-state back+rightshoulder, load-state back+leftshoulder",
+            config.hotkeys.summary()
+        );
+        assert_eq!(config, Config::parse(&config.to_text()).unwrap());
+
+        let config = Config::parse("key-5 = w, Up\npad-c = start\n").unwrap();
+        assert_eq!(Some(0x5), config.keymap.key("up"));
+        assert_eq!(Some(0xC), config.keymap.pad_key("start"));
+        assert_eq!(config, Config::parse(&config.to_text()).unwrap());
+
+        let config = Config::parse("two-player = Pong, tank\nkey2-c = down\n").unwrap();
+        assert!(config.is_two_player("roms/pong.ch8"));
+        assert!(!config.is_two_player("roms/pong2.ch8"));
+        assert_eq!(config, Config::parse(&config.to_text()).unwrap());
+
+        let config = Config::parse(
+            "layout = qwertz\nkey-5 = i\nscale = 6\npalette = Amber\nrom-dir = /roms/a b\n",
+        )
+        .unwrap();
+        assert_eq!(Some(0xA), config.keymap.key("y"));
+        assert_eq!(Some(0x5), config.keymap.key("i"));
+        assert_eq!(6, config.scale);
+        assert_eq!("amber", config.palette_name());
+        assert_eq!([255, 176, 0, 255], config.colours()[1]);
+        assert_eq!(Some(PathBuf::from("/roms/a b")), config.rom_dir);
+        assert_eq!(config, Config::parse(&config.to_text()).unwrap());
+
+        let config = Config::parse("idle = c0ffee").unwrap();
+        assert_eq!(vec![0xC, 0, 0xF, 0xF, 0xE, 0xE], config.idle);
+        assert!(config.to_text().contains("idle = C0FFEE\n"));
+        assert_eq!(config, Config::parse(&config.to_text()).unwrap());
+        assert!(!Config::default().to_text().contains("idle"));
+
+        assert_eq!(Config::default(), Config::parse("").unwrap());
+        assert!(Config::parse("layout = colemak").is_err());
+        assert!(Config::parse("scale = 0").is_err());
+        assert!(Config::parse("scale = 31").is_err());
+        assert!(Config::parse("palette = pink").is_err());
+        assert!(Config::parse("volume = 101").is_err());
+        assert!(Config::parse("muted = maybe").is_err());
+        assert!(Config::parse("colour = red").is_err());
+        assert!(Config::parse("hotkey-menu = back+x").is_err());
+        assert!(Config::parse("hotkey-quit = back+select").is_err());
+        assert!(Config::parse("pad-5 = select").is_err());
+        assert!(Config::parse("key-g = g").is_err());
+        assert!(Config::parse("idle = hello").is_err());
+        let e = Config::parse("volume = 10\nvolume").unwrap_err();
+        assert!(e.starts_with("line 2:"), "{}", e);
+    }
+
+    #[test]
+    fn config_volume() {
+        let mut config = Config::default();
+        config.change_volume(30);
+        assert_eq!(80, config.volume);
+        config.change_volume(30);
+        assert_eq!(MAX_VOLUME, config.volume);
+        assert_eq!(1.0, config.gain());
+
+        config.muted = true;
+        assert_eq!(0.0, config.gain());
+        // changing the volume unmutes
+        config.change_volume(-110);
+        assert_eq!((0, false), (config.volume, config.muted));
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore)]
+    fn config_save_load() {
+        let dir = std::env::temp_dir().join(format!("chip8-config-{}", std::process::id()));
+        let path = dir.join("chip8").join("config");
+        let mut config = Config::load(path.clone()).unwrap();
+        assert!(config.is_new());
+        assert_eq!(DEFAULT_VOLUME, config.volume);
+        config.volume = 20;
+        config.audio_device = Some(String::from("USB Audio"));
+        config.save().unwrap();
+        let loaded = Config::load(path).unwrap();
+        assert!(!loaded.is_new());
+        assert_eq!(config, loaded);
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
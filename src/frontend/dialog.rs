@@ -0,0 +1,192 @@
+// File dialog to pick a ROM, for when the emulator isn't started from a
+// terminal: shown when no ROM is given, and on Ctrl+O. It opens in the ROM
+// folder of the config, which is picked in a folder dialog on the first run.
+//
+// The dialog is the system's own, run as a command: an AppleScript `choose
+// file` on macOS, the Windows Forms one through PowerShell on Windows, and
+// zenity or kdialog elsewhere. The chosen path is read from its output;
+// cancelling prints nothing. The folder to open in goes to the scripts in
+// an environment variable, never in their text, whatever it's called.
+
+use std::io;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+const PROMPT: &str = "Open a CHIP-8 ROM";
+const FOLDER_PROMPT: &str = "Choose the folder of your CHIP-8 ROMs";
+// the folder the AppleScript and PowerShell dialogs open in
+#[cfg(any(target_os = "macos", windows))]
+const DIR_VARIABLE: &str = "CHIP8_ROM_DIR";
+
+#[cfg(target_os = "macos")]
+fn commands(dir: Option<&Path>) -> Vec<Command> {
+    let mut c = Command::new("osascript");
+    let location = match dir {
+        Some(dir) => {
+            c.env(DIR_VARIABLE, dir);
+            format!(
+                " default location POSIX file (system attribute \"{}\")",
+                DIR_VARIABLE
+            )
+        }
+        None => String::new(),
+    };
+    c.arg("-e").arg(format!(
+        "POSIX path of (choose file with prompt \"{}\"{})",
+        PROMPT, location
+    ));
+    vec![c]
+}
+
+#[cfg(target_os = "macos")]
+fn folder_commands() -> Vec<Command> {
+    let mut c = Command::new("osascript");
+    c.arg("-e").arg(format!(
+        "POSIX path of (choose folder with prompt \"{}\")",
+        FOLDER_PROMPT
+    ));
+    vec![c]
+}
+
+// the path is printed in UTF-8, which any Windows path is
+#[cfg(windows)]
+const UTF8_OUTPUT: &str =
+    "[Console]::OutputEncoding = New-Object System.Text.UTF8Encoding $false; ";
+
+#[cfg(windows)]
+fn commands(dir: Option<&Path>) -> Vec<Command> {
+    let mut c = Command::new("powershell");
+    let location = match dir {
+        Some(dir) => {
+            c.env(DIR_VARIABLE, dir);
+            format!("$d.InitialDirectory = $env:{}; ", DIR_VARIABLE)
+        }
+        None => String::new(),
+    };
+    c.args(["-NoProfile", "-Command"]).arg(format!(
+        "{}Add-Type -AssemblyName System.Windows.Forms; \
+         $d = New-Object System.Windows.Forms.OpenFileDialog; \
+         $d.Title = '{}'; {}\
+         $d.Filter = 'CHIP-8 ROMs (*.ch8;*.rom)|*.ch8;*.rom|All files (*.*)|*.*'; \
+         if ($d.ShowDialog() -eq 'OK') {{ $d.FileName }}",
+        UTF8_OUTPUT, PROMPT, location
+    ));
+    vec![c]
+}
+
+#[cfg(windows)]
+fn folder_commands() -> Vec<Command> {
+    let mut c = Command::new("powershell");
+    c.args(["-NoProfile", "-Command"]).arg(format!(
+        "{}Add-Type -AssemblyName System.Windows.Forms; \
+         $d = New-Object System.Windows.Forms.FolderBrowserDialog; \
+         $d.Description = '{}'; \
+         if ($d.ShowDialog() -eq 'OK') {{ $d.SelectedPath }}",
+        UTF8_OUTPUT, FOLDER_PROMPT
+    ));
+    vec![c]
+}
+
+#[cfg(not(any(target_os = "macos", windows)))]
+fn commands(dir: Option<&Path>) -> Vec<Command> {
+    // zenity opens in the folder of the file given, with a trailing slash
+    // for the folder itself
+    let dir = dir.unwrap_or_else(|| Path::new("."));
+    let mut zenity = Command::new("zenity");
+    zenity
+        .arg("--file-selection")
+        .arg(format!("--title={}", PROMPT))
+        .arg(format!("--filename={}/", dir.display()))
+        .arg("--file-filter=CHIP-8 ROMs | *.ch8 *.rom")
+        .arg("--file-filter=All files | *");
+    let mut kdialog = Command::new("kdialog");
+    kdialog
+        .args(["--title", PROMPT, "--getopenfilename"])
+        .arg(dir)
+        .arg("*.ch8 *.rom");
+    vec![zenity, kdialog]
+}
+
+#[cfg(not(any(target_os = "macos", windows)))]
+fn folder_commands() -> Vec<Command> {
+    let mut zenity = Command::new("zenity");
+    zenity
+        .args(["--file-selection", "--directory"])
+        .arg(format!("--title={}", FOLDER_PROMPT));
+    let mut kdialog = Command::new("kdialog");
+    kdialog.args(["--title", FOLDER_PROMPT, "--getexistingdirectory", "."]);
+    vec![zenity, kdialog]
+}
+
+// the path printed by a dialog, none when it was cancelled. On Unix the
+// bytes are the path's, whatever their encoding
+fn chosen(stdout: &[u8]) -> Option<PathBuf> {
+    let end = stdout
+        .iter()
+        .rposition(|b| !matches!(b, b'\r' | b'\n'))
+        .map_or(0, |i| i + 1);
+    let path = &stdout[..end];
+    if path.is_empty() {
+        return None;
+    }
+    #[cfg(unix)]
+    {
+        use std::ffi::OsStr;
+        use std::os::unix::ffi::OsStrExt;
+        Some(PathBuf::from(OsStr::from_bytes(path)))
+    }
+    #[cfg(not(unix))]
+    Some(PathBuf::from(String::from_utf8_lossy(path).into_owned()))
+}
+
+// the ROM picked by the user, starting in `dir`, none when the dialog was
+// cancelled. Fails when none of the dialog commands can be run
+pub fn pick_rom(dir: Option<&Path>) -> io::Result<Option<PathBuf>> {
+    run(commands(dir))
+}
+
+// the folder picked by the user, none when the dialog was cancelled
+pub fn pick_folder() -> io::Result<Option<PathBuf>> {
+    run(folder_commands())
+}
+
+// the path printed by the first dialog command that runs
+fn run(commands: Vec<Command>) -> io::Result<Option<PathBuf>> {
+    let mut error = io::Error::new(io::ErrorKind::NotFound, "no file dialog available");
+    for mut command in commands {
+        match command.output() {
+            Ok(output) => return Ok(chosen(&output.stdout)),
+            // try the next one
+            Err(e) => error = e,
+        }
+    }
+    Err(error)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn dialog_chosen() {
+        assert_eq!(
+            Some(PathBuf::from("/roms/pong.ch8")),
+            chosen(b"/roms/pong.ch8\n")
+        );
+        assert_eq!(
+            Some(PathBuf::from(r"C:\roms\pong.ch8")),
+            chosen(b"C:\\roms\\pong.ch8\r\n")
+        );
+        assert_eq!(Some(PathBuf::from("/roms/a b")), chosen(b"/roms/a b\n"));
+        assert_eq!(None, chosen(b""));
+        assert_eq!(None, chosen(b"\n"));
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn dialog_chosen_not_unicode() {
+        use std::os::unix::ffi::OsStrExt;
+        let path = chosen(b"/roms/caf\xE9.ch8\n").unwrap();
+        assert_eq!(b"/roms/caf\xE9.ch8", path.as_os_str().as_bytes());
+    }
+}
@@ -0,0 +1,155 @@
+// Gamepad button chords bound to emulator actions, so a couch or cabinet
+// setup never needs a keyboard. They're set in the config, one chord per
+// action, buttons by their SDL game controller names joined with `+`:
+//
+//     hotkey-reset = back+a
+//     hotkey-save-state = back+rightshoulder
+//     hotkey-quit =
+//
+// An empty chord unbinds the action. An action fires when the last button
+// of its chord goes down while the others are held.
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Action {
+    Reset,
+    SaveState,
+    LoadState,
+    Quit,
+}
+
+// action, config name and default chord
+pub const ACTIONS: [(Action, &str, &str); 4] = [
+    (Action::Reset, "reset", "back+a"),
+    (Action::SaveState, "save-state", "back+rightshoulder"),
+    (Action::LoadState, "load-state", "back+leftshoulder"),
+    (Action::Quit, "quit", "back+start"),
+];
+
+pub const BUTTONS: [&str; 15] = [
+    "a",
+    "b",
+    "x",
+    "y",
+    "back",
+    "guide",
+    "start",
+    "leftstick",
+    "rightstick",
+    "leftshoulder",
+    "rightshoulder",
+    "dpup",
+    "dpdown",
+    "dpleft",
+    "dpright",
+];
+
+pub type Chord = Vec<&'static str>;
+
+pub fn parse_chord(text: &str) -> Result<Chord, String> {
+    let text = text.trim();
+    if text.is_empty() {
+        return Ok(Vec::new());
+    }
+    let mut chord = Vec::new();
+    for name in text.split('+').map(str::trim) {
+        let button = BUTTONS
+            .iter()
+            .find(|b| b.eq_ignore_ascii_case(name))
+            .ok_or_else(|| format!("unknown button `{}`", name))?;
+        if !chord.contains(button) {
+            chord.push(*button);
+        }
+    }
+    Ok(chord)
+}
+
+pub fn action_name(action: Action) -> &'static str {
+    ACTIONS.iter().find(|a| a.0 == action).map_or("", |a| a.1)
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Hotkeys {
+    pub bindings: Vec<(Action, Chord)>,
+    held: Vec<&'static str>,
+}
+
+impl Default for Hotkeys {
+    fn default() -> Hotkeys {
+        Hotkeys {
+            bindings: ACTIONS
+                .iter()
+                .map(|(action, _, chord)| (*action, parse_chord(chord).unwrap()))
+                .collect(),
+            held: Vec::new(),
+        }
+    }
+}
+
+impl Hotkeys {
+    pub fn bind(&mut self, action: Action, chord: Chord) {
+        for binding in self.bindings.iter_mut().filter(|b| b.0 == action) {
+            binding.1 = chord.clone();
+        }
+    }
+
+    // e.g. `reset back+a, quit back+start`
+    pub fn summary(&self) -> String {
+        self.bindings
+            .iter()
+            .filter(|(_, chord)| !chord.is_empty())
+            .map(|(action, chord)| format!("{} {}", action_name(*action), chord.join("+")))
+            .collect::<Vec<_>>()
+            .join(", ")
+    }
+
+    // the action completed by pressing this button, if any
+    pub fn press(&mut self, button: &'static str) -> Option<Action> {
+        if !self.held.contains(&button) {
+            self.held.push(button);
+        }
+        let held = &self.held;
+        self.bindings
+            .iter()
+            .find(|(_, chord)| chord.contains(&button) && chord.iter().all(|b| held.contains(b)))
+            .map(|b| b.0)
+    }
+
+    pub fn release(&mut self, button: &'static str) {
+        self.held.retain(|b| *b != button);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn hotkeys_parse_chord() {
+        assert_eq!(Ok(vec!["back", "start"]), parse_chord("back+start"));
+        assert_eq!(Ok(vec!["back", "a"]), parse_chord(" Back + A + back"));
+        assert_eq!(Ok(vec![]), parse_chord(""));
+        assert!(parse_chord("back+select").is_err());
+    }
+
+    #[test]
+    fn hotkeys_chords() {
+        let mut hotkeys = Hotkeys::default();
+        // start alone is a game button
+        assert_eq!(None, hotkeys.press("start"));
+        hotkeys.release("start");
+        assert_eq!(None, hotkeys.press("back"));
+        assert_eq!(Some(Action::Quit), hotkeys.press("start"));
+        hotkeys.release("start");
+        assert_eq!(Some(Action::Reset), hotkeys.press("a"));
+        hotkeys.release("back");
+        assert_eq!(None, hotkeys.press("a"));
+
+        hotkeys.bind(Action::Reset, Vec::new());
+        assert_eq!(
+            "save-state back+rightshoulder, load-state back+leftshoulder, quit back+start",
+            hotkeys.summary()
+        );
+        hotkeys.press("back");
+        assert_eq!(None, hotkeys.press("a"));
+    }
+}
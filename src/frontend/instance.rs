@@ -0,0 +1,244 @@
+// Single instance: the first emulator listens on a control socket, and the
+// next ones started with a ROM hand it over and exit instead of opening a
+// second window. The socket is `$XDG_RUNTIME_DIR/chip8.sock`, or
+// `chip8-$USER/chip8.sock` in the temporary directory, a directory only
+// its user can enter (refused when it's anyone else's); each connection
+// sends one command
+//
+//     open /home/me/roms/pong.ch8
+//
+// and gets `ok` back once it's queued. The connections are read on threads
+// of their own, the window only picks up the ROMs queued. Unix only:
+// elsewhere every run opens its own window.
+
+use std::fs::{self, DirBuilder};
+use std::io::{self, BufRead, BufReader, Write};
+use std::os::unix::fs::{DirBuilderExt, MetadataExt, PermissionsExt};
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{mpsc, Arc};
+use std::thread;
+use std::time::Duration;
+
+// how long a connection may take to send its command
+const TIMEOUT: Duration = Duration::from_millis(500);
+
+pub fn socket_path() -> io::Result<PathBuf> {
+    match std::env::var_os("XDG_RUNTIME_DIR") {
+        Some(dir) if !dir.is_empty() => Ok(Path::new(&dir).join("chip8.sock")),
+        _ => {
+            let user = std::env::var("USER").unwrap_or_else(|_| String::from("user"));
+            let dir = std::env::temp_dir().join(format!("chip8-{}", user));
+            private_dir(&dir)?;
+            Ok(dir.join("chip8.sock"))
+        }
+    }
+}
+
+// `dir` created for this user only, or checked to be when it's there: the
+// temporary directory is everyone's, another user could have made it first
+fn private_dir(dir: &Path) -> io::Result<()> {
+    match DirBuilder::new().mode(0o700).create(dir) {
+        Ok(()) => return Ok(()),
+        Err(e) if e.kind() == io::ErrorKind::AlreadyExists => {}
+        Err(e) => return Err(e),
+    }
+    let metadata = fs::symlink_metadata(dir)?;
+    // the user's own, as the processes it starts see it
+    let uid = unsafe { libc::geteuid() };
+    if !metadata.is_dir() || metadata.uid() != uid || metadata.permissions().mode() & 0o077 != 0 {
+        return Err(io::Error::new(
+            io::ErrorKind::PermissionDenied,
+            format!("{} isn't a directory of this user's only", dir.display()),
+        ));
+    }
+    Ok(())
+}
+
+// give the ROM to the instance listening on `socket`, false when there's
+// none
+pub fn hand_over(socket: &Path, rom: &str) -> io::Result<bool> {
+    let mut stream = match UnixStream::connect(socket) {
+        Ok(stream) => stream,
+        // no socket, or one left behind by an instance that crashed
+        Err(e)
+            if e.kind() == io::ErrorKind::NotFound
+                || e.kind() == io::ErrorKind::ConnectionRefused =>
+        {
+            return Ok(false)
+        }
+        Err(e) => return Err(e),
+    };
+    // the other instance runs from another directory
+    let rom = std::fs::canonicalize(rom)?;
+    stream.set_read_timeout(Some(TIMEOUT))?;
+    writeln!(stream, "open {}", rom.display())?;
+    let mut reply = String::new();
+    BufReader::new(stream).read_line(&mut reply)?;
+    match reply.trim_end() {
+        "ok" => Ok(true),
+        e => Err(io::Error::other(format!(
+            "the running instance says `{}`",
+            e
+        ))),
+    }
+}
+
+pub struct Control {
+    roms: mpsc::Receiver<String>,
+    path: PathBuf,
+    // tells the accepting thread to stop, woken by a connection
+    closing: Arc<AtomicBool>,
+}
+
+impl Control {
+    // listen on `socket`, replacing a stale one. Fails when another
+    // instance listens on it
+    pub fn listen(socket: &Path) -> io::Result<Control> {
+        if socket.exists() {
+            if UnixStream::connect(socket).is_ok() {
+                return Err(io::Error::new(
+                    io::ErrorKind::AddrInUse,
+                    "another instance is running",
+                ));
+            }
+            std::fs::remove_file(socket)?;
+        }
+        let listener = UnixListener::bind(socket)?;
+        let (sender, roms) = mpsc::channel();
+        let closing = Arc::new(AtomicBool::new(false));
+        let stop = closing.clone();
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                if stop.load(Ordering::SeqCst) {
+                    break;
+                }
+                let stream = match stream {
+                    Ok(stream) => stream,
+                    Err(e) => {
+                        println!("control socket: {}", e);
+                        continue;
+                    }
+                };
+                // a connection saying nothing holds its own thread only
+                let sender = sender.clone();
+                thread::spawn(move || match read_command(stream) {
+                    Ok(Some(rom)) => {
+                        let _ = sender.send(rom);
+                    }
+                    Ok(None) => {}
+                    Err(e) => println!("control socket: {}", e),
+                });
+            }
+        });
+        Ok(Control {
+            roms,
+            path: socket.to_path_buf(),
+            closing,
+        })
+    }
+
+    // the ROMs handed over since the last call
+    pub fn poll(&self) -> Vec<String> {
+        self.roms.try_iter().collect()
+    }
+}
+
+impl Drop for Control {
+    fn drop(&mut self) {
+        self.closing.store(true, Ordering::SeqCst);
+        let _ = UnixStream::connect(&self.path);
+        let _ = std::fs::remove_file(&self.path);
+    }
+}
+
+// the ROM sent by a connection, none when it closed without a command
+fn read_command(stream: UnixStream) -> io::Result<Option<String>> {
+    stream.set_read_timeout(Some(TIMEOUT))?;
+    let mut line = String::new();
+    if BufReader::new(&stream).read_line(&mut line)? == 0 {
+        return Ok(None);
+    }
+    let reply = match line.trim_end().strip_prefix("open ") {
+        Some(rom) if !rom.is_empty() => Ok(rom.to_string()),
+        _ => Err(format!("unknown command `{}`", line.trim_end())),
+    };
+    let mut stream = &stream;
+    match reply {
+        Ok(rom) => {
+            writeln!(stream, "ok")?;
+            Ok(Some(rom))
+        }
+        Err(e) => {
+            writeln!(stream, "{}", e)?;
+            Err(io::Error::new(io::ErrorKind::InvalidData, e))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Instant;
+
+    #[test]
+    #[cfg_attr(miri, ignore)]
+    fn instance_hand_over() {
+        let dir = std::env::temp_dir().join(format!("chip8-instance-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let socket = dir.join("chip8.sock");
+        let rom = dir.join("pong.ch8");
+        std::fs::write(&rom, [0x12, 0x00]).unwrap();
+
+        // nobody listening
+        assert!(!hand_over(&socket, "pong.ch8").unwrap());
+
+        let control = Control::listen(&socket).unwrap();
+        assert!(Control::listen(&socket).is_err());
+        // a connection saying nothing doesn't hold up the others
+        let _silent = UnixStream::connect(&socket).unwrap();
+        let client = {
+            let socket = socket.clone();
+            let rom = rom.to_string_lossy().into_owned();
+            std::thread::spawn(move || hand_over(&socket, &rom))
+        };
+        let start = Instant::now();
+        let mut roms = Vec::new();
+        while roms.is_empty() && start.elapsed() < Duration::from_secs(5) {
+            roms = control.poll();
+            std::thread::sleep(Duration::from_millis(10));
+        }
+        assert!(client.join().unwrap().unwrap());
+        assert_eq!(
+            vec![std::fs::canonicalize(&rom).unwrap()],
+            roms.iter().map(PathBuf::from).collect::<Vec<_>>()
+        );
+
+        // the socket goes with the instance
+        drop(control);
+        assert!(!socket.exists());
+        assert!(!hand_over(&socket, "pong.ch8").unwrap());
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn instance_private_dir() {
+        let dir = std::env::temp_dir().join(format!("chip8-private-{}", std::process::id()));
+        private_dir(&dir).unwrap();
+        assert_eq!(
+            0o700,
+            fs::metadata(&dir).unwrap().permissions().mode() & 0o777
+        );
+        // there already, and still private
+        private_dir(&dir).unwrap();
+        // opened to others
+        fs::set_permissions(&dir, fs::Permissions::from_mode(0o777)).unwrap();
+        assert!(private_dir(&dir).is_err());
+        fs::remove_dir(&dir).unwrap();
+        // not a directory
+        fs::write(&dir, "").unwrap();
+        assert!(private_dir(&dir).is_err());
+        fs::remove_file(&dir).unwrap();
+    }
+}
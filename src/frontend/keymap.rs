@@ -0,0 +1,356 @@
+// Keys and gamepad buttons pressing the CHIP-8 keypad keys. By default the
+// keypad is the left of the keyboard, the COSMAC layout on the right
+// becoming
+//
+//     1 2 3 C        1 2 3 4
+//     4 5 6 D   ->   Q W E R
+//     7 8 9 E        A S D F
+//     A 0 B F        Z X C V
+//
+// and the gamepad plays the usual Octo controls: the d-pad on 5/7/8/9
+// (W/A/S/D) and A/B on 6/4 (E/Q). Both are changed in the config, keypad
+// key by keypad key, with SDL key names and game controller button names
+// (see hotkeys.rs), comma separated:
+//
+//     key-5 = w, up
+//     pad-6 = a, x
+//
+// An empty list unbinds the key. On other keyboards, `layout = azerty`
+// (or `qwertz`, `dvorak`) puts the keypad back on the same physical keys
+// before those bindings apply. `layout = scancodes` goes by where the keys
+// are rather than what's printed on them, so the same block works on any
+// layout, keypad-labelled keyboards included: keys are then SDL scancode
+// names, the keys of a US keyboard in the same place (`key-5 = w` is the
+// key right of Tab, whatever it says).
+//
+// For two players on one keyboard, a second block on the right of the
+// keyboard presses the same keypad keys:
+//
+//     1 2 3 C        7 8 9 0
+//     4 5 6 D   ->   U I O P
+//     7 8 9 E        J K L ;
+//     A 0 B F        M , . /
+//
+// rebound with `key2-5 = i`. It's on for the ROMs listed in the config
+// (see config.rs) or with `--two-player`, the first block winning a key
+// both have. M, the mute key with one player, is then the second player's A.
+
+use super::hotkeys;
+
+// the COSMAC VIP keypad, row by row
+pub const COSMAC: [u8; 16] = [
+    0x1, 0x2, 0x3, 0xC, 0x4, 0x5, 0x6, 0xD, 0x7, 0x8, 0x9, 0xE, 0xA, 0x0, 0xB, 0xF,
+];
+// the layout matching keys by position
+pub const SCANCODES: &str = "scancodes";
+// per keyboard layout, the keys in its place, row by row
+pub const LAYOUTS: [(&str, [&str; 16]); 5] = [
+    (
+        "qwerty",
+        [
+            "1", "2", "3", "4", "q", "w", "e", "r", "a", "s", "d", "f", "z", "x", "c", "v",
+        ],
+    ),
+    (
+        "azerty",
+        [
+            "1", "2", "3", "4", "a", "z", "e", "r", "q", "s", "d", "f", "w", "x", "c", "v",
+        ],
+    ),
+    (
+        "qwertz",
+        [
+            "1", "2", "3", "4", "q", "w", "e", "r", "a", "s", "d", "f", "y", "x", "c", "v",
+        ],
+    ),
+    (
+        "dvorak",
+        [
+            "1", "2", "3", "4", "'", ",", ".", "p", "a", "o", "e", "u", ";", "q", "j", "k",
+        ],
+    ),
+    (
+        SCANCODES,
+        [
+            "1", "2", "3", "4", "q", "w", "e", "r", "a", "s", "d", "f", "z", "x", "c", "v",
+        ],
+    ),
+];
+
+// the second player's keys, row by row
+pub const RIGHT_HAND: [&str; 16] = [
+    "7", "8", "9", "0", "u", "i", "o", "p", "j", "k", "l", ";", "m", ",", ".", "/",
+];
+
+// the keys the window takes before the keypad does (see frontend/mod.rs),
+// lowercase SDL names, and the ones it leaves to the keypad with two
+// players: mute on M
+pub const WINDOW_KEYS: [&str; 13] = [
+    "escape",
+    "backspace",
+    "f3",
+    "f5",
+    "f6",
+    "f8",
+    "f9",
+    "f10",
+    "f12",
+    "-",
+    "=",
+    "keypad -",
+    "keypad +",
+];
+pub const ONE_PLAYER_KEYS: [&str; 1] = ["m"];
+
+const PAD_LAYOUT: [(&str, u8); 6] = [
+    ("dpup", 0x5),
+    ("dpleft", 0x7),
+    ("dpdown", 0x8),
+    ("dpright", 0x9),
+    ("a", 0x6),
+    ("b", 0x4),
+];
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Keymap {
+    // the keyboard layout the default keys are for
+    pub layout: &'static str,
+    // per keypad key, the keyboard keys (lowercase SDL names) and gamepad
+    // buttons pressing it
+    pub keys: [Vec<String>; 16],
+    pub pad: [Vec<&'static str>; 16],
+    // the second player's keys, and whether they play
+    pub keys2: [Vec<String>; 16],
+    pub two_player: bool,
+}
+
+impl Default for Keymap {
+    fn default() -> Keymap {
+        Keymap::for_layout(LAYOUTS[0].0).unwrap()
+    }
+}
+
+// the kind (`key`, `key2` or `pad`) and keypad key of a setting, e.g.
+// `key-a`
+pub fn parse_setting(name: &str) -> Option<(&str, u8)> {
+    let (kind, key) = name.split_once('-')?;
+    if !matches!(kind, "key" | "key2" | "pad") || key.len() != 1 {
+        return None;
+    }
+    u8::from_str_radix(key, 16).ok().map(|key| (kind, key))
+}
+
+fn list(text: &str) -> impl Iterator<Item = &str> {
+    text.split(',').map(str::trim).filter(|s| !s.is_empty())
+}
+
+impl Keymap {
+    // the default bindings on a keyboard layout, none when it's unknown
+    pub fn for_layout(name: &str) -> Option<Keymap> {
+        let (layout, keyboard) = LAYOUTS.iter().find(|(n, _)| n.eq_ignore_ascii_case(name))?;
+        let mut keymap = Keymap {
+            layout,
+            keys: Default::default(),
+            pad: Default::default(),
+            keys2: Default::default(),
+            two_player: false,
+        };
+        for (name, key) in keyboard.iter().zip(COSMAC) {
+            keymap.keys[usize::from(key)].push(name.to_string());
+        }
+        for (name, key) in RIGHT_HAND.iter().zip(COSMAC) {
+            keymap.keys2[usize::from(key)].push(name.to_string());
+        }
+        for (button, key) in PAD_LAYOUT {
+            keymap.pad[usize::from(key)].push(button);
+        }
+        Some(keymap)
+    }
+
+    // the keyboard keys of another layout, the gamepad buttons are kept
+    pub fn set_layout(&mut self, name: &str) -> Result<(), String> {
+        let other = Keymap::for_layout(name).ok_or(format!("unknown layout `{}`", name))?;
+        self.layout = other.layout;
+        self.keys = other.keys;
+        Ok(())
+    }
+
+    pub fn bind_keys(&mut self, key: u8, names: &str) {
+        self.keys[usize::from(key)] = list(names).map(str::to_lowercase).collect();
+    }
+
+    pub fn bind_keys2(&mut self, key: u8, names: &str) {
+        self.keys2[usize::from(key)] = list(names).map(str::to_lowercase).collect();
+    }
+
+    pub fn bind_pad(&mut self, key: u8, buttons: &str) -> Result<(), String> {
+        let mut bound = Vec::new();
+        for name in list(buttons) {
+            let chord = hotkeys::parse_chord(name)?;
+            if chord.len() != 1 {
+                return Err(format!("`{}` isn't a single button", name));
+            }
+            bound.push(chord[0]);
+        }
+        self.pad[usize::from(key)] = bound;
+        Ok(())
+    }
+
+    // keys are scancodes rather than keycodes
+    pub fn by_position(&self) -> bool {
+        self.layout == SCANCODES
+    }
+
+    // whether the window takes a keyboard key, by its SDL name, before the
+    // keypad does
+    pub fn window_key(&self, name: &str) -> bool {
+        let listed = |keys: &[&str]| keys.iter().any(|k| k.eq_ignore_ascii_case(name));
+        listed(&WINDOW_KEYS) || (!self.two_player && listed(&ONE_PLAYER_KEYS))
+    }
+
+    // the keypad key of a keyboard key, by its SDL name
+    pub fn key(&self, name: &str) -> Option<u8> {
+        let find = |keys: &[Vec<String>; 16]| {
+            (0..16u8).find(|k| {
+                keys[usize::from(*k)]
+                    .iter()
+                    .any(|n| n.eq_ignore_ascii_case(name))
+            })
+        };
+        find(&self.keys).or_else(|| find(&self.keys2).filter(|_| self.two_player))
+    }
+
+    // the keypad key of a gamepad button
+    pub fn pad_key(&self, button: &str) -> Option<u8> {
+        (0..16u8).find(|k| self.pad[usize::from(*k)].contains(&button))
+    }
+
+    // config lines of the layout and of the bindings that differ from its
+    // default ones
+    pub fn to_text(&self) -> String {
+        let default = Keymap::for_layout(self.layout).unwrap();
+        let mut text = String::new();
+        if self.layout != Keymap::default().layout {
+            text.push_str(&format!("layout = {}\n", self.layout));
+        }
+        for k in 0..16 {
+            if self.keys[k] != default.keys[k] {
+                text.push_str(&format!("key-{:x} = {}\n", k, self.keys[k].join(", ")));
+            }
+            if self.keys2[k] != default.keys2[k] {
+                text.push_str(&format!("key2-{:x} = {}\n", k, self.keys2[k].join(", ")));
+            }
+            if self.pad[k] != default.pad[k] {
+                text.push_str(&format!("pad-{:x} = {}\n", k, self.pad[k].join(", ")));
+            }
+        }
+        text
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn keymap_window_keys() {
+        // the second player's keys all reach the keypad
+        for key in RIGHT_HAND.iter() {
+            assert!(!WINDOW_KEYS.contains(key), "{}", key);
+        }
+        let mut keymap = Keymap::default();
+        assert!(keymap.window_key("M") && keymap.window_key("Keypad +"));
+        keymap.two_player = true;
+        assert!(!keymap.window_key("M") && keymap.window_key("Escape"));
+        for key in WINDOW_KEYS.iter() {
+            assert_eq!(None, keymap.key(key), "{}", key);
+        }
+        assert_eq!(Some(0xA), keymap.key(ONE_PLAYER_KEYS[0]));
+    }
+
+    #[test]
+    fn keymap_default() {
+        let keymap = Keymap::default();
+        assert_eq!(Some(0x1), keymap.key("1"));
+        assert_eq!(Some(0xC), keymap.key("4"));
+        assert_eq!(Some(0x0), keymap.key("X"));
+        assert_eq!(Some(0xF), keymap.key("V"));
+        assert_eq!(None, keymap.key("0"));
+        assert_eq!(Some(0x5), keymap.pad_key("dpup"));
+        assert_eq!(Some(0x6), keymap.pad_key("a"));
+        assert_eq!(None, keymap.pad_key("start"));
+        assert_eq!("", keymap.to_text());
+    }
+
+    #[test]
+    fn keymap_bind() {
+        assert_eq!(Some(("key", 0xA)), parse_setting("key-a"));
+        assert_eq!(Some(("pad", 0x5)), parse_setting("pad-5"));
+        assert_eq!(None, parse_setting("key-10"));
+        assert_eq!(None, parse_setting("pedal-1"));
+
+        let mut keymap = Keymap::default();
+        keymap.bind_keys(0x5, "W, Up");
+        keymap.bind_keys(0xF, "");
+        assert_eq!(Some(0x5), keymap.key("Up"));
+        assert_eq!(None, keymap.key("V"));
+        keymap.bind_pad(0x6, "a, x").unwrap();
+        assert_eq!(Some(0x6), keymap.pad_key("x"));
+        assert!(keymap.bind_pad(0x6, "select").is_err());
+        assert!(keymap.bind_pad(0x6, "back+a").is_err());
+        assert_eq!("key-5 = w, up\npad-6 = a, x\nkey-f = \n", keymap.to_text());
+    }
+
+    #[test]
+    fn keymap_two_players() {
+        assert_eq!(Some(("key2", 0xC)), parse_setting("key2-c"));
+        let mut keymap = Keymap::default();
+        // the second block only plays when asked
+        assert_eq!(None, keymap.key("0"));
+        keymap.two_player = true;
+        assert_eq!(Some(0xC), keymap.key("0"));
+        assert_eq!(Some(0xC), keymap.key("4"));
+        assert_eq!(Some(0xF), keymap.key("/"));
+        keymap.bind_keys2(0xD, "Down");
+        assert_eq!(Some(0xD), keymap.key("down"));
+        assert_eq!(None, keymap.key("p"));
+        assert_eq!("key2-d = down\n", keymap.to_text());
+        // the first player's keys win
+        keymap.bind_keys2(0x5, "w");
+        assert_eq!(Some(0x5), keymap.key("w"));
+        keymap.bind_keys2(0x6, "q");
+        assert_eq!(Some(0x4), keymap.key("q"));
+    }
+
+    #[test]
+    fn keymap_layouts() {
+        let mut keymap = Keymap::for_layout("AZERTY").unwrap();
+        assert_eq!("azerty", keymap.layout);
+        assert_eq!(Some(0x4), keymap.key("a"));
+        assert_eq!(Some(0x5), keymap.key("z"));
+        assert_eq!(Some(0xA), keymap.key("w"));
+        assert_eq!(Some(0x6), keymap.pad_key("a"));
+        assert_eq!("layout = azerty\n", keymap.to_text());
+
+        keymap.bind_pad(0x6, "x").unwrap();
+        keymap.set_layout("dvorak").unwrap();
+        assert_eq!(Some(0x5), keymap.key(","));
+        assert_eq!(Some(0x6), keymap.pad_key("x"));
+        assert!(keymap.set_layout("colemak").is_err());
+        assert!(!keymap.by_position());
+        keymap.set_layout("scancodes").unwrap();
+        assert!(keymap.by_position());
+        assert_eq!(Some(0x5), keymap.key("W"));
+        assert!(keymap.to_text().starts_with("layout = scancodes\n"));
+        keymap.set_layout("dvorak").unwrap();
+        assert_eq!(Some(0x5), keymap.key(","));
+        // every layout has its own key for each keypad key
+        for (name, keys) in LAYOUTS {
+            let keymap = Keymap::for_layout(name).unwrap();
+            let mut pressed: Vec<u8> = keys.iter().filter_map(|k| keymap.key(k)).collect();
+            pressed.sort_unstable();
+            pressed.dedup();
+            assert_eq!(16, pressed.len(), "{}", name);
+        }
+    }
+}
@@ -0,0 +1,1714 @@
+// SDL frontend of the CHIP-8 emulator: the window, the keyboard, gamepads
+// and touch screens, and the speaker. Built with the `sdl` feature, as the
+// `chip8` binary on the desktop and as the library an SDL activity loads on
+// Android (see platform.rs).
+
+use sdl2::audio::{AudioCallback, AudioDevice, AudioSpecDesired};
+use sdl2::controller::{Button, GameController};
+use sdl2::event::{Event, WindowEvent};
+use sdl2::keyboard::{Keycode, Mod, Scancode};
+use sdl2::pixels::{Color, PixelFormatEnum};
+use sdl2::rect::Rect;
+use sdl2::render::{BlendMode, Texture, TextureCreator, WindowCanvas};
+use sdl2::surface::Surface;
+use sdl2::video::{FullscreenType, WindowContext};
+use std::convert::TryFrom;
+use std::fs;
+use std::io;
+use std::panic;
+use std::path::Path;
+use std::sync::mpsc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::agent::KeyMask;
+use crate::capture::{self, Capture};
+use crate::crash::CrashDump;
+use crate::framebuffer::FrameBuffer;
+use crate::leaderboard::{InitialsEntry, Leaderboard};
+use crate::plugin::Plugins;
+use crate::replay::{Recording, Replay};
+use crate::snapshot::{History, StateConfig};
+use crate::{
+    achievements, aliases, annotations, audio, clock, debugger, demo, discord, exit, gameover,
+    icon, info, leaderboard, marquee, scheduler, text, trace, utils, Chip8Error, Machine, Timer,
+    FONT, GFX_HEIGHT, GFX_WIDTH, TIMER_HZ,
+};
+use touch::TouchKeypad;
+use watchdog::Stage;
+
+mod cli;
+mod config;
+mod dialog;
+mod hotkeys;
+#[cfg(all(unix, not(target_os = "android")))]
+mod instance;
+mod keymap;
+mod platform;
+mod power;
+mod setup;
+mod smoothing;
+mod touch;
+mod watchdog;
+
+// global constant
+const WINDOW_TITLE: &str = "CHIP-8";
+// the name the taskbar and the dock show, and the window class the desktop
+// entry is matched with (StartupWMClass in packaging/linux/chip8.desktop)
+const APP_NAME: &str = "CHIP-8";
+const APP_CLASS: &str = "chip8";
+const TOAST_DURATION: Duration = Duration::from_secs(3);
+// frame rate of the frontend
+const FRAME_PERIOD: Duration = Duration::from_nanos(1_000_000_000 / 120);
+// SDL's mouse id for the mouse events made from touches
+const TOUCH_MOUSE_ID: u32 = u32::MAX;
+// the touch keypad's finger id for the mouse
+const MOUSE_FINGER: i64 = -1;
+// longest sleep while idle, so the window title and panel keep up
+const IDLE_TIMEOUT: Duration = Duration::from_millis(500);
+// longest sleep while paused, so debugger commands are run promptly
+const CONSOLE_POLL: Duration = Duration::from_millis(50);
+// the handheld preset saves the state this often, a suspended device that
+// runs out of battery loses at most that much
+const AUTOSAVE_PERIOD: Duration = Duration::from_secs(30);
+// a snapshot per frame is kept to rewind, 10 seconds' worth
+const REWIND_FRAMES: usize = 10 * 120;
+// the annotations panel is a fifth of the window's width, right of the
+// screen
+const PANEL_SHARE: u32 = 5;
+
+// the texture the screen is converted to, then scaled to the window by
+// the renderer, and the RGBA bytes it's updated from. Renderers that can't
+// make one draw the pixels as rectangles instead
+struct Screen<'a> {
+    creator: &'a TextureCreator<WindowContext>,
+    texture: Option<(Texture<'a>, (u32, u32))>,
+    rgba: Vec<u8>,
+    rects: bool,
+    // RGBA colour of each plane combination
+    palette: [[u8; 4]; 4],
+    // cross-fading between screens, not when drawing rectangles
+    smoothing: Option<smoothing::Smoothing>,
+    // the toast shown, and its text drawn
+    toast: Option<(String, Texture<'a>)>,
+    // the annotated values shown in the panel, and their text drawn
+    panel: Option<(String, Texture<'a>)>,
+}
+
+impl Screen<'_> {
+    // whether it has to be drawn again to go on cross-fading
+    fn fading(&self, now: Instant) -> bool {
+        !self.rects && self.smoothing.as_ref().is_some_and(|s| s.fading(now))
+    }
+}
+
+// the screen, with the touch keypad under it when there's one, the
+// annotations panel right of it and the toast over it
+#[allow(clippy::too_many_arguments)]
+fn render(
+    canvas: &mut WindowCanvas,
+    screen: &mut Screen,
+    gfx: &FrameBuffer,
+    keypad: Option<&TouchKeypad>,
+    toast: Option<&str>,
+    panel: Option<&str>,
+    plugins: &Plugins,
+    now: Instant,
+) {
+    canvas.set_draw_color(Color::RGB(0, 0, 0));
+    canvas.clear();
+
+    // scale the screen to the window, whatever the resolution, keeping
+    // square pixels centered in a fullscreen window
+    let scale = usize::from(config::DEFAULT_SCALE);
+    let (w, full_height) = canvas.output_size().unwrap_or((
+        u32::try_from(GFX_WIDTH * scale).unwrap(),
+        u32::try_from(GFX_HEIGHT * scale).unwrap(),
+    ));
+    let h = match keypad {
+        Some(_) => (full_height as f32 * (1.0 - touch::KEYPAD_SHARE)) as u32,
+        None => full_height,
+    };
+    let full_width = w;
+    let w = match panel {
+        Some(_) => w - w / PANEL_SHARE,
+        None => w,
+    };
+    let (gw, gh) = (
+        u32::try_from(gfx.width()).unwrap(),
+        u32::try_from(gfx.height()).unwrap(),
+    );
+    let scale = (w / gw).min(h / gh).max(1);
+    let area = Rect::new(
+        i32::try_from(w.saturating_sub(gw * scale) / 2).unwrap(),
+        i32::try_from(h.saturating_sub(gh * scale) / 2).unwrap(),
+        gw * scale,
+        gh * scale,
+    );
+
+    if !screen.rects {
+        if let Err(e) = draw_texture(canvas, screen, gfx, area, plugins, now) {
+            println!("{}, drawing rectangles instead", e);
+            screen.rects = true;
+        }
+    }
+    if screen.rects {
+        draw_rects(canvas, gfx, &screen.palette, area, scale);
+    }
+    if let Some(text) = toast {
+        if let Err(e) = draw_toast(canvas, screen, text, area) {
+            println!("{}", e);
+        }
+    }
+    if let Some(text) = panel {
+        let area = Rect::new(i32::try_from(w).unwrap(), 0, full_width - w, h);
+        if let Err(e) = draw_panel(canvas, screen, text, area) {
+            println!("{}", e);
+        }
+    }
+
+    if let Some(keypad) = keypad {
+        let area = Rect::new(0, i32::try_from(h).unwrap(), full_width, full_height - h);
+        draw_keypad(canvas, keypad, area);
+    }
+    canvas.present();
+}
+
+fn draw_texture(
+    canvas: &mut WindowCanvas,
+    screen: &mut Screen,
+    gfx: &FrameBuffer,
+    area: Rect,
+    plugins: &Plugins,
+    now: Instant,
+) -> Result<(), String> {
+    let size = (
+        u32::try_from(gfx.width()).unwrap(),
+        u32::try_from(gfx.height()).unwrap(),
+    );
+    // a new texture when the resolution changes
+    let texture = match &mut screen.texture {
+        Some((texture, s)) if *s == size => texture,
+        texture => {
+            let created = screen
+                .creator
+                .create_texture_streaming(PixelFormatEnum::RGBA32, size.0, size.1)
+                .map_err(|e| format!("cannot create the screen texture: {}", e))?;
+            &mut texture.insert((created, size)).0
+        }
+    };
+    screen.rgba.resize(gfx.pixels().len() * 4, 0);
+    gfx.to_rgba(&screen.palette, &mut screen.rgba);
+    if let Some(smoothing) = &mut screen.smoothing {
+        smoothing.apply(&mut screen.rgba, now);
+    }
+    plugins.filter(&mut screen.rgba, gfx.width(), gfx.height());
+    texture
+        .update(None, &screen.rgba, gfx.width() * 4)
+        .map_err(|e| format!("cannot update the screen texture: {}", e))?;
+    canvas.copy(texture, None, area)
+}
+
+// the texture of some text, drawn again when the text changes
+fn text_texture<'a, 't>(
+    creator: &'t TextureCreator<WindowContext>,
+    drawn: &'a mut Option<(String, Texture<'t>)>,
+    text: &str,
+    background: [u8; 4],
+) -> Result<&'a mut Texture<'t>, String> {
+    let shown = match drawn.take() {
+        Some((shown, texture)) if shown == text => (shown, texture),
+        _ => {
+            let (rgba, w, h) = text::image(text, [255, 255, 255, 255], background);
+            let pitch = w * 4;
+            let (w, h) = (u32::try_from(w).unwrap(), u32::try_from(h).unwrap());
+            let mut texture = creator
+                .create_texture_static(PixelFormatEnum::RGBA32, w, h)
+                .map_err(|e| format!("cannot create a text texture: {}", e))?;
+            texture.set_blend_mode(BlendMode::Blend);
+            texture
+                .update(None, &rgba, pitch)
+                .map_err(|e| format!("cannot draw `{}`: {}", text, e))?;
+            (text.to_string(), texture)
+        }
+    };
+    Ok(&mut drawn.insert(shown).1)
+}
+
+// a line of text at the bottom left of the screen, in pixels of a 128
+// pixels high screen
+fn draw_toast(
+    canvas: &mut WindowCanvas,
+    screen: &mut Screen,
+    toast: &str,
+    area: Rect,
+) -> Result<(), String> {
+    let texture = text_texture(screen.creator, &mut screen.toast, toast, [0, 0, 0, 192])?;
+    let query = texture.query();
+    let (w, h) = (query.width, query.height);
+    let scale = (area.height() / 128).max(1);
+    let margin = i32::try_from(4 * scale).unwrap();
+    let target = Rect::new(
+        area.x() + margin,
+        area.bottom() - margin - i32::try_from(h * scale).unwrap(),
+        w * scale,
+        h * scale,
+    );
+    canvas.copy(texture, None, target)
+}
+
+// the annotated values at the top of the panel, as large as the toasts
+// unless that's too wide
+fn draw_panel(
+    canvas: &mut WindowCanvas,
+    screen: &mut Screen,
+    values: &str,
+    area: Rect,
+) -> Result<(), String> {
+    let texture = text_texture(screen.creator, &mut screen.panel, values, [0, 0, 0, 0])?;
+    let query = texture.query();
+    let (w, h) = (query.width, query.height);
+    let scale = (area.height() / 128).min(area.width() / (w + 4)).max(1);
+    let margin = i32::try_from(2 * scale).unwrap();
+    let target = Rect::new(area.x() + margin, area.y() + margin, w * scale, h * scale);
+    canvas.copy(texture, None, target)
+}
+
+// the lit pixels as rectangles, one per run of the same colour in a row,
+// filled in one call per colour
+fn draw_rects(
+    canvas: &mut WindowCanvas,
+    gfx: &FrameBuffer,
+    palette: &[[u8; 4]; 4],
+    area: Rect,
+    scale: u32,
+) {
+    let runs = gfx.runs();
+    for (value, [r, g, b, _]) in palette.iter().copied().enumerate().skip(1) {
+        let rects: Vec<Rect> = runs
+            .iter()
+            .filter(|run| usize::from(run.0) == value)
+            .map(|&(_, x, y, len)| {
+                Rect::new(
+                    area.x() + (x as u32 * scale) as i32,
+                    area.y() + (y as u32 * scale) as i32,
+                    len as u32 * scale,
+                    scale,
+                )
+            })
+            .collect();
+        canvas.set_draw_color(Color::RGB(r, g, b));
+        let _ = canvas.fill_rects(&rects);
+    }
+}
+
+// the second player's keys on for the program at `path`
+fn set_two_player(config: &mut config::Config, path: &str, forced: bool) {
+    config.keymap.two_player = forced || config.is_two_player(path);
+    if config.keymap.two_player {
+        println!("two players, the second on the right of the keyboard");
+    }
+}
+
+// the keypad key a keyboard key presses, by what it says or where it is
+fn keypad_key(keymap: &keymap::Keymap, keycode: Keycode, scancode: Option<Scancode>) -> Option<u8> {
+    if keymap.by_position() {
+        scancode.and_then(|s| keymap.key(s.name()))
+    } else {
+        keymap.key(&keycode.name())
+    }
+}
+
+// the 4x4 keys, labelled with their hex digit in the CHIP-8 font
+fn draw_keypad(canvas: &mut WindowCanvas, keypad: &TouchKeypad, area: Rect) {
+    let (cw, ch) = (area.width() / 4, area.height() / 4);
+    let dot = (ch / 10).max(1);
+    for (i, key) in keymap::COSMAC.iter().copied().enumerate() {
+        let cell = Rect::new(
+            area.x() + (i % 4) as i32 * cw as i32,
+            area.y() + (i / 4) as i32 * ch as i32,
+            cw,
+            ch,
+        );
+        canvas.set_draw_color(if keypad.held(key) {
+            Color::RGB(85, 85, 85)
+        } else {
+            Color::RGB(25, 25, 25)
+        });
+        let _ = canvas.fill_rect(cell);
+        canvas.set_draw_color(Color::RGB(170, 170, 170));
+        let _ = canvas.draw_rect(cell);
+
+        let x = cell.center().x() - 2 * dot as i32;
+        let y = cell.center().y() - (5 * dot / 2) as i32;
+        for (row, bits) in FONT[usize::from(key)].iter().enumerate() {
+            for col in 0..4 {
+                if bits & (0x80 >> col) != 0 {
+                    let _ = canvas.fill_rect(Rect::new(
+                        x + col * dot as i32,
+                        y + row as i32 * dot as i32,
+                        dot,
+                        dot,
+                    ));
+                }
+            }
+        }
+    }
+}
+
+// window coordinates from 0 to 1, as touches have them
+fn normalize(canvas: &WindowCanvas, x: i32, y: i32) -> (f32, f32) {
+    let (w, h) = canvas.window().size();
+    (x as f32 / w.max(1) as f32, y as f32 / h.max(1) as f32)
+}
+
+fn sdl_error(what: &str, e: impl std::fmt::Display) -> io::Error {
+    io::Error::other(format!("{}: {}", what, e))
+}
+
+// the window keeps its width, its height follows the screen's aspect ratio
+// (64x32 and 128x64 are the same window, 64x64 a square one), plus the
+// touch keypad and the annotations panel
+fn window_size(gfx: &FrameBuffer, touch: bool, panel: bool, scale: u8) -> (u32, u32) {
+    let mut width = GFX_WIDTH * usize::from(scale);
+    let mut height = width * gfx.height() / gfx.width();
+    if touch {
+        height = (height as f32 / (1.0 - touch::KEYPAD_SHARE)) as usize;
+    }
+    if panel {
+        let share = usize::try_from(PANEL_SHARE).unwrap();
+        width += width / (share - 1);
+    }
+    (
+        u32::try_from(width).unwrap(),
+        u32::try_from(height).unwrap(),
+    )
+}
+
+// window canvas, hardware accelerated if possible
+fn create_canvas(video: &sdl2::VideoSubsystem, size: (u32, u32)) -> io::Result<WindowCanvas> {
+    let window = || {
+        let mut window = video
+            .window(WINDOW_TITLE, size.0, size.1)
+            .position_centered()
+            .build()
+            .map_err(|e| sdl_error("cannot create the window", e))?;
+        // the window keeps a copy
+        let mut pixels = icon::ICON;
+        let pitch = u32::try_from(icon::SIZE * 4).unwrap();
+        let side = u32::try_from(icon::SIZE).unwrap();
+        match Surface::from_data(&mut pixels, side, side, pitch, PixelFormatEnum::RGBA32) {
+            Ok(surface) => window.set_icon(surface),
+            Err(e) => println!("no window icon: {}", e),
+        }
+        Ok::<_, io::Error>(window)
+    };
+
+    match window()?.into_canvas().accelerated().build() {
+        Ok(canvas) => Ok(canvas),
+        Err(e) => {
+            println!(
+                "no hardware acceleration ({}), using the software renderer",
+                e
+            );
+            window()?
+                .into_canvas()
+                .software()
+                .build()
+                .map_err(|e| sdl_error("cannot create the renderer", e))
+        }
+    }
+}
+
+// the machine's sound, played from the SDL audio thread
+struct Speaker {
+    voice: audio::Voice,
+    playing: bool,
+    gain: f32,
+}
+
+impl AudioCallback for Speaker {
+    type Channel = f32;
+
+    fn callback(&mut self, out: &mut [f32]) {
+        for sample in out.iter_mut() {
+            *sample = if self.playing {
+                f32::from(self.voice.next_sample()) / f32::from(audio::FULL_SCALE) * self.gain
+            } else {
+                0.0
+            };
+        }
+    }
+}
+
+impl Speaker {
+    fn update(&mut self, m: &Machine, playing: bool, gain: f32) {
+        if playing && !self.playing {
+            self.voice.restart();
+        }
+        self.playing = playing;
+        self.voice.pattern = m.audio_pattern();
+        self.voice.pitch = m.audio_pitch();
+        self.gain = gain;
+    }
+}
+
+// the playback device by name, or the default one
+fn open_speaker(
+    audio: &sdl2::AudioSubsystem,
+    device: Option<&str>,
+    tone: u32,
+) -> io::Result<AudioDevice<Speaker>> {
+    let spec = AudioSpecDesired {
+        freq: Some(i32::try_from(audio::SAMPLE_RATE).unwrap()),
+        channels: Some(1),
+        samples: Some(512),
+    };
+    let speaker = audio
+        .open_playback(device, &spec, |obtained| {
+            let mut voice = audio::Voice::new(
+                audio::Filter::Box,
+                u32::try_from(obtained.freq).unwrap_or(audio::SAMPLE_RATE),
+            );
+            voice.tone = tone;
+            Speaker {
+                voice,
+                playing: false,
+                gain: 0.0,
+            }
+        })
+        .map_err(|e| {
+            let devices: Vec<String> = (0..audio.num_audio_playback_devices().unwrap_or(0))
+                .filter_map(|i| audio.audio_playback_device_name(i).ok())
+                .map(|name| format!("`{}`", name))
+                .collect();
+            sdl_error(
+                &format!(
+                    "cannot open the audio device `{}` (available: {})",
+                    device.unwrap_or("default"),
+                    devices.join(", ")
+                ),
+                e,
+            )
+        })?;
+    speaker.resume();
+    Ok(speaker)
+}
+
+// a ROM opened from the file manager that came as an event, as on macOS
+fn dropped_file(event_pump: &mut sdl2::EventPump) -> Option<String> {
+    event_pump.poll_iter().find_map(|event| match event {
+        Event::DropFile { filename, .. } => Some(filename),
+        _ => None,
+    })
+}
+
+// a ROM picked in the file dialog, starting in `dir`, none when cancelled
+fn pick_program(dir: Option<&Path>) -> Option<String> {
+    if !platform::FILE_DIALOG {
+        return None;
+    }
+    match dialog::pick_rom(dir) {
+        Ok(path) => path.map(|p| p.to_string_lossy().into_owned()),
+        Err(e) => {
+            println!("no file dialog: {}", e);
+            None
+        }
+    }
+}
+
+fn paste_program(video: &sdl2::VideoSubsystem) -> Result<Vec<u8>, String> {
+    let text = video.clipboard().clipboard_text()?;
+    let program = utils::parse_hex(&text)?;
+    if program.is_empty() {
+        return Err(String::from("the clipboard holds no hex dump"));
+    }
+    Ok(program)
+}
+
+// reset the machine and load `program`, then its data `overlays`; the
+// machine is left as it is when the program doesn't fit
+fn restart(
+    m: &mut Machine,
+    program: &[u8],
+    overlays: &[(usize, Vec<u8>)],
+    protect: bool,
+) -> Result<(), Chip8Error> {
+    if program.len() > m.max_program_size() {
+        return Err(Chip8Error::ProgramTooLarge {
+            size: program.len(),
+            max: m.max_program_size(),
+        });
+    }
+    m.init();
+    m.load_program(program.to_vec())?;
+    for (address, bytes) in overlays {
+        m.load_at(*address, bytes)?;
+    }
+    if protect {
+        m.enable_protection();
+    }
+    Ok(())
+}
+
+// the name of a gamepad button in the config
+fn button_name(button: Button) -> &'static str {
+    match button {
+        Button::A => "a",
+        Button::B => "b",
+        Button::X => "x",
+        Button::Y => "y",
+        Button::Back => "back",
+        Button::Guide => "guide",
+        Button::Start => "start",
+        Button::LeftStick => "leftstick",
+        Button::RightStick => "rightstick",
+        Button::LeftShoulder => "leftshoulder",
+        Button::RightShoulder => "rightshoulder",
+        Button::DPadUp => "dpup",
+        Button::DPadDown => "dpdown",
+        Button::DPadLeft => "dpleft",
+        Button::DPadRight => "dpright",
+    }
+}
+
+// the lines typed on the console, read on a thread of their own
+fn console() -> mpsc::Receiver<String> {
+    let (sender, receiver) = mpsc::channel();
+    thread::spawn(move || {
+        let mut line = String::new();
+        while io::stdin().read_line(&mut line).is_ok_and(|n| n > 0) {
+            if sender.send(line.clone()).is_err() {
+                break;
+            }
+            line.clear();
+        }
+    });
+    receiver
+}
+
+// the handheld preset's state, kept apart from the hotkey save slot
+fn autosave_path(m: &Machine) -> Option<std::path::PathBuf> {
+    Machine::state_path(m.program_hash()).map(|p| p.with_extension("autosave"))
+}
+
+fn autosave(m: &Machine, cpu_hz: u32) {
+    if let Some(path) = autosave_path(m) {
+        if let Err(e) = m.save_state(&path, cpu_hz) {
+            println!("cannot save the state: {}", e);
+        }
+    }
+}
+
+// a save state loaded with the quirks and speed it was saved under, the
+// speed to run at updated, and what changed told
+fn load_state(m: &mut Machine, path: &Path, cpu_hz: &mut u32) -> io::Result<()> {
+    let current = StateConfig {
+        quirks: m.quirks(),
+        cpu_hz: *cpu_hz,
+    };
+    let saved = m.load_state(path)?;
+    if let Some(changes) = current.changes(&saved) {
+        println!("the state was saved with {}, running with them", changes);
+    }
+    *cpu_hz = saved.cpu_hz;
+    Ok(())
+}
+
+// where the keypad inputs are recorded, or played back from
+enum Inputs {
+    Record(Recording, String),
+    Replay(Replay),
+}
+
+// a keypad key from the player, recorded, or dropped during a replay
+fn set_key(m: &mut Machine, inputs: &mut Option<Inputs>, cycle: u64, key: u8, pressed: bool) {
+    match inputs {
+        Some(Inputs::Replay(_)) => return,
+        Some(Inputs::Record(recording, _)) => recording.push(cycle, key, pressed),
+        None => {}
+    }
+    m.set_key_state(key, pressed);
+}
+
+// the machine jumps to another state (a reset, a state load, another
+// program): the rewind history and the recorded inputs don't lead to it, the
+// recording is saved as it is and a replay stops
+fn break_timeline(history: &mut History, inputs: &mut Option<Inputs>) {
+    history.clear();
+    match inputs.take() {
+        Some(Inputs::Record(recording, path)) => match recording.save(Path::new(&path)) {
+            Ok(()) => println!("inputs recorded in {}", path),
+            Err(e) => println!("cannot save the inputs `{}`: {}", path, e),
+        },
+        Some(Inputs::Replay(_)) => println!("replay stopped"),
+        None => {}
+    }
+}
+
+fn record_score(board: &mut Leaderboard, initials: String, score: u32) -> io::Result<()> {
+    if !board.qualifies(score) {
+        return Ok(());
+    }
+    if let Some(rank) = board.insert(leaderboard::Entry { initials, score }) {
+        println!("high score #{}: {}", rank, score);
+        board.save()?;
+    }
+    Ok(())
+}
+
+// the emulator window, `args` are the command line options (see cli.rs)
+pub fn run(args: &[String]) -> io::Result<()> {
+    let opts = cli::parse_args(args).map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+
+    // hand the ROM over to the emulator already running, or be the one the
+    // next ROMs are handed to
+    #[cfg(all(unix, not(target_os = "android")))]
+    let control = if opts.new_instance {
+        None
+    } else {
+        match instance::socket_path() {
+            Ok(socket) => {
+                if let Some(rom) = &opts.program_file {
+                    match instance::hand_over(&socket, rom) {
+                        Ok(true) => {
+                            println!("{} handed over to the running emulator", rom);
+                            return Ok(());
+                        }
+                        Ok(false) => {}
+                        Err(e) => println!("cannot hand {} over: {}", rom, e),
+                    }
+                }
+                instance::Control::listen(&socket)
+                    .map_err(|e| println!("no control socket: {}", e))
+                    .ok()
+            }
+            Err(e) => {
+                println!("no control socket: {}", e);
+                None
+            }
+        }
+    };
+
+    // set video, before picking the ROM: macOS sends the one opened from
+    // the Finder as an event
+    sdl2::hint::set("SDL_APP_NAME", APP_NAME);
+    sdl2::hint::set("SDL_VIDEO_X11_WMCLASS", APP_CLASS);
+    sdl2::hint::set("SDL_VIDEO_WAYLAND_WMCLASS", APP_CLASS);
+    let sdl_context = sdl2::init().map_err(|e| sdl_error("cannot initialize SDL", e))?;
+    let video_subsystem = sdl_context.video().map_err(|e| {
+        sdl_error(
+            "no display available (soak, fuzz and selftest run without one)",
+            e,
+        )
+    })?;
+    let mut event_pump = sdl_context
+        .event_pump()
+        .map_err(|e| sdl_error("cannot read SDL events", e))?;
+
+    let mut config = match config::Config::default_path() {
+        Some(path) => config::Config::load(path.clone()).map_err(|e| {
+            io::Error::new(
+                e.kind(),
+                format!("cannot load the config `{}`: {}", path.display(), e),
+            )
+        })?,
+        None => config::Config::default(),
+    };
+    // the handheld preset, also on when Steam runs it on a Steam Deck
+    let handheld = opts.handheld || std::env::var_os("SteamDeck").is_some_and(|v| v == "1");
+
+    let demo = opts.demo;
+    let smooth = opts.smooth;
+    let program_file = opts
+        .program_file
+        .or_else(|| Some(String::from(demo::NAME)).filter(|_| demo))
+        .or_else(|| dropped_file(&mut event_pump));
+
+    // the first run setup, not on handhelds and phones where there's no
+    // keyboard to set up
+    if (config.is_new() || opts.setup) && !handheld && !platform::TOUCH_KEYPAD {
+        let carry_on = setup::run(&video_subsystem, &mut event_pump, &mut config)?;
+        config.save()?;
+        if !carry_on {
+            return Ok(());
+        }
+    }
+
+    let program_file = program_file
+        .or_else(|| pick_program(config.rom_dir.as_deref()))
+        .or_else(|| platform::DEFAULT_PROGRAM.map(String::from));
+    // nothing to run: the idle screen until a ROM is opened
+    let idle = program_file.is_none();
+    let program_file = program_file.unwrap_or_else(|| String::from(marquee::NAME));
+    set_two_player(&mut config, &program_file, opts.two_player);
+
+    // a replay runs with the seed, quirks and speed it was recorded with
+    let recording = match &opts.replay {
+        Some(path) => Some(Recording::load(Path::new(path)).map_err(|e| {
+            io::Error::new(
+                e.kind(),
+                format!("cannot load the inputs `{}`: {}", path, e),
+            )
+        })?),
+        None => None,
+    };
+    let seed = recording.as_ref().map_or_else(rand::random, |r| r.seed);
+    let (quirks, mut cpu_hz) = recording
+        .as_ref()
+        .map_or((opts.quirks, opts.cpu_hz), |r| (r.quirks, r.cpu_hz));
+
+    // the wall-clock time the window keeps to
+    let time = clock::system();
+    let mut m = Machine::new();
+    m.set_clock(time.clone());
+    m.seed_rng(seed);
+    m.set_quirks(quirks);
+    let mut plugins = Plugins::new();
+    for path in &opts.plugins {
+        // the user asked for these libraries to run, as plugins
+        unsafe { plugins.load(Path::new(path)) }.map_err(io::Error::other)?;
+    }
+    plugins.install(&mut m).map_err(io::Error::other)?;
+
+    // load program, kept to reset the machine
+    let protect = opts.protect;
+    let mut program = if demo {
+        demo::program()
+    } else if idle {
+        marquee::program(&config.idle)
+    } else {
+        platform::read_rom(&program_file).map_err(|e| exit::rom_load(&program_file, e))?
+    };
+    // the overlays go with this ROM, not the ones opened later
+    let mut overlays = Vec::new();
+    for (address, file) in &opts.overlays {
+        let bytes = platform::read_rom(file).map_err(|e| exit::rom_load(file, e))?;
+        overlays.push((*address, bytes));
+    }
+    restart(&mut m, &program, &overlays, protect).map_err(|e| {
+        exit::rom_load(&program_file, io::Error::new(io::ErrorKind::InvalidData, e))
+    })?;
+    println!("program loaded!");
+
+    // the ROM played on the Discord profile, titled from the ROM database
+    let presence = discord::Presence::from_env().map(|presence| {
+        let database = info::database_path().and_then(|path| fs::read_to_string(path).ok());
+        (presence, database)
+    });
+    let publish = |m: &Machine, name: &str| {
+        if let Some((presence, database)) = &presence {
+            presence.playing(&discord::title(database.as_deref(), m.program_hash(), name));
+        }
+    };
+    if !idle {
+        publish(&m, &capture::rom_name(&program_file));
+    }
+
+    let mut inputs = match (recording, &opts.record) {
+        (Some(recording), _) => {
+            if recording.program_hash != m.program_hash() {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!(
+                        "{} isn't the ROM the inputs were recorded with",
+                        program_file
+                    ),
+                ));
+            }
+            println!("replaying {} inputs", recording.events.len());
+            Some(Inputs::Replay(Replay::new(recording)))
+        }
+        (None, Some(path)) => Some(Inputs::Record(
+            Recording::new(m.program_hash(), seed, quirks, cpu_hz),
+            path.clone(),
+        )),
+        (None, None) => None,
+    };
+    let mut history = History::new(REWIND_FRAMES);
+    let mut rewinding = false;
+
+    let mut achievements = match &opts.achievements {
+        Some(path) => {
+            let a = achievements::Achievements::load(path).map_err(|e| {
+                io::Error::new(
+                    e.kind(),
+                    format!("cannot load achievements `{}`: {}", path, e),
+                )
+            })?;
+            println!("{} achievements loaded", a.len());
+            Some(a)
+        }
+        None => None,
+    };
+
+    // annotations given explicitly or found next to the ROM
+    let annotations_file = match &opts.annotations {
+        Some(path) => Some(std::path::PathBuf::from(path)),
+        None => Some(annotations::Annotations::sidecar_path(&program_file)).filter(|p| p.exists()),
+    };
+    let annotations = match annotations_file {
+        Some(path) => {
+            let a = annotations::Annotations::load(&path).map_err(|e| {
+                io::Error::new(
+                    e.kind(),
+                    format!("cannot load annotations `{}`: {}", path.display(), e),
+                )
+            })?;
+            println!(
+                "{} annotations loaded from {}",
+                a.list.len(),
+                path.display()
+            );
+            Some(a)
+        }
+        None => None,
+    };
+
+    let mut debugger = if opts.debug || opts.debug_script.is_some() {
+        // the names given to this ROM's locations
+        let aliases = match aliases::Aliases::path_for(m.program_hash()) {
+            Some(file) => aliases::Aliases::load(file.clone()).map_err(|e| {
+                io::Error::new(
+                    e.kind(),
+                    format!("cannot load aliases `{}`: {}", file.display(), e),
+                )
+            })?,
+            None => aliases::Aliases::default(),
+        };
+        let d = match &opts.debug_script {
+            Some(path) => debugger::Debugger::load_script(std::path::Path::new(path), &m, aliases)
+                .map_err(|e| {
+                    io::Error::new(
+                        e.kind(),
+                        format!("cannot load debug script `{}`: {}", path, e),
+                    )
+                })?,
+            None => debugger::Debugger::from_script("", &m, aliases)?,
+        };
+        if d.paused {
+            println!("paused, F5 to resume, F10 to step");
+            println!("{}", m.report());
+            print!("{}", d.aliases.summary(&m));
+        }
+        Some(d)
+    } else {
+        None
+    };
+    // debugger commands typed while the program runs
+    let console = debugger.as_ref().map(|_| console());
+
+    if handheld && inputs.is_none() {
+        if let Some(path) = autosave_path(&m).filter(|p| p.exists()) {
+            match load_state(&mut m, &path, &mut cpu_hz) {
+                Ok(()) => println!("state restored from {}", path.display()),
+                Err(e) => println!("cannot restore the state: {}", e),
+            }
+        }
+    }
+
+    // the keypad on the screen, for phones and touch screens
+    let mut touch_keypad = if opts.touch_keypad || platform::TOUCH_KEYPAD {
+        Some(TouchKeypad::default())
+    } else {
+        None
+    };
+    let mut size = window_size(
+        m.framebuffer(),
+        touch_keypad.is_some(),
+        annotations.is_some(),
+        config.scale,
+    );
+    let mut canvas = create_canvas(&video_subsystem, size)?;
+    if handheld {
+        canvas
+            .window_mut()
+            .set_fullscreen(FullscreenType::Desktop)
+            .map_err(|e| sdl_error("cannot go fullscreen", e))?;
+    }
+
+    canvas.set_draw_color(Color::RGB(0, 0, 0));
+    canvas.clear();
+    canvas.present();
+    let texture_creator = canvas.texture_creator();
+    let mut screen = Screen {
+        creator: &texture_creator,
+        texture: None,
+        rgba: Vec::new(),
+        rects: false,
+        palette: *config.colours(),
+        smoothing: Some(smoothing::Smoothing::new()).filter(|_| smooth),
+        toast: None,
+        panel: None,
+    };
+
+    // set audio, the emulator runs silent without it
+    if opts.audio_device.is_some() {
+        config.audio_device = opts.audio_device.clone();
+    }
+    if let Some(volume) = opts.volume {
+        config.volume = volume;
+        config.muted = false;
+    }
+    let tone = opts.tone.unwrap_or(audio::DEFAULT_TONE);
+    let mut speaker = match sdl_context.audio() {
+        Ok(audio) => Some(open_speaker(&audio, config.audio_device.as_deref(), tone)?),
+        Err(e) => {
+            println!("no sound: {}", e);
+            None
+        }
+    };
+    if opts.audio_device.is_some() || opts.volume.is_some() {
+        config.save()?;
+    }
+
+    // gamepads, opened as they're plugged in, for the keypad and the hotkeys
+    let game_controller = sdl_context
+        .game_controller()
+        .map_err(|e| println!("no gamepads: {}", e))
+        .ok();
+    let mut gamepads: Vec<GameController> = Vec::new();
+
+    // high scores, for ROMs with an annotated score
+    let score = annotations
+        .as_ref()
+        .and_then(|a| a.list.iter().find(|a| a.name.eq_ignore_ascii_case("score")))
+        .cloned();
+    let mut leaderboard = match &score {
+        Some(_) => {
+            let board = Leaderboard::load(&Leaderboard::sidecar_path(&program_file))?;
+            if !board.entries.is_empty() {
+                println!("high scores: {}", board.summary());
+            }
+            Some(board)
+        }
+        None => None,
+    };
+    let mut best_score = 0;
+    let mut initials: Option<InitialsEntry> = None;
+    let mut game_over = gameover::GameOverDetector::new(annotations.as_ref());
+
+    let event_subsystem = sdl_context
+        .event()
+        .map_err(|e| sdl_error("cannot initialize SDL events", e))?;
+    let mut stopped = false;
+    // the window title shows the annotated values, or the last achievement
+    // toast for a few seconds
+    let mut toast: Option<(String, Instant)> = None;
+    let mut toast_drawn = false;
+    let mut title = String::from(WINDOW_TITLE);
+    // the frame pacing stats in the title, F3 toggles them
+    let mut perf = false;
+
+    // the file of the save and load state hotkeys
+    let mut state_file = match &opts.load_state {
+        Some(path) => Some(std::path::PathBuf::from(path)),
+        None => Machine::state_path(m.program_hash()),
+    };
+    if let Some(path) = &opts.load_state {
+        load_state(&mut m, Path::new(path), &mut cpu_hz).map_err(|e| {
+            io::Error::new(e.kind(), format!("cannot load the state `{}`: {}", path, e))
+        })?;
+        println!("state restored from {}", path);
+    }
+
+    let mut tracer = match &opts.trace {
+        Some(path) => {
+            let file = fs::File::create(path).map_err(|e| {
+                io::Error::new(
+                    e.kind(),
+                    format!("cannot create the trace `{}`: {}", path, e),
+                )
+            })?;
+            Some(trace::Tracer::with_filter(
+                io::BufWriter::new(file),
+                opts.trace_filter.clone().unwrap_or_default(),
+            ))
+        }
+        None => None,
+    };
+
+    let mut last_autosave = time.now();
+    let mut power_save = power::PowerSave::new(opts.power_save);
+    let mut scheduler = scheduler::Scheduler::new(
+        cpu_hz,
+        if power_save.saving() {
+            power::SAVING_FRAME
+        } else {
+            FRAME_PERIOD
+        },
+        time.now(),
+    );
+    let mut clock = scheduler::TimerClock::new(cpu_hz);
+    // the timer clock's count, the time the recorded inputs are given in
+    let mut cycles: u64 = 0;
+    // frames polled from the input providers, and the keys they held
+    let mut frame: u64 = 0;
+    let mut plugin_keys: KeyMask = 0;
+    // timer ticks since the start, the frame numbers of the captures
+    let mut ticks: u64 = 0;
+    let mut capture = Capture::new(
+        &opts.capture_path,
+        &capture::rom_name(&program_file),
+        opts.capture_frames,
+        opts.capture_at,
+    );
+    let watchdog = watchdog::Watchdog::start();
+    let crash = CrashDump::install();
+    'running: loop {
+        watchdog.enter(Stage::Events);
+        let mut refresh_window = false;
+        // ROM to run instead of the current one
+        #[cfg(all(unix, not(target_os = "android")))]
+        let mut open = control.as_ref().and_then(|c| c.poll().pop());
+        #[cfg(not(all(unix, not(target_os = "android"))))]
+        let mut open = None;
+
+        // Handle events
+        for event in event_pump.poll_iter() {
+            // keypad keys changed by the event
+            let mut pressed = None;
+            let mut released = None;
+            let mut action = None;
+            match event {
+                Event::KeyDown {
+                    keycode: Some(Keycode::Escape),
+                    ..
+                } if initials.is_none()
+                    && leaderboard
+                        .as_ref()
+                        .is_some_and(|b| b.qualifies(best_score)) =>
+                {
+                    // ask for the initials before leaving
+                    initials = Some(InitialsEntry::new());
+                }
+                Event::Quit { .. }
+                | Event::KeyDown {
+                    keycode: Some(Keycode::Escape),
+                    ..
+                } => {
+                    if let Some(board) = leaderboard.as_mut() {
+                        let name = initials
+                            .as_ref()
+                            .map_or(String::from("???"), |e| e.initials());
+                        record_score(board, name, best_score)?;
+                    }
+                    if handheld {
+                        autosave(&m, cpu_hz);
+                    }
+                    println!("exiting");
+                    break 'running;
+                }
+                Event::KeyDown {
+                    keycode: Some(Keycode::F5),
+                    ..
+                } if debugger.as_ref().is_some_and(|d| d.paused) => {
+                    if let Some(d) = debugger.as_mut() {
+                        d.resume();
+                    }
+                    println!("resumed");
+                }
+                Event::KeyDown {
+                    keycode: Some(Keycode::F6),
+                    ..
+                } if debugger.as_ref().is_some_and(|d| !d.paused) => {
+                    if let Some(d) = debugger.as_mut() {
+                        d.pause();
+                    }
+                    println!("paused, F5 to resume, F10 to step");
+                }
+                Event::KeyDown {
+                    keycode: Some(Keycode::F10),
+                    ..
+                } if debugger.as_ref().is_some_and(|d| d.paused) => {
+                    if let Some(d) = debugger.as_mut() {
+                        d.step(1);
+                    }
+                }
+                Event::KeyDown {
+                    keycode:
+                        Some(
+                            kcode @ (Keycode::M
+                            | Keycode::Minus
+                            | Keycode::KpMinus
+                            | Keycode::Equals
+                            | Keycode::KpPlus),
+                        ),
+                    ..
+                } if config.keymap.window_key(&kcode.name()) => {
+                    // M is the second player's A
+                    match kcode {
+                        Keycode::M => config.muted = !config.muted,
+                        Keycode::Minus | Keycode::KpMinus => config.change_volume(-10),
+                        _ => config.change_volume(10),
+                    }
+                    config.save()?;
+                    let text = if config.muted {
+                        String::from("Muted")
+                    } else {
+                        format!("Volume {}%", config.volume)
+                    };
+                    toast = Some((text, time.now()));
+                }
+                Event::ControllerDeviceAdded { which, .. } => {
+                    if let Some(subsystem) = &game_controller {
+                        match subsystem.open(which) {
+                            Ok(pad) => {
+                                println!(
+                                    "gamepad connected: {} ({})",
+                                    pad.name(),
+                                    config.hotkeys.summary()
+                                );
+                                gamepads.push(pad);
+                            }
+                            Err(e) => println!("cannot open gamepad {}: {}", which, e),
+                        }
+                    }
+                }
+                Event::ControllerDeviceRemoved { .. } => gamepads.retain(|pad| pad.attached()),
+                Event::ControllerButtonUp { button, .. } => {
+                    config.hotkeys.release(button_name(button));
+                    released = config.keymap.pad_key(button_name(button));
+                }
+                Event::ControllerButtonDown { button, .. } => {
+                    // a button completing a chord doesn't press its key
+                    action = config.hotkeys.press(button_name(button));
+                    if action.is_none() {
+                        pressed = config.keymap.pad_key(button_name(button));
+                    }
+                }
+                Event::KeyDown {
+                    keycode: Some(Keycode::Backspace),
+                    repeat: false,
+                    ..
+                } => {
+                    if inputs.is_some() {
+                        toast = Some((
+                            String::from("No rewinding while inputs are recorded or replayed"),
+                            time.now(),
+                        ));
+                    } else {
+                        rewinding = true;
+                    }
+                }
+                Event::KeyUp {
+                    keycode: Some(Keycode::Backspace),
+                    ..
+                } if rewinding => {
+                    rewinding = false;
+                    // the keys held in the snapshot are up by now
+                    for k in 0..16 {
+                        m.set_key_state(k, false);
+                    }
+                }
+                Event::KeyDown {
+                    keycode: Some(Keycode::F3),
+                    ..
+                } => perf = !perf,
+                Event::KeyDown {
+                    keycode: Some(Keycode::F12),
+                    ..
+                } => {
+                    capture.start();
+                    println!("capturing {} frames", opts.capture_frames);
+                }
+                Event::KeyDown {
+                    keycode: Some(Keycode::F8),
+                    ..
+                } => action = Some(hotkeys::Action::SaveState),
+                Event::KeyDown {
+                    keycode: Some(Keycode::F9),
+                    ..
+                } => action = Some(hotkeys::Action::LoadState),
+                Event::KeyDown {
+                    keycode: Some(Keycode::V),
+                    keymod,
+                    ..
+                } if keymod.intersects(Mod::LCTRLMOD | Mod::RCTRLMOD) => {
+                    // run a program pasted as a hex dump
+                    let pasted = paste_program(&video_subsystem).and_then(|pasted| {
+                        restart(&mut m, &pasted, &[], protect).map_err(|e| e.to_string())?;
+                        Ok(pasted)
+                    });
+                    match pasted {
+                        Ok(pasted) => {
+                            println!("{} bytes pasted", pasted.len());
+                            publish(&m, "a pasted ROM");
+                            break_timeline(&mut history, &mut inputs);
+                            program = pasted;
+                            overlays.clear();
+                            stopped = false;
+                            game_over.reset();
+                            refresh_window = true;
+                        }
+                        Err(e) => println!("cannot paste a program: {}", e),
+                    }
+                }
+                // dropped on the window, or opened from the Finder
+                Event::DropFile { filename, .. } => open = Some(filename),
+                Event::KeyDown {
+                    keycode: Some(Keycode::O),
+                    keymod,
+                    ..
+                } if keymod.intersects(Mod::LCTRLMOD | Mod::RCTRLMOD) => {
+                    // run a program picked in the file dialog
+                    watchdog.enter(Stage::Dialog);
+                    open = pick_program(config.rom_dir.as_deref());
+                    watchdog.enter(Stage::Events);
+                }
+                Event::KeyDown {
+                    keycode: Some(kcode),
+                    scancode,
+                    ..
+                } => pressed = keypad_key(&config.keymap, kcode, scancode),
+                Event::KeyUp {
+                    keycode: Some(kcode),
+                    scancode,
+                    ..
+                } => released = keypad_key(&config.keymap, kcode, scancode),
+                Event::FingerDown {
+                    finger_id, x, y, ..
+                } => {
+                    pressed = touch_keypad.as_mut().and_then(|t| t.down(finger_id, x, y));
+                }
+                Event::FingerMotion {
+                    finger_id, x, y, ..
+                } => {
+                    if let Some(t) = touch_keypad.as_mut() {
+                        (released, pressed) = t.motion(finger_id, x, y);
+                    }
+                }
+                Event::FingerUp { finger_id, .. } => {
+                    released = touch_keypad.as_mut().and_then(|t| t.up(finger_id));
+                }
+                // the mouse stands in for a finger on the desktop, touches
+                // also come as mouse events but are handled above
+                Event::MouseButtonDown { which, x, y, .. } if which != TOUCH_MOUSE_ID => {
+                    let (x, y) = normalize(&canvas, x, y);
+                    pressed = touch_keypad
+                        .as_mut()
+                        .and_then(|t| t.down(MOUSE_FINGER, x, y));
+                }
+                Event::MouseMotion {
+                    which,
+                    mousestate,
+                    x,
+                    y,
+                    ..
+                } if which != TOUCH_MOUSE_ID && mousestate.left() => {
+                    let (x, y) = normalize(&canvas, x, y);
+                    if let Some(t) = touch_keypad.as_mut() {
+                        (released, pressed) = t.motion(MOUSE_FINGER, x, y);
+                    }
+                }
+                Event::MouseButtonUp { which, .. } if which != TOUCH_MOUSE_ID => {
+                    released = touch_keypad.as_mut().and_then(|t| t.up(MOUSE_FINGER));
+                }
+                Event::Window {
+                    win_event: WindowEvent::FocusLost,
+                    ..
+                }
+                | Event::AppWillEnterBackground { .. }
+                    if handheld =>
+                {
+                    // the device may be suspended next
+                    autosave(&m, cpu_hz);
+                    last_autosave = time.now();
+                    refresh_window = true;
+                }
+                Event::Window { .. } => {
+                    refresh_window = true;
+                }
+                _ => {}
+            }
+
+            if let Some(k) = released {
+                set_key(&mut m, &mut inputs, cycles, k, false);
+            }
+            if let Some(k) = pressed {
+                match initials.as_mut() {
+                    Some(entry) => {
+                        if entry.key(k) {
+                            if let Some(board) = leaderboard.as_mut() {
+                                record_score(board, entry.initials(), best_score)?;
+                            }
+                            best_score = 0;
+                            initials = None;
+                            game_over.reset();
+                        }
+                    }
+                    None => set_key(&mut m, &mut inputs, cycles, k, true),
+                }
+            }
+            // show the keys held on the touch keypad
+            if touch_keypad.is_some() && (pressed.is_some() || released.is_some()) {
+                refresh_window = true;
+            }
+
+            // hotkeys, from the gamepad or the keyboard
+            let action = match action {
+                Some(action) => action,
+                None => continue,
+            };
+            let text = match action {
+                hotkeys::Action::Reset => match restart(&mut m, &program, &overlays, protect) {
+                    Ok(()) => {
+                        break_timeline(&mut history, &mut inputs);
+                        stopped = false;
+                        game_over.reset();
+                        refresh_window = true;
+                        String::from("Reset")
+                    }
+                    Err(e) => format!("Cannot reset: {}", e),
+                },
+                hotkeys::Action::SaveState => match &state_file {
+                    Some(path) => match m.save_state(path, cpu_hz) {
+                        Ok(()) => String::from("State saved"),
+                        Err(e) => format!("Cannot save the state: {}", e),
+                    },
+                    None => String::from("No home directory for the state"),
+                },
+                hotkeys::Action::LoadState => match &state_file {
+                    Some(path) => match load_state(&mut m, path, &mut cpu_hz) {
+                        Ok(()) => {
+                            // at the state's pace from now on
+                            if cpu_hz != clock.ips() {
+                                scheduler = scheduler::Scheduler::new(
+                                    cpu_hz,
+                                    scheduler.frame(),
+                                    time.now(),
+                                );
+                                clock = scheduler::TimerClock::new(cpu_hz);
+                            }
+                            break_timeline(&mut history, &mut inputs);
+                            stopped = false;
+                            refresh_window = true;
+                            String::from("State loaded")
+                        }
+                        Err(e) => format!("Cannot load the state: {}", e),
+                    },
+                    None => String::from("No home directory for the state"),
+                },
+                hotkeys::Action::Quit => {
+                    event_subsystem
+                        .push_event(Event::Quit { timestamp: 0 })
+                        .map_err(|e| sdl_error("cannot queue an SDL event", e))?;
+                    continue;
+                }
+            };
+            println!("{}", text);
+            toast = Some((text, time.now()));
+        }
+
+        if let Some(path) = open {
+            let opened = platform::read_rom(&path).and_then(|opened| {
+                restart(&mut m, &opened, &[], protect)
+                    .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+                Ok(opened)
+            });
+            match opened {
+                Ok(opened) => {
+                    println!("{} loaded", path);
+                    publish(&m, &capture::rom_name(&path));
+                    set_two_player(&mut config, &path, opts.two_player);
+                    break_timeline(&mut history, &mut inputs);
+                    program = opened;
+                    overlays.clear();
+                    if opts.load_state.is_none() {
+                        state_file = Machine::state_path(m.program_hash());
+                    }
+                    stopped = false;
+                    game_over.reset();
+                    refresh_window = true;
+                    canvas.window_mut().raise();
+                }
+                Err(e) => println!("cannot load program file `{}`: {}", path, e),
+            }
+        }
+
+        if let (Some(d), Some(console)) = (debugger.as_mut(), &console) {
+            while let Ok(line) = console.try_recv() {
+                let line = line.trim();
+                if line.is_empty() {
+                    continue;
+                }
+                match debugger::parse_command(line, &d.aliases) {
+                    Ok(command) => print!("{}", d.execute(command, &m)?),
+                    Err(e) => println!("{}", e),
+                }
+            }
+        }
+
+        // keys held by the plugins' input providers, pressed and released
+        // as they change
+        if !plugins.inputs.is_empty() {
+            let held = plugins.poll(frame);
+            for k in (0..16).filter(|k| (held ^ plugin_keys) & 1 << k != 0) {
+                set_key(&mut m, &mut inputs, cycles, k, held & 1 << k != 0);
+            }
+            plugin_keys = held;
+            frame += 1;
+        }
+
+        // hold backspace to go back in time, a frame at a time
+        if rewinding {
+            if let Some(snapshot) = history.pop() {
+                if let Err(e) = m.restore(&snapshot) {
+                    println!("cannot rewind: {}", e);
+                }
+                stopped = false;
+                game_over.reset();
+                refresh_window = true;
+            }
+        }
+
+        // run the instructions due since the last frame, the machine waits
+        // while initials are entered and stands still while rewinding
+        let due = scheduler.batch(time.now());
+        for _ in 0..if rewinding { 0 } else { due } {
+            if let Some(Inputs::Replay(replay)) = inputs.as_mut() {
+                for e in replay.due(cycles) {
+                    m.set_key_state(e.key, e.pressed);
+                }
+            }
+            if let Some(d) = debugger.as_mut() {
+                let was_paused = d.paused;
+                for note in d.check(&m) {
+                    println!("{}", note);
+                }
+                if d.paused {
+                    if !was_paused {
+                        capture.breakpoint();
+                        println!("{}", m.report());
+                        print!("{}", d.aliases.summary(&m));
+                    }
+                    break;
+                }
+            }
+            // the instruction a stopped program halted on is traced once
+            if let Some(t) = tracer.as_mut().filter(|_| initials.is_none() && !stopped) {
+                t.before(&m);
+            }
+            watchdog.instruction(m.pc, m.fetch_opcode());
+            let halt = if initials.is_some() {
+                None
+            } else {
+                match panic::catch_unwind(panic::AssertUnwindSafe(|| m.exec_single())) {
+                    Ok(result) => result.err(),
+                    Err(payload) => {
+                        eprintln!("{}", m.report());
+                        crash.publish(&m, cpu_hz);
+                        crash.report(crash.redump());
+                        panic::resume_unwind(payload);
+                    }
+                }
+            };
+            let alive = initials.is_none() && halt.is_none();
+            if let Some(t) = tracer.as_mut() {
+                t.after(&m)?;
+            }
+            if let Some(e) = halt.filter(|_| !stopped) {
+                stopped = true;
+                match e {
+                    Chip8Error::Exit { .. } => println!("program exited"),
+                    e => println!("ROM halted: {}", e),
+                }
+                if leaderboard
+                    .as_ref()
+                    .is_some_and(|b| b.qualifies(best_score))
+                {
+                    initials = Some(InitialsEntry::new());
+                }
+            }
+            if let Some(s) = &score {
+                best_score = best_score.max(s.read(&m));
+            }
+            if let Some(reason) = game_over.update(&m) {
+                println!("game over: {}", reason);
+                if initials.is_none()
+                    && leaderboard
+                        .as_ref()
+                        .is_some_and(|b| b.qualifies(best_score))
+                {
+                    initials = Some(InitialsEntry::new());
+                }
+            }
+
+            // 60 Hz timers
+            for _ in 0..clock.advance(1) {
+                m.tick_timers();
+                ticks += 1;
+            }
+            cycles += 1;
+            if !alive {
+                break;
+            }
+        }
+        let paused = debugger.as_ref().is_some_and(|d| d.paused);
+        if due > 0 && !rewinding && !stopped && !paused {
+            history.record(&m);
+        }
+        if due > 0 {
+            crash.publish(&m, cpu_hz);
+        }
+        // the keypad is the player's again
+        if let Some(Inputs::Replay(replay)) = &inputs {
+            if replay.finished() {
+                println!("replay finished");
+                inputs = None;
+            }
+        }
+
+        // Render, resizing the window when a program switches resolution
+        watchdog.enter(Stage::Render);
+        let wanted = window_size(
+            m.framebuffer(),
+            touch_keypad.is_some(),
+            annotations.is_some(),
+            config.scale,
+        );
+        if !handheld && wanted != size {
+            size = wanted;
+            canvas
+                .window_mut()
+                .set_size(size.0, size.1)
+                .map_err(|e| sdl_error("cannot resize the window", e))?;
+            refresh_window = true;
+        }
+        let fading = screen.fading(time.now());
+        // drawn while there's a toast, and once more when it's gone
+        let toasting = toast.is_some() || toast_drawn;
+        // the panel when a value changed
+        let values = annotations.as_ref().map(|a| a.summary(&m));
+        let changed = screen.panel.as_ref().map(|(text, _)| text) != values.as_ref();
+        if m.framebuffer_mut().take_dirty() || refresh_window || fading || toasting || changed {
+            render(
+                &mut canvas,
+                &mut screen,
+                m.framebuffer(),
+                touch_keypad.as_ref(),
+                toast.as_ref().map(|(text, _)| text.as_str()),
+                values.as_deref(),
+                &plugins,
+                time.now(),
+            );
+            toast_drawn = toast.is_some();
+        }
+
+        let values = values.as_deref().unwrap_or("");
+        match capture.frame(ticks, cycles, m.framebuffer(), &screen.palette, values) {
+            Ok(Some(path)) if !capture.capturing() => {
+                println!("capture done, the last frame is {}", path.display())
+            }
+            Ok(_) => {}
+            Err(e) => {
+                println!("cannot save frame {}: {}", ticks, e);
+                capture.stop();
+            }
+        }
+
+        if handheld && time.now().saturating_duration_since(last_autosave) >= AUTOSAVE_PERIOD {
+            autosave(&m, cpu_hz);
+            last_autosave = time.now();
+        }
+
+        // sound, silent while paused
+        if let Some(s) = speaker.as_mut() {
+            s.lock()
+                .update(&m, m.get_timer(Timer::Sound) > 0 && !paused, config.gain());
+        }
+
+        // achievements
+        if let Some(a) = achievements.as_mut() {
+            for name in a.update(&m) {
+                let text = format!(
+                    "Achievement unlocked: {} ({}/{})",
+                    name,
+                    a.unlocked(),
+                    a.len()
+                );
+                println!("{}", text);
+                toast = Some((text, time.now()));
+            }
+        }
+        if toast
+            .as_ref()
+            .is_some_and(|(_, t)| time.now().saturating_duration_since(*t) >= TOAST_DURATION)
+        {
+            toast = None;
+        }
+        let pacing = scheduler.pacing().filter(|_| perf);
+        let new_title = match (&initials, &toast, pacing) {
+            (Some(entry), _, _) => format!(
+                "{} - High score {}! Initials: {} (2/8 letter, 4/6 move, 5 ok)",
+                WINDOW_TITLE,
+                best_score,
+                entry.prompt()
+            ),
+            (None, Some((text, _)), _) => format!("{} - {}", WINDOW_TITLE, text),
+            (None, None, Some(p)) => format!("{} - {}", WINDOW_TITLE, p),
+            (None, None, None) => String::from(WINDOW_TITLE),
+        };
+        if new_title != title {
+            let _ = canvas.window_mut().set_title(&new_title);
+            title = new_title;
+        }
+
+        // fewer frames on battery, the timers still tick with the cycles
+        if power_save.update(time.now(), platform::on_battery) {
+            if power_save.saving() {
+                scheduler.set_frame(power::SAVING_FRAME);
+                println!("on battery, saving power");
+            } else {
+                scheduler.set_frame(FRAME_PERIOD);
+                println!("power saving off");
+            }
+        }
+
+        // nothing can happen before an event or a timer running out: sleep
+        // on the event queue, then let the timers catch up. Not with
+        // recorded inputs, they're timed in cycles actually run, nor while
+        // a screen fades in
+        let idle = !screen.fading(time.now())
+            && (paused
+                || (initials.is_none()
+                    && !rewinding
+                    && inputs.is_none()
+                    && (stopped || m.waiting().is_some())));
+        if idle {
+            let timers = [m.get_timer(Timer::Delay), m.get_timer(Timer::Sound)];
+            let timeout = match timers.iter().filter(|t| !paused && **t > 0).min() {
+                Some(t) => (Duration::from_secs(u64::from(*t)) / TIMER_HZ).min(IDLE_TIMEOUT),
+                None if paused => CONSOLE_POLL,
+                None => IDLE_TIMEOUT,
+            };
+            if timeout > scheduler.frame() {
+                if let Some(event) = event_pump.wait_event_timeout(timeout.as_millis() as u32) {
+                    event_subsystem
+                        .push_event(event)
+                        .map_err(|e| sdl_error("cannot queue an SDL event", e))?;
+                }
+                let elapsed = scheduler.skip(time.now());
+                // time stands still while paused
+                if !paused {
+                    for _ in 0..clock.advance(elapsed as u64) {
+                        m.tick_timers();
+                        ticks += 1;
+                    }
+                }
+                continue;
+            }
+        }
+
+        // wait for the next frame
+        time.sleep(scheduler.sleep_time(time.now()));
+    }
+
+    break_timeline(&mut history, &mut inputs);
+
+    // quitting a program that hit an invalid opcode
+    match m.invalid_opcode() {
+        Some((pc, opcode)) if stopped => Err(exit::invalid_opcode(pc, opcode)),
+        _ => Ok(()),
+    }
+}
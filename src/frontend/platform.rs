@@ -0,0 +1,62 @@
+// What differs between the desktop and the Android builds of the frontend.
+//
+// On Android, SDL's Java activity loads the library as `libmain.so` and
+// calls `SDL_main`; the library is built for it with
+//
+//     cargo rustc --lib --release --target aarch64-linux-android --crate-type cdylib
+//
+// There's no command line nor file dialog: the default ROM is read from the
+// APK assets and the keypad is on the touch screen. On the desktop, the
+// window scrolls the idle screen until a ROM is opened.
+
+use std::io;
+
+// the keypad is on the screen
+pub const TOUCH_KEYPAD: bool = cfg!(target_os = "android");
+
+// ROMs can be picked in a file dialog (see dialog.rs)
+pub const FILE_DIALOG: bool = !cfg!(target_os = "android");
+
+// the program run without arguments, an asset on Android; none on the
+// desktop, where the idle screen (see marquee.rs) waits for one
+#[cfg(target_os = "android")]
+pub const DEFAULT_PROGRAM: Option<&str> = Some("test_opcode.rom");
+#[cfg(not(target_os = "android"))]
+pub const DEFAULT_PROGRAM: Option<&str> = None;
+
+// read a ROM, from the APK assets on Android (SDL falls back on them for
+// relative paths)
+#[cfg(target_os = "android")]
+pub fn read_rom(path: &str) -> io::Result<Vec<u8>> {
+    use std::io::Read;
+    let mut file = sdl2::rwops::RWops::from_file(path, "rb").map_err(io::Error::other)?;
+    let mut data = Vec::new();
+    file.read_to_end(&mut data)?;
+    Ok(data)
+}
+
+#[cfg(not(target_os = "android"))]
+pub fn read_rom(path: &str) -> io::Result<Vec<u8>> {
+    std::fs::read(path)
+}
+
+// running on battery, as SDL sees it
+pub fn on_battery() -> bool {
+    let state = unsafe { sdl2::sys::SDL_GetPowerInfo(std::ptr::null_mut(), std::ptr::null_mut()) };
+    state == sdl2::sys::SDL_PowerState::SDL_POWERSTATE_ON_BATTERY
+}
+
+#[cfg(target_os = "android")]
+#[no_mangle]
+pub extern "C" fn SDL_main(
+    _argc: std::os::raw::c_int,
+    _argv: *const *const std::os::raw::c_char,
+) -> std::os::raw::c_int {
+    match super::run(&[]) {
+        Ok(()) => 0,
+        Err(e) => {
+            eprintln!("{}", e);
+            1
+        }
+    }
+}
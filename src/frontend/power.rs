@@ -0,0 +1,100 @@
+// Power saving, for laptops: frames at 30 Hz instead of 120, so the window
+// is drawn half as often as the screen refreshes and the loop sleeps in
+// longer stretches. The program runs as fast as ever and its timers still
+// tick at 60 Hz of emulated time (see scheduler.rs), only the input and the
+// picture come a little later. `--power-save MODE`:
+//
+//     auto   while the machine runs on battery (the default), as SDL's
+//            power info tells, checked every few seconds
+//     on     always
+//     off    never
+
+use std::time::{Duration, Instant};
+
+// the frame of the loop when saving power
+pub const SAVING_FRAME: Duration = Duration::from_nanos(1_000_000_000 / 30);
+// how often the power source is checked
+const CHECK_PERIOD: Duration = Duration::from_secs(5);
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Mode {
+    Auto,
+    On,
+    Off,
+}
+
+impl Mode {
+    pub fn parse(text: &str) -> Result<Mode, String> {
+        match text {
+            "auto" => Ok(Mode::Auto),
+            "on" => Ok(Mode::On),
+            "off" => Ok(Mode::Off),
+            _ => Err(format!("invalid power saving `{}` (auto, on or off)", text)),
+        }
+    }
+}
+
+pub struct PowerSave {
+    mode: Mode,
+    saving: bool,
+    // the last check of the power source
+    checked: Option<Instant>,
+}
+
+impl PowerSave {
+    pub fn new(mode: Mode) -> PowerSave {
+        PowerSave {
+            mode,
+            saving: mode == Mode::On,
+            checked: None,
+        }
+    }
+
+    pub fn saving(&self) -> bool {
+        self.saving
+    }
+
+    // call on each frame, `on_battery` asks the platform when it's time to;
+    // true when saving was turned on or off
+    pub fn update(&mut self, now: Instant, on_battery: impl FnOnce() -> bool) -> bool {
+        if self.mode != Mode::Auto
+            || self
+                .checked
+                .is_some_and(|t| now.saturating_duration_since(t) < CHECK_PERIOD)
+        {
+            return false;
+        }
+        self.checked = Some(now);
+        let saving = on_battery();
+        let changed = saving != self.saving;
+        self.saving = saving;
+        changed
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn power_save_modes() {
+        assert_eq!(Ok(Mode::Auto), Mode::parse("auto"));
+        assert!(Mode::parse("battery").is_err());
+
+        let now = Instant::now();
+        let mut p = PowerSave::new(Mode::On);
+        assert!(p.saving());
+        assert!(!p.update(now, || panic!("not asked")));
+        assert!(!PowerSave::new(Mode::Off).saving());
+
+        // auto follows the battery, asked every few seconds
+        let mut p = PowerSave::new(Mode::Auto);
+        assert!(!p.saving());
+        assert!(p.update(now, || true));
+        assert!(p.saving());
+        assert!(!p.update(now + Duration::from_secs(1), || panic!("asked too soon")));
+        assert!(!p.update(now + CHECK_PERIOD, || true));
+        assert!(p.update(now + CHECK_PERIOD * 2, || false));
+        assert!(!p.saving());
+    }
+}
@@ -0,0 +1,248 @@
+// First run setup: with no config yet (or with --setup), a window to pick
+// the keyboard layout, the window scale, the palette and the ROM folder
+// before the emulator starts, then saved as the config. The step and its
+// choice are in the window title: Left/Right change the choice, Enter goes
+// to the next step and Escape keeps the defaults for the rest. The demo
+// plays in the window meanwhile, at the scale and in the palette picked.
+//
+// The ROM folder is picked in the system's folder dialog (see dialog.rs),
+// where there's one; the file dialog opens in it from then on.
+
+use super::config::{Config, MAX_SCALE, PALETTES};
+use super::keymap::LAYOUTS;
+use super::{create_canvas, dialog, render, restart, sdl_error, window_size, Screen};
+use crate::plugin::Plugins;
+use crate::{demo, Machine, CPU_IPS};
+use sdl2::event::Event;
+use sdl2::keyboard::Keycode;
+use std::io;
+use std::thread;
+use std::time::{Duration, Instant};
+
+// frame rate of the preview
+const FRAME_PERIOD: Duration = Duration::from_millis(1000 / 60);
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Step {
+    Layout,
+    Scale,
+    Palette,
+    RomDir,
+}
+
+const STEPS: [Step; 4] = [Step::Layout, Step::Scale, Step::Palette, Step::RomDir];
+
+struct Setup {
+    step: usize,
+    config: Config,
+}
+
+// `i` moved by `delta` around `len` choices
+fn cycle(i: usize, delta: i32, len: usize) -> usize {
+    (i as i32 + delta).rem_euclid(len as i32) as usize
+}
+
+impl Setup {
+    fn step(&self) -> Step {
+        STEPS[self.step]
+    }
+
+    // the current step's choice moved by `delta`
+    fn change(&mut self, delta: i32) {
+        let step = self.step();
+        let config = &mut self.config;
+        match step {
+            Step::Layout => {
+                let i = LAYOUTS
+                    .iter()
+                    .position(|l| l.0 == config.keymap.layout)
+                    .unwrap_or(0);
+                let layout = LAYOUTS[cycle(i, delta, LAYOUTS.len())].0;
+                config.keymap.set_layout(layout).unwrap();
+            }
+            Step::Scale => {
+                config.scale =
+                    (i32::from(config.scale) + delta).clamp(1, i32::from(MAX_SCALE)) as u8
+            }
+            Step::Palette => config.palette = cycle(config.palette, delta, PALETTES.len()),
+            // picked in a dialog
+            Step::RomDir => {}
+        }
+    }
+
+    // on to the next step, false after the last one
+    fn next(&mut self) -> bool {
+        self.step += 1;
+        self.step < STEPS.len()
+    }
+
+    fn title(&self) -> String {
+        let config = &self.config;
+        let choice = match self.step() {
+            Step::Layout => format!(
+                "keyboard layout < {} > (keypad on {})",
+                config.keymap.layout,
+                LAYOUTS
+                    .iter()
+                    .find(|l| l.0 == config.keymap.layout)
+                    .map_or(String::new(), |l| l.1[4..8].concat().to_uppercase())
+            ),
+            Step::Scale => format!("window scale < {}x >", config.scale),
+            Step::Palette => format!("palette < {} >", config.palette_name()),
+            Step::RomDir => {
+                return format!(
+                    "Setup {}/{}: ROM folder, Enter to choose it, Escape to skip",
+                    self.step + 1,
+                    STEPS.len()
+                )
+            }
+        };
+        format!(
+            "Setup {}/{}: {}, Left/Right to change, Enter for the next step, Escape to skip",
+            self.step + 1,
+            STEPS.len(),
+            choice
+        )
+    }
+}
+
+// the setup in a window of its own, `config` updated with the choices;
+// false when the window was closed, to quit
+pub fn run(
+    video: &sdl2::VideoSubsystem,
+    event_pump: &mut sdl2::EventPump,
+    config: &mut Config,
+) -> io::Result<bool> {
+    let mut setup = Setup {
+        step: 0,
+        config: config.clone(),
+    };
+    let mut m = Machine::new();
+    let program = demo::program();
+    restart(&mut m, &program, &[], false).map_err(|e| io::Error::other(e.to_string()))?;
+
+    let mut size = window_size(m.framebuffer(), false, false, setup.config.scale);
+    let mut canvas = create_canvas(video, size)?;
+    let texture_creator = canvas.texture_creator();
+    let mut screen = Screen {
+        creator: &texture_creator,
+        texture: None,
+        rgba: Vec::new(),
+        rects: false,
+        palette: *setup.config.colours(),
+        smoothing: None,
+        toast: None,
+        panel: None,
+    };
+
+    let mut title = String::new();
+    'setup: loop {
+        for event in event_pump.poll_iter() {
+            match event {
+                Event::Quit { .. } => return Ok(false),
+                Event::KeyDown {
+                    keycode: Some(key), ..
+                } => match key {
+                    Keycode::Left => setup.change(-1),
+                    Keycode::Right => setup.change(1),
+                    Keycode::Escape => break 'setup,
+                    Keycode::Return | Keycode::KpEnter => {
+                        if setup.step() == Step::RomDir {
+                            match dialog::pick_folder() {
+                                Ok(Some(dir)) => setup.config.rom_dir = Some(dir),
+                                Ok(None) => {}
+                                Err(e) => println!("no folder dialog: {}", e),
+                            }
+                        }
+                        if !setup.next() {
+                            break 'setup;
+                        }
+                    }
+                    _ => {}
+                },
+                _ => {}
+            }
+        }
+
+        if setup.title() != title {
+            title = setup.title();
+            canvas
+                .window_mut()
+                .set_title(&title)
+                .map_err(|e| sdl_error("cannot set the window title", e))?;
+        }
+        let wanted = window_size(m.framebuffer(), false, false, setup.config.scale);
+        if wanted != size {
+            size = wanted;
+            canvas
+                .window_mut()
+                .set_size(size.0, size.1)
+                .map_err(|e| sdl_error("cannot resize the window", e))?;
+        }
+        screen.palette = *setup.config.colours();
+
+        // the demo starts over when it stops
+        if m.run_for(u64::from(CPU_IPS) / 60).is_err() {
+            restart(&mut m, &program, &[], false).map_err(|e| io::Error::other(e.to_string()))?;
+        }
+        render(
+            &mut canvas,
+            &mut screen,
+            m.framebuffer(),
+            None,
+            None,
+            None,
+            &Plugins::new(),
+            Instant::now(),
+        );
+        thread::sleep(FRAME_PERIOD);
+    }
+    *config = setup.config;
+    Ok(true)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn setup_steps() {
+        let mut setup = Setup {
+            step: 0,
+            config: Config::default(),
+        };
+        assert!(setup
+            .title()
+            .starts_with("Setup 1/4: keyboard layout < qwerty > (keypad on QWER)"));
+        setup.change(-1);
+        assert_eq!("scancodes", setup.config.keymap.layout);
+        setup.change(-1);
+        assert_eq!("dvorak", setup.config.keymap.layout);
+        assert_eq!(Some(0x5), setup.config.keymap.key(","));
+        setup.change(3);
+        assert_eq!("azerty", setup.config.keymap.layout);
+
+        assert!(setup.next());
+        assert_eq!(Step::Scale, setup.step());
+        setup.change(-3);
+        assert_eq!(7, setup.config.scale);
+        setup.change(100);
+        assert_eq!(MAX_SCALE, setup.config.scale);
+        assert!(setup.title().contains("< 30x >"));
+
+        assert!(setup.next());
+        setup.change(1);
+        assert_eq!("amber", setup.config.palette_name());
+        setup.change(-2);
+        assert_eq!("octo", setup.config.palette_name());
+
+        assert!(setup.next());
+        assert_eq!(Step::RomDir, setup.step());
+        assert!(setup.title().starts_with("Setup 4/4: ROM folder"));
+        assert!(!setup.next());
+
+        let text = setup.config.to_text();
+        assert!(text.contains("layout = azerty\n"), "{}", text);
+        assert!(text.contains("scale = 30\npalette = octo\n"), "{}", text);
+    }
+}
@@ -0,0 +1,99 @@
+// Motion smoothing, `--smooth`: a display filter cross-fading from one
+// screen to the next, for ROMs that only redraw a few times a second and
+// jump from one picture to the other. Screens coming less than
+// MIN_INTERVAL apart are shown as they are, so fast games and flickering
+// sprites don't smear; slower ones fade over half the time between them, at
+// most MAX_FADE. It only changes what the window shows: the machine, the
+// captures and the save states never see it.
+
+use std::time::{Duration, Instant};
+
+const MIN_INTERVAL: Duration = Duration::from_millis(100);
+const MAX_FADE: Duration = Duration::from_millis(200);
+
+#[derive(Default)]
+pub struct Smoothing {
+    // the screen faded from, the one faded to, and the last one shown
+    from: Vec<u8>,
+    to: Vec<u8>,
+    shown: Vec<u8>,
+    // when the screen faded to came, and the length of its fade
+    changed: Option<Instant>,
+    fade: Duration,
+}
+
+impl Smoothing {
+    pub fn new() -> Smoothing {
+        Smoothing::default()
+    }
+
+    // the RGBA pixels of the screen made the ones to show
+    pub fn apply(&mut self, rgba: &mut [u8], now: Instant) {
+        if rgba != &self.to[..] {
+            let interval = self.changed.map(|t| now.saturating_duration_since(t));
+            self.fade = match interval {
+                Some(i) if i >= MIN_INTERVAL && self.shown.len() == rgba.len() => {
+                    (i / 2).min(MAX_FADE)
+                }
+                _ => Duration::ZERO,
+            };
+            self.from = std::mem::take(&mut self.shown);
+            self.to = rgba.to_vec();
+            self.changed = Some(now);
+        }
+        if self.fading(now) {
+            let elapsed = now.saturating_duration_since(self.changed.unwrap());
+            let weight = (elapsed.as_micros() * 256 / self.fade.as_micros()) as u32;
+            for (out, (from, to)) in rgba.iter_mut().zip(self.from.iter().zip(&self.to)) {
+                let (from, to) = (u32::from(*from), u32::from(*to));
+                *out = ((from * (256 - weight) + to * weight) / 256) as u8;
+            }
+        }
+        self.shown = rgba.to_vec();
+    }
+
+    // whether the window has to be drawn again before the screen changes
+    pub fn fading(&self, now: Instant) -> bool {
+        self.changed
+            .is_some_and(|t| now.saturating_duration_since(t) < self.fade)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn smoothing_fades() {
+        let start = Instant::now();
+        let ms = Duration::from_millis;
+        let mut s = Smoothing::new();
+        let mut black = [0, 0, 0, 255];
+        s.apply(&mut black, start);
+        assert_eq!([0, 0, 0, 255], black);
+
+        // a screen 400ms later fades in over 200ms
+        let mut pixel = [200, 100, 0, 255];
+        s.apply(&mut pixel, start + ms(400));
+        assert_eq!([0, 0, 0, 255], pixel);
+        assert!(s.fading(start + ms(450)));
+        let mut pixel = [200, 100, 0, 255];
+        s.apply(&mut pixel, start + ms(500));
+        assert_eq!([100, 50, 0, 255], pixel);
+        let mut pixel = [200, 100, 0, 255];
+        s.apply(&mut pixel, start + ms(600));
+        assert_eq!([200, 100, 0, 255], pixel);
+        assert!(!s.fading(start + ms(600)));
+
+        // the next one fades from what was shown when it came
+        let mut pixel = [0, 0, 0, 255];
+        s.apply(&mut pixel, start + ms(800));
+        assert_eq!([200, 100, 0, 255], pixel);
+        assert!(s.fading(start + ms(850)));
+        // and one right after isn't faded at all
+        let mut pixel = [10, 10, 10, 255];
+        s.apply(&mut pixel, start + ms(820));
+        assert_eq!([10, 10, 10, 255], pixel);
+        assert!(!s.fading(start + ms(821)));
+    }
+}
@@ -0,0 +1,98 @@
+// On-screen keypad for touch screens: the bottom half of the window is a
+// 4x4 grid of keys laid out as the COSMAC VIP keypad (see keymap.rs), like
+// the keyboard keys. Coordinates are normalized to the window, 0 to 1
+// across, as SDL reports touches.
+//
+// Several fingers can hold keys at once, and a finger sliding from key to
+// key releases the first and presses the next.
+
+use super::keymap::COSMAC;
+
+// part of the window height taken by the keypad
+pub const KEYPAD_SHARE: f32 = 0.5;
+const COLUMNS: u8 = 4;
+
+// the key under a point, if any
+pub fn key_at(x: f32, y: f32) -> Option<u8> {
+    let top = 1.0 - KEYPAD_SHARE;
+    if !(0.0..1.0).contains(&x) || !(top..1.0).contains(&y) {
+        return None;
+    }
+    let col = (x * f32::from(COLUMNS)) as u8;
+    let row = ((y - top) / KEYPAD_SHARE * f32::from(COLUMNS)) as u8;
+    let cell = row.min(COLUMNS - 1) * COLUMNS + col.min(COLUMNS - 1);
+    Some(COSMAC[usize::from(cell)])
+}
+
+#[derive(Debug, Default)]
+pub struct TouchKeypad {
+    // the key under each finger
+    fingers: Vec<(i64, u8)>,
+}
+
+impl TouchKeypad {
+    // the key pressed by a finger touching the screen
+    pub fn down(&mut self, finger: i64, x: f32, y: f32) -> Option<u8> {
+        let key = key_at(x, y)?;
+        self.fingers.push((finger, key));
+        Some(key)
+    }
+
+    // the key released by a finger leaving the screen, unless another
+    // finger still holds it
+    pub fn up(&mut self, finger: i64) -> Option<u8> {
+        let i = self.fingers.iter().position(|f| f.0 == finger)?;
+        let (_, key) = self.fingers.remove(i);
+        Some(key).filter(|k| !self.held(*k))
+    }
+
+    // (released, pressed) keys of a moving finger
+    pub fn motion(&mut self, finger: i64, x: f32, y: f32) -> (Option<u8>, Option<u8>) {
+        let old = self.fingers.iter().find(|f| f.0 == finger).map(|f| f.1);
+        if old == key_at(x, y) {
+            return (None, None);
+        }
+        (self.up(finger), self.down(finger, x, y))
+    }
+
+    pub fn held(&self, key: u8) -> bool {
+        self.fingers.iter().any(|f| f.1 == key)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn touch_key_at() {
+        // the screen
+        assert_eq!(None, key_at(0.5, 0.2));
+        assert_eq!(Some(0x1), key_at(0.0, 0.5));
+        assert_eq!(Some(0xC), key_at(0.9, 0.6));
+        assert_eq!(Some(0xA), key_at(0.1, 0.99));
+        assert_eq!(Some(0xF), key_at(0.99, 0.99));
+        assert_eq!(None, key_at(1.0, 0.9));
+        assert_eq!(None, key_at(-0.1, 0.9));
+    }
+
+    #[test]
+    fn touch_fingers() {
+        let mut keypad = TouchKeypad::default();
+        assert_eq!(Some(5), keypad.down(1, 0.3, 0.7));
+        assert_eq!(Some(5), keypad.down(2, 0.4, 0.7));
+        // still held by finger 2
+        assert_eq!(None, keypad.up(1));
+        assert!(keypad.held(5));
+
+        // sliding right
+        assert_eq!((None, None), keypad.motion(2, 0.45, 0.7));
+        assert_eq!((Some(5), Some(6)), keypad.motion(2, 0.6, 0.7));
+        // and off the keypad
+        assert_eq!((Some(6), None), keypad.motion(2, 0.6, 0.3));
+        assert_eq!(None, keypad.up(2));
+
+        assert_eq!(None, keypad.down(3, 0.5, 0.1));
+        assert_eq!(None, keypad.up(3));
+    }
+}
@@ -0,0 +1,228 @@
+// Watchdog for the window: the main loop beats as it goes through its
+// stages (events, emulation, rendering), and a thread checks that the beats
+// keep coming. When they stop for FREEZE_TIMEOUT (the loop stuck in the
+// interpreter, in SDL or in a driver) it logs the stage the loop was in and
+// the last instruction started; if the loop starts again it logs the
+// recovery, and after SHUTDOWN_TIMEOUT it ends the process (exit code 5),
+// since a stuck thread can't be stopped.
+//
+// The file dialog blocks the loop for as long as the user browses, so the
+// time spent in it doesn't count.
+
+use crate::{exit, trace};
+use std::process;
+use std::sync::atomic::{AtomicBool, AtomicU32, AtomicU64, AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+const PERIOD: Duration = Duration::from_millis(500);
+const FREEZE_TIMEOUT: Duration = Duration::from_secs(5);
+const SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(30);
+// no instruction started yet
+const NO_OPCODE: u32 = u32::MAX;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Stage {
+    Events,
+    Emulation,
+    Render,
+    Dialog,
+}
+
+const STAGES: [Stage; 4] = [
+    Stage::Events,
+    Stage::Emulation,
+    Stage::Render,
+    Stage::Dialog,
+];
+
+impl Stage {
+    fn name(self) -> &'static str {
+        match self {
+            Stage::Events => "handling events",
+            Stage::Emulation => "running the program",
+            Stage::Render => "rendering",
+            Stage::Dialog => "in the file dialog",
+        }
+    }
+}
+
+#[derive(Default)]
+struct Beats {
+    count: AtomicU64,
+    stage: AtomicUsize,
+    pc: AtomicUsize,
+    opcode: AtomicU32,
+    stop: AtomicBool,
+}
+
+impl Beats {
+    // the last instruction started, e.g. `0x2A4: D015  DRW V0, V1, 5`
+    fn instruction(&self) -> String {
+        let pc = self.pc.load(Ordering::Relaxed);
+        match self.opcode.load(Ordering::Relaxed) {
+            NO_OPCODE => String::from("none"),
+            word => trace::listing(pc, word as u16),
+        }
+    }
+}
+
+// what the watchdog makes of the beats
+#[derive(Debug, PartialEq)]
+enum Verdict {
+    Alive,
+    // no beat for that long, reported once
+    Frozen(Duration),
+    // beating again after that long
+    Recovered(Duration),
+    Shutdown(Duration),
+}
+
+struct Monitor {
+    count: u64,
+    since: Instant,
+    frozen: bool,
+}
+
+impl Monitor {
+    fn new(now: Instant) -> Monitor {
+        Monitor {
+            count: 0,
+            since: now,
+            frozen: false,
+        }
+    }
+
+    fn observe(&mut self, count: u64, stage: Stage, now: Instant) -> Verdict {
+        let stalled = now.duration_since(self.since);
+        if count != self.count || stage == Stage::Dialog {
+            self.count = count;
+            self.since = now;
+            if std::mem::replace(&mut self.frozen, false) {
+                return Verdict::Recovered(stalled);
+            }
+            return Verdict::Alive;
+        }
+        if stalled >= SHUTDOWN_TIMEOUT {
+            Verdict::Shutdown(stalled)
+        } else if stalled >= FREEZE_TIMEOUT && !self.frozen {
+            self.frozen = true;
+            Verdict::Frozen(stalled)
+        } else {
+            Verdict::Alive
+        }
+    }
+}
+
+pub struct Watchdog {
+    beats: Arc<Beats>,
+    thread: Option<thread::JoinHandle<()>>,
+}
+
+impl Watchdog {
+    pub fn start() -> Watchdog {
+        let beats = Arc::new(Beats::default());
+        beats.opcode.store(NO_OPCODE, Ordering::Relaxed);
+        let watched = Arc::clone(&beats);
+        let thread = thread::spawn(move || {
+            let mut monitor = Monitor::new(Instant::now());
+            while !watched.stop.load(Ordering::Relaxed) {
+                thread::park_timeout(PERIOD);
+                let stage = STAGES[watched.stage.load(Ordering::Relaxed)];
+                let count = watched.count.load(Ordering::Relaxed);
+                match monitor.observe(count, stage, Instant::now()) {
+                    Verdict::Alive => {}
+                    Verdict::Frozen(d) => eprintln!(
+                        "watchdog: no progress for {:.1?} {}, last instruction {}",
+                        d,
+                        stage.name(),
+                        watched.instruction()
+                    ),
+                    Verdict::Recovered(d) => eprintln!("watchdog: recovered after {:.1?}", d),
+                    Verdict::Shutdown(d) => {
+                        eprintln!(
+                            "watchdog: no progress for {:.1?} {}, last instruction {}: giving up",
+                            d,
+                            stage.name(),
+                            watched.instruction()
+                        );
+                        process::exit(exit::FROZEN);
+                    }
+                }
+            }
+        });
+        Watchdog {
+            beats,
+            thread: Some(thread),
+        }
+    }
+
+    pub fn enter(&self, stage: Stage) {
+        let index = STAGES.iter().position(|s| *s == stage).unwrap_or(0);
+        self.beats.stage.store(index, Ordering::Relaxed);
+        self.beats.count.fetch_add(1, Ordering::Relaxed);
+    }
+
+    // an instruction about to run
+    pub fn instruction(&self, pc: usize, opcode: Option<u16>) {
+        self.beats.pc.store(pc, Ordering::Relaxed);
+        self.beats
+            .opcode
+            .store(opcode.map_or(NO_OPCODE, u32::from), Ordering::Relaxed);
+        self.enter(Stage::Emulation);
+    }
+}
+
+impl Drop for Watchdog {
+    fn drop(&mut self) {
+        self.beats.stop.store(true, Ordering::Relaxed);
+        if let Some(thread) = self.thread.take() {
+            thread.thread().unpark();
+            let _ = thread.join();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn watchdog_monitor() {
+        let start = Instant::now();
+        let at = |secs: u64| start + Duration::from_secs(secs);
+        let mut monitor = Monitor::new(start);
+
+        assert_eq!(Verdict::Alive, monitor.observe(1, Stage::Events, at(1)));
+        assert_eq!(Verdict::Alive, monitor.observe(1, Stage::Render, at(4)));
+        // reported once
+        assert_eq!(
+            Verdict::Frozen(Duration::from_secs(5)),
+            monitor.observe(1, Stage::Render, at(6))
+        );
+        assert_eq!(Verdict::Alive, monitor.observe(1, Stage::Render, at(8)));
+        assert_eq!(
+            Verdict::Recovered(Duration::from_secs(9)),
+            monitor.observe(2, Stage::Events, at(10))
+        );
+
+        // the dialog may stay open
+        assert_eq!(Verdict::Alive, monitor.observe(3, Stage::Dialog, at(11)));
+        assert_eq!(Verdict::Alive, monitor.observe(3, Stage::Dialog, at(60)));
+        assert_eq!(
+            Verdict::Shutdown(Duration::from_secs(30)),
+            monitor.observe(3, Stage::Emulation, at(90))
+        );
+    }
+
+    #[test]
+    fn watchdog_instruction() {
+        let watchdog = Watchdog::start();
+        assert_eq!("none", watchdog.beats.instruction());
+        watchdog.instruction(0x2A4, Some(0x7101));
+        assert_eq!("0x2A4: 7101  ADD V1, 0x01", watchdog.beats.instruction());
+        assert_eq!(1, watchdog.beats.count.load(Ordering::Relaxed));
+        assert_eq!(1, watchdog.beats.stage.load(Ordering::Relaxed));
+    }
+}
@@ -0,0 +1,156 @@
+// ROM fuzzer: run many randomly mutated copies of a real ROM headless, with
+// random input, looking for panics, invalid states and hangs in the
+// interpreter. Mutant N is derived from `seed + N` only, so any failure can
+// be reproduced with the same seed.
+
+use crate::exit;
+use crate::soak::{run_parallel, run_session, summarize, Outcome, Report};
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::thread;
+
+const DEFAULT_MUTANTS: usize = 500;
+const DEFAULT_CYCLES: usize = 10_000;
+const DEFAULT_MUTATIONS: usize = 4;
+
+struct Options {
+    mutants: usize,
+    cycles: usize,
+    mutations: usize,
+    threads: usize,
+    seed: u64,
+    // where failing mutants are written
+    out: Option<PathBuf>,
+    rom: String,
+}
+
+fn parse_args(args: &[String]) -> Result<Options, String> {
+    let mut opts = Options {
+        mutants: DEFAULT_MUTANTS,
+        cycles: DEFAULT_CYCLES,
+        mutations: DEFAULT_MUTATIONS,
+        threads: thread::available_parallelism().map_or(1, |n| n.get()),
+        seed: rand::thread_rng().gen(),
+        out: None,
+        rom: String::new(),
+    };
+
+    let mut it = args.iter();
+    while let Some(arg) = it.next() {
+        let mut value = |name: &str| it.next().ok_or(format!("missing value for {}", name));
+        let mut number = |name: &str| -> Result<u64, String> {
+            let v = value(name)?;
+            v.parse::<u64>()
+                .map_err(|e| format!("invalid value `{}` for {}: {}", v, name, e))
+        };
+        match arg.as_str() {
+            "--mutants" => opts.mutants = number(arg)? as usize,
+            "--cycles" => opts.cycles = number(arg)? as usize,
+            "--mutations" => opts.mutations = (number(arg)? as usize).max(1),
+            "--threads" => opts.threads = (number(arg)? as usize).max(1),
+            "--seed" => opts.seed = number(arg)?,
+            "--out" => opts.out = Some(PathBuf::from(value(arg)?)),
+            s if s.starts_with("--") => return Err(format!("unknown option `{}`", s)),
+            rom if opts.rom.is_empty() => opts.rom = rom.to_string(),
+            s => return Err(format!("unexpected argument `{}`", s)),
+        }
+    }
+
+    if opts.rom.is_empty() {
+        return Err(String::from("no ROM given"));
+    }
+    Ok(opts)
+}
+
+// apply `mutations` random edits: byte replacement, bit flip or a swap
+// with another byte (the size never changes)
+pub fn mutate(program: &[u8], seed: u64, mutations: usize) -> Vec<u8> {
+    let mut rng = StdRng::seed_from_u64(seed);
+    let mut mutant = program.to_vec();
+    if mutant.is_empty() {
+        return mutant;
+    }
+    for _ in 0..mutations {
+        let i = rng.gen_range(0..mutant.len());
+        match rng.gen_range(0..3) {
+            0 => mutant[i] = rng.gen(),
+            1 => mutant[i] ^= 1 << rng.gen_range(0..8),
+            _ => {
+                let j = rng.gen_range(0..mutant.len());
+                mutant.swap(i, j);
+            }
+        }
+    }
+    mutant
+}
+
+pub fn run(args: &[String]) -> io::Result<()> {
+    let opts = parse_args(args).map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+    let program = fs::read(&opts.rom).map_err(|e| exit::rom_load(&opts.rom, e))?;
+
+    println!(
+        "fuzz: {} mutants of {} x {} cycles, {} mutations each, {} threads, seed {}",
+        opts.mutants, opts.rom, opts.cycles, opts.mutations, opts.threads, opts.seed
+    );
+
+    let shared = program.clone();
+    let (base, mutations, cycles) = (opts.seed, opts.mutations, opts.cycles);
+    let outcomes = run_parallel(opts.mutants, opts.threads, move |i, heartbeat| {
+        let seed = base.wrapping_add(i as u64);
+        let mutant = mutate(&shared, seed, mutations);
+        run_session(&mutant, cycles, seed, heartbeat)
+    })
+    .map_err(|hang| exit::check_failed(format!("HANG {}", hang)))?;
+
+    let stem = Path::new(&opts.rom)
+        .file_stem()
+        .map_or(String::from("rom"), |s| s.to_string_lossy().into_owned());
+    let mut reports = Vec::new();
+    for (i, outcome) in outcomes.into_iter().enumerate() {
+        let seed = opts.seed.wrapping_add(i as u64);
+        let mut rom = format!("{}#{}", stem, seed);
+        if let (Some(dir), Outcome::Failed(_, _)) = (&opts.out, &outcome) {
+            fs::create_dir_all(dir)?;
+            let path = dir.join(format!("{}-{}.rom", stem, seed));
+            fs::write(&path, mutate(&program, seed, opts.mutations))?;
+            rom = path.to_string_lossy().into_owned();
+        }
+        reports.push(Report { rom, seed, outcome });
+    }
+
+    summarize("fuzz", &reports)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn mutate_is_deterministic() {
+        let program: Vec<u8> = (0..64).collect();
+        let a = mutate(&program, 7, 4);
+        assert_eq!(a, mutate(&program, 7, 4));
+        assert_eq!(program.len(), a.len());
+        assert_ne!(program, a);
+        assert_ne!(a, mutate(&program, 8, 4));
+        assert!(mutate(&[], 7, 4).is_empty());
+    }
+
+    #[test]
+    fn fuzz_parse_args() {
+        let args: Vec<String> = ["--mutants", "10", "--out", "crashes", "pong.rom"]
+            .iter()
+            .map(|s| s.to_string())
+            .collect();
+        let opts = parse_args(&args).unwrap();
+        assert_eq!(10, opts.mutants);
+        assert_eq!(Some(PathBuf::from("crashes")), opts.out);
+        assert_eq!("pong.rom", opts.rom);
+
+        assert!(parse_args(&[]).is_err());
+        assert!(parse_args(&args[..1]).is_err());
+    }
+}
@@ -0,0 +1,161 @@
+// Game over detection heuristics. A program is considered over when:
+//
+// - it spins on a jump to itself (the usual way CHIP-8 programs halt)
+// - an annotated `game over` flag becomes non zero
+// - an annotated `lives` counter drops to zero after being positive
+//
+// The detector reports a game over once, until it is reset.
+
+use crate::annotations::{Annotation, Annotations};
+use crate::Machine;
+use std::fmt;
+
+// cycles spent on a jump to itself before calling it a halt
+const HALT_CYCLES: u32 = 16;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum GameOver {
+    HaltLoop(usize),
+    Flag(String),
+    NoLives,
+}
+
+impl fmt::Display for GameOver {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            GameOver::HaltLoop(pc) => write!(f, "halted at {:#05X}", pc),
+            GameOver::Flag(name) => write!(f, "`{}` is set", name),
+            GameOver::NoLives => write!(f, "no lives left"),
+        }
+    }
+}
+
+pub struct GameOverDetector {
+    flags: Vec<Annotation>,
+    lives: Option<Annotation>,
+    had_lives: bool,
+    halt_cycles: u32,
+    reported: bool,
+}
+
+fn is_flag_name(name: &str) -> bool {
+    let name: String = name
+        .chars()
+        .filter(|c| c.is_alphanumeric())
+        .collect::<String>()
+        .to_ascii_lowercase();
+    name == "gameover"
+}
+
+impl GameOverDetector {
+    pub fn new(annotations: Option<&Annotations>) -> GameOverDetector {
+        let list: &[Annotation] = annotations.map_or(&[], |a| &a.list);
+        GameOverDetector {
+            flags: list
+                .iter()
+                .filter(|a| is_flag_name(&a.name))
+                .cloned()
+                .collect(),
+            lives: list
+                .iter()
+                .find(|a| a.name.eq_ignore_ascii_case("lives"))
+                .cloned(),
+            had_lives: false,
+            halt_cycles: 0,
+            reported: false,
+        }
+    }
+
+    // call after every executed instruction
+    pub fn update(&mut self, m: &Machine) -> Option<GameOver> {
+        let reason = self.check(m)?;
+        if self.reported {
+            return None;
+        }
+        self.reported = true;
+        Some(reason)
+    }
+
+    fn check(&mut self, m: &Machine) -> Option<GameOver> {
+        if let Some(flag) = self.flags.iter().find(|a| a.read(m) != 0) {
+            return Some(GameOver::Flag(flag.name.clone()));
+        }
+
+        if let Some(lives) = &self.lives {
+            if lives.read(m) > 0 {
+                self.had_lives = true;
+            } else if self.had_lives {
+                return Some(GameOver::NoLives);
+            }
+        }
+
+        let jump_to_self = m.pc + 1 < m.memory.len()
+            && u16::from(m.memory[m.pc]) << 8 | u16::from(m.memory[m.pc + 1])
+                == 0x1000 | m.pc as u16;
+        if jump_to_self {
+            self.halt_cycles += 1;
+            if self.halt_cycles >= HALT_CYCLES {
+                return Some(GameOver::HaltLoop(m.pc));
+            }
+        } else {
+            self.halt_cycles = 0;
+        }
+        None
+    }
+
+    // re-arm after a new game starts
+    pub fn reset(&mut self) {
+        self.had_lives = false;
+        self.halt_cycles = 0;
+        self.reported = false;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn machine(program: Vec<u8>) -> Machine {
+        let mut m = Machine::new();
+        m.init();
+        m.load_program(program).unwrap();
+        m
+    }
+
+    #[test]
+    fn gameover_halt_loop() {
+        let mut m = machine(vec![
+            0x60, 0x01, // V0 = 1
+            0x12, 0x02, // jump 0x202
+        ]);
+        let mut d = GameOverDetector::new(None);
+        let mut reports = Vec::new();
+        for _ in 0..100 {
+            m.exec_single().unwrap();
+            reports.extend(d.update(&m));
+        }
+        assert_eq!(vec![GameOver::HaltLoop(0x202)], reports);
+    }
+
+    #[test]
+    fn gameover_annotations() {
+        let mut m = machine(vec![0x12, 0x00]);
+        let a = Annotations::parse("Game Over: [0x300]\nlives: V1").unwrap();
+        let mut d = GameOverDetector::new(Some(&a));
+
+        // no lives seen yet
+        assert_eq!(None, d.update(&m));
+        m.registers[1] = 3;
+        assert_eq!(None, d.update(&m));
+        m.registers[1] = 0;
+        assert_eq!(Some(GameOver::NoLives), d.update(&m));
+        assert_eq!(None, d.update(&m));
+
+        d.reset();
+        m.memory[0x300] = 1;
+        assert_eq!(
+            Some(GameOver::Flag(String::from("Game Over"))),
+            d.update(&m)
+        );
+    }
+}
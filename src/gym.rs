@@ -0,0 +1,238 @@
+// Gym-like environment around the machine, for reinforcement learning:
+//
+//     let reward = Reward::parse("score")?;
+//     let mut env = Env::new(rom, Some(annotations), Some(reward));
+//     let mut obs = env.reset();
+//     loop {
+//         let step = env.step(policy(&obs));
+//         if step.done { break; }
+//         obs = step.observation;
+//     }
+//
+// The reward of a step is the weighted change of annotated values over it,
+// e.g. `score - 10 * lives` rewards points and punishes lost lives.
+
+use crate::agent::KeyMask;
+use crate::annotations::Annotations;
+use crate::gameover::GameOverDetector;
+use crate::scheduler::TimerClock;
+use crate::{Machine, CPU_IPS};
+
+pub const DEFAULT_CYCLES_PER_STEP: usize = 8;
+
+// weighted annotated values
+#[derive(Debug, Clone, PartialEq)]
+pub struct Reward {
+    terms: Vec<(String, f64)>,
+}
+
+impl Reward {
+    // "score", "score - 10 * lives", "0.5 * hits + score"
+    pub fn parse(text: &str) -> Result<Reward, String> {
+        let mut terms = Vec::new();
+        let normalized = text.replace('-', "+ -");
+        for term in normalized
+            .split('+')
+            .map(str::trim)
+            .filter(|t| !t.is_empty())
+        {
+            let (sign, term) = match term.strip_prefix('-') {
+                Some(t) => (-1.0, t.trim()),
+                None => (1.0, term),
+            };
+            let (weight, name) = match term.split_once('*') {
+                Some((w, n)) => (
+                    w.trim()
+                        .parse::<f64>()
+                        .map_err(|_| format!("invalid weight `{}`", w.trim()))?,
+                    n.trim(),
+                ),
+                None => (1.0, term),
+            };
+            if name.is_empty() {
+                return Err(format!("missing value name in `{}`", term));
+            }
+            terms.push((name.to_string(), sign * weight));
+        }
+        if terms.is_empty() {
+            return Err(String::from("empty reward"));
+        }
+        Ok(Reward { terms })
+    }
+
+    fn value(&self, values: &[(&str, u32)]) -> f64 {
+        self.terms
+            .iter()
+            .map(|(name, w)| {
+                let v = values
+                    .iter()
+                    .find(|(n, _)| n == name)
+                    .map_or(0, |(_, v)| *v);
+                w * f64::from(v)
+            })
+            .sum()
+    }
+}
+
+pub struct Step {
+    // framebuffer after the step, 0 or 1 per pixel
+    pub observation: Vec<u8>,
+    pub reward: f64,
+    // the episode is over (game over, halt, or step limit)
+    pub done: bool,
+}
+
+pub struct Env {
+    program: Vec<u8>,
+    annotations: Option<Annotations>,
+    reward: Option<Reward>,
+    pub cycles_per_step: usize,
+    pub max_steps: Option<u64>,
+
+    machine: Machine,
+    clock: TimerClock,
+    game_over: GameOverDetector,
+    steps: u64,
+    last_reward_value: f64,
+    done: bool,
+}
+
+impl Env {
+    pub fn new(program: Vec<u8>, annotations: Option<Annotations>, reward: Option<Reward>) -> Env {
+        let game_over = GameOverDetector::new(annotations.as_ref());
+        let mut env = Env {
+            program,
+            annotations,
+            reward,
+            cycles_per_step: DEFAULT_CYCLES_PER_STEP,
+            max_steps: None,
+            machine: Machine::new(),
+            clock: TimerClock::new(CPU_IPS),
+            game_over,
+            steps: 0,
+            last_reward_value: 0.0,
+            done: false,
+        };
+        env.reset();
+        env
+    }
+
+    // restart the program, returning the first observation
+    pub fn reset(&mut self) -> Vec<u8> {
+        self.machine.init();
+        // a program that doesn't fit ends every episode at once
+        self.done = self.machine.load_program(self.program.clone()).is_err();
+        self.clock = TimerClock::new(CPU_IPS);
+        self.game_over.reset();
+        self.steps = 0;
+        self.last_reward_value = self.reward_value();
+        self.machine.gfx.pixels().to_vec()
+    }
+
+    fn reward_value(&self) -> f64 {
+        match (&self.reward, &self.annotations) {
+            (Some(r), Some(a)) => r.value(&a.values(&self.machine)),
+            _ => 0.0,
+        }
+    }
+
+    // hold `action` for one step
+    pub fn step(&mut self, action: KeyMask) -> Step {
+        if !self.done {
+            self.machine.set_keypad(action);
+            for _ in 0..self.cycles_per_step {
+                let alive = self.machine.exec_single().is_ok();
+                for _ in 0..self.clock.advance(1) {
+                    self.machine.tick_timers();
+                }
+                if !alive || self.game_over.update(&self.machine).is_some() {
+                    self.done = true;
+                    break;
+                }
+            }
+            self.steps += 1;
+            if self.max_steps.is_some_and(|max| self.steps >= max) {
+                self.done = true;
+            }
+        }
+
+        let value = self.reward_value();
+        let reward = value - self.last_reward_value;
+        self.last_reward_value = value;
+        Step {
+            observation: self.machine.gfx.pixels().to_vec(),
+            reward,
+            done: self.done,
+        }
+    }
+
+    pub fn machine(&self) -> &Machine {
+        &self.machine
+    }
+
+    pub fn steps(&self) -> u64 {
+        self.steps
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reward_parse() {
+        let r = Reward::parse("score - 10 * lives + 0.5*hits").unwrap();
+        assert_eq!(
+            vec![
+                (String::from("score"), 1.0),
+                (String::from("lives"), -10.0),
+                (String::from("hits"), 0.5)
+            ],
+            r.terms
+        );
+        assert!(Reward::parse("").is_err());
+        assert!(Reward::parse("x * score").is_err());
+        assert!(Reward::parse("2 *").is_err());
+    }
+
+    #[test]
+    fn env_rewards_annotated_score() {
+        let program = vec![
+            0x60, 0x05, // V0 = 5
+            0xE0, 0xA1, // skip if key V0 not pressed
+            0x71, 0x01, // V1 += 1
+            0x31, 0x04, // skip if V1 == 4
+            0x12, 0x02, // jump 0x202
+            0x12, 0x0A, // halt
+        ];
+        let a = Annotations::parse("score: V1").unwrap();
+        let mut env = Env::new(program, Some(a), Some(Reward::parse("score").unwrap()));
+        env.cycles_per_step = 4;
+
+        let obs = env.reset();
+        assert_eq!(crate::GFX_WIDTH * crate::GFX_HEIGHT, obs.len());
+
+        let mut total = 0.0;
+        let mut steps = 0;
+        loop {
+            let step = env.step(1 << 5);
+            total += step.reward;
+            steps += 1;
+            if step.done || steps > 100 {
+                break;
+            }
+        }
+        assert_eq!(4.0, total);
+        assert!(steps < 100);
+
+        // no reward once done
+        assert_eq!(0.0, env.step(0).reward);
+
+        env.reset();
+        env.max_steps = Some(3);
+        assert!(!env.step(0).done);
+        assert!(!env.step(0).done);
+        assert!(env.step(0).done);
+        assert_eq!(0, env.machine().registers[1]);
+    }
+}
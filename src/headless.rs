@@ -0,0 +1,561 @@
+// Headless run: the interpreter without a window, for SSH sessions and CI.
+//
+//     chip8 --headless [--tui] [--max-cycles N] [--dump-gfx FILE]
+//                      [--quirks LIST] [--cosmac] [--cpu-hz N] [--replay FILE]
+//                      [--overlay ADDR:FILE]... [--limits LIST]
+//                      [--capture-at cycle:N] [--capture-frames N]
+//                      [--capture-path TEMPLATE] [--annotations FILE] ROM
+//
+// The program runs as fast as it can until it halts (an invalid opcode, a
+// jump to itself, a loop its whole state keeps coming back around, see
+// idle.rs, or waiting for a key that can't come), or for
+// `--max-cycles` instructions, then the screen is printed in Unicode
+// blocks, two pixel rows per line. `--tui` (which implies `--headless`)
+// redraws it in the terminal as the program runs, at `--cpu-hz` like the
+// window. There's no keypad input, but `--replay` plays the keys of an
+// input recording made in the window (see replay.rs), with its quirks and
+// speed, so a reported bug runs the same way in CI.
+//
+// `--dump-gfx` writes the final screen in the format of the selftest
+// `.expected` files (`#` for a lit pixel, see selftest.rs), for CI jobs to
+// compare. `--capture-at cycle:N` saves a burst of frames as PNGs (see
+// capture.rs), with the game values named by `--annotations` (see
+// annotations.rs). A program halting on an invalid opcode ends with exit code 3.
+// Built with the `alloc-audit` feature, the heap allocations made by the
+// interpreter are reported per frame (see alloc_audit.rs). A panic leaves
+// a crash dump (see crash.rs).
+
+use crate::alloc_audit::{self, Audit};
+use crate::annotations::Annotations;
+use crate::capture::{self, Capture, Trigger};
+use crate::clock::{Clock, SystemClock};
+use crate::crash::CrashDump;
+use crate::framebuffer::FrameBuffer;
+use crate::gameover::GameOverDetector;
+use crate::idle::{LoopDetector, Wait};
+use crate::limits::Limits;
+use crate::quirks::{self, Quirks};
+use crate::replay::{Recording, Replay};
+use crate::scheduler::{Scheduler, TimerClock};
+use crate::{exit, selftest, text, utils, Chip8Error, Machine, CPU_IPS, TIMER_HZ};
+use std::convert::TryFrom;
+use std::fs;
+use std::io::{self, Write};
+use std::path::Path;
+use std::time::Duration;
+
+const FRAME_PERIOD: Duration = Duration::from_nanos(1_000_000_000 / 60);
+// back to the top left corner, to draw over the previous frame
+const HOME: &str = "\x1b[H";
+const CLEAR: &str = "\x1b[2J";
+
+#[derive(Debug)]
+struct Options {
+    tui: bool,
+    max_cycles: Option<u64>,
+    dump_gfx: Option<String>,
+    quirks: Quirks,
+    cpu_hz: u32,
+    replay: Option<String>,
+    // data loaded over or after the ROM, at their address
+    overlays: Vec<(usize, String)>,
+    // execution limits, for ROMs that can't be trusted
+    limits: Limits,
+    // frames saved as PNGs from a cycle on
+    capture_at: Option<Trigger>,
+    capture_frames: u32,
+    capture_path: String,
+    // game values saved with the captured frames
+    annotations: Option<String>,
+    rom: String,
+}
+
+fn parse_args(args: &[String]) -> Result<Options, String> {
+    let mut opts = Options {
+        tui: false,
+        max_cycles: None,
+        dump_gfx: None,
+        quirks: Quirks::default(),
+        cpu_hz: CPU_IPS,
+        replay: None,
+        overlays: Vec::new(),
+        limits: Limits::default(),
+        capture_at: None,
+        capture_frames: capture::DEFAULT_FRAMES,
+        capture_path: String::from(capture::DEFAULT_TEMPLATE),
+        annotations: None,
+        rom: String::new(),
+    };
+    let mut rom = None;
+
+    let mut it = args.iter();
+    while let Some(arg) = it.next() {
+        let mut value = |name: &str| it.next().ok_or(format!("missing value for {}", name));
+        match arg.as_str() {
+            "--headless" => {}
+            "--tui" => opts.tui = true,
+            "--max-cycles" => {
+                let v = value(arg)?;
+                opts.max_cycles = Some(
+                    v.parse()
+                        .map_err(|e| format!("invalid value `{}` for --max-cycles: {}", v, e))?,
+                );
+            }
+            "--dump-gfx" => opts.dump_gfx = Some(value(arg)?.clone()),
+            "--replay" => opts.replay = Some(value(arg)?.clone()),
+            "--quirks" => opts.quirks = Quirks::parse(value(arg)?)?,
+            "--cosmac" => {
+                opts.quirks = Quirks::parse("vip")?;
+                opts.cpu_hz = quirks::VIP_CPU_HZ;
+            }
+            "--limits" => opts.limits = Limits::parse(value(arg)?)?,
+            "--capture-at" => match Trigger::parse(value(arg)?)? {
+                Trigger::Breakpoint => {
+                    return Err(String::from("there are no breakpoints in --headless"))
+                }
+                trigger => opts.capture_at = Some(trigger),
+            },
+            "--capture-frames" => {
+                let v = value(arg)?;
+                opts.capture_frames = v
+                    .parse()
+                    .ok()
+                    .filter(|n| *n > 0)
+                    .ok_or(format!("invalid frame count `{}`", v))?;
+            }
+            "--capture-path" => opts.capture_path = value(arg)?.clone(),
+            "--annotations" => opts.annotations = Some(value(arg)?.clone()),
+            "--overlay" => opts.overlays.push(utils::parse_overlay(value(arg)?)?),
+            "--cpu-hz" => {
+                let v = value(arg)?;
+                opts.cpu_hz = v
+                    .parse()
+                    .ok()
+                    .filter(|hz| *hz > 0)
+                    .ok_or(format!("invalid speed `{}`", v))?;
+            }
+            s if s.starts_with("--") => return Err(format!("unknown option `{}`", s)),
+            path if rom.is_none() => rom = Some(path.to_string()),
+            s => return Err(format!("unexpected argument `{}`", s)),
+        }
+    }
+
+    opts.rom = rom.ok_or_else(|| {
+        String::from(
+            "usage: chip8 --headless [--tui] [--max-cycles N] [--dump-gfx FILE] \
+             [--quirks LIST] [--cosmac] [--cpu-hz N] [--replay FILE] [--overlay ADDR:FILE]... [--limits LIST] \
+             [--capture-at cycle:N] [--capture-frames N] [--capture-path TEMPLATE] \
+             [--annotations FILE] ROM",
+        )
+    })?;
+    Ok(opts)
+}
+
+// the screen in Unicode half blocks, two rows per line
+pub fn blocks(fb: &FrameBuffer) -> String {
+    text::half_blocks(fb.width(), fb.height(), |x, y| fb.pixel(x, y) > 0)
+}
+
+// run a machine with its program loaded as --headless does, without
+// input: the line telling how it ended, and the machine as it was left
+pub fn run_machine(m: Machine, max_cycles: Option<u64>) -> (String, Machine) {
+    let mut run = Run::new(m, CPU_IPS, max_cycles, None);
+    let end = loop {
+        if let Some(end) = run.steps(u64::from(CPU_IPS)) {
+            break end;
+        }
+    };
+    (run.summary(&end), run.m)
+}
+
+// why a run stopped
+#[derive(Debug, PartialEq)]
+enum End {
+    // the interpreter stopped on an error
+    Halted(Chip8Error),
+    // a jump to itself
+    Spinning(usize),
+    // the same state every this many seconds
+    Looping(u64),
+    WaitingForKey,
+    MaxCycles,
+}
+
+struct Run {
+    m: Machine,
+    clock: TimerClock,
+    halt: GameOverDetector,
+    loops: LoopDetector,
+    cpu_hz: u64,
+    cycles: u64,
+    max_cycles: Option<u64>,
+    // the recorded keys, pressed as their cycle comes
+    replay: Option<Replay>,
+    audit: Audit,
+    // timer ticks since the start, the frame numbers of the capture
+    frames: u64,
+    capture: Option<Capture>,
+    annotations: Option<Annotations>,
+    // published every TIMER_HZ frames of the run, for a panic
+    crash: Option<CrashDump>,
+    run_frames: u64,
+}
+
+impl Run {
+    fn new(m: Machine, cpu_hz: u32, max_cycles: Option<u64>, replay: Option<Replay>) -> Run {
+        Run {
+            m,
+            clock: TimerClock::new(cpu_hz),
+            halt: GameOverDetector::new(None),
+            loops: LoopDetector::new(),
+            cpu_hz: u64::from(cpu_hz),
+            cycles: 0,
+            max_cycles,
+            replay,
+            audit: Audit::default(),
+            frames: 0,
+            capture: None,
+            annotations: None,
+            crash: None,
+            run_frames: 0,
+        }
+    }
+
+    // how the run ended, in a line
+    fn summary(&self, end: &End) -> String {
+        match end {
+            End::Halted(e) => format!("halted after {} cycles: {}", self.cycles, e),
+            End::Spinning(pc) => format!("halted at {:#05X} after {} cycles", pc, self.cycles),
+            End::Looping(seconds) => format!(
+                "looping after {} cycles, the same state every {} s",
+                self.cycles, seconds
+            ),
+            End::WaitingForKey => format!("waiting for a key after {} cycles", self.cycles),
+            End::MaxCycles => format!("stopped after {} cycles", self.cycles),
+        }
+    }
+
+    // a frame of up to `n` instructions, counting its allocations
+    fn frame(&mut self, n: u64) -> Option<End> {
+        let before = alloc_audit::allocations();
+        let end = self.steps(n);
+        self.audit.frame(alloc_audit::allocations() - before);
+        self.run_frames += 1;
+        if let Some(crash) = self.crash.as_ref() {
+            if self.run_frames.is_multiple_of(u64::from(TIMER_HZ)) {
+                crash.publish(&self.m, u32::try_from(self.cpu_hz).unwrap_or(u32::MAX));
+            }
+        }
+        end
+    }
+
+    // the frame to the capture, which is dropped when it can't be saved
+    fn capture_frame(&mut self) {
+        let capture = match self.capture.as_mut() {
+            Some(c) => c,
+            None => return,
+        };
+        let m = &self.m;
+        let values = self
+            .annotations
+            .as_ref()
+            .map_or(String::new(), |a| a.summary(m));
+        let frame = capture.frame(
+            self.frames,
+            self.cycles,
+            self.m.framebuffer(),
+            &capture::DEFAULT_PALETTE,
+            &values,
+        );
+        if let Err(e) = frame {
+            println!("cannot save frame {}: {}", self.frames, e);
+            self.capture = None;
+        }
+    }
+
+    // run up to `n` instructions, none when the program is still going
+    fn steps(&mut self, n: u64) -> Option<End> {
+        for _ in 0..n {
+            if self.max_cycles.is_some_and(|max| self.cycles >= max) {
+                return Some(End::MaxCycles);
+            }
+            if let Some(replay) = self.replay.as_mut() {
+                for e in replay.due(self.cycles) {
+                    self.m.set_key_state(e.key, e.pressed);
+                    self.loops.reset();
+                }
+            }
+            // nobody can press one
+            if self.m.waiting() == Some(Wait::Key)
+                && self.replay.as_ref().is_none_or(Replay::finished)
+            {
+                return Some(End::WaitingForKey);
+            }
+            if let Err(e) = self.m.exec_single() {
+                return Some(End::Halted(e));
+            }
+            self.cycles += 1;
+            if self.halt.update(&self.m).is_some() {
+                return Some(End::Spinning(self.m.pc));
+            }
+            for _ in 0..self.clock.advance(1) {
+                self.m.tick_timers();
+                self.frames += 1;
+                self.capture_frame();
+            }
+            // keys still to come can break the loop
+            if self.cycles.is_multiple_of(self.cpu_hz)
+                && self.replay.as_ref().is_none_or(Replay::finished)
+            {
+                if let Some(found) = self.loops.sample(&self.m) {
+                    return Some(End::Looping(found.samples));
+                }
+            }
+        }
+        None
+    }
+}
+
+pub fn run(args: &[String]) -> io::Result<()> {
+    let mut opts = parse_args(args).map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+    let replay = match &opts.replay {
+        Some(path) => {
+            let recording = Recording::load(Path::new(path)).map_err(|e| {
+                io::Error::new(
+                    e.kind(),
+                    format!("cannot load the inputs `{}`: {}", path, e),
+                )
+            })?;
+            opts.quirks = recording.quirks;
+            opts.cpu_hz = recording.cpu_hz;
+            Some(Replay::new(recording))
+        }
+        None => None,
+    };
+
+    let mut m = Machine::new();
+    m.set_quirks(opts.quirks);
+    m.set_limits(opts.limits);
+    m.init();
+    m.load_program_file(&opts.rom)
+        .map_err(|e| exit::rom_load(&opts.rom, e))?;
+    for (address, file) in &opts.overlays {
+        let bytes = fs::read(file).map_err(|e| exit::rom_load(file, e))?;
+        m.load_at(*address, &bytes)
+            .map_err(|e| exit::rom_load(file, io::Error::new(io::ErrorKind::InvalidData, e)))?;
+    }
+    if let Some(recording) = replay.as_ref().map(Replay::recording) {
+        if recording.program_hash != m.program_hash() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("{} isn't the ROM the inputs were recorded with", opts.rom),
+            ));
+        }
+        m.seed_rng(recording.seed);
+    }
+    let mut run = Run::new(m, opts.cpu_hz, opts.max_cycles, replay);
+    let crash = CrashDump::install();
+    crash.publish(&run.m, opts.cpu_hz);
+    run.crash = Some(crash);
+    run.capture = opts.capture_at.map(|trigger| {
+        Capture::new(
+            &opts.capture_path,
+            &capture::rom_name(&opts.rom),
+            opts.capture_frames,
+            Some(trigger),
+        )
+    });
+    if let Some(path) = &opts.annotations {
+        let a = Annotations::load(Path::new(path)).map_err(|e| {
+            io::Error::new(
+                e.kind(),
+                format!("cannot load annotations `{}`: {}", path, e),
+            )
+        })?;
+        run.annotations = Some(a);
+    }
+
+    let stdout = io::stdout();
+    let end = if opts.tui {
+        let mut out = stdout.lock();
+        write!(out, "{}{}", CLEAR, HOME)?;
+        let clock = SystemClock;
+        let mut scheduler = Scheduler::new(opts.cpu_hz, FRAME_PERIOD, clock.now());
+        loop {
+            let end = run.frame(scheduler.batch(clock.now()) as u64);
+            if run.m.framebuffer_mut().take_dirty() || end.is_some() {
+                write!(out, "{}{}", HOME, blocks(run.m.framebuffer()))?;
+                out.flush()?;
+            }
+            if let Some(end) = end {
+                break end;
+            }
+            clock.sleep(scheduler.sleep_time(clock.now()));
+        }
+    } else {
+        let frame = u64::from(opts.cpu_hz / TIMER_HZ).max(1);
+        let end = loop {
+            if let Some(end) = run.frame(frame) {
+                break end;
+            }
+        };
+        print!("{}", blocks(run.m.framebuffer()));
+        end
+    };
+
+    println!("{}", run.summary(&end));
+    if cfg!(feature = "alloc-audit") {
+        println!("{}", run.audit);
+    }
+    if let Some(path) = &opts.dump_gfx {
+        fs::write(path, selftest::screen_text(&run.m))?;
+    }
+    match end {
+        End::Halted(Chip8Error::InvalidOpcode { pc, opcode }) => {
+            Err(exit::invalid_opcode(pc, opcode))
+        }
+        End::Halted(e @ Chip8Error::LimitReached { .. }) => Err(exit::limit_reached(e.to_string())),
+        _ => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(list: &[&str]) -> Vec<String> {
+        list.iter().map(|s| s.to_string()).collect()
+    }
+
+    #[test]
+    fn headless_parse_args() {
+        let opts = parse_args(&args(&[
+            "--headless",
+            "--max-cycles",
+            "500",
+            "--dump-gfx",
+            "out.txt",
+            "pong.ch8",
+        ]))
+        .unwrap();
+        assert!(!opts.tui);
+        assert_eq!(Some(500), opts.max_cycles);
+        assert_eq!(Some(String::from("out.txt")), opts.dump_gfx);
+        assert_eq!("pong.ch8", opts.rom);
+
+        let opts = parse_args(&args(&["--tui", "--quirks", "schip", "pong.ch8"])).unwrap();
+        assert!(opts.tui);
+        assert!(opts.quirks.jump_vx);
+        assert_eq!(None, opts.replay);
+        let opts = parse_args(&args(&["--cosmac", "pong.ch8"])).unwrap();
+        assert_eq!(Quirks::parse("vip").unwrap(), opts.quirks);
+        assert_eq!(quirks::VIP_CPU_HZ, opts.cpu_hz);
+
+        let opts = parse_args(&args(&[
+            "--capture-at",
+            "cycle:700",
+            "--capture-frames",
+            "10",
+            "--annotations",
+            "pong.ann",
+            "pong.ch8",
+        ]))
+        .unwrap();
+        assert_eq!(Some(Trigger::Cycle(700)), opts.capture_at);
+        assert_eq!(Some(String::from("pong.ann")), opts.annotations);
+        assert_eq!(
+            (10, capture::DEFAULT_TEMPLATE),
+            (opts.capture_frames, &opts.capture_path[..])
+        );
+        assert!(parse_args(&args(&["--capture-at", "break", "pong.ch8"])).is_err());
+        assert!(parse_args(&args(&["--capture-frames", "0", "pong.ch8"])).is_err());
+
+        let opts = parse_args(&args(&["--replay", "pong.c8r", "pong.ch8"])).unwrap();
+        assert_eq!(Some(String::from("pong.c8r")), opts.replay);
+
+        let opts = parse_args(&args(&[
+            "--overlay",
+            "0x600:a.bin",
+            "--overlay",
+            "0x800:b.bin",
+            "pong.ch8",
+        ]))
+        .unwrap();
+        assert_eq!(
+            vec![
+                (0x600, String::from("a.bin")),
+                (0x800, String::from("b.bin"))
+            ],
+            opts.overlays
+        );
+        assert!(parse_args(&args(&["--overlay", "a.bin", "pong.ch8"])).is_err());
+
+        let opts = parse_args(&args(&["--limits", "cycles=100,timeout=1", "pong.ch8"])).unwrap();
+        assert_eq!(Some(100), opts.limits.cycles);
+        assert!(parse_args(&args(&["--limits", "cycles=x", "pong.ch8"])).is_err());
+
+        assert!(parse_args(&args(&["--headless"])).is_err());
+        assert!(parse_args(&args(&["--max-cycles", "x", "pong.ch8"])).is_err());
+        assert!(parse_args(&args(&["--cpu-hz", "0", "pong.ch8"])).is_err());
+        assert!(parse_args(&args(&["a.ch8", "b.ch8"])).is_err());
+    }
+
+    #[test]
+    fn headless_blocks_and_ends() {
+        let start = |program: Vec<u8>, max_cycles| {
+            let mut m = Machine::new();
+            m.init();
+            m.load_program(program).unwrap();
+            Run::new(m, CPU_IPS, max_cycles, None)
+        };
+
+        // I = "0" glyph; draw it at (0, 0); spin
+        let mut run = start(vec![0xA0, 0x00, 0xD0, 0x05, 0x12, 0x04], None);
+        assert_eq!(Some(End::Spinning(0x204)), run.steps(u64::MAX));
+        let text = blocks(run.m.framebuffer());
+        let lines: Vec<&str> = text.lines().collect();
+        assert_eq!(16, lines.len());
+        assert_eq!(64, lines[0].chars().count());
+        // rows 0-1: ****, *..*; rows 2-3: *..*, *..*; row 4: ****
+        assert!(lines[0].starts_with("█▀▀█ "), "{}", lines[0]);
+        assert!(lines[1].starts_with("█  █ "), "{}", lines[1]);
+        assert!(lines[2].starts_with("▀▀▀▀ "), "{}", lines[2]);
+
+        let mut run = start(vec![0x12, 0x00], Some(10));
+        // 16 cycles on the jump make a halt, cut short
+        assert_eq!(Some(End::MaxCycles), run.steps(u64::MAX));
+        assert_eq!(10, run.cycles);
+
+        // wait for a key
+        let mut run = start(vec![0xF0, 0x0A], None);
+        assert_eq!(Some(End::WaitingForKey), run.steps(u64::MAX));
+
+        // two jumps to each other: the state of the 1st second comes back
+        let mut run = start(vec![0x12, 0x02, 0x12, 0x00], None);
+        assert_eq!(Some(End::Looping(1)), run.steps(u64::MAX));
+        assert_eq!(2 * u64::from(CPU_IPS), run.cycles);
+        assert_eq!(
+            "looping after 1400 cycles, the same state every 1 s",
+            run.summary(&End::Looping(1))
+        );
+
+        let mut run = start(vec![0xF0, 0xFF], None);
+        assert_eq!(
+            Some(End::Halted(Chip8Error::InvalidOpcode {
+                pc: 0x200,
+                opcode: 0xF0FF
+            })),
+            run.steps(u64::MAX)
+        );
+
+        // the key comes from the recording: wait for it into V0, then spin
+        let mut run = start(vec![0xF0, 0x0A, 0x12, 0x02], None);
+        let mut recording = Recording::new(run.m.program_hash(), 0, Quirks::default(), CPU_IPS);
+        recording.push(50, 0x7, true);
+        run.replay = Some(Replay::new(recording));
+        assert_eq!(Some(End::Spinning(0x202)), run.steps(u64::MAX));
+        assert_eq!(7, run.m.registers[0]);
+        // the key comes after 50 cycles, then 15 more on the jump
+        assert_eq!(51 + 15, run.cycles);
+    }
+}
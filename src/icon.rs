@@ -0,0 +1,159 @@
+// The program icon: "C8" in the interpreter's own font, lit pixels on a
+// dark rounded square. The pixels are worked out at compile time (`ICON`),
+// the window sets them at startup, and `chip8 icon` writes them as PNGs for
+// the packaging (the desktop entry, the macOS bundle):
+//
+//     chip8 icon [--size N] out.png
+//
+// N is 64 (the icon itself) or a multiple of it, scaled up pixel for pixel.
+
+use crate::{png, FONT};
+use std::fs;
+use std::io;
+
+// width and height
+pub const SIZE: usize = 64;
+// window pixels per font pixel
+const SCALE: usize = 6;
+// the two glyphs, font pixels apart
+const GLYPHS: [usize; 2] = [0xC, 0x8];
+const GAP: usize = 1;
+const LEFT: usize = (SIZE - (GLYPHS.len() * (4 + GAP) - GAP) * SCALE) / 2;
+const TOP: usize = (SIZE - 5 * SCALE) / 2;
+const CORNER: usize = 10;
+const BACKGROUND: [u8; 4] = [24, 24, 24, 255];
+const LIT: [u8; 4] = [255, 176, 0, 255];
+
+const fn lit(x: usize, y: usize) -> bool {
+    if x < LEFT || y < TOP || y >= TOP + 5 * SCALE {
+        return false;
+    }
+    let (column, row) = ((x - LEFT) / SCALE, (y - TOP) / SCALE);
+    let glyph = column / (4 + GAP);
+    let bit = column % (4 + GAP);
+    if glyph >= GLYPHS.len() || bit >= 4 {
+        return false;
+    }
+    FONT[GLYPHS[glyph]][row] & (0x80 >> bit) != 0
+}
+
+// outside the rounded corners
+const fn clear(x: usize, y: usize) -> bool {
+    let dx = if x < CORNER {
+        CORNER - x
+    } else if x >= SIZE - CORNER {
+        x + 1 - (SIZE - CORNER)
+    } else {
+        0
+    };
+    let dy = if y < CORNER {
+        CORNER - y
+    } else if y >= SIZE - CORNER {
+        y + 1 - (SIZE - CORNER)
+    } else {
+        0
+    };
+    dx * dx + dy * dy > CORNER * CORNER
+}
+
+const fn generate() -> [u8; SIZE * SIZE * 4] {
+    let mut rgba = [0; SIZE * SIZE * 4];
+    let mut i = 0;
+    while i < SIZE * SIZE {
+        let (x, y) = (i % SIZE, i / SIZE);
+        let colour = if clear(x, y) {
+            [0; 4]
+        } else if lit(x, y) {
+            LIT
+        } else {
+            BACKGROUND
+        };
+        let mut c = 0;
+        while c < 4 {
+            rgba[i * 4 + c] = colour[c];
+            c += 1;
+        }
+        i += 1;
+    }
+    rgba
+}
+
+// SIZE x SIZE RGBA pixels
+pub const ICON: [u8; SIZE * SIZE * 4] = generate();
+
+// the icon `factor` times as large
+pub fn scaled(factor: usize) -> Vec<u8> {
+    let size = SIZE * factor;
+    let mut rgba = Vec::with_capacity(size * size * 4);
+    for y in 0..size {
+        for x in 0..size {
+            let i = (y / factor * SIZE + x / factor) * 4;
+            rgba.extend_from_slice(&ICON[i..i + 4]);
+        }
+    }
+    rgba
+}
+
+pub fn run(args: &[String]) -> io::Result<()> {
+    let usage = || {
+        io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "usage: chip8 icon [--size N] out.png",
+        )
+    };
+    let mut size = SIZE;
+    let mut out = None;
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--size" => {
+                let v = args.next().ok_or_else(usage)?;
+                size = v
+                    .parse()
+                    .ok()
+                    .filter(|s| *s > 0 && s % SIZE == 0)
+                    .ok_or_else(|| {
+                        io::Error::new(
+                            io::ErrorKind::InvalidInput,
+                            format!("invalid size `{}` (a multiple of {})", v, SIZE),
+                        )
+                    })?;
+            }
+            path if out.is_none() && !path.starts_with("--") => out = Some(path),
+            _ => return Err(usage()),
+        }
+    }
+    let out = out.ok_or_else(usage)?;
+    fs::write(out, png::encode(&scaled(size / SIZE), size, size))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pixel(rgba: &[u8], size: usize, x: usize, y: usize) -> [u8; 4] {
+        let i = (y * size + x) * 4;
+        [rgba[i], rgba[i + 1], rgba[i + 2], rgba[i + 3]]
+    }
+
+    #[test]
+    fn icon_pixels() {
+        // the corners are see-through, the middle is the background
+        assert_eq!([0; 4], pixel(&ICON, SIZE, 0, 0));
+        assert_eq!([0; 4], pixel(&ICON, SIZE, SIZE - 1, SIZE - 1));
+        assert_eq!(BACKGROUND, pixel(&ICON, SIZE, SIZE / 2, 2));
+        // the top left pixel of the C, and the hole of its opening
+        assert_eq!(LIT, pixel(&ICON, SIZE, LEFT, TOP));
+        assert_eq!(
+            BACKGROUND,
+            pixel(&ICON, SIZE, LEFT + SCALE, TOP + 2 * SCALE)
+        );
+        // the top right pixel of the 8
+        let eight = LEFT + (4 + GAP) * SCALE;
+        assert_eq!(LIT, pixel(&ICON, SIZE, eight + 3 * SCALE, TOP));
+
+        let big = scaled(2);
+        assert_eq!(SIZE * SIZE * 16, big.len());
+        assert_eq!(LIT, pixel(&big, SIZE * 2, LEFT * 2 + 1, TOP * 2 + 1));
+    }
+}
@@ -0,0 +1,185 @@
+// Idle detection: recognise when the program can't do anything but wait,
+// so the frontend can sleep on the event queue instead of spinning.
+//
+// Loop detection: a machine is deterministic, so once its whole state comes
+// back with no input in between, it goes around the same loop forever. The
+// state hash is sampled at the same phase of the timer clock each time
+// (every `cpu_hz` cycles, when the timers have ticked 60 times) and checked
+// against a checkpoint moved at doubling intervals (Brent's algorithm), so
+// loops of any length are caught for a hash per emulated second.
+
+use crate::Machine;
+
+#[derive(Debug, PartialEq)]
+pub enum Wait {
+    // FX0A with no key down
+    Key,
+    // a loop polling the delay timer until it reaches 0:
+    //     FX07; 3X00; 1NNN (back to FX07)
+    Delay,
+}
+
+// a state seen again, on the checkpoint sample
+#[derive(Debug, PartialEq)]
+pub struct Loop {
+    // samples since the checkpoint: the length of the loop
+    pub samples: u64,
+}
+
+pub struct LoopDetector {
+    checkpoint: Option<u64>,
+    since: u64,
+    interval: u64,
+}
+
+impl Default for LoopDetector {
+    fn default() -> LoopDetector {
+        LoopDetector::new()
+    }
+}
+
+impl LoopDetector {
+    pub fn new() -> LoopDetector {
+        LoopDetector {
+            checkpoint: None,
+            since: 0,
+            interval: 1,
+        }
+    }
+
+    // call every `cpu_hz` cycles; a key changing in between needs a reset
+    pub fn sample(&mut self, m: &Machine) -> Option<Loop> {
+        let hash = m.state_hash();
+        self.since += 1;
+        if self.checkpoint == Some(hash) {
+            return Some(Loop {
+                samples: self.since,
+            });
+        }
+        if self.checkpoint.is_none() || self.since == self.interval {
+            self.checkpoint = Some(hash);
+            self.since = 0;
+            self.interval *= 2;
+        }
+        None
+    }
+
+    pub fn reset(&mut self) {
+        *self = LoopDetector::new();
+    }
+}
+
+impl Machine {
+    fn word_at(&self, address: usize) -> Option<u16> {
+        let hi = *self.memory.get(address)?;
+        let lo = *self.memory.get(address + 1)?;
+        Some(u16::from(hi) << 8 | u16::from(lo))
+    }
+
+    fn delay_loop_at(&self, start: usize) -> bool {
+        let (read, skip, jump) = match (
+            self.word_at(start),
+            self.word_at(start + 2),
+            self.word_at(start + 4),
+        ) {
+            (Some(a), Some(b), Some(c)) => (a, b, c),
+            _ => return false,
+        };
+        let x = read & 0x0F00;
+        read & 0xF0FF == 0xF007 && skip == 0x3000 | x && jump == 0x1000 | start as u16
+    }
+
+    // what the program is provably waiting for, if anything
+    pub fn waiting(&self) -> Option<Wait> {
+        let opcode = self.word_at(self.pc)?;
+        if opcode & 0xF0FF == 0xF00A && self.keys.values().all(|v| *v == 0) {
+            return Some(Wait::Key);
+        }
+        // the pc can be on any of the three instructions of the loop
+        let in_delay_loop = (0..3)
+            .filter_map(|i| self.pc.checked_sub(i * 2))
+            .any(|start| self.delay_loop_at(start));
+        if in_delay_loop && self.delay_timer > 0 {
+            return Some(Wait::Delay);
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn idle_key_wait() {
+        let mut m = Machine::new();
+        m.init();
+        m.load_program_hex("6001 F30A").unwrap();
+        assert_eq!(None, m.waiting());
+        m.exec_single().unwrap();
+        assert_eq!(Some(Wait::Key), m.waiting());
+        m.set_key_state(4, true);
+        assert_eq!(None, m.waiting());
+    }
+
+    #[test]
+    fn idle_delay_loop() {
+        let mut m = Machine::new();
+        m.init();
+        // DT = 3; loop: V2 = DT; skip if V2 == 0; jump loop
+        m.load_program_hex("6003 F015 F207 3200 1204 6101").unwrap();
+        m.exec_single().unwrap();
+        m.exec_single().unwrap();
+        m.delay_timer = 3;
+        let mut waits = 0;
+        while m.pc < 0x20A {
+            if m.waiting() == Some(Wait::Delay) {
+                waits += 1;
+            }
+            m.exec_single().unwrap();
+            m.delay_timer = m.delay_timer.saturating_sub(1);
+        }
+        assert!(waits >= 3);
+        assert_eq!(None, m.waiting());
+
+        // a loop on another register doesn't count
+        m.init();
+        m.load_program_hex("F207 3300 1200").unwrap();
+        m.delay_timer = 10;
+        assert_eq!(None, m.waiting());
+    }
+
+    #[test]
+    fn idle_loop_detection() {
+        let mut m = Machine::new();
+        m.init();
+        // count V0 up to 3, then V1 goes around 1..5 for ever
+        m.load_program_hex("7001 3003 1200 7101 3105 1206 6100 1206")
+            .unwrap();
+        let mut loops = LoopDetector::new();
+        let mut samples = 0;
+        let found = loop {
+            // a sample every few instructions stands in for every second
+            for _ in 0..4 {
+                m.exec_single().unwrap();
+            }
+            samples += 1;
+            if let Some(found) = loops.sample(&m) {
+                break found;
+            }
+            assert!(samples < 100, "no loop found");
+        };
+        assert_eq!(3, m.registers[0]);
+        // 4 instructions a sample, around a loop of 3, 4 or 5 instructions
+        assert!(found.samples <= 15, "{:?}", found);
+
+        // a counter never repeats
+        m.init();
+        m.load_program_hex("7001 7101 1200").unwrap();
+        loops.reset();
+        for _ in 0..100 {
+            m.exec_single().unwrap();
+            assert_eq!(None, loops.sample(&m));
+        }
+    }
+}
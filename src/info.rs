@@ -0,0 +1,305 @@
+// What a ROM file is, without running it:
+//
+//     chip8 info [--db FILE] [--variant NAME] ROM...
+//     chip8 info --capabilities
+//
+// prints its size, its hash (the one state files, aliases and recordings
+// know it by), the instructions of the later interpreters it uses, its
+// first instructions and its entry in the ROM database. The instructions
+// are found by reading the ROM as code from 0x200, so sprites and other
+// data that happen to look like them make false hints: they're hints, with
+// where each was first seen. With `--variant`, a quirks profile, the ones
+// that interpreter lacks are warned of (see capabilities.rs, which
+// `--capabilities` prints the matrix of).
+//
+// The database is a text file, `$XDG_DATA_HOME/chip8/roms.txt` unless
+// `--db` gives another, with a ROM per line, its hash then what's known
+// about it:
+//
+//     0123456789abcdef Pong (1990, Paul Vervalin), keypad 1/4 and C/D
+
+use crate::aliases::Aliases;
+use crate::capabilities::{self, Capabilities};
+use crate::disasm::{self, Syntax};
+use crate::exit;
+use crate::utils::{self, data_dir};
+use crate::{CHIP8_MAX_PROGRAM_SIZE, PROGRAM_START_ADDRESS};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+// instructions disassembled
+const FIRST: usize = 8;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Variant {
+    SuperChip,
+    XoChip,
+}
+
+impl Variant {
+    pub fn name(self) -> &'static str {
+        match self {
+            Variant::SuperChip => "SUPER-CHIP",
+            Variant::XoChip => "XO-CHIP",
+        }
+    }
+}
+
+// the instructions of each later interpreter: mask, pattern, name
+const EXTENSIONS: [(u16, u16, &str, Variant); 16] = [
+    (0xFFF0, 0x00C0, "00CN", Variant::SuperChip),
+    (0xFFFF, 0x00FB, "00FB", Variant::SuperChip),
+    (0xFFFF, 0x00FC, "00FC", Variant::SuperChip),
+    (0xFFFF, 0x00FD, "00FD", Variant::SuperChip),
+    (0xFFFF, 0x00FE, "00FE", Variant::SuperChip),
+    (0xFFFF, 0x00FF, "00FF", Variant::SuperChip),
+    (0xF00F, 0xD000, "DXY0", Variant::SuperChip),
+    (0xF0FF, 0xF030, "FX30", Variant::SuperChip),
+    (0xF0FF, 0xF075, "FX75", Variant::SuperChip),
+    (0xF0FF, 0xF085, "FX85", Variant::SuperChip),
+    (0xFFF0, 0x00D0, "00DN", Variant::XoChip),
+    (0xF00F, 0x5002, "5XY2", Variant::XoChip),
+    (0xF00F, 0x5003, "5XY3", Variant::XoChip),
+    (0xF0FF, 0xF001, "FN01", Variant::XoChip),
+    (0xFFFF, 0xF002, "F002", Variant::XoChip),
+    (0xF0FF, 0xF03A, "FX3A", Variant::XoChip),
+];
+
+// the names of the instructions `variant` adds
+pub fn instructions(variant: Variant) -> Vec<&'static str> {
+    let mut names: Vec<&str> = EXTENSIONS
+        .iter()
+        .filter(|e| e.3 == variant)
+        .map(|e| e.2)
+        .collect();
+    if variant == Variant::XoChip {
+        names.push("F000");
+    }
+    names
+}
+
+// an instruction of a later interpreter found in a ROM
+#[derive(Debug, Clone, PartialEq)]
+pub struct Hint {
+    pub variant: Variant,
+    pub name: &'static str,
+    // where it's first seen, and how many times
+    pub address: usize,
+    pub count: usize,
+}
+
+// the later instructions in `program`, in the order they're first seen
+pub fn hints(program: &[u8]) -> Vec<Hint> {
+    let mut hints: Vec<Hint> = Vec::new();
+    let mut found = |variant, name, address| match hints.iter_mut().find(|h| h.name == name) {
+        Some(hint) => hint.count += 1,
+        None => hints.push(Hint {
+            variant,
+            name,
+            address,
+            count: 1,
+        }),
+    };
+    let mut offset = 0;
+    while offset + 1 < program.len() {
+        let address = PROGRAM_START_ADDRESS + offset;
+        let word = u16::from(program[offset]) << 8 | u16::from(program[offset + 1]);
+        offset += 2;
+        // F000 NNNN takes the word after it
+        if word == 0xF000 {
+            found(Variant::XoChip, "F000", address);
+            offset += 2;
+            continue;
+        }
+        if let Some(e) = EXTENSIONS.iter().find(|e| word & e.0 == e.1) {
+            found(e.3, e.2, address);
+        }
+    }
+    hints
+}
+
+pub fn database_path() -> Option<PathBuf> {
+    data_dir().map(|dir| dir.join("chip8").join("roms.txt"))
+}
+
+// what the database says of the ROM with that hash
+pub fn lookup(database: &str, hash: u64) -> Option<&str> {
+    database
+        .lines()
+        .map(str::trim)
+        .filter(|l| !l.is_empty() && !l.starts_with('#'))
+        .find_map(|line| {
+            let (key, entry) = line.split_once(char::is_whitespace)?;
+            let key = key.trim_start_matches("0x");
+            (u64::from_str_radix(key, 16).ok()? == hash).then(|| entry.trim())
+        })
+}
+
+// the report of a ROM, `database` the text of the database if there's one,
+// `variant` the interpreter it's checked against if any
+pub fn describe(
+    name: &str,
+    program: &[u8],
+    database: Option<&str>,
+    variant: Option<&Capabilities>,
+) -> String {
+    let hash = utils::fnv1a(utils::FNV_OFFSET, program);
+    let mut text = format!("{}\n", name);
+    text.push_str(&format!("  size: {} bytes", program.len()));
+    if program.len() > CHIP8_MAX_PROGRAM_SIZE {
+        text.push_str(" (past 0xFFF, XO-CHIP memory)");
+    }
+    text.push_str(&format!("\n  hash: {:016x}\n", hash));
+
+    let hints = hints(program);
+    if hints.is_empty() {
+        text.push_str("  variant: CHIP-8, no later instruction found\n");
+    }
+    for variant in [Variant::SuperChip, Variant::XoChip].iter() {
+        let uses: Vec<String> = hints
+            .iter()
+            .filter(|h| h.variant == *variant)
+            .map(|h| format!("{} x{} (first at {:#05X})", h.name, h.count, h.address))
+            .collect();
+        if !uses.is_empty() {
+            text.push_str(&format!("  {}: {}\n", variant.name(), uses.join(", ")));
+        }
+    }
+    for missing in variant.map_or_else(Vec::new, |v| v.missing(&hints)) {
+        text.push_str(&format!(
+            "  warning: {} lacks {} (first at {:#05X})\n",
+            variant.map_or("", |v| v.profile),
+            missing.name,
+            missing.address
+        ));
+    }
+
+    let entry = match database {
+        Some(database) => lookup(database, hash).unwrap_or("no entry"),
+        None => "none",
+    };
+    text.push_str(&format!("  database: {}\n", entry));
+
+    text.push_str("  first instructions:\n");
+    let code = disasm::disassemble(program, Syntax::Raw, &Aliases::default());
+    for line in code.lines().take(FIRST) {
+        text.push_str(&format!("    {}\n", line));
+    }
+    text
+}
+
+pub fn run(args: &[String]) -> io::Result<()> {
+    let invalid = |e: String| io::Error::new(io::ErrorKind::InvalidInput, e);
+    let mut database_file = database_path();
+    let mut variant = None;
+    let mut roms = Vec::new();
+    let mut it = args.iter();
+    while let Some(arg) = it.next() {
+        match arg.as_str() {
+            "--db" => {
+                let path = it
+                    .next()
+                    .ok_or_else(|| invalid(String::from("missing value for --db")))?;
+                database_file = Some(PathBuf::from(path));
+            }
+            "--variant" => {
+                let name = it
+                    .next()
+                    .ok_or_else(|| invalid(String::from("missing value for --variant")))?;
+                variant = Some(Capabilities::of(name).ok_or_else(|| {
+                    invalid(format!(
+                        "unknown variant `{}` (one of {})",
+                        name,
+                        crate::quirks::Quirks::profile_names().join(", ")
+                    ))
+                })?);
+            }
+            "--capabilities" => {
+                print!("{}", capabilities::table());
+                return Ok(());
+            }
+            s if s.starts_with("--") => return Err(invalid(format!("unknown option `{}`", s))),
+            s => roms.push(s.to_string()),
+        }
+    }
+    if roms.is_empty() {
+        return Err(invalid(String::from(
+            "usage: chip8 info [--db FILE] [--variant NAME] ROM... | --capabilities",
+        )));
+    }
+
+    // a missing database is no database
+    let database = match database_file.as_deref().map(fs::read_to_string) {
+        Some(Ok(text)) => Some(text),
+        Some(Err(e)) if e.kind() == io::ErrorKind::NotFound => None,
+        Some(Err(e)) => return Err(e),
+        None => None,
+    };
+    for rom in &roms {
+        let program = fs::read(rom).map_err(|e| exit::rom_load(rom, e))?;
+        let name = Path::new(rom)
+            .file_name()
+            .map_or_else(|| rom.clone(), |n| n.to_string_lossy().into_owned());
+        print!(
+            "{}",
+            describe(&name, &program, database.as_deref(), variant.as_ref())
+        );
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn info_hints() {
+        let program = [
+            0x00, 0xFF, // 200 HIGH
+            0xF0, 0x00, 0xF0, 0x02, // 202 LD I, LONG 0xF002
+            0xD0, 0x10, // 206 DRW V0, V1, 0
+            0x00, 0xFF, // 208 HIGH
+            0x60, 0x05, // 20A LD V0, 5
+        ];
+        let found = hints(&program);
+        let names: Vec<(&str, usize, usize)> =
+            found.iter().map(|h| (h.name, h.address, h.count)).collect();
+        // the word after F000 isn't an instruction
+        assert_eq!(
+            vec![("00FF", 0x200, 2), ("F000", 0x202, 1), ("DXY0", 0x206, 1)],
+            names
+        );
+        assert!(hints(&[0x60, 0x05, 0xD0, 0x15, 0x12]).is_empty());
+    }
+
+    #[test]
+    fn info_describe() {
+        let program = [0x00, 0xE0, 0x60, 0x05, 0xF0, 0x3A];
+        let hash = utils::fnv1a(utils::FNV_OFFSET, &program);
+        let database = format!("# roms\n0x{:016x}  Beep, a test\n1234 other\n", hash);
+        assert_eq!(Some("Beep, a test"), lookup(&database, hash));
+        assert_eq!(None, lookup("zz nothing\n", hash));
+
+        let text = describe("beep.ch8", &program, Some(&database), None);
+        assert!(text.starts_with("beep.ch8\n  size: 6 bytes\n"), "{}", text);
+        assert!(text.contains(&format!("  hash: {:016x}\n", hash)));
+        assert!(text.contains("  XO-CHIP: FX3A x1 (first at 0x204)\n"));
+        assert!(text.contains("  database: Beep, a test\n"));
+        assert!(text.contains("    200: 00E0  ; CLS\n"), "{}", text);
+        assert!(describe("beep.ch8", &[0x00, 0xE0], None, None).contains("  database: none\n"));
+        assert!(describe("beep.ch8", &[0x00, 0xE0], None, None).contains("  variant: CHIP-8"));
+        assert!(!text.contains("warning"));
+
+        // checked against an interpreter without XO-CHIP
+        let schip = Capabilities::of("schip");
+        let text = describe("beep.ch8", &program, None, schip.as_ref());
+        assert!(
+            text.contains("  warning: schip lacks FX3A (first at 0x204)\n"),
+            "{}",
+            text
+        );
+        let xochip = Capabilities::of("xochip");
+        assert!(!describe("beep.ch8", &program, None, xochip.as_ref()).contains("warning"));
+    }
+}
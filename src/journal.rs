@@ -0,0 +1,184 @@
+// Frame hash journal: each side of a session records the state hash of every
+// frame, then compares it against the hashes reported by its peer. The first
+// mismatch is reported with its frame number and a dump of the local state.
+
+use crate::Machine;
+use std::collections::VecDeque;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Desync {
+    pub frame: u64,
+    pub local: u64,
+    pub remote: u64,
+}
+
+impl fmt::Display for Desync {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "desync at frame {}: local state {:016x}, remote state {:016x}",
+            self.frame, self.local, self.remote
+        )
+    }
+}
+
+pub struct FrameJournal {
+    capacity: usize,
+    // (frame, hash) of the most recent frames, oldest first
+    entries: VecDeque<(u64, u64)>,
+    desync: Option<Desync>,
+}
+
+impl FrameJournal {
+    pub fn new(capacity: usize) -> FrameJournal {
+        FrameJournal {
+            capacity: capacity.max(1),
+            entries: VecDeque::new(),
+            desync: None,
+        }
+    }
+
+    // record the state reached at the end of `frame`
+    pub fn record(&mut self, frame: u64, m: &Machine) -> u64 {
+        let hash = m.state_hash();
+        if self.entries.len() == self.capacity {
+            self.entries.pop_front();
+        }
+        self.entries.push_back((frame, hash));
+        hash
+    }
+
+    pub fn hash_at(&self, frame: u64) -> Option<u64> {
+        self.entries
+            .iter()
+            .rev()
+            .find(|(f, _)| *f == frame)
+            .map(|(_, h)| *h)
+    }
+
+    // compare with the hash a peer computed for `frame`; frames already
+    // dropped from the journal (or not reached yet) can't be checked
+    pub fn check(&mut self, frame: u64, remote: u64) -> Option<Desync> {
+        let local = self.hash_at(frame)?;
+        if local == remote {
+            return None;
+        }
+        let desync = Desync {
+            frame,
+            local,
+            remote,
+        };
+        // keep the earliest one, later frames are just consequences
+        if self.desync.is_none_or(|d| frame < d.frame) {
+            self.desync = Some(desync);
+        }
+        Some(desync)
+    }
+
+    // first desync detected so far
+    pub fn desync(&self) -> Option<Desync> {
+        self.desync
+    }
+
+    pub fn latest(&self) -> Option<(u64, u64)> {
+        self.entries.back().copied()
+    }
+}
+
+// write `desync-<frame>.txt` (report) and `desync-<frame>.state` (snapshot)
+// into `dir`, returning the path of the report
+pub fn write_dump(dir: &Path, desync: &Desync, m: &Machine) -> io::Result<PathBuf> {
+    let base = dir.join(format!("desync-{}", desync.frame));
+    let report = base.with_extension("txt");
+    let mut text = format!("{}\n", desync);
+    text.push_str(&format!(
+        "pc: {:#05X}\nI: {:#05X}\nstack: {:X?}\n",
+        m.pc, m.index_register, m.stack
+    ));
+    for (i, r) in m.registers.iter().enumerate() {
+        text.push_str(&format!("V{:X}: {:#04X}\n", i, r));
+    }
+    text.push_str(&format!(
+        "delay timer: {}\nsound timer: {}\n",
+        m.delay_timer, m.sound_timer
+    ));
+    fs::write(&report, text)?;
+    fs::write(base.with_extension("state"), m.snapshot())?;
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn machine() -> Machine {
+        let mut m = Machine::new();
+        m.init();
+        m.load_program(vec![
+            0x70, 0x01, // V0 += 1
+            0x12, 0x00, // jump 0x200
+        ])
+        .unwrap();
+        m
+    }
+
+    #[test]
+    fn journal_detects_first_desync() {
+        let mut a = machine();
+        let mut b = machine();
+        let mut ja = FrameJournal::new(8);
+        let mut jb = FrameJournal::new(8);
+
+        for frame in 0..6 {
+            if frame == 3 {
+                b.registers[5] = 1;
+            }
+            a.exec_single().unwrap();
+            b.exec_single().unwrap();
+            ja.record(frame, &a);
+            jb.record(frame, &b);
+        }
+
+        for frame in 0..3 {
+            assert_eq!(None, ja.check(frame, jb.hash_at(frame).unwrap()));
+        }
+        let d = ja.check(4, jb.hash_at(4).unwrap()).unwrap();
+        assert_eq!(4, d.frame);
+        ja.check(3, jb.hash_at(3).unwrap()).unwrap();
+        assert_eq!(3, ja.desync().unwrap().frame);
+    }
+
+    #[test]
+    fn journal_is_bounded() {
+        let m = machine();
+        let mut j = FrameJournal::new(4);
+        for frame in 0..10 {
+            j.record(frame, &m);
+        }
+        assert_eq!(None, j.hash_at(5));
+        assert_eq!(None, j.check(0, 0));
+        assert_eq!(Some(9), j.latest().map(|(f, _)| f));
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore)] // file system access
+    fn journal_write_dump() {
+        let m = machine();
+        let dir = std::env::temp_dir().join(format!("chip8-journal-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let d = Desync {
+            frame: 42,
+            local: 1,
+            remote: 2,
+        };
+
+        let report = write_dump(&dir, &d, &m).unwrap();
+        assert!(fs::read_to_string(&report).unwrap().contains("frame 42"));
+        assert_eq!(m.snapshot(), fs::read(dir.join("desync-42.state")).unwrap());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
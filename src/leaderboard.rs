@@ -0,0 +1,223 @@
+// Local high-score table of a ROM, kept in the `.scores` file next to it.
+// Scores come from the `score` memory annotation; the player enters three
+// initials with the keypad: 2/8 change the letter, 4/6 move, 5 confirms.
+
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+pub const EXTENSION: &str = "scores";
+pub const MAX_ENTRIES: usize = 10;
+const INITIALS: usize = 3;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Entry {
+    pub initials: String,
+    pub score: u32,
+}
+
+pub struct Leaderboard {
+    path: PathBuf,
+    pub entries: Vec<Entry>,
+}
+
+impl Leaderboard {
+    pub fn sidecar_path(program_file: &str) -> PathBuf {
+        Path::new(program_file).with_extension(EXTENSION)
+    }
+
+    // load the table, a missing file is an empty table
+    pub fn load(path: &Path) -> io::Result<Leaderboard> {
+        let text = match fs::read_to_string(path) {
+            Ok(text) => text,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => String::new(),
+            Err(e) => return Err(e),
+        };
+        let mut entries = Vec::new();
+        for (n, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() {
+                continue;
+            }
+            let entry = line
+                .split_once(' ')
+                .and_then(|(initials, score)| {
+                    Some(Entry {
+                        initials: initials.to_string(),
+                        score: score.trim().parse().ok()?,
+                    })
+                })
+                .ok_or_else(|| {
+                    io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        format!("line {}: expected `INITIALS SCORE`", n + 1),
+                    )
+                })?;
+            entries.push(entry);
+        }
+        let mut board = Leaderboard {
+            path: path.to_path_buf(),
+            entries: Vec::new(),
+        };
+        for e in entries {
+            board.insert(e);
+        }
+        Ok(board)
+    }
+
+    pub fn save(&self) -> io::Result<()> {
+        let text: String = self
+            .entries
+            .iter()
+            .map(|e| format!("{} {}\n", e.initials, e.score))
+            .collect();
+        fs::write(&self.path, text)
+    }
+
+    // would `score` make it into the table?
+    pub fn qualifies(&self, score: u32) -> bool {
+        score > 0
+            && (self.entries.len() < MAX_ENTRIES
+                || self.entries.last().is_some_and(|e| score > e.score))
+    }
+
+    // insert keeping the table sorted, returns the 1-based rank if kept
+    pub fn insert(&mut self, entry: Entry) -> Option<usize> {
+        let pos = self
+            .entries
+            .iter()
+            .position(|e| entry.score > e.score)
+            .unwrap_or(self.entries.len());
+        if pos >= MAX_ENTRIES {
+            return None;
+        }
+        self.entries.insert(pos, entry);
+        self.entries.truncate(MAX_ENTRIES);
+        Some(pos + 1)
+    }
+
+    pub fn summary(&self) -> String {
+        self.entries
+            .iter()
+            .map(|e| format!("{} {}", e.initials, e.score))
+            .collect::<Vec<_>>()
+            .join(", ")
+    }
+}
+
+// initials being entered with the keypad
+pub struct InitialsEntry {
+    letters: [u8; INITIALS],
+    cursor: usize,
+}
+
+impl Default for InitialsEntry {
+    fn default() -> InitialsEntry {
+        InitialsEntry::new()
+    }
+}
+
+impl InitialsEntry {
+    pub fn new() -> InitialsEntry {
+        InitialsEntry {
+            letters: [b'A'; INITIALS],
+            cursor: 0,
+        }
+    }
+
+    // handle a keypad key press, returns true when the initials are confirmed
+    pub fn key(&mut self, k: u8) -> bool {
+        let l = &mut self.letters[self.cursor];
+        match k {
+            0x2 => *l = if *l == b'Z' { b'A' } else { *l + 1 },
+            0x8 => *l = if *l == b'A' { b'Z' } else { *l - 1 },
+            0x4 => self.cursor = self.cursor.saturating_sub(1),
+            0x6 => self.cursor = (self.cursor + 1).min(INITIALS - 1),
+            0x5 => return true,
+            _ => {}
+        }
+        false
+    }
+
+    pub fn initials(&self) -> String {
+        self.letters.iter().map(|b| char::from(*b)).collect()
+    }
+
+    // initials with the current letter in brackets, e.g. "A[B]C"
+    pub fn prompt(&self) -> String {
+        self.letters
+            .iter()
+            .enumerate()
+            .map(|(i, b)| {
+                if i == self.cursor {
+                    format!("[{}]", char::from(*b))
+                } else {
+                    char::from(*b).to_string()
+                }
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(initials: &str, score: u32) -> Entry {
+        Entry {
+            initials: initials.to_string(),
+            score,
+        }
+    }
+
+    #[test]
+    fn leaderboard_insert_sorted_and_bounded() {
+        let mut board = Leaderboard {
+            path: PathBuf::new(),
+            entries: Vec::new(),
+        };
+        for s in 1..=MAX_ENTRIES as u32 {
+            board.insert(entry("AAA", s * 10));
+        }
+        assert_eq!(100, board.entries[0].score);
+        assert!(!board.qualifies(10));
+        assert!(board.qualifies(11));
+        assert_eq!(Some(2), board.insert(entry("BBB", 95)));
+        assert_eq!(MAX_ENTRIES, board.entries.len());
+        assert_eq!(20, board.entries.last().unwrap().score);
+        assert_eq!(None, board.insert(entry("CCC", 5)));
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore)] // file system access
+    fn leaderboard_save_load() {
+        let path = std::env::temp_dir().join(format!("chip8-{}.scores", std::process::id()));
+        let mut board = Leaderboard::load(&path).unwrap();
+        assert!(board.entries.is_empty());
+        board.insert(entry("ABC", 12));
+        board.insert(entry("XYZ", 40));
+        board.save().unwrap();
+
+        let loaded = Leaderboard::load(&path).unwrap();
+        assert_eq!(vec![entry("XYZ", 40), entry("ABC", 12)], loaded.entries);
+        assert_eq!("XYZ 40, ABC 12", loaded.summary());
+
+        fs::write(&path, "ABC twelve\n").unwrap();
+        assert!(Leaderboard::load(&path).is_err());
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn initials_entry_keys() {
+        let mut e = InitialsEntry::new();
+        assert!(!e.key(0x8)); // A -> Z
+        assert!(!e.key(0x6));
+        assert!(!e.key(0x2));
+        assert!(!e.key(0x2)); // A -> C
+        assert_eq!("Z[C]A", e.prompt());
+        assert!(!e.key(0x6));
+        assert!(!e.key(0x6));
+        assert!(e.key(0x5));
+        assert_eq!("ZCA", e.initials());
+    }
+}
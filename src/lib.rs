@@ -0,0 +1,1994 @@
+// CHIP-8 interpreter, independent of any frontend: load a program into a
+// `Machine`, run it with `exec_single`, tick its timers at the frontend's
+// rate, feed it the keypad with `set_key_state` and draw its `framebuffer`.
+// The SDL window in main.rs is one such frontend.
+//
+// references:
+// - https://multigesture.net/articles/how-to-write-an-emulator-chip-8-interpreter/
+// - https://en.wikipedia.org/wiki/CHIP-8
+// - http://devernay.free.fr/hacks/chip8/C8TECH10.HTM
+//
+// The core only does integer arithmetic, so a program runs the same, bit
+// for bit, on every platform: replays verify and netplay peers agree. The
+// lint below keeps it that way; the frontends and the tools feeding it
+// (the window, the gym rewards, the perf stats) opt out where they must.
+#![deny(clippy::float_arithmetic)]
+
+use std::collections::{HashMap, VecDeque};
+use std::error::Error;
+use std::fmt;
+use std::fs::File;
+use std::io;
+use std::io::prelude::*;
+use std::sync::Arc;
+use std::time::Instant;
+
+use clock::Clock;
+use extensions::Extension;
+use framebuffer::FrameBuffer;
+use limits::{Limit, Limits, Usage};
+use protection::{Access, MemoryMap, Violation};
+use quirks::Quirks;
+use rng::XorShiftRng;
+use scheduler::TimerClock;
+
+pub mod achievements;
+pub mod agent;
+pub mod aliases;
+pub mod alloc_audit;
+pub mod annotations;
+pub mod asm;
+pub mod audio;
+pub mod bench;
+pub mod capabilities;
+pub mod capture;
+pub mod clock;
+pub mod crash;
+pub mod debugger;
+pub mod demo;
+pub mod disasm;
+pub mod discord;
+pub mod exit;
+pub mod extensions;
+pub mod framebuffer;
+#[cfg(feature = "sdl")]
+#[allow(clippy::float_arithmetic)]
+pub mod frontend;
+pub mod fuzz;
+pub mod gameover;
+#[allow(clippy::float_arithmetic)]
+pub mod gym;
+pub mod headless;
+pub mod icon;
+pub mod idle;
+pub mod info;
+pub mod journal;
+pub mod leaderboard;
+pub mod limits;
+pub mod marquee;
+pub mod plugin;
+pub mod png;
+pub mod protection;
+pub mod quirks;
+pub mod replay;
+mod report;
+mod rng;
+pub mod scheduler;
+pub mod search;
+pub mod selftest;
+#[cfg(feature = "server")]
+pub mod server;
+pub mod snapshot;
+pub mod soak;
+pub mod text;
+pub mod timeline;
+pub mod trace;
+pub mod utils;
+pub mod watch;
+
+// global constant
+pub const GFX_WIDTH: usize = 64;
+pub const GFX_HEIGHT: usize = 32;
+// 64K, XO-CHIP; CHIP-8 and SCHIP programs only use the first 4K
+pub const MEMORY_SIZE: usize = 0x10000;
+pub const PROGRAM_START_ADDRESS: usize = 0x200;
+pub const MAX_PROGRAM_SIZE: usize = MEMORY_SIZE - PROGRAM_START_ADDRESS;
+// the most the 4K of CHIP-8 and SCHIP hold past 0x200, 3584 bytes
+pub const CHIP8_MAX_PROGRAM_SIZE: usize = 0x1000 - PROGRAM_START_ADDRESS;
+// hi-res programs start with the patched interpreter, their code is here
+const HIRES_START_ADDRESS: usize = 0x2C0;
+const STACK_DEPTH: usize = 16;
+// executed opcodes kept for the machine report
+const RECENT_OPCODES: usize = 16;
+// the 4x5 hex digits, then the 8x10 SCHIP ones
+const FONT_ADDRESS: usize = 0;
+const BIG_FONT_ADDRESS: usize = 80;
+// HP48 RPL user flags saved and loaded by FX75/FX85
+const RPL_FLAGS: usize = 16;
+// keypad keys
+const KEYS: usize = 16;
+// default emulation speed (instructions per second)
+pub const CPU_IPS: u32 = 700;
+// the delay and sound timers count down at this rate, whatever the speed
+pub const TIMER_HZ: u32 = 60;
+
+pub struct Machine {
+    // main memory
+    memory: [u8; MEMORY_SIZE],
+    registers: [u8; 16],
+    index_register: u16,
+    pc: usize,
+
+    // graphics
+    gfx: FrameBuffer,
+    // timers
+    delay_timer: u8,
+    sound_timer: u8,
+    // XO-CHIP audio: 128 1-bit samples played while the sound timer runs
+    audio_pattern: [u8; audio::PATTERN_SIZE],
+    // XO-CHIP pitch, the pattern plays at 4000 * 2 ^ ((pitch - 64) / 48) Hz
+    audio_pitch: u8,
+    // SCHIP RPL user flags
+    rpl_flags: [u8; RPL_FLAGS],
+    // stack
+    stack: Vec<usize>,
+
+    // program size and the hash of the loaded program
+    program_size: usize,
+    program_hash: u64,
+
+    // current keys press state
+    keys: HashMap<u16, u8>,
+
+    // draw flag
+    draw_flag: bool,
+    // a frame started since the last draw, for the display-wait quirk
+    vblank: bool,
+    // the key FX0A saw pressed, waiting for its release (key-release quirk)
+    key_wait: Option<u8>,
+
+    // called with the pc and the decoded opcode before each execution
+    pre_exec_hook: Option<Box<PreExecHook>>,
+
+    // optional per-page access permissions and the last access they denied
+    memory_map: Option<MemoryMap>,
+    violation: Option<Violation>,
+
+    // CXNN random numbers, seeded so runs can be reproduced
+    rng: XorShiftRng,
+
+    // interpreter specific behaviours
+    quirks: Quirks,
+
+    // (pc, opcode) of the last executed instructions, oldest first
+    recent: VecDeque<(usize, u16)>,
+
+    // the timer ticks of run_for, counted from init
+    clock: TimerClock,
+
+    // execution limits and what the program used of them
+    limits: Limits,
+    usage: Usage,
+
+    // handlers of the opcodes the interpreter doesn't know
+    extensions: Vec<Extension>,
+
+    // the time of the limits' timeout, the system's when none
+    time: Option<Arc<dyn Clock>>,
+}
+
+// what to do with an opcode inspected by a pre-execution hook
+#[derive(Debug, PartialEq)]
+pub enum HookAction {
+    Allow,
+    // don't execute it, move to the next instruction
+    Skip,
+    // execute this opcode instead
+    Replace(OpCode),
+}
+
+pub type PreExecHook = dyn FnMut(usize, &OpCode) -> HookAction + Send;
+
+// why the interpreter stopped: a program fault, or the program's own exit
+#[derive(Debug, Clone, PartialEq)]
+pub enum Chip8Error {
+    InvalidOpcode { pc: usize, opcode: u16 },
+    // the pc left the memory
+    PcOutOfRange { pc: usize },
+    // 00EE without a call
+    StackUnderflow { pc: usize },
+    // 2NNN with STACK_DEPTH calls already open
+    StackOverflow { pc: usize },
+    // an access through I past the end of the memory
+    OutOfMemory { pc: usize, address: usize },
+    // an access the memory protection denied
+    Protection(Violation),
+    // 00FD (SCHIP)
+    Exit { pc: usize },
+    // load_program with more than the memory holds past 0x200: `max`, 64K
+    // of it for XO-CHIP, 4K for CHIP-8 and SCHIP (the memory-4k quirk)
+    ProgramTooLarge { size: usize, max: usize },
+    // load_at with bytes past the end of the memory
+    OverlayTooLarge { address: usize, size: usize },
+    // one of the execution limits was reached, see limits.rs
+    LimitReached { pc: usize, limit: Limit },
+}
+
+impl fmt::Display for Chip8Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Chip8Error::InvalidOpcode { pc, opcode } => {
+                write!(f, "invalid opcode {:04X} at {:#05X}", opcode, pc)
+            }
+            Chip8Error::PcOutOfRange { pc } => write!(f, "pc out of memory at {:#05X}", pc),
+            Chip8Error::StackUnderflow { pc } => write!(f, "stack underflow at {:#05X}", pc),
+            Chip8Error::StackOverflow { pc } => write!(f, "stack overflow at {:#05X}", pc),
+            Chip8Error::OutOfMemory { pc, address } => write!(
+                f,
+                "access to {:#X}, past the end of the memory, at {:#05X}",
+                address, pc
+            ),
+            Chip8Error::Protection(v) => write!(f, "memory protection: {}", v),
+            Chip8Error::Exit { pc } => write!(f, "exited at {:#05X}", pc),
+            Chip8Error::ProgramTooLarge { size, max } if *max < MAX_PROGRAM_SIZE => write!(
+                f,
+                "{} bytes don't fit in the 4K of CHIP-8 and SCHIP: programs are {} bytes \
+                 at most, {} for XO-CHIP (--quirks xochip)",
+                size, max, MAX_PROGRAM_SIZE
+            ),
+            Chip8Error::ProgramTooLarge { size, max } => write!(
+                f,
+                "{} bytes don't fit in memory: programs are {} bytes at most",
+                size, max
+            ),
+            Chip8Error::OverlayTooLarge { address, size } => write!(
+                f,
+                "{} bytes at {:#05X} go past the end of the memory ({} at most there)",
+                size,
+                address,
+                MEMORY_SIZE.saturating_sub(*address)
+            ),
+            Chip8Error::LimitReached { pc, limit } => {
+                write!(f, "limit of {} reached at {:#05X}", limit, pc)
+            }
+        }
+    }
+}
+
+impl Error for Chip8Error {}
+
+pub enum Timer {
+    Sound,
+    Delay,
+}
+
+pub type Register = usize;
+
+// NNN: address
+// NN: 8-bit constant
+// N: 4-bit constant
+// X and Y: 4-bit register identifier
+// I : 16bit register (For memory address) (Similar to void pointer)
+// VN: One of the 16 available variables. N may be 0 to F (hexadecimal)
+// In an addition operation, VF is the carry flag, while in subtraction, it is the "no borrow" flag.
+// In the draw instruction VF is set upon pixel collision.
+// The address register, which is named I, is 16 bits wide and is used with several opcodes that involve memory operations.
+#[derive(Debug, Clone, PartialEq)]
+pub enum OpCode {
+    Clear,                            // 00E0: Clears the screen
+    Return,                           // 00EE: Returns from a subroutine
+    ScrollDown(u8),                   // 00CN: Scrolls the screen down N pixels
+    ScrollUp(u8),                     // 00DN: Scrolls the screen up N pixels
+    ScrollRight,                      // 00FB: Scrolls the screen right 4 pixels
+    ScrollLeft,                       // 00FC: Scrolls the screen left 4 pixels
+    Exit,                             // 00FD: Exits the interpreter (SCHIP)
+    LowRes,                           // 00FE: Switches to the 64x32 screen (SCHIP)
+    HighRes,                          // 00FF: Switches to the 128x64 screen (SCHIP)
+    JumpTo(u16),                      // 1NNN: Jumps to address NNN
+    Call(u16),                        // 2NNN: Calls subroutine at NNN
+    SkipEq(Register, u8), // 3XNN: Skips the next instruction if VX equals NN. (Usually the next instruction is a jump to skip a code block)
+    SkipNotEq(Register, u8), // 4XNN: Skips the next instruction if VX doesn't equal NN. (Usually the next instruction is a jump to skip a code block)
+    SkipEqXY(Register, Register), // 5XY0: Skips the next instruction if VX equals VY. (Usually the next instruction is a jump to skip a code block)
+    SaveRange(Register, Register), // 5XY2: Stores VX to VY (in reverse when X > Y) in memory starting at address I, I is left unmodified (XO-CHIP)
+    LoadRange(Register, Register), // 5XY3: Fills VX to VY (in reverse when X > Y) from memory starting at address I, I is left unmodified (XO-CHIP)
+    SetX(Register, u8),            // 6XNN: Sets VX to NN
+    AddX(Register, u8),            // 7XNN: Adds NN to VX. (Carry flag is not changed)
+    AssignXY(Register, Register),  // 8XY0: Sets VX to the value of VY
+    OrXY(Register, Register),      // 8XY1: Vx = Vx | Vy
+    AndXY(Register, Register),     // 8XY2: Vx = Vx & Vy
+    XorXY(Register, Register),     // 8XY3: Vx = Vx ^ Vy
+    AddXY(Register, Register), // 8XY4: Vx += Vy (VF is set to 1 when there's a carry, and to 0 when there isn't)
+    SubXY(Register, Register), // 8XY5: Vx -= Vy (VF is set to 0 when there's a borrow, and 1 when there isn't)
+    ShiftRightX1(Register, Register), // 8XY6: Vx >> = 1 (Stores the least significant bit of VX in VF and then shifts VX to the right by 1; VY with the shift-vy quirk)
+    SubYX(Register, Register), // 8XY7: Vx = Vy - Vx (Sets VX to VY minus VX. VF is set to 0 when there's a borrow, and 1 when there isn't)
+    ShiftLeftX1(Register, Register), // 8XYE: Vx << = 1 (Stores the most significant bit of VX in VF and then shifts VX to the left by 1; VY with the shift-vy quirk)
+    SkipNotEqXY(Register, Register), // 9XY0: Skips the next instruction if VX doesn't equal VY. (Usually the next instruction is a jump to skip a code block)
+    SetIR(u16),                      // ANNN: Sets I to the address NNN
+    LongIndex, // F000 NNNN: Sets I to the 16-bit address NNNN, the word after the opcode (XO-CHIP)
+    Flow(u16), // BNNN: PC = V0 + NNN (Jumps to the address NNN plus V0)
+    RandX(Register, u8), // CXNN: Vx = rand() & NN (Sets VX to the result of a bitwise and operation on a random number (Typically: 0 to 255) and NN)
+    Draw(Register, Register, u16), // DXYN: Draws a sprite at coordinate (Vx, Vy) that has a width of 8 pixels and a height of N+1 pixels. Each row of 8 pixels is read as bit-coded starting from memory location I. DXY0 draws a 16x16 sprite of 2 bytes per row (SCHIP)
+    KeyPressedX(Register), // EX9E: if(key() == Vx) Skips the next instruction if the key stored in VX is pressed. (Usually the next instruction is a jump to skip a code block)
+    KeyNotPressedX(Register), // EXA1: if(key() != Vx) Skips the next instruction if the key stored in VX isn't pressed. (Usually the next instruction is a jump to skip a code block)
+    TimerX(Register),         // FX07: Vx = get_delay()
+    KeyPressX(Register),      // FX0A: Vx = get_key()
+    SetDelayTimer(Register),  // FX15: delay_timer(Vx) Sets the delay timer to VX
+    SetSoundTimer(Register),  // FX18: sound_timer(Vx) Sets the sound timer to VX
+    MemAdd(Register),         // FX1E: I += Vx Adds VX to I. VF is not affected
+    SpriteX(Register), // FX29: I = sprite_addr[Vx] (Sets I to the location of the sprite for the character in VX. Characters 0-F (in hexadecimal) are represented by a 4x5 font)
+    BigSpriteX(Register), // FX30: I = big_sprite_addr[Vx] (Sets I to the 8x10 sprite of the digit in VX) (SCHIP)
+    Bcd(Register),        // FX33: set_BCD(Vx)
+    DumpX(Register),      // FX55: Stores V0 to VX (including VX) in memory starting at address I
+    SelectPlanes(u8), // FN01: Selects the display planes drawn to, bit 0 for the first plane (XO-CHIP)
+    LoadPattern,      // F002: Loads the 16 byte audio pattern from I (XO-CHIP)
+    SetPitch(Register), // FX3A: Sets the audio pitch to VX (XO-CHIP)
+    LoadX(Register), // FX65: Fills V0 to VX (including VX) with values from memory starting at address I. The offset from I is increased by 1 for each value written, but I itself is left unmodified
+    SaveFlags(Register), // FX75: Stores V0 to VX in the RPL user flags (SCHIP)
+    LoadFlags(Register), // FX85: Fills V0 to VX from the RPL user flags (SCHIP)
+    Invalid,
+}
+
+fn extract_x(opcode: u16) -> Register {
+    usize::from((opcode & 0x0F00) >> 8)
+}
+fn extract_y(opcode: u16) -> Register {
+    usize::from((opcode & 0x00F0) >> 4)
+}
+fn extract_nn(opcode: u16) -> u8 {
+    (opcode & 0x00FF) as u8
+}
+
+pub fn parse_opcode(op: Option<u16>) -> OpCode {
+    if op.is_none() {
+        return OpCode::Invalid;
+    }
+
+    let opcode = op.unwrap();
+    if opcode == 0x00E0 {
+        return OpCode::Clear;
+    }
+    if opcode == 0x00EE {
+        return OpCode::Return;
+    }
+    match opcode & 0xFFF0 {
+        0x00C0 => return OpCode::ScrollDown((opcode & 0x000F) as u8),
+        0x00D0 => return OpCode::ScrollUp((opcode & 0x000F) as u8),
+        _ => {}
+    }
+    if opcode == 0x00FB {
+        return OpCode::ScrollRight;
+    }
+    if opcode == 0x00FC {
+        return OpCode::ScrollLeft;
+    }
+    if opcode == 0x00FD {
+        return OpCode::Exit;
+    }
+    if opcode == 0x00FE {
+        return OpCode::LowRes;
+    }
+    if opcode == 0x00FF {
+        return OpCode::HighRes;
+    }
+    if opcode == 0xF000 {
+        return OpCode::LongIndex;
+    }
+    if opcode == 0xF002 {
+        return OpCode::LoadPattern;
+    }
+    if opcode == 0x0230 {
+        // hi-res CHIP-8 clear screen
+        return OpCode::Clear;
+    }
+
+    let class = (opcode & 0xF000) >> 12;
+    let selector = opcode & 0x000F;
+
+    match (class, selector) {
+        (1, _) => OpCode::JumpTo(opcode & 0x0FFF),
+        (2, _) => OpCode::Call(opcode & 0x0FFF),
+        (3, _) => OpCode::SkipEq(extract_x(opcode), extract_nn(opcode)),
+        (4, _) => OpCode::SkipNotEq(extract_x(opcode), extract_nn(opcode)),
+        (5, 0) => OpCode::SkipEqXY(extract_x(opcode), extract_y(opcode)),
+        (5, 2) => OpCode::SaveRange(extract_x(opcode), extract_y(opcode)),
+        (5, 3) => OpCode::LoadRange(extract_x(opcode), extract_y(opcode)),
+        (6, _) => OpCode::SetX(extract_x(opcode), extract_nn(opcode)),
+        (7, _) => OpCode::AddX(extract_x(opcode), extract_nn(opcode)),
+        (8, 0) => OpCode::AssignXY(extract_x(opcode), extract_y(opcode)),
+        (8, 1) => OpCode::OrXY(extract_x(opcode), extract_y(opcode)),
+        (8, 2) => OpCode::AndXY(extract_x(opcode), extract_y(opcode)),
+        (8, 3) => OpCode::XorXY(extract_x(opcode), extract_y(opcode)),
+        (8, 4) => OpCode::AddXY(extract_x(opcode), extract_y(opcode)),
+        (8, 5) => OpCode::SubXY(extract_x(opcode), extract_y(opcode)),
+        (8, 6) => OpCode::ShiftRightX1(extract_x(opcode), extract_y(opcode)),
+        (8, 7) => OpCode::SubYX(extract_x(opcode), extract_y(opcode)),
+        (8, 0xE) => OpCode::ShiftLeftX1(extract_x(opcode), extract_y(opcode)),
+        (9, 0) => OpCode::SkipNotEqXY(extract_x(opcode), extract_y(opcode)),
+        (0xA, _) => OpCode::SetIR(opcode & 0x0FFF),
+        (0xB, _) => OpCode::Flow(opcode & 0x0FFF),
+        (0xC, _) => OpCode::RandX(extract_x(opcode), extract_nn(opcode)),
+        (0xD, _) => OpCode::Draw(extract_x(opcode), extract_y(opcode), opcode & 0x000F),
+        (0xE, _) if opcode & 0x00FF == 0x9E => OpCode::KeyPressedX(extract_x(opcode)),
+        (0xE, _) if opcode & 0x00FF == 0xA1 => OpCode::KeyNotPressedX(extract_x(opcode)),
+        (0xF, _) => {
+            let sub_group = (opcode & 0x00F0) >> 4;
+            match (sub_group, selector) {
+                (0, 1) => OpCode::SelectPlanes(extract_x(opcode) as u8),
+                (0, 7) => OpCode::TimerX(extract_x(opcode)),
+                (0, 0xA) => OpCode::KeyPressX(extract_x(opcode)),
+                (1, 5) => OpCode::SetDelayTimer(extract_x(opcode)),
+                (1, 8) => OpCode::SetSoundTimer(extract_x(opcode)),
+                (1, 0xE) => OpCode::MemAdd(extract_x(opcode)),
+                (2, 9) => OpCode::SpriteX(extract_x(opcode)),
+                (3, 0) => OpCode::BigSpriteX(extract_x(opcode)),
+                (3, 3) => OpCode::Bcd(extract_x(opcode)),
+                (3, 0xA) => OpCode::SetPitch(extract_x(opcode)),
+                (5, 5) => OpCode::DumpX(extract_x(opcode)),
+                (6, 5) => OpCode::LoadX(extract_x(opcode)),
+                (7, 5) => OpCode::SaveFlags(extract_x(opcode)),
+                (8, 5) => OpCode::LoadFlags(extract_x(opcode)),
+                _ => OpCode::Invalid,
+            }
+        }
+        _ => OpCode::Invalid,
+    }
+}
+
+// the 4x5 hex digits
+pub const FONT: [[u8; 5]; 16] = [
+    [0xF0, 0x90, 0x90, 0x90, 0xF0], // 0
+    [0x20, 0x60, 0x20, 0x20, 0x70], // 1
+    [0xF0, 0x10, 0xF0, 0x80, 0xF0], // 2
+    [0xF0, 0x10, 0xF0, 0x10, 0xF0], // 3
+    [0x90, 0x90, 0xF0, 0x10, 0x10], // 4
+    [0xF0, 0x80, 0xF0, 0x10, 0xF0], // 5
+    [0xF0, 0x80, 0xF0, 0x90, 0xF0], // 6
+    [0xF0, 0x10, 0x20, 0x40, 0x40], // 7
+    [0xF0, 0x90, 0xF0, 0x90, 0xF0], // 8
+    [0xF0, 0x90, 0xF0, 0x10, 0xF0], // 9
+    [0xF0, 0x90, 0xF0, 0x90, 0x90], // A
+    [0xE0, 0x90, 0xE0, 0x90, 0xE0], // B
+    [0xF0, 0x80, 0x80, 0x80, 0xF0], // C
+    [0xE0, 0x90, 0x90, 0x90, 0xE0], // D
+    [0xF0, 0x80, 0xF0, 0x80, 0xF0], // E
+    [0xF0, 0x80, 0xF0, 0x80, 0x80], // F
+];
+
+// the hex digits of the COSMAC VIP's ROM (vip-font quirk)
+pub const VIP_FONT: [[u8; 5]; 16] = [
+    [0xF0, 0x90, 0x90, 0x90, 0xF0], // 0
+    [0x60, 0x20, 0x20, 0x20, 0x70], // 1
+    [0xF0, 0x10, 0xF0, 0x80, 0xF0], // 2
+    [0xF0, 0x10, 0xF0, 0x10, 0xF0], // 3
+    [0xA0, 0xA0, 0xF0, 0x20, 0x20], // 4
+    [0xF0, 0x80, 0xF0, 0x10, 0xF0], // 5
+    [0xF0, 0x80, 0xF0, 0x90, 0xF0], // 6
+    [0xF0, 0x10, 0x10, 0x10, 0x10], // 7
+    [0xF0, 0x90, 0xF0, 0x90, 0xF0], // 8
+    [0xF0, 0x90, 0xF0, 0x10, 0xF0], // 9
+    [0xF0, 0x90, 0xF0, 0x90, 0x90], // A
+    [0xF0, 0x50, 0x70, 0x50, 0xF0], // B
+    [0xF0, 0x80, 0x80, 0x80, 0xF0], // C
+    [0xF0, 0x50, 0x50, 0x50, 0xF0], // D
+    [0xF0, 0x80, 0xF0, 0x80, 0xF0], // E
+    [0xF0, 0x80, 0xF0, 0x80, 0x80], // F
+];
+
+// the register `offset` steps from X towards Y, for 5XY2/5XY3
+fn range_register(x: Register, y: Register, offset: usize) -> Register {
+    if x <= y {
+        x + offset
+    } else {
+        x - offset
+    }
+}
+
+// bytes of a DXYN sprite in one plane
+fn sprite_size(n: u16) -> usize {
+    if n == 0 {
+        32
+    } else {
+        usize::from(n)
+    }
+}
+
+impl Default for Machine {
+    fn default() -> Machine {
+        Machine::new()
+    }
+}
+
+impl Machine {
+    pub fn new() -> Machine {
+        Machine {
+            memory: [0; MEMORY_SIZE],
+            registers: [0; 16],
+            index_register: 0,
+            pc: 0,
+            gfx: FrameBuffer::new(),
+            delay_timer: 0,
+            sound_timer: 0,
+            audio_pattern: audio::DEFAULT_PATTERN,
+            audio_pitch: audio::DEFAULT_PITCH,
+            rpl_flags: [0; RPL_FLAGS],
+            // sized up front, running a program doesn't allocate
+            stack: Vec::with_capacity(STACK_DEPTH),
+            program_size: 0,
+            program_hash: utils::FNV_OFFSET,
+            keys: HashMap::with_capacity(KEYS),
+            draw_flag: false,
+            vblank: false,
+            key_wait: None,
+            pre_exec_hook: None,
+            memory_map: None,
+            violation: None,
+            rng: XorShiftRng::seed_from_u64(0),
+            quirks: Quirks::default(),
+            recent: VecDeque::with_capacity(RECENT_OPCODES),
+            clock: TimerClock::new(CPU_IPS),
+            limits: Limits::default(),
+            usage: Usage::default(),
+            extensions: Vec::new(),
+            time: None,
+        }
+    }
+
+    pub fn init(&mut self) {
+        // reset (keeping the registered hook, the random sequence, the quirks,
+        // the limits, the opcode extensions and the clock)
+        let hook = self.pre_exec_hook.take();
+        let rng = self.rng.clone();
+        let quirks = self.quirks;
+        let limits = self.limits;
+        let extensions = std::mem::take(&mut self.extensions);
+        let time = self.time.take();
+        *self = Machine::new();
+        self.pre_exec_hook = hook;
+        self.rng = rng;
+        self.quirks = quirks;
+        self.limits = limits;
+        self.extensions = extensions;
+        self.time = time;
+
+        // set the Program Counter
+        self.pc = PROGRAM_START_ADDRESS;
+
+        // load fontset
+        self.load_fontset();
+    }
+
+    pub fn set_timer(&mut self, t: Timer, v: u8) {
+        match t {
+            Timer::Sound => self.sound_timer = v,
+            Timer::Delay => self.delay_timer = v,
+        }
+    }
+    pub fn get_timer(&self, t: Timer) -> u8 {
+        match t {
+            Timer::Sound => self.sound_timer,
+            Timer::Delay => self.delay_timer,
+        }
+    }
+
+    pub fn load_program_file(&mut self, file: &str) -> Result<(), io::Error> {
+        let mut f = File::open(file)?;
+        let mut buffer = Vec::new();
+        // read the whole file
+        f.read_to_end(&mut buffer)?;
+        self.load_program(buffer)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+    }
+
+    pub fn load_program(&mut self, p: Vec<u8>) -> Result<(), Chip8Error> {
+        if p.len() > self.max_program_size() {
+            return Err(Chip8Error::ProgramTooLarge {
+                size: p.len(),
+                max: self.max_program_size(),
+            });
+        }
+        // program start at 0x200
+        let i = p.len();
+        self.memory[PROGRAM_START_ADDRESS..PROGRAM_START_ADDRESS + i].copy_from_slice(&p);
+        // clear what a previous program left past this one
+        self.memory[PROGRAM_START_ADDRESS + i..]
+            .iter_mut()
+            .for_each(|b| *b = 0);
+        self.program_size = i;
+        self.program_hash = utils::fnv1a(
+            utils::FNV_OFFSET,
+            &self.memory[PROGRAM_START_ADDRESS..PROGRAM_START_ADDRESS + i],
+        );
+
+        // hi-res CHIP-8 programs begin with a jump to the patched interpreter
+        if self.memory[PROGRAM_START_ADDRESS..PROGRAM_START_ADDRESS + 2] == [0x12, 0x60] {
+            self.gfx.set_resolution(GFX_WIDTH, 64);
+            self.pc = HIRES_START_ADDRESS;
+        }
+        Ok(())
+    }
+
+    // copy `bytes` at `address`, over or after the program: the data
+    // overlays some programs are made of. The program is extended over the
+    // ones past it (the pc can run there, the protection map covers them)
+    // and its hash takes them in, so they count as part of the ROM
+    pub fn load_at(&mut self, address: usize, bytes: &[u8]) -> Result<(), Chip8Error> {
+        let end = address + bytes.len();
+        if end > MEMORY_SIZE {
+            return Err(Chip8Error::OverlayTooLarge {
+                address,
+                size: bytes.len(),
+            });
+        }
+        self.memory[address..end].copy_from_slice(bytes);
+        if end > PROGRAM_START_ADDRESS {
+            self.program_size = self.program_size.max(end - PROGRAM_START_ADDRESS);
+        }
+        let hash = utils::fnv1a(self.program_hash, &(address as u32).to_le_bytes());
+        self.program_hash = utils::fnv1a(hash, bytes);
+        Ok(())
+    }
+
+    // the most load_program takes with the quirks set: the 64K of XO-CHIP
+    // past 0x200, or the 4K of CHIP-8 and SCHIP
+    pub fn max_program_size(&self) -> usize {
+        if self.quirks.memory_4k {
+            CHIP8_MAX_PROGRAM_SIZE
+        } else {
+            MAX_PROGRAM_SIZE
+        }
+    }
+
+    // load a program written as hex, e.g. "6005 6102 8014"
+    pub fn load_program_hex(&mut self, hex: &str) -> Result<(), String> {
+        let program = utils::parse_hex(hex)?;
+        self.load_program(program).map_err(|e| e.to_string())
+    }
+
+    // protect memory using the standard map for the loaded program
+    pub fn enable_protection(&mut self) {
+        self.memory_map = Some(MemoryMap::standard(self.program_size));
+    }
+
+    // check an access against the memory map, recording any violation
+    fn check_access(
+        &mut self,
+        address: usize,
+        len: usize,
+        access: Access,
+    ) -> Result<(), Chip8Error> {
+        let map = match &self.memory_map {
+            Some(map) => map,
+            None => return Ok(()),
+        };
+        match map.check(address, len, access) {
+            None => Ok(()),
+            Some(a) => {
+                let violation = Violation {
+                    pc: self.pc,
+                    address: a,
+                    access,
+                    perm: map.get(a),
+                };
+                self.violation = Some(violation.clone());
+                Err(Chip8Error::Protection(violation))
+            }
+        }
+    }
+
+    // memory touched through I by an opcode
+    fn memory_access(&self, opcode: &OpCode) -> Option<(usize, usize, Access)> {
+        let i = usize::from(self.index_register);
+        match *opcode {
+            OpCode::Draw(_, _, n) => {
+                // a sprite per selected plane
+                let planes = self.gfx.planes().count_ones() as usize;
+                Some((i, sprite_size(n) * planes, Access::Read))
+            }
+            OpCode::Bcd(_) => Some((i, 3, Access::Write)),
+            OpCode::DumpX(r) => Some((i, r + 1, Access::Write)),
+            OpCode::LoadX(r) => Some((i, r + 1, Access::Read)),
+            OpCode::SaveRange(x, y) => Some((i, x.abs_diff(y) + 1, Access::Write)),
+            OpCode::LoadRange(x, y) => Some((i, x.abs_diff(y) + 1, Access::Read)),
+            OpCode::LoadPattern => Some((i, audio::PATTERN_SIZE, Access::Read)),
+            _ => None,
+        }
+    }
+
+    // the instruction at `address`, which can be odd (the VIP fetches any
+    // two bytes), past the ROM as well: programs run code they wrote in
+    // memory, and the zeros after the ROM are invalid opcodes. None when
+    // its second byte is past the memory; the last byte of an odd-length
+    // ROM pairs with the zero after it
+    fn opcode_at(&self, address: usize) -> Option<u16> {
+        let hi = *self.memory.get(address)?;
+        let lo = *self.memory.get(address + 1)?;
+        Some(u16::from(hi) << 8 | u16::from(lo))
+    }
+
+    // none when the pc is past the memory
+    pub fn fetch_opcode(&mut self) -> Option<u16> {
+        self.opcode_at(self.pc)
+    }
+
+    // the address and opcode of the invalid instruction at pc, where a
+    // program that hit one halted
+    pub fn invalid_opcode(&self) -> Option<(usize, u16)> {
+        let opcode = self.opcode_at(self.pc)?;
+        match parse_opcode(Some(opcode)) {
+            OpCode::Invalid => Some((self.pc, opcode)),
+            _ => None,
+        }
+    }
+
+    pub fn seed_rng(&mut self, seed: u64) {
+        self.rng = XorShiftRng::seed_from_u64(seed);
+    }
+
+    // keypad key 0x0 to 0xF pressed or released
+    pub fn set_key_state(&mut self, key: u8, pressed: bool) {
+        self.keys.insert(u16::from(key), u8::from(pressed));
+    }
+
+    pub fn is_key_pressed(&self, key: u8) -> bool {
+        self.keys.get(&u16::from(key)).is_some_and(|v| *v > 0)
+    }
+
+    pub fn framebuffer(&self) -> &FrameBuffer {
+        &self.gfx
+    }
+
+    // hash of the screen size and pixels, to compare with a known good one
+    pub fn framebuffer_hash(&self) -> u64 {
+        let size = [self.gfx.width() as u8, self.gfx.height() as u8];
+        utils::fnv1a(utils::fnv1a(utils::FNV_OFFSET, &size), self.gfx.pixels())
+    }
+
+    // run `cycles` instructions at CPU_IPS, the timers ticking at TIMER_HZ
+    // of the instructions run since init, however the calls split them
+    pub fn run_for(&mut self, cycles: u64) -> Result<(), Chip8Error> {
+        for _ in 0..cycles {
+            self.exec_single()?;
+            for _ in 0..self.clock.advance(1) {
+                self.tick_timers();
+            }
+        }
+        Ok(())
+    }
+
+    // for `take_dirty`
+    pub fn framebuffer_mut(&mut self) -> &mut FrameBuffer {
+        &mut self.gfx
+    }
+
+    pub fn quirks(&self) -> Quirks {
+        self.quirks
+    }
+
+    // interpreter behaviours, kept by `init`
+    pub fn set_quirks(&mut self, quirks: Quirks) {
+        let font = quirks.vip_font != self.quirks.vip_font;
+        self.quirks = quirks;
+        if font {
+            self.load_fontset();
+        }
+    }
+
+    // identifies the loaded program, e.g. to keep per-ROM data
+    pub fn program_hash(&self) -> u64 {
+        self.program_hash
+    }
+
+    // bytes loaded at 0x200
+    pub fn program_size(&self) -> usize {
+        self.program_size
+    }
+
+    // the access the memory protection stopped the program on
+    pub fn violation(&self) -> Option<&Violation> {
+        self.violation.as_ref()
+    }
+
+    pub fn audio_pattern(&self) -> [u8; audio::PATTERN_SIZE] {
+        self.audio_pattern
+    }
+
+    pub fn audio_pitch(&self) -> u8 {
+        self.audio_pitch
+    }
+
+    pub fn set_pre_exec_hook<F>(&mut self, hook: F)
+    where
+        F: FnMut(usize, &OpCode) -> HookAction + Send + 'static,
+    {
+        self.pre_exec_hook = Some(Box::new(hook));
+    }
+
+    pub fn clear_pre_exec_hook(&mut self) {
+        self.pre_exec_hook = None;
+    }
+
+    // the clock the limits' timeout is measured on, e.g. a VirtualClock in
+    // tests; kept by init
+    pub fn set_clock(&mut self, clock: Arc<dyn Clock>) {
+        self.time = Some(clock);
+    }
+
+    fn now(&self) -> Instant {
+        self.time.as_ref().map_or_else(Instant::now, |c| c.now())
+    }
+
+    // run the instruction at pc; the program can't go on after an error
+    // (the pc stays on the instruction that caused it)
+    pub fn exec_single(&mut self) -> Result<(), Chip8Error> {
+        self.check_access(self.pc, 2, Access::Execute)?;
+        let fetched = self
+            .fetch_opcode()
+            .ok_or(Chip8Error::PcOutOfRange { pc: self.pc })?;
+        if self.recent.len() == RECENT_OPCODES {
+            self.recent.pop_front();
+        }
+        self.recent.push_back((self.pc, fetched));
+        let mut opcode = parse_opcode(Some(fetched));
+
+        if let Some(hook) = self.pre_exec_hook.as_mut() {
+            match hook(self.pc, &opcode) {
+                HookAction::Allow => {}
+                HookAction::Skip => {
+                    self.draw_flag = false;
+                    self.pc_inc();
+                    return Ok(());
+                }
+                HookAction::Replace(op) => opcode = op,
+            }
+        }
+
+        if let Some((address, len, access)) = self.memory_access(&opcode) {
+            if address + len > MEMORY_SIZE {
+                return Err(Chip8Error::OutOfMemory {
+                    pc: self.pc,
+                    address: address.max(MEMORY_SIZE),
+                });
+            }
+            self.check_access(address, len, access)?;
+        }
+        self.check_limits(&opcode)?;
+
+        self.draw_flag = false;
+        match opcode {
+            OpCode::Invalid => {
+                if !self.exec_extension(fetched) {
+                    return Err(Chip8Error::InvalidOpcode {
+                        pc: self.pc,
+                        opcode: fetched,
+                    });
+                }
+            }
+            OpCode::Clear => {
+                self.gfx.clear();
+                self.draw_flag = true;
+                self.pc_inc();
+            }
+            OpCode::ScrollDown(n) => {
+                self.gfx.scroll_down(self.scroll_amount(n));
+                self.draw_flag = true;
+                self.pc_inc();
+            }
+            OpCode::ScrollUp(n) => {
+                self.gfx.scroll_up(self.scroll_amount(n));
+                self.draw_flag = true;
+                self.pc_inc();
+            }
+            OpCode::ScrollRight => {
+                self.gfx.scroll_right(self.scroll_amount(4));
+                self.draw_flag = true;
+                self.pc_inc();
+            }
+            OpCode::ScrollLeft => {
+                self.gfx.scroll_left(self.scroll_amount(4));
+                self.draw_flag = true;
+                self.pc_inc();
+            }
+            OpCode::Exit => return Err(Chip8Error::Exit { pc: self.pc }),
+            OpCode::LowRes => {
+                self.gfx.set_resolution(GFX_WIDTH, GFX_HEIGHT);
+                self.draw_flag = true;
+                self.pc_inc();
+            }
+            OpCode::HighRes => {
+                self.gfx
+                    .set_resolution(framebuffer::MAX_WIDTH, framebuffer::MAX_HEIGHT);
+                self.draw_flag = true;
+                self.pc_inc();
+            }
+            OpCode::Return => {
+                let v = self
+                    .stack
+                    .pop()
+                    .ok_or(Chip8Error::StackUnderflow { pc: self.pc })?;
+                self.pc = v;
+                self.pc_inc();
+            }
+            OpCode::JumpTo(n) => {
+                self.pc = usize::from(n);
+            }
+            OpCode::Call(n) => {
+                if self.stack.len() >= STACK_DEPTH {
+                    return Err(Chip8Error::StackOverflow { pc: self.pc });
+                }
+                self.stack.push(self.pc);
+                self.pc = usize::from(n);
+            }
+            OpCode::SkipEq(r, n) => {
+                if self.registers[r] == n {
+                    self.skip_next();
+                }
+                self.pc_inc();
+            }
+            OpCode::SkipNotEq(r, n) => {
+                if self.registers[r] != n {
+                    self.skip_next();
+                }
+                self.pc_inc();
+            }
+            OpCode::SkipEqXY(rx, ry) => {
+                if self.registers[rx] == self.registers[ry] {
+                    self.skip_next();
+                }
+                self.pc_inc();
+            }
+            OpCode::SetX(r, n) => {
+                self.registers[r] = n;
+                self.pc_inc();
+            }
+            OpCode::AddX(r, n) => {
+                // carry flag is not changed
+                self.registers[r] = self.registers[r].wrapping_add(n);
+                self.pc_inc();
+            }
+            OpCode::AssignXY(rx, ry) => {
+                self.registers[rx] = self.registers[ry];
+                self.pc_inc();
+            }
+            OpCode::OrXY(rx, ry) => {
+                self.registers[rx] |= self.registers[ry];
+                self.vf_reset();
+                self.pc_inc();
+            }
+            OpCode::AndXY(rx, ry) => {
+                self.registers[rx] &= self.registers[ry];
+                self.vf_reset();
+                self.pc_inc();
+            }
+            OpCode::XorXY(rx, ry) => {
+                self.registers[rx] ^= self.registers[ry];
+                self.vf_reset();
+                self.pc_inc();
+            }
+            OpCode::AddXY(rx, ry) => {
+                let (v, carry) = self.registers[rx].overflowing_add(self.registers[ry]);
+                self.registers[rx] = v;
+                self.registers[0xF] = u8::from(carry);
+                self.pc_inc();
+            }
+            OpCode::SubXY(rx, ry) => {
+                // VF is the "no borrow" flag
+                let (v, borrow) = self.registers[rx].overflowing_sub(self.registers[ry]);
+                self.registers[rx] = v;
+                self.registers[0xF] = u8::from(!borrow);
+                self.pc_inc();
+            }
+            OpCode::ShiftRightX1(rx, ry) => {
+                let v = self.registers[if self.quirks.shift_vy { ry } else { rx }];
+                self.registers[rx] = v >> 1;
+                self.registers[0xF] = v & 0x01;
+                self.pc_inc();
+            }
+            OpCode::SubYX(rx, ry) => {
+                let (v, borrow) = self.registers[ry].overflowing_sub(self.registers[rx]);
+                self.registers[rx] = v;
+                self.registers[0xF] = u8::from(!borrow);
+                self.pc_inc();
+            }
+            OpCode::ShiftLeftX1(rx, ry) => {
+                let v = self.registers[if self.quirks.shift_vy { ry } else { rx }];
+                self.registers[rx] = v << 1;
+                self.registers[0xF] = v >> 7;
+                self.pc_inc();
+            }
+            OpCode::SkipNotEqXY(rx, ry) => {
+                if self.registers[rx] != self.registers[ry] {
+                    self.skip_next();
+                }
+                self.pc_inc();
+            }
+            OpCode::SaveRange(x, y) => {
+                let i = usize::from(self.index_register);
+                for offset in 0..=x.abs_diff(y) {
+                    self.memory[i + offset] = self.registers[range_register(x, y, offset)];
+                }
+                self.pc_inc();
+            }
+            OpCode::LoadRange(x, y) => {
+                let i = usize::from(self.index_register);
+                for offset in 0..=x.abs_diff(y) {
+                    self.registers[range_register(x, y, offset)] = self.memory[i + offset];
+                }
+                self.pc_inc();
+            }
+            OpCode::LongIndex => {
+                self.check_access(self.pc + 2, 2, Access::Execute)?;
+                match self.memory.get(self.pc + 2..self.pc + 4) {
+                    Some(&[hi, lo]) => self.index_register = u16::from(hi) << 8 | u16::from(lo),
+                    _ => return Err(Chip8Error::PcOutOfRange { pc: self.pc }),
+                }
+                self.pc += 4;
+            }
+            OpCode::SelectPlanes(mask) => {
+                self.gfx.select_planes(mask);
+                self.pc_inc();
+            }
+            OpCode::SetIR(n) => {
+                self.index_register = n;
+                self.pc_inc();
+            }
+            OpCode::Flow(n) => {
+                // BXNN: jump to XNN + VX
+                let r = if self.quirks.jump_vx {
+                    usize::from(n >> 8)
+                } else {
+                    0
+                };
+                self.pc = usize::from(self.registers[r]) + usize::from(n);
+            }
+            OpCode::RandX(r, n) => {
+                self.registers[r] = self.rng.next_u8() & n;
+                self.pc_inc();
+            }
+            OpCode::KeyPressedX(r) => {
+                if let Some(v) = self.keys.get(&u16::from(self.registers[r])) {
+                    if *v > 0 {
+                        self.skip_next();
+                    }
+                }
+                self.pc_inc();
+            }
+            OpCode::KeyNotPressedX(r) => {
+                match self.keys.get(&u16::from(self.registers[r])) {
+                    Some(v) => {
+                        if *v == 0 {
+                            self.skip_next();
+                        }
+                    }
+                    None => self.skip_next(),
+                }
+                self.pc_inc();
+            }
+            OpCode::KeyPressX(r) => {
+                // wait here until a key is down, the lowest one wins
+                match self.key_wait {
+                    // and then up again on the VIP
+                    Some(k) if !self.is_key_pressed(k) => {
+                        self.key_wait = None;
+                        self.registers[r] = k;
+                        self.pc_inc();
+                    }
+                    Some(_) => {}
+                    None => {
+                        if let Some(k) = (0..16).find(|k| self.is_key_pressed(*k)) {
+                            if self.quirks.key_release {
+                                self.key_wait = Some(k);
+                            } else {
+                                self.registers[r] = k;
+                                self.pc_inc();
+                            }
+                        }
+                    }
+                }
+            }
+            OpCode::TimerX(r) => {
+                self.registers[r] = self.get_timer(Timer::Delay);
+                self.pc_inc();
+            }
+            OpCode::SetDelayTimer(r) => {
+                self.set_timer(Timer::Delay, self.registers[r]);
+                self.pc_inc();
+            }
+            OpCode::SetSoundTimer(r) => {
+                self.set_timer(Timer::Sound, self.registers[r]);
+                self.pc_inc();
+            }
+            OpCode::MemAdd(r) => {
+                self.index_register = self
+                    .index_register
+                    .wrapping_add(u16::from(self.registers[r]));
+                if self.quirks.index_overflow {
+                    self.registers[0xF] = u8::from(self.index_register > 0x0FFF);
+                }
+                self.pc_inc();
+            }
+            OpCode::SpriteX(r) => {
+                let digit = usize::from(self.registers[r] & 0xF);
+                self.index_register = (FONT_ADDRESS + digit * 5) as u16;
+                self.pc_inc();
+            }
+            OpCode::BigSpriteX(r) => {
+                let digit = usize::from(self.registers[r] & 0xF);
+                self.index_register = (BIG_FONT_ADDRESS + digit * 10) as u16;
+                self.pc_inc();
+            }
+            OpCode::DumpX(r) => {
+                for i in 0..=r {
+                    let location = usize::from(self.index_register) + i;
+                    self.memory[location] = self.registers[i];
+                }
+                self.load_store_increment(r);
+                self.pc_inc();
+            }
+            OpCode::LoadPattern => {
+                let i = usize::from(self.index_register);
+                self.audio_pattern
+                    .copy_from_slice(&self.memory[i..i + audio::PATTERN_SIZE]);
+                self.pc_inc();
+            }
+            OpCode::SetPitch(r) => {
+                self.audio_pitch = self.registers[r];
+                self.pc_inc();
+            }
+            OpCode::LoadX(r) => {
+                for i in 0..=r {
+                    let location = usize::from(self.index_register) + i;
+                    self.registers[i] = self.memory[location];
+                }
+                self.load_store_increment(r);
+                self.pc_inc();
+            }
+            OpCode::SaveFlags(r) => {
+                self.rpl_flags[..=r].copy_from_slice(&self.registers[..=r]);
+                self.pc_inc();
+            }
+            OpCode::LoadFlags(r) => {
+                self.registers[..=r].copy_from_slice(&self.rpl_flags[..=r]);
+                self.pc_inc();
+            }
+            // waiting for the next frame
+            OpCode::Draw(..) if self.quirks.display_wait && !self.vblank => {}
+            OpCode::Draw(rx, ry, n) => {
+                self.vblank = false;
+                let x = usize::from(self.registers[rx]);
+                let y = usize::from(self.registers[ry]);
+
+                // each selected plane takes the next n bytes, DXY0 is 16x16
+                let mut collision = false;
+                let mut sprite = usize::from(self.index_register);
+                let size = sprite_size(n);
+                let width = if n == 0 { 16 } else { 8 };
+                for plane in 0..framebuffer::PLANES {
+                    if self.gfx.planes() & (1 << plane) != 0 {
+                        let rows = &self.memory[sprite..sprite + size];
+                        collision |=
+                            self.gfx
+                                .draw(plane, x, y, rows, width, self.quirks.clip_sprites);
+                        sprite += size;
+                    }
+                }
+                self.registers[0xF] = u8::from(collision);
+                self.draw_flag = true;
+                self.pc_inc();
+            }
+            OpCode::Bcd(r) => {
+                let ds = utils::convert_to_bcd(u16::from(self.registers[r]));
+                let i = usize::from(self.index_register);
+                self.memory[i..i + 3].copy_from_slice(&ds);
+                self.pc_inc();
+            }
+        }
+        Ok(())
+    }
+
+    // decrement the timers, returns true when the sound timer expires
+    pub fn tick_timers(&mut self) -> bool {
+        self.new_frame_limits();
+        self.vblank = true;
+        if self.delay_timer > 0 {
+            self.delay_timer -= 1;
+        }
+        let mut beep = false;
+        if self.sound_timer > 0 {
+            beep = self.sound_timer == 1;
+            self.sound_timer -= 1;
+        }
+        beep
+    }
+
+    // 8XY1/8XY2/8XY3 clear VF on the VIP
+    fn vf_reset(&mut self) {
+        if self.quirks.vf_reset {
+            self.registers[0xF] = 0;
+        }
+    }
+
+    // FX55/FX65 move I past the registers on the VIP
+    fn load_store_increment(&mut self, r: Register) {
+        if self.quirks.load_store_increment {
+            self.index_register = self.index_register.wrapping_add(r as u16 + 1);
+        }
+    }
+
+    // scroll distances are in hi-res pixels: in lo-res the SCHIP 1.1
+    // interpreter moves by half as many screen pixels (rounding down)
+    fn scroll_amount(&self, n: u8) -> usize {
+        let lores = self.gfx.width() < framebuffer::MAX_WIDTH;
+        if lores && self.quirks.half_pixel_scroll {
+            usize::from(n / 2)
+        } else {
+            usize::from(n)
+        }
+    }
+
+    // past the instruction after this one, F000 NNNN is two words
+    fn skip_next(&mut self) {
+        let long = self.memory.get(self.pc + 2..self.pc + 4) == Some(&[0xF0, 0x00][..]);
+        self.pc += if long { 4 } else { 2 };
+    }
+
+    fn pc_inc(&mut self) {
+        let opcode_mem_size = 2;
+        self.pc += opcode_mem_size;
+    }
+
+    fn load_fontset(&mut self) {
+        let big: [[u8; 10]; 16] = [
+            [0xFF, 0xFF, 0xC3, 0xC3, 0xC3, 0xC3, 0xC3, 0xC3, 0xFF, 0xFF], // 0
+            [0x18, 0x78, 0x78, 0x18, 0x18, 0x18, 0x18, 0x18, 0xFF, 0xFF], // 1
+            [0xFF, 0xFF, 0x03, 0x03, 0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF], // 2
+            [0xFF, 0xFF, 0x03, 0x03, 0xFF, 0xFF, 0x03, 0x03, 0xFF, 0xFF], // 3
+            [0xC3, 0xC3, 0xC3, 0xC3, 0xFF, 0xFF, 0x03, 0x03, 0x03, 0x03], // 4
+            [0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, 0x03, 0x03, 0xFF, 0xFF], // 5
+            [0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, 0xC3, 0xC3, 0xFF, 0xFF], // 6
+            [0xFF, 0xFF, 0x03, 0x03, 0x06, 0x0C, 0x18, 0x18, 0x18, 0x18], // 7
+            [0xFF, 0xFF, 0xC3, 0xC3, 0xFF, 0xFF, 0xC3, 0xC3, 0xFF, 0xFF], // 8
+            [0xFF, 0xFF, 0xC3, 0xC3, 0xFF, 0xFF, 0x03, 0x03, 0xFF, 0xFF], // 9
+            [0x7E, 0xFF, 0xC3, 0xC3, 0xC3, 0xFF, 0xFF, 0xC3, 0xC3, 0xC3], // A
+            [0xFC, 0xFC, 0xC3, 0xC3, 0xFC, 0xFC, 0xC3, 0xC3, 0xFC, 0xFC], // B
+            [0x3C, 0xFF, 0xC3, 0xC0, 0xC0, 0xC0, 0xC0, 0xC3, 0xFF, 0x3C], // C
+            [0xFC, 0xFE, 0xC3, 0xC3, 0xC3, 0xC3, 0xC3, 0xC3, 0xFE, 0xFC], // D
+            [0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF], // E
+            [0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, 0xC0, 0xC0, 0xC0, 0xC0], // F
+        ];
+        for (i, glyph) in big.iter().enumerate() {
+            let start = BIG_FONT_ADDRESS + i * 10;
+            self.memory[start..start + 10].copy_from_slice(glyph);
+        }
+
+        let font = if self.quirks.vip_font {
+            &VIP_FONT
+        } else {
+            &FONT
+        };
+        let mut x = FONT_ADDRESS;
+        for font_bytes in font {
+            // copy the font to the memory
+            for b in font_bytes {
+                self.memory[x] = *b;
+                x += 1;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn machine_fetch_opcode() {
+        let mut m = Machine::new();
+        // init
+        m.init();
+        m.load_program(vec![0xA2, 0xF0]).unwrap();
+
+        assert_eq!(0xA2F0, m.fetch_opcode().unwrap());
+    }
+
+    #[test]
+    fn machine_fetch_simple_exec() {
+        let mut m = Machine::new();
+        // init
+        m.init();
+
+        // v0 = 5 + 2
+        m.load_program_hex("7005 7102 8014").unwrap();
+
+        while m.exec_single().is_ok() {}
+
+        assert_eq!(7, m.registers[0]);
+    }
+
+    fn run(program: &[u8]) -> Machine {
+        let mut m = Machine::new();
+        m.init();
+        m.load_program(program.to_vec()).unwrap();
+        while m.exec_single().is_ok() {}
+        m
+    }
+
+    // every opcode once, small enough to run under Miri
+    #[test]
+    fn machine_opcode_suite() {
+        // 2NNN, 00EE, 1NNN
+        let m = run(&[
+            0x22, 0x06, // call 0x206
+            0x61, 0x01, // V1 = 1
+            0x12, 0x0A, // jump 0x20A
+            0x60, 0x07, // V0 = 7
+            0x00, 0xEE, // return
+        ]);
+        assert_eq!((7, 1), (m.registers[0], m.registers[1]));
+        assert!(m.stack.is_empty());
+
+        // 3XNN, 4XNN, 5XY0, 9XY0
+        let m = run(&[
+            0x60, 0x05, 0x30, 0x05, 0x61, 0x01, // skipped
+            0x40, 0x05, 0x62, 0x01, // not skipped
+            0x63, 0x05, 0x50, 0x30, 0x64, 0x01, // skipped
+            0x90, 0x30, 0x65, 0x01, // not skipped
+        ]);
+        assert_eq!([0, 1, 5, 0, 1], m.registers[1..6]);
+
+        // 6XNN, 7XNN, 8XY0 - 8XYE
+        assert_eq!(0x42, alu(0x0, 0x00, 0x42).0);
+        assert_eq!(0x3F, alu(0x1, 0x0F, 0x3C).0);
+        assert_eq!(0x0C, alu(0x2, 0x0F, 0x3C).0);
+        assert_eq!(0x33, alu(0x3, 0x0F, 0x3C).0);
+        assert_eq!((0x10, 1), alu(0x4, 0xF0, 0x20));
+        assert_eq!((0x30, 0), alu(0x4, 0x10, 0x20));
+        assert_eq!((0xF0, 0), alu(0x5, 0x10, 0x20));
+        assert_eq!((0x02, 1), alu(0x6, 0x05, 0x00));
+        assert_eq!((0x10, 1), alu(0x7, 0x10, 0x20));
+        assert_eq!(0x02, alu(0xE, 0x81, 0x00).0);
+        assert_eq!(0x01, run(&[0x60, 0xFF, 0x70, 0x02]).registers[0]);
+
+        // ANNN, FX1E, BNNN
+        let m = run(&[0xA1, 0x00, 0x60, 0x05, 0xF0, 0x1E]);
+        assert_eq!(0x105, m.index_register);
+        let m = run(&[0x60, 0x04, 0xB2, 0x04, 0x61, 0x01, 0x61, 0x02, 0x62, 0x03]);
+        assert_eq!((0, 3), (m.registers[1], m.registers[2]));
+
+        // CXNN, reproducible for a seed
+        let rand = |seed| {
+            let mut m = Machine::new();
+            m.seed_rng(seed);
+            m.init();
+            m.load_program(vec![0xC0, 0x0F, 0xC1, 0xFF]).unwrap();
+            while m.exec_single().is_ok() {}
+            (m.registers[0], m.registers[1])
+        };
+        assert!(rand(7).0 <= 0x0F);
+        assert_eq!(rand(7), rand(7));
+
+        // DXYN, 00E0
+        let m = run(&[0xA0, 0x00, 0xD0, 0x05]);
+        assert_eq!([1, 1, 1, 1, 0], m.gfx.pixels()[..5]);
+        assert_eq!(0, m.registers[0xF]);
+        let m = run(&[0xA0, 0x00, 0xD0, 0x05, 0xD0, 0x05]);
+        assert!(m.gfx.pixels().iter().all(|p| *p == 0));
+        assert_eq!(1, m.registers[0xF]);
+        let m = run(&[0xA0, 0x00, 0xD0, 0x05, 0x00, 0xE0]);
+        assert!(m.gfx.pixels().iter().all(|p| *p == 0));
+
+        // EX9E, EXA1
+        let mut m = Machine::new();
+        m.init();
+        m.load_program(vec![
+            0x60, 0x05, 0xE0, 0x9E, 0x61, 0x01, // skipped
+            0xE0, 0xA1, 0x62, 0x01, // not skipped
+        ])
+        .unwrap();
+        m.set_key_state(5, true);
+        while m.exec_single().is_ok() {}
+        assert_eq!((0, 1), (m.registers[1], m.registers[2]));
+
+        // FX0A waits for a key
+        let mut m = Machine::new();
+        m.init();
+        m.load_program(vec![0xF0, 0x0A]).unwrap();
+        m.exec_single().unwrap();
+        assert_eq!(PROGRAM_START_ADDRESS, m.pc);
+        m.set_key_state(9, true);
+        m.set_key_state(3, true);
+        m.exec_single().unwrap();
+        assert_eq!(3, m.registers[0]);
+        assert_eq!(PROGRAM_START_ADDRESS + 2, m.pc);
+
+        // FX07, FX15, FX18
+        let m = run(&[0x60, 0x09, 0xF0, 0x15, 0xF0, 0x18, 0xF1, 0x07]);
+        assert_eq!((9, 9, 9), (m.delay_timer, m.sound_timer, m.registers[1]));
+
+        // FX29, FX33
+        let m = run(&[0x60, 0x0A, 0xF0, 0x29]);
+        assert_eq!(50, m.index_register);
+        let m = run(&[0x60, 0xFE, 0xA3, 0x00, 0xF0, 0x33]);
+        assert_eq!([2, 5, 4], m.memory[0x300..0x303]);
+
+        // FX55, FX65
+        let m = run(&[
+            0x60, 0x01, 0x61, 0x02, 0x62, 0x03, 0xA3, 0x00, 0xF2, 0x55, // dump
+            0x60, 0x00, 0x61, 0x00, 0x62, 0x00, 0xF2, 0x65, // load
+        ]);
+        assert_eq!([1, 2, 3], m.memory[0x300..0x303]);
+        assert_eq!([1, 2, 3], m.registers[..3]);
+    }
+
+    // VX and VF after `8XYop` with V0 = x, V1 = y
+    fn alu(op: u8, x: u8, y: u8) -> (u8, u8) {
+        let m = run(&[0x60, x, 0x61, y, 0x80, 0x10 | op]);
+        (m.registers[0], m.registers[0xF])
+    }
+
+    #[test]
+    fn machine_alu_boundaries() {
+        // 8XY4: VF = carry
+        assert_eq!((0x00, 0), alu(0x4, 0x00, 0x00));
+        assert_eq!((0xFF, 0), alu(0x4, 0xFE, 0x01));
+        assert_eq!((0x00, 1), alu(0x4, 0xFF, 0x01));
+        assert_eq!((0xFE, 1), alu(0x4, 0xFF, 0xFF));
+
+        // 8XY5 and 8XY7: VF = no borrow
+        assert_eq!((0x00, 1), alu(0x5, 0x00, 0x00));
+        assert_eq!((0xFF, 0), alu(0x5, 0x00, 0x01));
+        assert_eq!((0x00, 1), alu(0x5, 0x01, 0x01));
+        assert_eq!((0x01, 1), alu(0x5, 0xFF, 0xFE));
+        assert_eq!((0x01, 0), alu(0x5, 0x00, 0xFF));
+        assert_eq!((0x00, 1), alu(0x7, 0x00, 0x00));
+        assert_eq!((0xFF, 0), alu(0x7, 0x01, 0x00));
+        assert_eq!((0xFE, 1), alu(0x7, 0x01, 0xFF));
+
+        // 8XY6 and 8XYE: VF = the bit shifted out
+        assert_eq!((0x00, 1), alu(0x6, 0x01, 0x00));
+        assert_eq!((0x7F, 1), alu(0x6, 0xFF, 0x00));
+        assert_eq!((0x00, 0), alu(0x6, 0x00, 0x00));
+        assert_eq!((0x00, 1), alu(0xE, 0x80, 0x00));
+        assert_eq!((0xFE, 1), alu(0xE, 0xFF, 0x00));
+        assert_eq!((0x02, 0), alu(0xE, 0x01, 0x00));
+
+        // 7XNN wraps without touching VF
+        let m = run(&[0x6F, 0x05, 0x60, 0xFF, 0x70, 0x01]);
+        assert_eq!((0x00, 0x05), (m.registers[0], m.registers[0xF]));
+    }
+
+    #[test]
+    fn machine_index_overflow_quirk() {
+        let program = [
+            0x6F, 0x07, // VF = 7
+            0xAF, 0xFE, // I = 0xFFE
+            0x60, 0x01, // V0 = 1
+            0xF0, 0x1E, // I += V0
+        ];
+        let m = run(&program);
+        assert_eq!((0xFFF, 7), (m.index_register, m.registers[0xF]));
+
+        let mut m = Machine::new();
+        m.quirks.index_overflow = true;
+        m.init();
+        m.load_program(program.to_vec()).unwrap();
+        while m.exec_single().is_ok() {}
+        assert_eq!((0xFFF, 0), (m.index_register, m.registers[0xF]));
+
+        m.pc -= 2;
+        m.exec_single().unwrap();
+        assert_eq!((0x1000, 1), (m.index_register, m.registers[0xF]));
+    }
+
+    #[test]
+    fn machine_jump_vx_quirk() {
+        let program = vec![
+            0x60, 0x02, // V0 = 2
+            0x62, 0x04, // V2 = 4
+            0xB2, 0x06, // jump
+            0x61, 0x01, // 0x206: V1 = 1
+            0x61, 0x02, // 0x208: V1 = 2
+            0x61, 0x03, // 0x20A: V1 = 3
+        ];
+        let mut m = Machine::new();
+        m.init();
+        m.load_program(program.clone()).unwrap();
+        m.exec_single().unwrap();
+        m.exec_single().unwrap();
+        m.exec_single().unwrap();
+        assert_eq!(0x208, m.pc);
+
+        m.quirks.jump_vx = true;
+        m.init();
+        m.load_program(program).unwrap();
+        m.exec_single().unwrap();
+        m.exec_single().unwrap();
+        m.exec_single().unwrap();
+        assert_eq!(0x20A, m.pc);
+    }
+
+    #[test]
+    fn machine_vip_quirks() {
+        let run_with = |quirks: &str, program: &[u8]| {
+            let mut m = Machine::new();
+            m.quirks = Quirks::parse(quirks).unwrap();
+            m.init();
+            m.load_program(program.to_vec()).unwrap();
+            while m.exec_single().is_ok() {}
+            m
+        };
+
+        // V0 = 1; V1 = 6; V0 >>= 1 (or V0 = V1 >> 1)
+        let shift = [0x60, 0x01, 0x61, 0x06, 0x80, 0x16];
+        let m = run_with("", &shift);
+        assert_eq!((0, 1), (m.registers[0], m.registers[0xF]));
+        let m = run_with("shift-vy", &shift);
+        assert_eq!((3, 0), (m.registers[0], m.registers[0xF]));
+        // the flag wins over the result in VF
+        let m = run_with("", &[0x6F, 0x81, 0x8F, 0xFE]);
+        assert_eq!(1, m.registers[0xF]);
+
+        // I = 0x300; store V0..V2
+        let store = [0xA3, 0x00, 0xF2, 0x55];
+        assert_eq!(0x300, run_with("", &store).index_register);
+        assert_eq!(
+            0x303,
+            run_with("load-store-increment", &store).index_register
+        );
+
+        // VF = 5; V0 |= V1
+        let or = [0x6F, 0x05, 0x80, 0x11];
+        assert_eq!(5, run_with("", &or).registers[0xF]);
+        assert_eq!(0, run_with("chip8", &or).registers[0xF]);
+
+        // V0 = 62, V1 = 0; I = "0" glyph; draw 8x5 at (62, 0)
+        let draw = [0x60, 0x3E, 0x61, 0x00, 0xA0, 0x00, 0xD0, 0x15];
+        let m = run_with("", &draw);
+        assert_eq!(1, m.gfx.pixel(0, 0));
+        let m = run_with("clip-sprites", &draw);
+        assert_eq!((0, 1), (m.gfx.pixel(0, 0), m.gfx.pixel(63, 0)));
+    }
+
+    #[test]
+    fn machine_strict_vip_quirks() {
+        let mut m = Machine::new();
+        m.set_quirks(Quirks::parse("vip").unwrap());
+        m.init();
+        assert_eq!(VIP_FONT[4], m.memory[20..25]);
+        // draws wait for the next frame: I = "0"; two draws; V0 = 1
+        m.load_program_hex("A000 D005 D005 6001").unwrap();
+        for _ in 0..5 {
+            m.exec_single().unwrap();
+        }
+        assert_eq!(0x202, m.pc);
+        m.tick_timers();
+        m.exec_single().unwrap();
+        m.exec_single().unwrap();
+        assert_eq!((0x204, 1), (m.pc, m.gfx.pixel(0, 0)));
+        m.tick_timers();
+        m.run_for(2).unwrap();
+        assert_eq!((0x208, 0), (m.pc, m.gfx.pixel(0, 0)));
+
+        // FX0A waits for the key to go up again: V0 = key; V1 = 1
+        m.init();
+        m.load_program_hex("F00A 6101").unwrap();
+        m.set_key_state(7, true);
+        m.exec_single().unwrap();
+        m.exec_single().unwrap();
+        assert_eq!(0x200, m.pc);
+        m.set_key_state(7, false);
+        m.run_for(2).unwrap();
+        assert_eq!((7, 1), (m.registers[0], m.registers[1]));
+
+        // the quirks changed after init change the font
+        m.set_quirks(Quirks::default());
+        assert_eq!(FONT[4], m.memory[20..25]);
+    }
+
+    #[test]
+    fn machine_invalid_opcode() {
+        let mut m = Machine::new();
+        m.init();
+        // V0 = 1; invalid
+        m.load_program(vec![0x60, 0x01, 0xF0, 0xFF]).unwrap();
+        assert_eq!(None, m.invalid_opcode());
+        while m.exec_single().is_ok() {}
+        assert_eq!(Some((0x202, 0xF0FF)), m.invalid_opcode());
+
+        // halting on a jump to itself isn't an invalid opcode
+        m.init();
+        m.load_program(vec![0x12, 0x00]).unwrap();
+        for _ in 0..10 {
+            m.exec_single().unwrap();
+        }
+        assert_eq!(None, m.invalid_opcode());
+    }
+
+    #[test]
+    fn machine_errors() {
+        let halt = |hex: &str| {
+            let mut m = Machine::new();
+            m.init();
+            m.load_program_hex(hex).unwrap();
+            loop {
+                if let Err(e) = m.exec_single() {
+                    break e;
+                }
+            }
+        };
+        assert_eq!(Chip8Error::StackUnderflow { pc: 0x200 }, halt("00EE"));
+        // calls itself until the 17th call
+        assert_eq!(Chip8Error::StackOverflow { pc: 0x200 }, halt("2200"));
+        // a sprite read past the end of memory
+        let e = halt("F000 FFFF D015");
+        assert_eq!(
+            Chip8Error::OutOfMemory {
+                pc: 0x204,
+                address: MEMORY_SIZE
+            },
+            e
+        );
+        assert_eq!(
+            "access to 0x10000, past the end of the memory, at 0x204",
+            e.to_string()
+        );
+        assert_eq!(Chip8Error::Exit { pc: 0x200 }, halt("00FD"));
+        assert_eq!(
+            "stack underflow at 0x200",
+            Chip8Error::StackUnderflow { pc: 0x200 }.to_string()
+        );
+
+        let mut m = Machine::new();
+        assert_eq!(
+            Err(Chip8Error::ProgramTooLarge {
+                size: MAX_PROGRAM_SIZE + 1,
+                max: MAX_PROGRAM_SIZE
+            }),
+            m.load_program(vec![0; MAX_PROGRAM_SIZE + 1])
+        );
+        assert_eq!(
+            "65025 bytes don't fit in memory: programs are 65024 bytes at most",
+            m.load_program(vec![0; MAX_PROGRAM_SIZE + 1])
+                .unwrap_err()
+                .to_string()
+        );
+        // a large XO-CHIP program loads whole
+        m.init();
+        m.load_program(vec![0xAB; MAX_PROGRAM_SIZE]).unwrap();
+        assert_eq!(0xAB, m.memory[MEMORY_SIZE - 1]);
+    }
+
+    #[test]
+    fn machine_program_size_by_profile() {
+        let large = vec![0; CHIP8_MAX_PROGRAM_SIZE + 1];
+        for (profile, max) in [
+            ("chip8", CHIP8_MAX_PROGRAM_SIZE),
+            ("schip", CHIP8_MAX_PROGRAM_SIZE),
+            ("vip", CHIP8_MAX_PROGRAM_SIZE),
+            ("xochip", MAX_PROGRAM_SIZE),
+        ]
+        .iter()
+        {
+            let mut m = Machine::new();
+            m.set_quirks(Quirks::parse(profile).unwrap());
+            m.init();
+            assert_eq!(*max, m.max_program_size(), "{}", profile);
+            m.load_program(vec![0; CHIP8_MAX_PROGRAM_SIZE]).unwrap();
+            assert_eq!(
+                *max == MAX_PROGRAM_SIZE,
+                m.load_program(large.clone()).is_ok(),
+                "{}",
+                profile
+            );
+        }
+
+        let mut m = Machine::new();
+        m.set_quirks(Quirks::parse("schip").unwrap());
+        let e = m.load_program(large).unwrap_err();
+        assert_eq!(
+            Chip8Error::ProgramTooLarge {
+                size: 3585,
+                max: 3584
+            },
+            e
+        );
+        assert_eq!(
+            "3585 bytes don't fit in the 4K of CHIP-8 and SCHIP: programs are 3584 bytes \
+             at most, 65024 for XO-CHIP (--quirks xochip)",
+            e.to_string()
+        );
+    }
+
+    #[test]
+    fn machine_scroll() {
+        let program = [
+            0xA0, 0x00, // I = 0x000 ("0" glyph)
+            0xD0, 0x01, // draw its top row at (0, 0)
+            0x00, 0xC2, // scroll down 2
+            0x00, 0xFB, // scroll right 4
+            0x00, 0xD2, // scroll up 2
+            0x00, 0xFC, // scroll left 4
+        ];
+        let lit = |m: &Machine| {
+            let w = m.gfx.width();
+            let p = m.gfx.pixels().iter().position(|p| *p != 0).unwrap();
+            (p % w, p / w)
+        };
+        let mut m = Machine::new();
+        m.init();
+        m.load_program(program.to_vec()).unwrap();
+        m.exec_single().unwrap();
+        m.exec_single().unwrap();
+        m.exec_single().unwrap();
+        assert_eq!((0, 2), lit(&m));
+        m.exec_single().unwrap();
+        assert_eq!((4, 2), lit(&m));
+        m.exec_single().unwrap();
+        m.exec_single().unwrap();
+        assert_eq!((0, 0), lit(&m));
+
+        // half the distance in lo-res
+        m.quirks.half_pixel_scroll = true;
+        m.init();
+        m.load_program(program.to_vec()).unwrap();
+        m.exec_single().unwrap();
+        m.exec_single().unwrap();
+        m.exec_single().unwrap();
+        assert_eq!((0, 1), lit(&m));
+        m.exec_single().unwrap();
+        assert_eq!((2, 1), lit(&m));
+        m.exec_single().unwrap();
+        assert_eq!((2, 0), lit(&m));
+    }
+
+    #[test]
+    fn machine_schip() {
+        let m = run(&[
+            0x00, 0xFF, // 128x64
+            0x60, 0x08, // V0 = 8
+            0xF0, 0x30, // I = big "8"
+            0xD0, 0x0A, // draw it at (8, 8)
+            0x61, 0x64, // V1 = 100
+            0xA2, 0x18, // I = 0x218
+            0xD1, 0x00, // 16x16 sprite at (100, 8)
+            0xF1, 0x75, // save V0, V1 to the flags
+            0x60, 0x00, 0x61, 0x00, // clear V0, V1
+            0xF1, 0x85, // load them back
+            0x00, 0xFD, // exit
+            0xFF, 0xFF, 0x80, 0x01, // 16x16 sprite rows, never executed
+        ]);
+        assert_eq!((128, 64), (m.gfx.width(), m.gfx.height()));
+        // the big "8": two full rows, then the sides
+        assert_eq!(1, m.gfx.pixel(15, 9));
+        assert_eq!(0, m.gfx.pixel(10, 10));
+        assert_eq!(1, m.gfx.pixel(9, 10));
+        // 16 pixels wide
+        assert_eq!(1, m.gfx.pixel(115, 8));
+        assert_eq!((1, 0), (m.gfx.pixel(115, 9), m.gfx.pixel(114, 9)));
+        assert_eq!([8, 100], m.registers[..2]);
+        assert_eq!(0x216, m.pc);
+
+        let m = run(&[0x00, 0xFF, 0x00, 0xFE]);
+        assert_eq!((GFX_WIDTH, GFX_HEIGHT), (m.gfx.width(), m.gfx.height()));
+    }
+
+    #[test]
+    fn machine_run_for() {
+        let mut m = Machine::new();
+        m.init();
+        // DT = 120; spin
+        m.load_program_hex("6078 F015 1204").unwrap();
+        m.run_for(2).unwrap();
+        assert_eq!(120, m.get_timer(Timer::Delay));
+        // a second at CPU_IPS, however it's split
+        m.run_for(300).unwrap();
+        m.run_for(u64::from(CPU_IPS) - 300).unwrap();
+        assert_eq!(60, m.get_timer(Timer::Delay));
+
+        m.init();
+        m.load_program_hex("00EE").unwrap();
+        assert_eq!(Err(Chip8Error::StackUnderflow { pc: 0x200 }), m.run_for(10));
+    }
+
+    #[test]
+    fn machine_load_at() {
+        let mut m = Machine::new();
+        m.init();
+        // I = 0x600; V0 = [I]; jump to the code overlay
+        m.load_program_hex("A600 F065 1400").unwrap();
+        let hash = m.program_hash();
+        m.load_at(0x600, &[0x2A]).unwrap();
+        // V1 = V0; spin
+        m.load_at(0x400, &[0x81, 0x00, 0x14, 0x02]).unwrap();
+        assert_eq!(0x600 - PROGRAM_START_ADDRESS + 1, m.program_size());
+        assert_ne!(hash, m.program_hash());
+        m.run_for(5).unwrap();
+        assert_eq!(0x2A, m.registers[1]);
+        assert_eq!(0x402, m.pc);
+
+        // overlays are part of the ROM: the same ones give the same hash
+        let mut other = Machine::new();
+        other.init();
+        other.load_program_hex("A600 F065 1400").unwrap();
+        other.load_at(0x600, &[0x2A]).unwrap();
+        assert_ne!(m.program_hash(), other.program_hash());
+        other.load_at(0x400, &[0x81, 0x00, 0x14, 0x02]).unwrap();
+        assert_eq!(m.program_hash(), other.program_hash());
+
+        let e = m.load_at(MEMORY_SIZE - 2, &[0; 3]).unwrap_err();
+        assert_eq!(
+            Chip8Error::OverlayTooLarge {
+                address: MEMORY_SIZE - 2,
+                size: 3
+            },
+            e
+        );
+        assert_eq!(
+            "3 bytes at 0xFFFE go past the end of the memory (2 at most there)",
+            e.to_string()
+        );
+        m.load_at(MEMORY_SIZE - 2, &[1, 2]).unwrap();
+    }
+
+    #[test]
+    fn machine_xo_chip() {
+        let m = run(&[
+            0x60, 0x01, 0x61, 0x02, 0x62, 0x03, // V0, V1, V2 = 1, 2, 3
+            0xF0, 0x00, 0x80, 0x00, // I = 0x8000
+            0x50, 0x22, // save V0-V2
+            0x52, 0x03, // load V2-V0, backwards
+            0x30, 0x03, // skip if V0 == 3: both words of the next one
+            0xF0, 0x00, 0x00, 0x00, // I = 0
+            0xF3, 0x01, // both planes
+            0xF0, 0x29, // I = "3"
+            0xD3, 0x31, // a row per plane at (0, 0)
+            0xF1, 0x01, // first plane
+            0x00, 0xFD, // exit
+        ]);
+        assert_eq!([1, 2, 3], m.memory[0x8000..0x8003]);
+        assert_eq!([3, 2, 1], m.registers[..3]);
+        // rows 0xF0 on the first plane, 0x10 on the second
+        assert_eq!(1, m.gfx.pixel(0, 0));
+        assert_eq!(3, m.gfx.pixel(3, 0));
+        assert_eq!(0, m.gfx.pixel(4, 0));
+        assert_eq!(1, m.gfx.planes());
+        assert_eq!(0x21C, m.pc);
+    }
+
+    #[test]
+    fn machine_hires() {
+        let mut program = vec![0; 0xC2];
+        program[..2].copy_from_slice(&[0x12, 0x60]);
+        program[0xC0..].copy_from_slice(&[0x60, 0x30]); // 0x2C0: V0 = 48
+        program.extend_from_slice(&[
+            0xA0, 0x00, // I = 0x000
+            0xD0, 0x05, // draw "0" at (48, 48)
+        ]);
+        let m = run(&program);
+        assert_eq!(64, m.gfx.height());
+        assert_eq!(GFX_WIDTH * 64, m.gfx.pixels().len());
+        assert_eq!(1, m.gfx.pixel(48, 48));
+        assert_eq!(48, m.registers[0]);
+
+        let m = run(&[0x60, 0x30, 0xA0, 0x00, 0xD0, 0x05]);
+        assert_eq!(GFX_HEIGHT, m.gfx.height());
+        assert_eq!(GFX_WIDTH * GFX_HEIGHT, m.gfx.pixels().len());
+        assert_eq!(1, m.gfx.pixel(48, 16));
+    }
+
+    #[test]
+    fn machine_load_program_hex() {
+        let mut m = Machine::new();
+        m.init();
+        m.load_program_hex("6005 6102\n8014").unwrap();
+        assert_eq!(6, m.program_size);
+        assert_eq!([0x60, 0x05, 0x61], m.memory[0x200..0x203]);
+
+        assert!(m.load_program_hex("6005 6G02").is_err());
+        assert!(m.load_program_hex(&"00".repeat(MEMORY_SIZE)).is_err());
+    }
+
+    #[test]
+    fn machine_odd_length_program() {
+        let mut m = Machine::new();
+        m.init();
+        m.load_program_hex("6005 6107 6203").unwrap();
+        // the last byte pairs with a zero, not what the previous program left
+        m.load_program_hex("6005 61").unwrap();
+        let halt = Chip8Error::InvalidOpcode {
+            pc: 0x204,
+            opcode: 0,
+        };
+        assert_eq!(Err(halt), m.run_for(3));
+        assert_eq!([5, 0, 0], m.registers[..3]);
+        assert_eq!(Some((0x204, 0)), m.invalid_opcode());
+    }
+
+    #[test]
+    fn machine_runs_code_past_the_rom() {
+        let mut m = Machine::new();
+        m.init();
+        // V0-V1 = 6042 (LD V0, 0x42), stored at 0x300 with FX55, then run
+        m.load_program_hex("6060 6142 A300 F155 1300").unwrap();
+        m.run_for(6).unwrap();
+        assert_eq!(0x42, m.registers[0]);
+        assert_eq!(0x302, m.pc);
+        // and the zeros after it
+        assert_eq!(
+            Err(Chip8Error::InvalidOpcode {
+                pc: 0x302,
+                opcode: 0
+            }),
+            m.exec_single()
+        );
+    }
+
+    #[test]
+    fn machine_misaligned_pc() {
+        let mut m = Machine::new();
+        m.init();
+        // jump over a byte to an odd address
+        m.load_program_hex("1203 00 6042 1205").unwrap();
+        m.run_for(4).unwrap();
+        assert_eq!(0x42, m.registers[0]);
+        assert_eq!(0x205, m.pc);
+
+        // past the program, the zeros it cleared
+        m.load_program_hex("1202").unwrap();
+        m.pc = PROGRAM_START_ADDRESS;
+        let halt = Chip8Error::InvalidOpcode {
+            pc: 0x202,
+            opcode: 0,
+        };
+        assert_eq!(Err(halt), m.run_for(2));
+
+        // an instruction can't start on the last byte of the memory
+        m.load_at(MEMORY_SIZE - 1, &[0x00]).unwrap();
+        m.pc = MEMORY_SIZE - 1;
+        assert_eq!(None, m.fetch_opcode());
+        assert_eq!(
+            Err(Chip8Error::PcOutOfRange {
+                pc: MEMORY_SIZE - 1
+            }),
+            m.exec_single()
+        );
+    }
+
+    #[test]
+    fn machine_memory_protection() {
+        let mut m = Machine::new();
+        m.init();
+        // BCD(V0) into the font area
+        m.load_program_hex("A000 F033").unwrap();
+        m.enable_protection();
+
+        while m.exec_single().is_ok() {}
+
+        let v = m.violation.unwrap();
+        assert_eq!(0x202, v.pc);
+        assert_eq!(0x000, v.address);
+        assert_eq!(Access::Write, v.access);
+    }
+
+    #[test]
+    fn machine_pre_exec_hook() {
+        let mut m = Machine::new();
+        m.init();
+        m.load_program_hex("6005 6102 6203").unwrap();
+
+        m.set_pre_exec_hook(|pc, op| match (pc, op) {
+            (0x200, _) => HookAction::Skip,
+            (_, OpCode::SetX(1, n)) => HookAction::Replace(OpCode::SetX(1, n + 1)),
+            _ => HookAction::Allow,
+        });
+        while m.exec_single().is_ok() {}
+
+        assert_eq!(0, m.registers[0]);
+        assert_eq!(3, m.registers[1]);
+        assert_eq!(3, m.registers[2]);
+
+        m.clear_pre_exec_hook();
+        m.pc = PROGRAM_START_ADDRESS;
+        while m.exec_single().is_ok() {}
+        assert_eq!(5, m.registers[0]);
+    }
+}
@@ -0,0 +1,230 @@
+// Execution limits, for services running ROMs they don't trust (a web
+// playground, a chat bot): a program can't run for ever, nest calls as deep
+// as it likes or redraw the screen without end. None is set by default;
+// when one is reached, exec_single stops with `Chip8Error::LimitReached`
+// before running the instruction, and the machine is left as it was.
+//
+// - cycles: instructions run since init
+// - stack depth: nested 2NNN calls (16 at most anyway)
+// - draws per frame: DXYN instructions between two timer ticks
+// - timeout: wall-clock time since the first instruction after init,
+//   checked every TIMEOUT_CHECK instructions so it costs next to nothing,
+//   on the machine's clock (see clock.rs)
+//
+// They're given as a comma separated list, e.g.
+// `cycles=1000000,stack=8,draws=200,timeout=5` (seconds).
+
+use crate::{Chip8Error, Machine, OpCode};
+use std::fmt;
+use std::time::{Duration, Instant};
+
+// instructions between two looks at the clock
+const TIMEOUT_CHECK: u64 = 1024;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Limits {
+    pub cycles: Option<u64>,
+    pub stack_depth: Option<usize>,
+    pub draws_per_frame: Option<u32>,
+    pub timeout: Option<Duration>,
+}
+
+// the limit a program reached
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Limit {
+    Cycles(u64),
+    StackDepth(usize),
+    DrawsPerFrame(u32),
+    Timeout(Duration),
+}
+
+impl fmt::Display for Limit {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Limit::Cycles(n) => write!(f, "{} cycles", n),
+            Limit::StackDepth(n) => write!(f, "a stack depth of {}", n),
+            Limit::DrawsPerFrame(n) => write!(f, "{} draws in a frame", n),
+            Limit::Timeout(d) => write!(f, "{} s of running time", d.as_secs_f64()),
+        }
+    }
+}
+
+// what the program used of the limits, counted from init
+#[derive(Debug, Clone, Default)]
+pub(crate) struct Usage {
+    cycles: u64,
+    draws: u32,
+    started: Option<Instant>,
+}
+
+impl Limits {
+    pub fn parse(text: &str) -> Result<Limits, String> {
+        let mut limits = Limits::default();
+        for item in text.split(',').map(str::trim).filter(|i| !i.is_empty()) {
+            let invalid = || format!("invalid limit `{}`", item);
+            let (name, value) = item.split_once('=').ok_or_else(invalid)?;
+            match name.trim() {
+                "cycles" => limits.cycles = Some(value.parse().map_err(|_| invalid())?),
+                "stack" => limits.stack_depth = Some(value.parse().map_err(|_| invalid())?),
+                "draws" => limits.draws_per_frame = Some(value.parse().map_err(|_| invalid())?),
+                "timeout" => {
+                    let seconds: f64 = value.parse().map_err(|_| invalid())?;
+                    let timeout = Duration::try_from_secs_f64(seconds).map_err(|_| invalid())?;
+                    limits.timeout = Some(timeout);
+                }
+                _ => return Err(format!("unknown limit `{}`", name)),
+            }
+        }
+        Ok(limits)
+    }
+}
+
+impl Machine {
+    // kept by init, which starts counting again
+    pub fn set_limits(&mut self, limits: Limits) {
+        self.limits = limits;
+        self.usage = Usage::default();
+    }
+
+    pub fn limits(&self) -> Limits {
+        self.limits
+    }
+
+    // count `opcode`, about to run at the pc, against the limits
+    pub(crate) fn check_limits(&mut self, opcode: &OpCode) -> Result<(), Chip8Error> {
+        let (limits, pc, depth) = (self.limits, self.pc, self.stack.len());
+        let now = match limits.timeout {
+            Some(_) if self.usage.cycles.is_multiple_of(TIMEOUT_CHECK) => Some(self.now()),
+            _ => None,
+        };
+        let usage = &mut self.usage;
+        let reached = |limit| Err(Chip8Error::LimitReached { pc, limit });
+        if let Some(max) = limits.cycles.filter(|max| usage.cycles >= *max) {
+            return reached(Limit::Cycles(max));
+        }
+        if let (Some(timeout), Some(now)) = (limits.timeout, now) {
+            let started = *usage.started.get_or_insert(now);
+            if now.saturating_duration_since(started) >= timeout {
+                return reached(Limit::Timeout(timeout));
+            }
+        }
+        match opcode {
+            OpCode::Call(_) => {
+                if let Some(max) = limits.stack_depth.filter(|max| depth >= *max) {
+                    return reached(Limit::StackDepth(max));
+                }
+            }
+            OpCode::Draw(..) => {
+                if let Some(max) = limits.draws_per_frame.filter(|max| usage.draws >= *max) {
+                    return reached(Limit::DrawsPerFrame(max));
+                }
+                usage.draws += 1;
+            }
+            _ => {}
+        }
+        usage.cycles += 1;
+        Ok(())
+    }
+
+    // a new frame: the draws are counted again
+    pub(crate) fn new_frame_limits(&mut self) {
+        self.usage.draws = 0;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clock::VirtualClock;
+    use std::sync::Arc;
+
+    fn halt(limits: &str, hex: &str, cycles: u64) -> Result<Machine, Chip8Error> {
+        let mut m = Machine::new();
+        m.set_limits(Limits::parse(limits).unwrap());
+        m.init();
+        m.load_program_hex(hex).unwrap();
+        m.run_for(cycles)?;
+        Ok(m)
+    }
+
+    #[test]
+    fn limits_parse() {
+        assert_eq!(Ok(Limits::default()), Limits::parse(""));
+        assert_eq!(
+            Ok(Limits {
+                cycles: Some(1000),
+                stack_depth: Some(4),
+                draws_per_frame: Some(20),
+                timeout: Some(Duration::from_millis(2500)),
+            }),
+            Limits::parse("cycles=1000, stack=4,draws=20,timeout=2.5")
+        );
+        assert!(Limits::parse("cycles").is_err());
+        assert!(Limits::parse("cycles=-1").is_err());
+        assert!(Limits::parse("timeout=-1").is_err());
+        assert!(Limits::parse("memory=10").is_err());
+    }
+
+    #[test]
+    fn limits_enforced() {
+        // a jump to itself runs its cycles and stops there
+        let e = halt("cycles=100", "1200", 1000).err().unwrap();
+        assert_eq!(
+            Chip8Error::LimitReached {
+                pc: 0x200,
+                limit: Limit::Cycles(100)
+            },
+            e
+        );
+        assert_eq!("limit of 100 cycles reached at 0x200", e.to_string());
+        assert!(halt("cycles=100", "1200", 100).is_ok());
+
+        // calls itself: the 4th call is refused, before it's made
+        let e = halt("stack=3", "2200", 100).err().unwrap();
+        assert_eq!(
+            Chip8Error::LimitReached {
+                pc: 0x200,
+                limit: Limit::StackDepth(3)
+            },
+            e
+        );
+
+        // draws in a loop, 5 or 6 a frame at CPU_IPS
+        let draws = "D001 1200";
+        assert!(halt("draws=6", draws, 10_000).is_ok());
+        let e = halt("draws=4", draws, 10_000).err().unwrap();
+        assert_eq!(
+            "limit of 4 draws in a frame reached at 0x200",
+            e.to_string()
+        );
+
+        let e = halt("timeout=0", "1200", 10).err().unwrap();
+        assert_eq!(
+            Chip8Error::LimitReached {
+                pc: 0x200,
+                limit: Limit::Timeout(Duration::ZERO)
+            },
+            e
+        );
+
+        // on the machine's clock, looked at every TIMEOUT_CHECK instructions
+        let clock = VirtualClock::new();
+        let mut m = Machine::new();
+        m.set_clock(Arc::new(clock.clone()));
+        m.set_limits(Limits::parse("timeout=5").unwrap());
+        m.init();
+        m.load_program_hex("1200").unwrap();
+        m.run_for(TIMEOUT_CHECK * 2 - 1).unwrap();
+        clock.advance(Duration::from_secs(5));
+        m.run_for(10).unwrap_err();
+        assert_eq!(TIMEOUT_CHECK * 2, m.usage.cycles);
+
+        // init counts again, with the same limits
+        let mut m = halt("cycles=100", "1200", 100).unwrap();
+        assert!(m.run_for(1).is_err());
+        m.init();
+        assert_eq!(Some(100), m.limits().cycles);
+        m.load_program_hex("1200").unwrap();
+        m.run_for(100).unwrap();
+    }
+}
@@ -0,0 +1,105 @@
+// The window's screen when there's no ROM to run: hex digits of the font
+// scrolling across, a row each way, until one is opened. It's a CHIP-8
+// program like the demo, made for the digits set in the config
+// (`idle = C8`), which the window runs as it would a ROM.
+
+// what the window title and messages call it
+pub const NAME: &str = "idle";
+
+pub const DEFAULT_DIGITS: &str = "C8";
+// the digits 6 pixels apart at least, all in a 64 pixels wide row
+pub const MAX_DIGITS: usize = 10;
+
+// the rows, and the frames between steps
+const TOP: u8 = 8;
+const BOTTOM: u8 = 19;
+const STEP_FRAMES: u8 = 3;
+
+// `digits` (hex digits, MAX_DIGITS at most) evenly spread across the screen
+pub fn parse(digits: &str) -> Result<Vec<u8>, String> {
+    let values: Vec<u8> = digits
+        .chars()
+        .map(|c| c.to_digit(16).map(|d| d as u8))
+        .collect::<Option<_>>()
+        .ok_or_else(|| format!("`{}` isn't hex digits", digits))?;
+    if values.is_empty() || values.len() > MAX_DIGITS {
+        return Err(format!("1 to {} digits, not {}", MAX_DIGITS, values.len()));
+    }
+    Ok(values)
+}
+
+pub fn program(digits: &[u8]) -> Vec<u8> {
+    let spacing = 64 / digits.len().clamp(1, MAX_DIGITS) as u8;
+    let mut words: Vec<u16> = vec![
+        0x6000,                     // LD V0, 0: how far the rows went
+        0x6A00 | u16::from(TOP),    // LD VA, TOP
+        0x6B00 | u16::from(BOTTOM), // LD VB, BOTTOM
+        0x00E0,                     // 206 CLS
+        0x8100,                     // LD V1, V0: the top row's x
+        0x6200,                     // LD V2, 0
+        0x8205,                     // SUB V2, V0: the bottom row's
+    ];
+    for digit in digits {
+        words.extend_from_slice(&[
+            0x6300 | u16::from(digit & 0xF), // LD V3, digit
+            0xF329,                          // LD F, V3
+            0xD1A5,                          // DRW V1, VA, 5
+            0xD2B5,                          // DRW V2, VB, 5
+            0x7100 | u16::from(spacing),     // ADD V1, spacing
+            0x7200 | u16::from(spacing),     // ADD V2, spacing
+        ]);
+    }
+    let wait = 0x200 + 2 * (words.len() + 3);
+    words.extend_from_slice(&[
+        0x7001,                          // ADD V0, 1
+        0x6300 | u16::from(STEP_FRAMES), // LD V3, STEP_FRAMES
+        0xF315,                          // LD DT, V3
+        0xF307,                          // wait: LD V3, DT
+        0x3300,                          // SE V3, 0
+        0x1000 | wait as u16,            // JP wait
+        0x1206,                          // JP 206
+    ]);
+    words
+        .iter()
+        .flat_map(|w| w.to_be_bytes().to_vec())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Machine;
+
+    #[test]
+    fn marquee_parse() {
+        assert_eq!(vec![0xC, 0x8], parse("C8").unwrap());
+        assert_eq!(vec![0xA, 0xB], parse("ab").unwrap());
+        assert!(parse("").is_err());
+        assert!(parse("C8G").is_err());
+        assert!(parse("0123456789A").is_err());
+        assert!(parse(&"F".repeat(MAX_DIGITS)).is_ok());
+    }
+
+    #[test]
+    fn marquee_scrolls() {
+        let mut m = Machine::new();
+        m.init();
+        m.load_program(program(&parse(DEFAULT_DIGITS).unwrap()))
+            .unwrap();
+        // a frame drawn: "C" at the left of the top row, "8" 32 pixels on
+        m.run_for(30).unwrap();
+        let lit = |m: &Machine, x, y| m.gfx.pixel(x, y) == 1;
+        assert!(lit(&m, 0, usize::from(TOP)) && lit(&m, 32, usize::from(TOP)));
+        assert!(lit(&m, 0, usize::from(BOTTOM)));
+        let before = m.gfx.pixels().to_vec();
+
+        // a few steps later, the rows moved the opposite ways, and never
+        // halted on the way
+        m.run_for(5000).unwrap();
+        assert_ne!(before, m.gfx.pixels());
+        let steps = usize::from(m.registers[0]) - 1;
+        assert!(steps > 0);
+        assert!(lit(&m, steps % 64, usize::from(TOP)));
+        assert!(lit(&m, (256 - steps) % 64, usize::from(BOTTOM)));
+    }
+}
@@ -0,0 +1,373 @@
+// Plugins: shared libraries loaded at runtime, so experimental features can
+// live outside this crate. A plugin exports
+//
+//     extern "C" fn chip8_plugin_init(host: *const Host) -> i32
+//
+// which checks `host.abi_version`, registers what it adds through the
+// host's functions and returns 0 (anything else refuses to start). The
+// host, its registry and its add functions are only valid during the call:
+// a plugin mustn't keep them, nor add anything after it returns. It can
+// add:
+//
+// - opcode extensions: opcodes the interpreter doesn't know matching a
+//   mask and a pattern, run on the registers, I and the memory (see
+//   extensions.rs)
+// - display filters: rewrite the RGBA pixels of each frame the window draws
+// - input providers: polled once per frame for the keypad keys to hold
+//
+// Everything crosses the boundary as `#[repr(C)]` structs and C functions,
+// so plugins can be written in any language and built with any compiler.
+// The `data` pointer of each registration is handed back to its functions
+// untouched; they may be called from any thread. Libraries stay loaded
+// until the process exits. Loading needs the `plugins` feature on Unix
+// (dlopen).
+
+use crate::agent::KeyMask;
+use crate::extensions::Next;
+use crate::Machine;
+use std::ffi::c_void;
+use std::path::Path;
+
+// bumped on any change to the structs below
+pub const ABI_VERSION: u32 = 1;
+
+// what an opcode extension sees of the machine
+#[repr(C)]
+pub struct Cpu {
+    pub registers: [u8; 16],
+    pub index: u16,
+    // the whole memory, memory_len bytes
+    pub memory: *mut u8,
+    pub memory_len: usize,
+}
+
+// runs `opcode` on the cpu: 0 when done (the pc moves to the next
+// instruction), anything else leaves it invalid
+pub type ExecFn = extern "C" fn(data: *mut c_void, opcode: u16, cpu: *mut Cpu) -> i32;
+// rewrites width x height RGBA pixels in place
+pub type FilterFn = extern "C" fn(data: *mut c_void, rgba: *mut u8, width: u32, height: u32);
+// keypad keys to hold during the frame, bit N for key N
+pub type PollFn = extern "C" fn(data: *mut c_void, frame: u64) -> u16;
+
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct OpcodeExtension {
+    // for opcodes with `opcode & mask == pattern`
+    pub mask: u16,
+    pub pattern: u16,
+    pub exec: ExecFn,
+    pub data: *mut c_void,
+}
+
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct DisplayFilter {
+    pub apply: FilterFn,
+    pub data: *mut c_void,
+}
+
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct InputProvider {
+    pub poll: PollFn,
+    pub data: *mut c_void,
+}
+
+// the plugins promise their functions can be called from any thread
+unsafe impl Send for OpcodeExtension {}
+unsafe impl Send for DisplayFilter {}
+unsafe impl Send for InputProvider {}
+
+// handed to chip8_plugin_init, `registry` is passed back to the add
+// functions, during the init only
+#[repr(C)]
+pub struct Host {
+    pub abi_version: u32,
+    pub registry: *mut c_void,
+    pub add_opcode: extern "C" fn(registry: *mut c_void, extension: OpcodeExtension),
+    pub add_filter: extern "C" fn(registry: *mut c_void, filter: DisplayFilter),
+    pub add_input: extern "C" fn(registry: *mut c_void, input: InputProvider),
+}
+
+pub type InitFn = unsafe extern "C" fn(host: *const Host) -> i32;
+
+// nul terminated, for dlsym
+#[cfg(all(feature = "plugins", unix))]
+const INIT_SYMBOL: &[u8] = b"chip8_plugin_init\0";
+
+// what the loaded plugins registered
+#[derive(Default)]
+pub struct Plugins {
+    pub opcodes: Vec<OpcodeExtension>,
+    pub filters: Vec<DisplayFilter>,
+    pub inputs: Vec<InputProvider>,
+}
+
+fn registry<'a>(registry: *mut c_void) -> &'a mut Plugins {
+    // the registry is the Plugins that `register` is running for: the add
+    // functions are only called during the init, the safety contract of
+    // `register`
+    unsafe { &mut *registry.cast::<Plugins>() }
+}
+
+extern "C" fn add_opcode(r: *mut c_void, extension: OpcodeExtension) {
+    registry(r).opcodes.push(extension);
+}
+
+extern "C" fn add_filter(r: *mut c_void, filter: DisplayFilter) {
+    registry(r).filters.push(filter);
+}
+
+extern "C" fn add_input(r: *mut c_void, input: InputProvider) {
+    registry(r).inputs.push(input);
+}
+
+impl Plugins {
+    pub fn new() -> Plugins {
+        Plugins::default()
+    }
+
+    /// Loads the library at `path` and runs its init.
+    ///
+    /// # Safety
+    ///
+    /// The library runs its initialisers and `chip8_plugin_init`, and its
+    /// functions are called later on: it must be a plugin for this ABI, and
+    /// keep to the contract of `register`.
+    pub unsafe fn load(&mut self, path: &Path) -> Result<(), String> {
+        let init = open(path).map_err(|e| format!("cannot load {}: {}", path.display(), e))?;
+        self.register(init)
+            .map_err(|e| format!("plugin {}: {}", path.display(), e))
+    }
+
+    /// Runs a plugin's init, for plugins linked in rather than loaded.
+    ///
+    /// # Safety
+    ///
+    /// `init` and the functions it registers are foreign code: they must be
+    /// sound for any arguments the ABI hands them, from any thread, for as
+    /// long as these plugins are used. `init` must only call the add
+    /// functions of the host before it returns, never keeping the host or
+    /// its registry.
+    pub unsafe fn register(&mut self, init: InitFn) -> Result<(), String> {
+        let host = Host {
+            abi_version: ABI_VERSION,
+            registry: (self as *mut Plugins).cast(),
+            add_opcode,
+            add_filter,
+            add_input,
+        };
+        match init(&host) {
+            0 => Ok(()),
+            code => Err(format!("refused to start ({})", code)),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.opcodes.is_empty() && self.filters.is_empty() && self.inputs.is_empty()
+    }
+
+    // the opcode extensions, to the machine
+    pub fn install(&self, m: &mut Machine) -> Result<(), String> {
+        for &e in &self.opcodes {
+            m.register_opcode(e.mask, e.pattern, move |ctx| {
+                let mut cpu = Cpu {
+                    registers: *ctx.registers,
+                    index: *ctx.index,
+                    memory: ctx.memory.as_mut_ptr(),
+                    memory_len: ctx.memory.len(),
+                };
+                if (e.exec)(e.data, ctx.opcode, &mut cpu) != 0 {
+                    return Next::Invalid;
+                }
+                *ctx.registers = cpu.registers;
+                *ctx.index = cpu.index;
+                Next::Step
+            })?;
+        }
+        Ok(())
+    }
+
+    // the filters, in registration order, on a frame of RGBA pixels
+    pub fn filter(&self, rgba: &mut [u8], width: usize, height: usize) {
+        assert_eq!(width * height * 4, rgba.len());
+        for f in &self.filters {
+            (f.apply)(f.data, rgba.as_mut_ptr(), width as u32, height as u32);
+        }
+    }
+
+    // the keys held by any of the input providers
+    pub fn poll(&self, frame: u64) -> KeyMask {
+        self.inputs
+            .iter()
+            .fold(0, |keys, i| keys | (i.poll)(i.data, frame))
+    }
+}
+
+#[cfg(all(feature = "plugins", unix))]
+fn open(path: &Path) -> Result<InitFn, String> {
+    use std::ffi::{CStr, CString};
+    use std::os::unix::ffi::OsStrExt;
+
+    let last_error = || {
+        let e = unsafe { libc::dlerror() };
+        if e.is_null() {
+            String::from("unknown error")
+        } else {
+            unsafe { CStr::from_ptr(e) }.to_string_lossy().into_owned()
+        }
+    };
+    let name = CString::new(path.as_os_str().as_bytes()).map_err(|e| e.to_string())?;
+    // never closed, the plugin's functions are kept around
+    let library = unsafe { libc::dlopen(name.as_ptr(), libc::RTLD_NOW | libc::RTLD_LOCAL) };
+    if library.is_null() {
+        return Err(last_error());
+    }
+    let init = unsafe { libc::dlsym(library, INIT_SYMBOL.as_ptr().cast()) };
+    if init.is_null() {
+        return Err(String::from("no chip8_plugin_init in it"));
+    }
+    Ok(unsafe { std::mem::transmute::<*mut c_void, InitFn>(init) })
+}
+
+#[cfg(not(all(feature = "plugins", unix)))]
+fn open(_: &Path) -> Result<InitFn, String> {
+    Err(String::from(
+        "plugins need a build with the `plugins` feature, on Unix",
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::slice;
+
+    // 5XY1: VX += VY and stored at I, 5XY4 is refused (slots no interpreter uses)
+    extern "C" fn add_registers(_: *mut c_void, opcode: u16, cpu: *mut Cpu) -> i32 {
+        let cpu = unsafe { &mut *cpu };
+        let (x, y) = (
+            usize::from(opcode >> 8 & 0xF),
+            usize::from(opcode >> 4 & 0xF),
+        );
+        match opcode & 0xF {
+            1 => {
+                cpu.registers[x] = cpu.registers[x].wrapping_add(cpu.registers[y]);
+                let memory = unsafe { slice::from_raw_parts_mut(cpu.memory, cpu.memory_len) };
+                memory[usize::from(cpu.index)] = cpu.registers[x];
+                0
+            }
+            _ => 1,
+        }
+    }
+
+    extern "C" fn invert(data: *mut c_void, rgba: *mut u8, width: u32, height: u32) {
+        let calls = unsafe { &mut *data.cast::<u32>() };
+        *calls += 1;
+        let rgba = unsafe { slice::from_raw_parts_mut(rgba, (width * height * 4) as usize) };
+        for b in rgba.chunks_exact_mut(4).flat_map(|p| &mut p[..3]) {
+            *b = !*b;
+        }
+    }
+
+    // holds key 5 on even frames
+    extern "C" fn blink(_: *mut c_void, frame: u64) -> u16 {
+        u16::from(frame.is_multiple_of(2)) << 5
+    }
+
+    static mut FILTER_CALLS: u32 = 0;
+
+    unsafe extern "C" fn init(host: *const Host) -> i32 {
+        let host = &*host;
+        if host.abi_version != ABI_VERSION {
+            return 1;
+        }
+        (host.add_opcode)(
+            host.registry,
+            OpcodeExtension {
+                mask: 0xF00F,
+                pattern: 0x5001,
+                exec: add_registers,
+                data: std::ptr::null_mut(),
+            },
+        );
+        (host.add_opcode)(
+            host.registry,
+            OpcodeExtension {
+                mask: 0xF00F,
+                pattern: 0x5004,
+                exec: add_registers,
+                data: std::ptr::null_mut(),
+            },
+        );
+        (host.add_filter)(
+            host.registry,
+            DisplayFilter {
+                apply: invert,
+                data: std::ptr::addr_of_mut!(FILTER_CALLS).cast(),
+            },
+        );
+        (host.add_input)(
+            host.registry,
+            InputProvider {
+                poll: blink,
+                data: std::ptr::null_mut(),
+            },
+        );
+        0
+    }
+
+    unsafe extern "C" fn refuse(_: *const Host) -> i32 {
+        7
+    }
+
+    #[test]
+    fn plugin_registration() {
+        let mut plugins = Plugins::new();
+        assert!(plugins.is_empty());
+        unsafe { plugins.register(init) }.unwrap();
+        assert_eq!(
+            (2, 1, 1),
+            (
+                plugins.opcodes.len(),
+                plugins.filters.len(),
+                plugins.inputs.len()
+            )
+        );
+        assert_eq!(Err(String::from("refused to start (7)")), unsafe {
+            plugins.register(refuse)
+        });
+        assert!(unsafe { plugins.load(Path::new("/nonexistent/plugin.so")) }
+            .unwrap_err()
+            .starts_with("cannot load /nonexistent/plugin.so: "));
+
+        let mut rgba = vec![0, 0xFF, 0x0F, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF];
+        plugins.filter(&mut rgba, 2, 1);
+        assert_eq!(vec![0xFF, 0, 0xF0, 0xFF, 0, 0, 0, 0xFF], rgba);
+        assert_eq!(1, unsafe { FILTER_CALLS });
+
+        assert_eq!(1 << 5, plugins.poll(0));
+        assert_eq!(0, plugins.poll(1));
+    }
+
+    #[test]
+    fn plugin_opcodes() {
+        let mut plugins = Plugins::new();
+        unsafe { plugins.register(init) }.unwrap();
+        let mut m = Machine::new();
+        plugins.install(&mut m).unwrap();
+        m.init();
+        // V0 = 3, V1 = 4, I = 0x300, V0 += V1, then 5014
+        m.load_program_hex("6003 6104 A300 5011 5014").unwrap();
+        m.run_for(4).unwrap();
+        assert_eq!(7, m.registers[0]);
+        assert_eq!(7, m.memory[0x300]);
+        assert_eq!(0x208, m.pc);
+        assert_eq!(
+            Err(crate::Chip8Error::InvalidOpcode {
+                pc: 0x208,
+                opcode: 0x5014
+            }),
+            m.exec_single()
+        );
+    }
+}
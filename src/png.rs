@@ -0,0 +1,142 @@
+// PNG encoding, enough for screenshots: 8 bit RGBA, no filtering, and the
+// image data in stored (uncompressed) deflate blocks, so it needs no
+// compression library. CHIP-8 screens are small, a 128x64 frame is 32K.
+// A comment, such as the game values of a captured frame, goes in an iTXt
+// chunk.
+
+const SIGNATURE: [u8; 8] = [0x89, b'P', b'N', b'G', b'\r', b'\n', 0x1A, b'\n'];
+// the most a stored deflate block holds
+const MAX_BLOCK: usize = 0xFFFF;
+
+fn crc32(data: &[u8]) -> u32 {
+    !data.iter().fold(!0u32, |crc, b| {
+        (0..8).fold(crc ^ u32::from(*b), |c, _| {
+            if c & 1 == 1 {
+                c >> 1 ^ 0xEDB8_8320
+            } else {
+                c >> 1
+            }
+        })
+    })
+}
+
+fn adler32(data: &[u8]) -> u32 {
+    const MOD: u32 = 65521;
+    let (a, b) = data.iter().fold((1u32, 0u32), |(a, b), d| {
+        let a = (a + u32::from(*d)) % MOD;
+        (a, (b + a) % MOD)
+    });
+    b << 16 | a
+}
+
+fn chunk(out: &mut Vec<u8>, kind: &[u8; 4], data: &[u8]) {
+    out.extend_from_slice(&(data.len() as u32).to_be_bytes());
+    let start = out.len();
+    out.extend_from_slice(kind);
+    out.extend_from_slice(data);
+    let crc = crc32(&out[start..]);
+    out.extend_from_slice(&crc.to_be_bytes());
+}
+
+// a zlib stream of stored blocks
+fn zlib(data: &[u8]) -> Vec<u8> {
+    let mut out = vec![0x78, 0x01];
+    let mut blocks = data.chunks(MAX_BLOCK).peekable();
+    if blocks.peek().is_none() {
+        out.extend_from_slice(&[1, 0, 0, 0xFF, 0xFF]);
+    }
+    while let Some(block) = blocks.next() {
+        out.push(u8::from(blocks.peek().is_none()));
+        let len = block.len() as u16;
+        out.extend_from_slice(&len.to_le_bytes());
+        out.extend_from_slice(&(!len).to_le_bytes());
+        out.extend_from_slice(block);
+    }
+    out.extend_from_slice(&adler32(data).to_be_bytes());
+    out
+}
+
+// a width x height image of RGBA pixels, as a PNG file
+pub fn encode(rgba: &[u8], width: usize, height: usize) -> Vec<u8> {
+    encode_commented(rgba, width, height, "")
+}
+
+// the same with a UTF-8 `Comment` text chunk, when there's a comment
+pub fn encode_commented(rgba: &[u8], width: usize, height: usize, comment: &str) -> Vec<u8> {
+    assert_eq!(width * height * 4, rgba.len());
+    let mut header = Vec::with_capacity(13);
+    header.extend_from_slice(&(width as u32).to_be_bytes());
+    header.extend_from_slice(&(height as u32).to_be_bytes());
+    // 8 bits per channel, RGBA, deflate, no filtering, not interlaced
+    header.extend_from_slice(&[8, 6, 0, 0, 0]);
+
+    // each row starts with its filter type, none
+    let mut raw = Vec::with_capacity((width * 4 + 1) * height);
+    for row in rgba.chunks(width * 4) {
+        raw.push(0);
+        raw.extend_from_slice(row);
+    }
+
+    let mut out = SIGNATURE.to_vec();
+    chunk(&mut out, b"IHDR", &header);
+    chunk(&mut out, b"IDAT", &zlib(&raw));
+    if !comment.is_empty() {
+        // keyword, not compressed, no language nor translated keyword
+        let mut text = b"Comment\0\0\0\0\0".to_vec();
+        text.extend_from_slice(comment.as_bytes());
+        chunk(&mut out, b"iTXt", &text);
+    }
+    chunk(&mut out, b"IEND", &[]);
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn png_checksums() {
+        assert_eq!(0xCBF4_3926, crc32(b"123456789"));
+        assert_eq!(0x11E6_0398, adler32(b"Wikipedia"));
+        // the IEND chunk every PNG ends with
+        let mut out = Vec::new();
+        chunk(&mut out, b"IEND", &[]);
+        assert_eq!(
+            [0, 0, 0, 0, b'I', b'E', b'N', b'D', 0xAE, 0x42, 0x60, 0x82],
+            out[..]
+        );
+    }
+
+    #[test]
+    fn png_encode() {
+        let rgba = [255, 0, 0, 255, 0, 0, 255, 255];
+        let png = encode(&rgba, 1, 2);
+        assert_eq!(SIGNATURE, png[..8]);
+        assert_eq!(b"IHDR", &png[12..16]);
+        assert_eq!([0, 0, 0, 1, 0, 0, 0, 2, 8, 6], png[16..26]);
+        // the rows as they were, each after its filter byte
+        let idat = 8 + 25;
+        assert_eq!(b"IDAT", &png[idat + 4..idat + 8]);
+        let data = &png[idat + 8..];
+        assert_eq!([0x78, 0x01, 1, 10, 0, !10, !0], data[..7]);
+        assert_eq!([0, 255, 0, 0, 255, 0, 0, 0, 255, 255], data[7..17]);
+        assert!(png.ends_with(&[0xAE, 0x42, 0x60, 0x82]));
+
+        // the comment goes between the data and the end
+        let commented = encode_commented(&rgba, 1, 2, "score: 3");
+        let itxt = png.len() - 12;
+        assert_eq!(png[..itxt], commented[..itxt]);
+        assert_eq!([0, 0, 0, 20], commented[itxt..itxt + 4]);
+        assert_eq!(
+            b"iTXtComment\0\0\0\0\0score: 3",
+            &commented[itxt + 4..itxt + 28]
+        );
+        assert_eq!(png[itxt..], commented[commented.len() - 12..]);
+
+        // images past a block are split in several
+        let big = zlib(&[7; MAX_BLOCK + 1]);
+        assert_eq!(2 + 5 + MAX_BLOCK + 5 + 1 + 4, big.len());
+        assert_eq!(0, big[2]);
+        assert_eq!(1, big[2 + 5 + MAX_BLOCK]);
+    }
+}
@@ -0,0 +1,145 @@
+// Optional memory protection: every page of the main memory gets a set of
+// read/write/execute permissions and accesses outside of them stop the
+// machine. The standard map makes the interpreter area read-only, the
+// loaded program read/execute and everything else read/write data.
+
+use std::fmt;
+
+pub const PAGE_SIZE: usize = 0x100;
+const PAGES: usize = crate::MEMORY_SIZE / PAGE_SIZE;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Perm(u8);
+
+impl Perm {
+    pub const NONE: Perm = Perm(0);
+    pub const R: Perm = Perm(0b001);
+    pub const W: Perm = Perm(0b010);
+    pub const X: Perm = Perm(0b100);
+    pub const RW: Perm = Perm(0b011);
+    pub const RX: Perm = Perm(0b101);
+
+    pub fn allows(self, access: Access) -> bool {
+        let bit = match access {
+            Access::Read => Perm::R,
+            Access::Write => Perm::W,
+            Access::Execute => Perm::X,
+        };
+        self.0 & bit.0 != 0
+    }
+}
+
+impl fmt::Display for Perm {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let flag = |p: Perm, c: char| if self.0 & p.0 != 0 { c } else { '-' };
+        write!(
+            f,
+            "{}{}{}",
+            flag(Perm::R, 'r'),
+            flag(Perm::W, 'w'),
+            flag(Perm::X, 'x')
+        )
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Access {
+    Read,
+    Write,
+    Execute,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Violation {
+    pub pc: usize,
+    pub address: usize,
+    pub access: Access,
+    pub perm: Perm,
+}
+
+impl fmt::Display for Violation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{:?} access to {:#05X} ({}) at pc {:#05X}",
+            self.access, self.address, self.perm, self.pc
+        )
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct MemoryMap {
+    pages: [Perm; PAGES],
+}
+
+impl MemoryMap {
+    pub fn new(perm: Perm) -> MemoryMap {
+        MemoryMap {
+            pages: [perm; PAGES],
+        }
+    }
+
+    // interpreter area r--, program rx (rounded up to a whole page), data rw
+    pub fn standard(program_size: usize) -> MemoryMap {
+        let program_end = crate::PROGRAM_START_ADDRESS + program_size;
+        let mut map = MemoryMap::new(Perm::RW);
+        map.set(0, crate::PROGRAM_START_ADDRESS, Perm::R);
+        map.set(crate::PROGRAM_START_ADDRESS, program_end, Perm::RX);
+        map
+    }
+
+    // set the permissions of all the pages touched by [start, end)
+    pub fn set(&mut self, start: usize, end: usize, perm: Perm) {
+        let first = start / PAGE_SIZE;
+        let last = end.div_ceil(PAGE_SIZE);
+        for p in first..last.min(PAGES) {
+            self.pages[p] = perm;
+        }
+    }
+
+    pub fn get(&self, address: usize) -> Perm {
+        self.pages
+            .get(address / PAGE_SIZE)
+            .copied()
+            .unwrap_or(Perm::NONE)
+    }
+
+    // returns the first address in [address, address + len) denied for access
+    pub fn check(&self, address: usize, len: usize, access: Access) -> Option<usize> {
+        (address..address + len).find(|a| !self.get(*a).allows(access))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn memory_map_standard_layout() {
+        let map = MemoryMap::standard(0x150);
+        assert_eq!(Perm::R, map.get(0x000));
+        assert_eq!(Perm::R, map.get(0x1FF));
+        assert_eq!(Perm::RX, map.get(0x200));
+        assert_eq!(Perm::RX, map.get(0x3FF));
+        assert_eq!(Perm::RW, map.get(0x400));
+        assert_eq!(Perm::RW, map.get(0xFFFF));
+        assert_eq!(Perm::NONE, map.get(0x10000));
+    }
+
+    #[test]
+    fn memory_map_check() {
+        let map = MemoryMap::standard(0x10);
+        assert_eq!(None, map.check(0x200, 2, Access::Execute));
+        assert_eq!(Some(0x300), map.check(0x2FF, 2, Access::Execute));
+        assert_eq!(Some(0x1FE), map.check(0x1FE, 3, Access::Write));
+        assert_eq!(None, map.check(0x050, 5, Access::Read));
+        assert_eq!(Some(0x10000), map.check(0xFFFE, 3, Access::Read));
+    }
+
+    #[test]
+    fn perm_display() {
+        assert_eq!("r-x", Perm::RX.to_string());
+        assert_eq!("rw-", Perm::RW.to_string());
+        assert_eq!("---", Perm::NONE.to_string());
+    }
+}
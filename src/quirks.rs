@@ -0,0 +1,263 @@
+// Behaviours that differ between CHIP-8 interpreters and that some ROMs
+// depend on. Every quirk is off by default, which is what most ROMs written
+// for modern interpreters expect, and can be enabled by name, e.g.
+// `--quirks index-overflow`, or all the ones of an interpreter by its
+// profile name:
+//
+// - `chip8`: the original COSMAC VIP interpreter
+// - `schip`: SUPER-CHIP 1.1 on the HP48
+// - `xochip`: Octo's XO-CHIP
+// - `vip`: the COSMAC VIP as closely as it's documented, `chip8` plus the
+//   quirks few ROMs notice (the draws waiting for the display, FX0A waiting
+//   for the key to be released, the VIP's font)
+//
+// e.g. `--quirks schip,index-overflow`. `--cosmac` is `vip` at the VIP's
+// pace (`VIP_CPU_HZ`).
+
+// instructions per second of the VIP preset: about 10 a frame, the average
+// of the VIP, whose instructions took very different times
+pub const VIP_CPU_HZ: u32 = 600;
+
+use std::fmt;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Quirks {
+    // FX1E sets VF when I + VX goes past 0xFFF (Amiga interpreter,
+    // Spacefight 2091! relies on it)
+    pub index_overflow: bool,
+    // BNNN jumps to XNN + VX instead of NNN + V0 (CHIP-48, SCHIP)
+    pub jump_vx: bool,
+    // in lo-res, 00CN/00DN/00FB/00FC scroll by half the distance, as
+    // the SCHIP 1.1 interpreter does (it works in hi-res pixels)
+    pub half_pixel_scroll: bool,
+    // 8XY6/8XYE shift VY into VX instead of shifting VX (VIP, XO-CHIP)
+    pub shift_vy: bool,
+    // FX55/FX65 leave I past the last register (VIP, XO-CHIP)
+    pub load_store_increment: bool,
+    // 8XY1/8XY2/8XY3 reset VF (VIP)
+    pub vf_reset: bool,
+    // sprites are cut at the screen edges instead of wrapping around (VIP,
+    // SCHIP); where they start still wraps
+    pub clip_sprites: bool,
+    // DXYN waits for the start of the next frame, as the VIP drew during
+    // the display interrupt: one sprite a frame at most
+    pub display_wait: bool,
+    // FX0A waits for the key to be released, not just pressed (VIP)
+    pub key_release: bool,
+    // the hex digits as the VIP's ROM drew them (4, 7, 9, B and D differ)
+    pub vip_font: bool,
+    // the 4K of memory of CHIP-8 and SCHIP: a program past 3584 bytes
+    // doesn't load (XO-CHIP has 64K)
+    pub memory_4k: bool,
+}
+
+// the quirks of the interpreters, by profile name
+const PROFILES: [(&str, Quirks); 4] = [
+    (
+        "chip8",
+        Quirks {
+            index_overflow: false,
+            jump_vx: false,
+            half_pixel_scroll: false,
+            shift_vy: true,
+            load_store_increment: true,
+            vf_reset: true,
+            clip_sprites: true,
+            display_wait: false,
+            key_release: false,
+            vip_font: false,
+            memory_4k: true,
+        },
+    ),
+    (
+        "schip",
+        Quirks {
+            index_overflow: false,
+            jump_vx: true,
+            half_pixel_scroll: true,
+            shift_vy: false,
+            load_store_increment: false,
+            vf_reset: false,
+            clip_sprites: true,
+            display_wait: false,
+            key_release: false,
+            vip_font: false,
+            memory_4k: true,
+        },
+    ),
+    (
+        "xochip",
+        Quirks {
+            index_overflow: false,
+            jump_vx: false,
+            half_pixel_scroll: false,
+            shift_vy: true,
+            load_store_increment: true,
+            vf_reset: false,
+            clip_sprites: false,
+            display_wait: false,
+            key_release: false,
+            vip_font: false,
+            memory_4k: false,
+        },
+    ),
+    (
+        "vip",
+        Quirks {
+            index_overflow: false,
+            jump_vx: false,
+            half_pixel_scroll: false,
+            shift_vy: true,
+            load_store_increment: true,
+            vf_reset: true,
+            clip_sprites: true,
+            display_wait: true,
+            key_release: true,
+            vip_font: true,
+            memory_4k: true,
+        },
+    ),
+];
+
+impl Quirks {
+    // comma separated quirk and profile names
+    pub fn parse(text: &str) -> Result<Quirks, String> {
+        let mut quirks = Quirks::default();
+        for name in text.split(',').map(str::trim).filter(|n| !n.is_empty()) {
+            if let Some(profile) = Quirks::profile(name) {
+                quirks = quirks.or(&profile);
+                continue;
+            }
+            match name {
+                "index-overflow" => quirks.index_overflow = true,
+                "jump-vx" => quirks.jump_vx = true,
+                "half-pixel-scroll" => quirks.half_pixel_scroll = true,
+                "shift-vy" => quirks.shift_vy = true,
+                "load-store-increment" => quirks.load_store_increment = true,
+                "vf-reset" => quirks.vf_reset = true,
+                "clip-sprites" => quirks.clip_sprites = true,
+                "display-wait" => quirks.display_wait = true,
+                "key-release" => quirks.key_release = true,
+                "vip-font" => quirks.vip_font = true,
+                "memory-4k" => quirks.memory_4k = true,
+                _ => return Err(format!("unknown quirk `{}`", name)),
+            }
+        }
+        Ok(quirks)
+    }
+
+    // the quirks of the profile with that name
+    pub fn profile(name: &str) -> Option<Quirks> {
+        PROFILES.iter().find(|(n, _)| *n == name).map(|(_, q)| *q)
+    }
+
+    pub fn profile_names() -> Vec<&'static str> {
+        PROFILES.iter().map(|(name, _)| *name).collect()
+    }
+
+    // every quirk by name, and whether it's enabled
+    pub fn flags(&self) -> [(&'static str, bool); 11] {
+        [
+            ("index-overflow", self.index_overflow),
+            ("jump-vx", self.jump_vx),
+            ("half-pixel-scroll", self.half_pixel_scroll),
+            ("shift-vy", self.shift_vy),
+            ("load-store-increment", self.load_store_increment),
+            ("vf-reset", self.vf_reset),
+            ("clip-sprites", self.clip_sprites),
+            ("display-wait", self.display_wait),
+            ("key-release", self.key_release),
+            ("vip-font", self.vip_font),
+            ("memory-4k", self.memory_4k),
+        ]
+    }
+
+    // the quirks of both
+    fn or(self, other: &Quirks) -> Quirks {
+        Quirks {
+            index_overflow: self.index_overflow || other.index_overflow,
+            jump_vx: self.jump_vx || other.jump_vx,
+            half_pixel_scroll: self.half_pixel_scroll || other.half_pixel_scroll,
+            shift_vy: self.shift_vy || other.shift_vy,
+            load_store_increment: self.load_store_increment || other.load_store_increment,
+            vf_reset: self.vf_reset || other.vf_reset,
+            clip_sprites: self.clip_sprites || other.clip_sprites,
+            display_wait: self.display_wait || other.display_wait,
+            key_release: self.key_release || other.key_release,
+            vip_font: self.vip_font || other.vip_font,
+            memory_4k: self.memory_4k || other.memory_4k,
+        }
+    }
+}
+
+// the enabled quirks, as accepted by parse
+impl fmt::Display for Quirks {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let names: Vec<&str> = self
+            .flags()
+            .iter()
+            .filter(|(_, on)| *on)
+            .map(|(name, _)| *name)
+            .collect();
+        write!(f, "{}", names.join(","))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn quirks_parse() {
+        assert_eq!(Quirks::default(), Quirks::parse("").unwrap());
+        let q = Quirks::parse(" index-overflow,jump-vx").unwrap();
+        assert!(q.index_overflow && q.jump_vx);
+        assert!(!Quirks::parse("jump-vx").unwrap().index_overflow);
+        assert!(Quirks::parse("index-overflow,nope").is_err());
+        assert!(
+            Quirks::parse("half-pixel-scroll")
+                .unwrap()
+                .half_pixel_scroll
+        );
+
+        assert_eq!("index-overflow,jump-vx", q.to_string());
+        assert_eq!(q, Quirks::parse(&q.to_string()).unwrap());
+        assert_eq!("", Quirks::default().to_string());
+    }
+
+    #[test]
+    fn quirks_profiles() {
+        let vip = Quirks::parse("chip8").unwrap();
+        assert!(vip.shift_vy && vip.load_store_increment && vip.vf_reset && vip.clip_sprites);
+        assert!(!vip.jump_vx);
+        assert_eq!(
+            "shift-vy,load-store-increment,vf-reset,clip-sprites,memory-4k",
+            vip.to_string()
+        );
+
+        let schip = Quirks::parse("schip").unwrap();
+        assert!(schip.jump_vx && schip.clip_sprites && !schip.shift_vy);
+        let xochip = Quirks::parse("xochip").unwrap();
+        assert!(xochip.shift_vy && !xochip.clip_sprites && !xochip.vf_reset);
+        assert!(schip.memory_4k && !xochip.memory_4k);
+        let strict = Quirks::parse("vip").unwrap();
+        assert_eq!(
+            strict,
+            Quirks::parse("chip8,display-wait,key-release,vip-font").unwrap()
+        );
+        assert_ne!(vip, strict);
+        assert_eq!(strict, Quirks::parse(&strict.to_string()).unwrap());
+
+        assert_eq!(Some(schip), Quirks::profile("schip"));
+        assert_eq!(None, Quirks::profile("jump-vx"));
+        assert_eq!(
+            vec!["chip8", "schip", "xochip", "vip"],
+            Quirks::profile_names()
+        );
+
+        // a profile plus quirks of its own
+        let q = Quirks::parse("schip,index-overflow").unwrap();
+        assert!(q.index_overflow && q.jump_vx);
+        assert_eq!(q, Quirks::parse(&q.to_string()).unwrap());
+    }
+}
@@ -0,0 +1,225 @@
+// Input recordings: the keypad events of a run, each at the cycle it came
+// in, with what it takes to run the program the same way again: the ROM
+// hash, the seed of the CXNN random numbers, the quirks and the speed (the
+// timers tick every `cpu_hz / 60` cycles). A recording starts from a reset
+// machine. They're text, one event per line, to attach to bug reports:
+//
+//     chip8 inputs 1
+//     program = 8c6b1c5a9e2f0d37
+//     seed = 1234
+//     quirks = shift-vy,vf-reset
+//     cpu-hz = 700
+//     1520 down 5
+//     1604 up 5
+
+use crate::quirks::Quirks;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+const HEADER: &str = "chip8 inputs 1";
+
+// a keypad key going down or up at a cycle
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Event {
+    pub cycle: u64,
+    pub key: u8,
+    pub pressed: bool,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Recording {
+    pub program_hash: u64,
+    pub seed: u64,
+    pub quirks: Quirks,
+    pub cpu_hz: u32,
+    // in cycle order
+    pub events: Vec<Event>,
+}
+
+impl Recording {
+    pub fn new(program_hash: u64, seed: u64, quirks: Quirks, cpu_hz: u32) -> Recording {
+        Recording {
+            program_hash,
+            seed,
+            quirks,
+            cpu_hz,
+            events: Vec::new(),
+        }
+    }
+
+    pub fn push(&mut self, cycle: u64, key: u8, pressed: bool) {
+        self.events.push(Event {
+            cycle,
+            key,
+            pressed,
+        });
+    }
+
+    pub fn parse(text: &str) -> Result<Recording, String> {
+        let mut lines = text
+            .lines()
+            .map(str::trim)
+            .enumerate()
+            .filter(|(_, l)| !l.is_empty() && !l.starts_with('#'));
+        if lines.next().map(|(_, l)| l) != Some(HEADER) {
+            return Err(format!("not an input recording (no `{}` line)", HEADER));
+        }
+
+        let (mut program_hash, mut seed, mut quirks, mut cpu_hz) = (None, None, None, None);
+        let mut events: Vec<Event> = Vec::new();
+        for (n, line) in lines {
+            let invalid = || format!("line {}: invalid `{}`", n + 1, line);
+            if let Some((key, value)) = line.split_once('=') {
+                let value = value.trim();
+                match key.trim() {
+                    "program" => {
+                        program_hash = Some(u64::from_str_radix(value, 16).map_err(|_| invalid())?)
+                    }
+                    "seed" => seed = Some(value.parse().map_err(|_| invalid())?),
+                    "quirks" => {
+                        quirks = Some(
+                            Quirks::parse(value).map_err(|e| format!("line {}: {}", n + 1, e))?,
+                        )
+                    }
+                    "cpu-hz" => {
+                        cpu_hz = Some(
+                            value
+                                .parse()
+                                .ok()
+                                .filter(|hz| *hz > 0)
+                                .ok_or_else(invalid)?,
+                        )
+                    }
+                    _ => return Err(format!("line {}: unknown setting `{}`", n + 1, key.trim())),
+                }
+                continue;
+            }
+
+            let mut fields = line.split_whitespace();
+            let event = match (fields.next(), fields.next(), fields.next(), fields.next()) {
+                (Some(cycle), Some(state @ ("down" | "up")), Some(key), None) => Event {
+                    cycle: cycle.parse().map_err(|_| invalid())?,
+                    key: u8::from_str_radix(key, 16)
+                        .ok()
+                        .filter(|k| *k < 16)
+                        .ok_or_else(invalid)?,
+                    pressed: state == "down",
+                },
+                _ => return Err(format!("line {}: expected `CYCLE down|up KEY`", n + 1)),
+            };
+            if events.last().is_some_and(|last| last.cycle > event.cycle) {
+                return Err(format!("line {}: events out of order", n + 1));
+            }
+            events.push(event);
+        }
+
+        let missing = |name| format!("no `{}` setting", name);
+        Ok(Recording {
+            program_hash: program_hash.ok_or_else(|| missing("program"))?,
+            seed: seed.ok_or_else(|| missing("seed"))?,
+            quirks: quirks.unwrap_or_default(),
+            cpu_hz: cpu_hz.ok_or_else(|| missing("cpu-hz"))?,
+            events,
+        })
+    }
+
+    pub fn to_text(&self) -> String {
+        let mut text = format!(
+            "{}\nprogram = {:016x}\nseed = {}\nquirks = {}\ncpu-hz = {}\n",
+            HEADER, self.program_hash, self.seed, self.quirks, self.cpu_hz
+        );
+        for e in &self.events {
+            let state = if e.pressed { "down" } else { "up" };
+            text.push_str(&format!("{} {} {:x}\n", e.cycle, state, e.key));
+        }
+        text
+    }
+
+    pub fn load(path: &Path) -> io::Result<Recording> {
+        let text = fs::read_to_string(path)?;
+        Recording::parse(&text).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+    }
+
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        fs::write(path, self.to_text())
+    }
+}
+
+// a recording played back, its events handed out as their cycle comes
+pub struct Replay {
+    recording: Recording,
+    next: usize,
+}
+
+impl Replay {
+    pub fn new(recording: Recording) -> Replay {
+        Replay { recording, next: 0 }
+    }
+
+    pub fn recording(&self) -> &Recording {
+        &self.recording
+    }
+
+    // the events due once `cycle` cycles have run
+    pub fn due(&mut self, cycle: u64) -> &[Event] {
+        let start = self.next;
+        let events = &self.recording.events;
+        while self.next < events.len() && events[self.next].cycle <= cycle {
+            self.next += 1;
+        }
+        &events[start..self.next]
+    }
+
+    pub fn finished(&self) -> bool {
+        self.next == self.recording.events.len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn replay_parse() {
+        let mut recording = Recording::new(0x8c6b, 1234, Quirks::parse("chip8").unwrap(), 700);
+        recording.push(1520, 0x5, true);
+        recording.push(1604, 0xA, false);
+        let text = recording.to_text();
+        assert!(text.starts_with("chip8 inputs 1\nprogram = 0000000000008c6b\n"));
+        assert!(text.ends_with("1520 down 5\n1604 up a\n"), "{}", text);
+        assert_eq!(recording, Recording::parse(&text).unwrap());
+
+        let header = "chip8 inputs 1\nprogram = 1f\nseed = 1\ncpu-hz = 700\n";
+        assert_eq!(Quirks::default(), Recording::parse(header).unwrap().quirks);
+        assert!(Recording::parse("program = 1f").is_err());
+        assert!(Recording::parse("chip8 inputs 1\nseed = 1\ncpu-hz = 700").is_err());
+        assert!(Recording::parse(&format!("{}10 down 16", header)).is_err());
+        assert!(Recording::parse(&format!("{}10 held 1", header)).is_err());
+        assert!(Recording::parse(&format!("{}cpu-hz = 0", header)).is_err());
+        let e = Recording::parse(&format!("{}10 down 1\n5 up 1", header)).unwrap_err();
+        assert_eq!("line 6: events out of order", e);
+    }
+
+    #[test]
+    fn replay_due() {
+        let mut recording = Recording::new(0, 0, Quirks::default(), 700);
+        recording.push(10, 1, true);
+        recording.push(10, 2, true);
+        recording.push(20, 1, false);
+        let mut replay = Replay::new(recording);
+        assert!(replay.due(9).is_empty());
+        assert_eq!(2, replay.due(10).len());
+        assert!(replay.due(10).is_empty());
+        assert!(!replay.finished());
+        assert_eq!(
+            &[Event {
+                cycle: 20,
+                key: 1,
+                pressed: false
+            }],
+            replay.due(100)
+        );
+        assert!(replay.finished());
+    }
+}
@@ -0,0 +1,96 @@
+// Human readable dump of the machine state, to paste in bug reports. The
+// SDL frontend prints it when the interpreter panics.
+
+use crate::{parse_opcode, Machine};
+use std::fmt::Write;
+
+impl Machine {
+    pub fn report(&self) -> String {
+        let mut out = String::new();
+        // writing to a String can't fail
+        let _ = self.write_report(&mut out);
+        out
+    }
+
+    fn write_report(&self, out: &mut String) -> std::fmt::Result {
+        writeln!(out, "--- CHIP-8 machine report ---")?;
+        writeln!(
+            out,
+            "ROM: {} bytes, hash {:016X}",
+            self.program_size, self.program_hash
+        )?;
+        let quirks = self.quirks.to_string();
+        writeln!(
+            out,
+            "quirks: {}",
+            if quirks.is_empty() { "none" } else { &quirks }
+        )?;
+        writeln!(
+            out,
+            "PC: {:#05X}  I: {:#05X}  DT: {}  ST: {}  screen: {}x{}",
+            self.pc,
+            self.index_register,
+            self.delay_timer,
+            self.sound_timer,
+            self.gfx.width(),
+            self.gfx.height()
+        )?;
+
+        for (i, values) in self.registers.chunks(4).enumerate() {
+            let cells: Vec<String> = values
+                .iter()
+                .enumerate()
+                .map(|(j, v)| format!("V{:X}: {:02X}", i * 4 + j, v))
+                .collect();
+            writeln!(out, "{}", cells.join("  "))?;
+        }
+
+        let stack: Vec<String> = self.stack.iter().map(|a| format!("{:#05X}", a)).collect();
+        writeln!(out, "stack: [{}]", stack.join(", "))?;
+
+        if let Some(v) = &self.violation {
+            writeln!(out, "memory protection: {}", v)?;
+        }
+
+        writeln!(out, "last opcodes:")?;
+        for (pc, op) in &self.recent {
+            writeln!(
+                out,
+                "  {:#05X}: {:04X}  {:?}",
+                pc,
+                op,
+                parse_opcode(Some(*op))
+            )?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn report_contents() {
+        let mut m = Machine::new();
+        m.quirks.jump_vx = true;
+        m.init();
+        m.load_program(vec![
+            0x6A, 0x2B, // VA = 0x2B
+            0x22, 0x06, // call 0x206
+            0x00, 0x00, //
+            0xA3, 0x00, // 0x206: I = 0x300
+        ])
+        .unwrap();
+        while m.exec_single().is_ok() {}
+
+        let r = m.report();
+        assert!(r.contains("ROM: 8 bytes"));
+        assert!(r.contains("quirks: jump-vx"));
+        assert!(r.contains("PC: 0x208  I: 0x300"));
+        assert!(r.contains("V8: 00  V9: 00  VA: 2B  VB: 00"));
+        assert!(r.contains("stack: [0x202]"));
+        assert!(r.contains("  0x206: A300  SetIR(768)"));
+        assert!(r.ends_with("  0x208: 0000  Invalid\n"));
+    }
+}
@@ -0,0 +1,76 @@
+// Random numbers for CXNN. A xorshift64* generator: not cryptographic, but
+// its whole state is one u64, so it fits in snapshots and a restored machine
+// keeps drawing the same numbers.
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct XorShiftRng {
+    // never 0
+    state: u64,
+}
+
+impl XorShiftRng {
+    pub fn seed_from_u64(seed: u64) -> XorShiftRng {
+        // splitmix64, so that close seeds give unrelated sequences
+        let mut z = seed.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^= z >> 31;
+        XorShiftRng { state: z.max(1) }
+    }
+
+    pub fn from_state(state: u64) -> Option<XorShiftRng> {
+        if state == 0 {
+            return None;
+        }
+        Some(XorShiftRng { state })
+    }
+
+    pub fn state(&self) -> u64 {
+        self.state
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        let mut x = self.state;
+        x ^= x >> 12;
+        x ^= x << 25;
+        x ^= x >> 27;
+        self.state = x;
+        x.wrapping_mul(0x2545_F491_4F6C_DD1D)
+    }
+
+    pub fn next_u8(&mut self) -> u8 {
+        // the high bits are the best ones
+        (self.next_u64() >> 56) as u8
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rng_resumes_from_state() {
+        let mut a = XorShiftRng::seed_from_u64(7);
+        a.next_u64();
+        let mut b = XorShiftRng::from_state(a.state()).unwrap();
+        let xs: Vec<u8> = (0..32).map(|_| a.next_u8()).collect();
+        let ys: Vec<u8> = (0..32).map(|_| b.next_u8()).collect();
+        assert_eq!(xs, ys);
+
+        assert_ne!(
+            XorShiftRng::seed_from_u64(1).next_u64(),
+            XorShiftRng::seed_from_u64(2).next_u64()
+        );
+        assert_eq!(None, XorShiftRng::from_state(0));
+    }
+
+    #[test]
+    fn rng_uses_all_byte_values() {
+        let mut rng = XorShiftRng::seed_from_u64(0);
+        let mut seen = [false; 256];
+        for _ in 0..10_000 {
+            seen[usize::from(rng.next_u8())] = true;
+        }
+        assert!(seen.iter().all(|s| *s));
+    }
+}
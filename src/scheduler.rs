@@ -0,0 +1,358 @@
+// Instruction scheduling for the frontend loop. Instead of a fixed number
+// of instructions per frame, each frame runs as many instructions as the
+// real time elapsed since the previous one is worth at the target rate, so
+// the emulated speed holds even when a frame takes longer than planned
+// (slow rendering, overlays, recording...).
+//
+// The timers tick at 60 Hz of emulated time whatever the speed: the timer
+// clock counts the instructions run and says when a tick is due, in the
+// frontend and in the headless runs alike.
+//
+// The OS wakes the frontend up late, by a fairly steady amount plus noise.
+// A PI controller takes the lateness of the frames off the next sleeps: the
+// proportional part evens out a late frame, the integral one cancels the
+// steady oversleep, so frames come at the planned rate. How close they
+// come is measured over each second, for the perf stats.
+//
+// The instructions owed and the corrections are counted in nanoseconds,
+// in integers, so the same times give the same batches on every platform;
+// only the perf stats shown to the user are floats.
+
+use crate::TIMER_HZ;
+use std::fmt;
+use std::time::{Duration, Instant};
+
+// never try to catch up more than this, e.g. after the window was dragged
+const MAX_CATCH_UP: Duration = Duration::from_millis(250);
+const NANOS_PER_SEC: u64 = 1_000_000_000;
+// gains of the sleep correction, in tenths
+const PACING_KP: i64 = 3;
+const PACING_KI: i64 = 1;
+// the pacing stats are renewed this often
+const PACING_WINDOW: Duration = Duration::from_secs(1);
+
+// how the frames kept time over the last second
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Pacing {
+    pub fps: f64,
+    // mean and largest distance of a frame's length from the planned one
+    pub jitter: Duration,
+    pub worst: Duration,
+    // the rate the timers ticked at in real time, 60 when on time
+    pub timer_hz: f64,
+}
+
+#[allow(clippy::float_arithmetic)]
+impl fmt::Display for Pacing {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{:.1} fps, jitter {:.2} ms (worst {:.2}), timers {:.2} Hz",
+            self.fps,
+            self.jitter.as_secs_f64() * 1000.0,
+            self.worst.as_secs_f64() * 1000.0,
+            self.timer_hz
+        )
+    }
+}
+
+// frames and instructions of the second being measured
+struct Window {
+    start: Instant,
+    frames: u32,
+    instructions: u64,
+    deviation: Duration,
+    worst: Duration,
+}
+
+impl Window {
+    fn new(start: Instant) -> Window {
+        Window {
+            start,
+            frames: 0,
+            instructions: 0,
+            deviation: Duration::ZERO,
+            worst: Duration::ZERO,
+        }
+    }
+}
+
+pub struct Scheduler {
+    // instructions per second
+    ips: u32,
+    frame: Duration,
+    last: Instant,
+    frame_start: Instant,
+    // fraction of an instruction owed by the previous frames, in
+    // billionths
+    owed: u64,
+    // the previous frame slept (rather than waited on events), its length
+    // tells how late the OS was
+    paced: bool,
+    // running sum of the frames' lateness, and the nanoseconds taken off
+    // the next sleep
+    lateness: i64,
+    correction: i64,
+    window: Window,
+    pacing: Option<Pacing>,
+}
+
+impl Scheduler {
+    pub fn new(ips: u32, frame: Duration, now: Instant) -> Scheduler {
+        Scheduler {
+            ips,
+            frame,
+            last: now,
+            frame_start: now,
+            owed: 0,
+            paced: false,
+            lateness: 0,
+            correction: 0,
+            window: Window::new(now),
+            pacing: None,
+        }
+    }
+
+    // start a frame: the number of instructions to run for the time elapsed
+    // since the previous one
+    pub fn batch(&mut self, now: Instant) -> usize {
+        if self.paced {
+            self.pace(now.saturating_duration_since(self.frame_start));
+        }
+        self.paced = true;
+        let elapsed = now.saturating_duration_since(self.last).min(MAX_CATCH_UP);
+        self.last = now;
+        self.frame_start = now;
+
+        // less than MAX_CATCH_UP, it can't overflow
+        self.owed += elapsed.as_nanos() as u64 * u64::from(self.ips);
+        let n = self.owed / NANOS_PER_SEC;
+        self.owed %= NANOS_PER_SEC;
+        self.count(now, 1, n);
+        n as usize
+    }
+
+    // correct the next sleeps by the length of the last frame
+    fn pace(&mut self, length: Duration) {
+        let frame = self.frame.as_nanos() as i64;
+        // a frame held up by the program or the window isn't the OS's doing
+        let late = (length.as_nanos() as i64 - frame).clamp(-frame, frame);
+        let most = frame * 10 / PACING_KI;
+        self.lateness = (self.lateness + late).clamp(-most, most);
+        self.correction =
+            ((PACING_KP * late + PACING_KI * self.lateness) / 10).clamp(-frame, frame);
+
+        let deviation = length.abs_diff(self.frame);
+        self.window.deviation += deviation;
+        self.window.worst = self.window.worst.max(deviation);
+    }
+
+    // frames and instructions towards the pacing stats
+    #[allow(clippy::float_arithmetic)]
+    fn count(&mut self, now: Instant, frames: u32, instructions: u64) {
+        let w = &mut self.window;
+        w.frames += frames;
+        w.instructions += instructions;
+        let elapsed = now.saturating_duration_since(w.start);
+        if elapsed < PACING_WINDOW {
+            return;
+        }
+        let seconds = elapsed.as_secs_f64();
+        self.pacing = Some(Pacing {
+            fps: f64::from(w.frames) / seconds,
+            jitter: w.deviation / w.frames.max(1),
+            worst: w.worst,
+            timer_hz: w.instructions as f64 / seconds * f64::from(TIMER_HZ) / f64::from(self.ips),
+        });
+        self.window = Window::new(now);
+    }
+
+    // plan frames of another length from the next one on, e.g. fewer of
+    // them to save power; the instructions keep their rate
+    pub fn set_frame(&mut self, frame: Duration) {
+        self.frame = frame;
+        // the corrections were made for the old length
+        self.paced = false;
+        self.lateness = 0;
+        self.correction = 0;
+    }
+
+    // the planned length of a frame
+    pub fn frame(&self) -> Duration {
+        self.frame
+    }
+
+    // the stats of the last full second, none before the first one
+    pub fn pacing(&self) -> Option<Pacing> {
+        self.pacing
+    }
+
+    // drop the time elapsed since the previous frame (spent idle), returning
+    // the number of instructions it was worth
+    pub fn skip(&mut self, now: Instant) -> usize {
+        let elapsed = now.saturating_duration_since(self.last);
+        self.last = now;
+        self.paced = false;
+        let n = (elapsed.as_nanos() * u128::from(self.ips) / u128::from(NANOS_PER_SEC)) as u64;
+        self.count(now, 0, n);
+        n as usize
+    }
+
+    // time taken by `n` instructions
+    pub fn duration_of(&self, n: u32) -> Duration {
+        Duration::from_secs(u64::from(n)) / self.ips
+    }
+
+    // how long to wait before the next frame, what's left of the frame
+    // after the work done since it started, less the expected lateness
+    pub fn sleep_time(&self, now: Instant) -> Duration {
+        let left = self
+            .frame
+            .saturating_sub(now.saturating_duration_since(self.frame_start));
+        let correction = Duration::from_nanos(self.correction.unsigned_abs());
+        if self.correction >= 0 {
+            left.saturating_sub(correction)
+        } else {
+            left + correction
+        }
+    }
+}
+
+pub struct TimerClock {
+    // instructions per second
+    ips: u32,
+    // instructions run
+    cycle: u64,
+}
+
+impl TimerClock {
+    pub fn new(ips: u32) -> TimerClock {
+        TimerClock { ips, cycle: 0 }
+    }
+
+    pub fn ips(&self) -> u32 {
+        self.ips
+    }
+
+    // the timer ticks due after `n` more instructions
+    pub fn advance(&mut self, n: u64) -> u64 {
+        let ips = u64::from(self.ips);
+        let ticks = |cycle: u64| cycle * u64::from(TIMER_HZ) / ips;
+        let before = ticks(self.cycle);
+        self.cycle += n;
+        ticks(self.cycle) - before
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const FRAME: Duration = Duration::from_millis(10);
+
+    #[test]
+    fn scheduler_holds_the_rate() {
+        let start = Instant::now();
+        let mut s = Scheduler::new(700, FRAME, start);
+
+        // 700 ips is 7 instructions per 10ms frame
+        assert_eq!(7, s.batch(start + FRAME));
+        // a frame that took 3 times as long runs 3 times the instructions
+        assert_eq!(21, s.batch(start + FRAME * 4));
+
+        // fractions carry over: 0.35 + 0.35 + 0.35
+        let mut s = Scheduler::new(35, FRAME, start);
+        assert_eq!(0, s.batch(start + FRAME));
+        assert_eq!(0, s.batch(start + FRAME * 2));
+        assert_eq!(1, s.batch(start + FRAME * 3));
+    }
+
+    #[test]
+    fn scheduler_limits_catch_up() {
+        let start = Instant::now();
+        let mut s = Scheduler::new(1000, FRAME, start);
+        assert_eq!(250, s.batch(start + Duration::from_secs(10)));
+    }
+
+    #[test]
+    fn scheduler_skips_idle_time() {
+        let start = Instant::now();
+        let mut s = Scheduler::new(100, FRAME, start);
+        assert_eq!(1000, s.skip(start + Duration::from_secs(10)));
+        assert_eq!(1, s.batch(start + Duration::from_secs(10) + FRAME));
+        assert_eq!(Duration::from_millis(50), s.duration_of(5));
+    }
+
+    #[test]
+    fn scheduler_corrects_oversleep() {
+        // every sleep ends 1.5 ms late, with +/- 0.5 ms of noise
+        let oversleep = |i: u32| Duration::from_micros(1500 + u64::from(i * 7919 % 1000) - 500);
+        let mut now = Instant::now();
+        let mut s = Scheduler::new(700, FRAME, now);
+        let start = now;
+        for i in 0..600 {
+            s.batch(now);
+            now += Duration::from_millis(1);
+            now += s.sleep_time(now) + oversleep(i);
+        }
+        // 600 frames of 10 ms within 1%, 6.9 s without the correction
+        let taken = now - start;
+        assert!(taken > FRAME * 594 && taken < FRAME * 606, "{:?}", taken);
+
+        let pacing = s.pacing().unwrap();
+        assert!((pacing.fps - 100.0).abs() < 1.0, "{}", pacing);
+        assert!((pacing.timer_hz - 60.0).abs() < 0.5, "{}", pacing);
+        assert!(pacing.jitter < Duration::from_millis(1), "{}", pacing);
+        assert!(pacing.worst < Duration::from_millis(2), "{}", pacing);
+
+        // the wait on events isn't held against the OS
+        let correction = s.correction;
+        assert!((correction - 1_500_000).abs() < 500_000, "{}", correction);
+        s.skip(now + Duration::from_secs(1));
+        s.batch(now + Duration::from_secs(2));
+        assert_eq!(correction, s.correction);
+    }
+
+    #[test]
+    fn scheduler_changes_frame_length() {
+        let start = Instant::now();
+        let mut s = Scheduler::new(700, FRAME, start);
+        assert_eq!(7, s.batch(start + FRAME));
+        // frames twice as long run twice the instructions, and sleep longer
+        s.set_frame(FRAME * 2);
+        assert_eq!(FRAME * 2, s.frame());
+        assert_eq!(14, s.batch(start + FRAME * 3));
+        assert_eq!(FRAME * 2, s.sleep_time(start + FRAME * 3));
+    }
+
+    #[test]
+    fn timer_clock_ticks_at_60hz() {
+        let mut clock = TimerClock::new(700);
+        let ticks: u64 = (0..700).map(|_| clock.advance(1)).sum();
+        assert_eq!(60, ticks);
+        // the 12th instruction is the first to end past 1/60 s
+        let mut clock = TimerClock::new(700);
+        let first = (1..=700).find(|_| clock.advance(1) == 1);
+        assert_eq!(Some(12), first);
+        // 600 in ten seconds
+        assert_eq!(599, clock.advance(7000 - 12));
+
+        let mut clock = TimerClock::new(60);
+        assert!((0..10).all(|_| clock.advance(1) == 1));
+    }
+
+    #[test]
+    fn scheduler_sleeps_the_rest_of_the_frame() {
+        let start = Instant::now();
+        let mut s = Scheduler::new(700, FRAME, start);
+        s.batch(start);
+        assert_eq!(
+            Duration::from_millis(6),
+            s.sleep_time(start + Duration::from_millis(4))
+        );
+        assert_eq!(
+            Duration::ZERO,
+            s.sleep_time(start + Duration::from_millis(12))
+        );
+    }
+}
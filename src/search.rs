@@ -0,0 +1,147 @@
+// Memory search, the way cheat finders work: every byte of memory starts
+// as a candidate, then each step keeps the ones that changed as what's seen
+// on screen did ("the lives went down", "the score is 3"), until the few
+// left hold it. Steps compare a byte with its value at the previous step:
+//
+//     eq N       equals N
+//     inc, dec   went up, went down
+//     same       didn't change
+//     changed    did
+//
+// The debugger runs them as `search start` and `search eq 3`... (see
+// debugger.rs), the candidates can then be watched or aliased.
+
+use crate::watch::parse_number;
+use crate::{Machine, MEMORY_SIZE};
+use std::convert::TryFrom;
+use std::fmt::Write;
+
+// candidates listed at most, past that only their number is given
+pub const MAX_LISTED: usize = 16;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Step {
+    Equals(u8),
+    Increased,
+    Decreased,
+    Unchanged,
+    Changed,
+}
+
+impl Step {
+    pub fn parse(args: &[&str]) -> Result<Step, String> {
+        let step = match args {
+            ["eq", n] => Step::Equals(
+                parse_number(n)
+                    .and_then(|n| u8::try_from(n).ok())
+                    .ok_or_else(|| format!("invalid value `{}`", n))?,
+            ),
+            ["inc"] => Step::Increased,
+            ["dec"] => Step::Decreased,
+            ["same"] => Step::Unchanged,
+            ["changed"] => Step::Changed,
+            ["eq"] => return Err(String::from("missing value for `eq`")),
+            [name, ..] => return Err(format!("unknown search `{}`", name)),
+            [] => return Err(String::from("missing search")),
+        };
+        Ok(step)
+    }
+
+    fn keeps(&self, last: u8, now: u8) -> bool {
+        match self {
+            Step::Equals(n) => now == *n,
+            Step::Increased => now > last,
+            Step::Decreased => now < last,
+            Step::Unchanged => now == last,
+            Step::Changed => now != last,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Search {
+    // the addresses left and their value at the last step
+    candidates: Vec<(usize, u8)>,
+}
+
+impl Search {
+    // every byte of memory
+    pub fn new(m: &Machine) -> Search {
+        Search {
+            candidates: m.memory.iter().copied().enumerate().collect(),
+        }
+    }
+
+    // keep the candidates the step matches, with their new value
+    pub fn narrow(&mut self, m: &Machine, step: Step) {
+        self.candidates.retain_mut(|(address, last)| {
+            let now = m.memory[*address];
+            let keep = step.keeps(*last, now);
+            *last = now;
+            keep
+        });
+    }
+
+    pub fn candidates(&self) -> &[(usize, u8)] {
+        &self.candidates
+    }
+
+    // how many are left, and which when there are few
+    pub fn summary(&self) -> String {
+        let mut out = match self.candidates.len() {
+            MEMORY_SIZE => format!("{} candidates, the whole memory\n", MEMORY_SIZE),
+            1 => String::from("1 candidate\n"),
+            n => format!("{} candidates\n", n),
+        };
+        if self.candidates.len() <= MAX_LISTED {
+            for (address, value) in &self.candidates {
+                let _ = writeln!(out, "{:#05X}: {:#04X}", address, value);
+            }
+        }
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn search_steps() {
+        assert_eq!(Ok(Step::Equals(3)), Step::parse(&["eq", "3"]));
+        assert_eq!(Ok(Step::Equals(0x2F)), Step::parse(&["eq", "0x2F"]));
+        assert_eq!(Ok(Step::Increased), Step::parse(&["inc"]));
+        assert_eq!(Ok(Step::Changed), Step::parse(&["changed"]));
+        assert!(Step::parse(&["eq", "256"]).is_err());
+        assert!(Step::parse(&["eq"]).is_err());
+        assert!(Step::parse(&["up"]).is_err());
+        assert!(Step::parse(&[]).is_err());
+    }
+
+    #[test]
+    fn search_narrows() {
+        // lives at 0x300, 3 of them; a counter at 0x301; the loop loses a
+        // life whenever V2 wraps around
+        let mut m = Machine::new();
+        m.init();
+        m.load_program_hex("A300 6003 F055 7201 3200 1206 A300 F065 70FF F055 1206")
+            .unwrap();
+        m.run_for(3).unwrap();
+        let mut search = Search::new(&m);
+        assert_eq!(MEMORY_SIZE, search.candidates().len());
+        assert!(search
+            .summary()
+            .starts_with("65536 candidates, the whole memory\n"));
+
+        search.narrow(&m, Step::Equals(3));
+        assert!(search.candidates().len() > 1);
+        // a life lost
+        m.run_for(800).unwrap();
+        search.narrow(&m, Step::Decreased);
+        search.narrow(&m, Step::Equals(2));
+        m.run_for(50).unwrap();
+        search.narrow(&m, Step::Unchanged);
+        assert_eq!(&[(0x300, 2)], search.candidates());
+        assert_eq!("1 candidate\n0x300: 0x02\n", search.summary());
+    }
+}
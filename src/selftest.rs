@@ -0,0 +1,257 @@
+// Self test: run test ROMs headless until they halt and compare the screen
+// they leave with the expected one stored next to the ROM (`NAME.expected`,
+// one line per row, `#` for a lit pixel). Made for test suites like
+// https://github.com/Timendus/chip8-test-suite whose ROMs draw their
+// results and then spin on a jump to themselves.
+//
+//     chip8 selftest [--bless] [--cycles N] [--select N] [rom or dir...]
+//
+// `--bless` records the current screens as the expected ones, `--select`
+// answers the menu of the ROMs that have one (the value is stored at 0x1FF).
+// The command fails when any ROM doesn't match, so it can be used in CI.
+
+use crate::exit;
+use crate::gameover::GameOverDetector;
+use crate::scheduler::TimerClock;
+use crate::{Chip8Error, Machine, CPU_IPS};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+const DEFAULT_CYCLES: usize = 1_000_000;
+const DEFAULT_DIR: &str = "./data";
+// where Timendus ROMs look for a preselected menu entry
+const SELECT_ADDRESS: usize = 0x1FF;
+
+struct Options {
+    bless: bool,
+    cycles: usize,
+    select: Option<u8>,
+    roms: Vec<PathBuf>,
+}
+
+#[derive(Debug, PartialEq)]
+enum Verdict {
+    Pass,
+    // number of pixels that differ
+    Fail(usize),
+    // the ROM didn't halt within the cycle budget
+    Timeout,
+    Missing,
+}
+
+fn parse_args(args: &[String]) -> Result<Options, String> {
+    let mut opts = Options {
+        bless: false,
+        cycles: DEFAULT_CYCLES,
+        select: None,
+        roms: Vec::new(),
+    };
+    let mut paths = Vec::new();
+
+    let mut it = args.iter();
+    while let Some(arg) = it.next() {
+        let mut value = |name: &str| it.next().ok_or(format!("missing value for {}", name));
+        match arg.as_str() {
+            "--bless" => opts.bless = true,
+            "--cycles" => {
+                let v = value(arg)?;
+                opts.cycles = v
+                    .parse()
+                    .map_err(|e| format!("invalid value `{}` for --cycles: {}", v, e))?;
+            }
+            "--select" => {
+                let v = value(arg)?;
+                opts.select = Some(
+                    v.parse()
+                        .map_err(|e| format!("invalid value `{}` for --select: {}", v, e))?,
+                );
+            }
+            s if s.starts_with("--") => return Err(format!("unknown option `{}`", s)),
+            path => paths.push(PathBuf::from(path)),
+        }
+    }
+
+    if paths.is_empty() {
+        paths.push(PathBuf::from(DEFAULT_DIR));
+    }
+    for path in paths {
+        if path.is_dir() {
+            opts.roms.extend(roms_in(&path).map_err(|e| e.to_string())?);
+        } else {
+            opts.roms.push(path);
+        }
+    }
+    Ok(opts)
+}
+
+fn roms_in(dir: &Path) -> io::Result<Vec<PathBuf>> {
+    let mut roms: Vec<PathBuf> = fs::read_dir(dir)?
+        .filter_map(|e| e.ok())
+        .map(|e| e.path())
+        .filter(|p| {
+            p.extension()
+                .is_some_and(|ext| ext == "ch8" || ext == "rom")
+        })
+        .collect();
+    roms.sort();
+    Ok(roms)
+}
+
+// run until the program halts, `None` if it doesn't within `cycles`
+fn run_until_halt(
+    program: &[u8],
+    cycles: usize,
+    select: Option<u8>,
+) -> Result<Option<Machine>, Chip8Error> {
+    let mut m = Machine::new();
+    m.init();
+    m.load_program(program.to_vec())?;
+    if let Some(v) = select {
+        m.memory[SELECT_ADDRESS] = v;
+    }
+
+    let mut halt = GameOverDetector::new(None);
+    let mut clock = TimerClock::new(CPU_IPS);
+    for _ in 0..cycles {
+        if m.exec_single().is_err() || halt.update(&m).is_some() {
+            return Ok(Some(m));
+        }
+        for _ in 0..clock.advance(1) {
+            m.tick_timers();
+        }
+    }
+    Ok(None)
+}
+
+pub(crate) fn screen_text(m: &Machine) -> String {
+    let mut text = String::new();
+    for row in m.gfx.pixels().chunks(m.gfx.width()) {
+        text.extend(row.iter().map(|p| if *p > 0 { '#' } else { '.' }));
+        text.push('\n');
+    }
+    text
+}
+
+fn compare(actual: &str, expected: &str) -> Verdict {
+    let lines = |s: &str| {
+        let text = s.lines().map(str::trim_end).collect::<Vec<_>>().join("\n");
+        text.trim_end().to_string()
+    };
+    let (actual, expected) = (lines(actual), lines(expected));
+    if actual == expected {
+        return Verdict::Pass;
+    }
+    let mut diff = actual
+        .chars()
+        .zip(expected.chars())
+        .filter(|(a, e)| a != e)
+        .count();
+    diff += actual.len().abs_diff(expected.len());
+    Verdict::Fail(diff)
+}
+
+fn check(rom: &Path, opts: &Options) -> io::Result<Verdict> {
+    let expected_path = rom.with_extension("expected");
+    let expected = match fs::read_to_string(&expected_path) {
+        Ok(e) => e,
+        Err(e) if e.kind() == io::ErrorKind::NotFound && !opts.bless => {
+            return Ok(Verdict::Missing)
+        }
+        Err(e) if e.kind() == io::ErrorKind::NotFound => String::new(),
+        Err(e) => return Err(e),
+    };
+
+    let program = fs::read(rom).map_err(|e| exit::rom_load(&rom.to_string_lossy(), e))?;
+    let halted = run_until_halt(&program, opts.cycles, opts.select).map_err(|e| {
+        exit::rom_load(
+            &rom.to_string_lossy(),
+            io::Error::new(io::ErrorKind::InvalidData, e),
+        )
+    })?;
+    let m = match halted {
+        Some(m) => m,
+        None => return Ok(Verdict::Timeout),
+    };
+    let actual = screen_text(&m);
+    if opts.bless {
+        fs::write(&expected_path, &actual)?;
+        return Ok(Verdict::Pass);
+    }
+
+    let verdict = compare(&actual, &expected);
+    if verdict != Verdict::Pass {
+        // keep what we got next to it, for a look or a diff
+        fs::write(rom.with_extension("actual"), &actual)?;
+    }
+    Ok(verdict)
+}
+
+pub fn run(args: &[String]) -> io::Result<()> {
+    let opts = parse_args(args).map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+
+    let mut failed = 0;
+    for rom in &opts.roms {
+        let verdict = match check(rom, &opts) {
+            // already names the ROM
+            Err(e) if exit::code(&e) == exit::ROM_LOAD => return Err(e),
+            r => r.map_err(|e| io::Error::new(e.kind(), format!("{}: {}", rom.display(), e)))?,
+        };
+        match verdict {
+            Verdict::Pass if opts.bless => println!("BLESS {}", rom.display()),
+            Verdict::Pass => println!("PASS  {}", rom.display()),
+            Verdict::Missing => println!("SKIP  {} (no .expected screen)", rom.display()),
+            Verdict::Fail(diff) => {
+                failed += 1;
+                println!("FAIL  {} ({} pixels differ)", rom.display(), diff);
+            }
+            Verdict::Timeout => {
+                failed += 1;
+                println!(
+                    "FAIL  {} (didn't halt in {} cycles)",
+                    rom.display(),
+                    opts.cycles
+                );
+            }
+        }
+    }
+
+    println!("selftest: {} ROMs, {} failed", opts.roms.len(), failed);
+    if failed > 0 {
+        return Err(exit::check_failed(format!("{} ROMs failed", failed)));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::GFX_WIDTH;
+
+    #[test]
+    fn selftest_compare() {
+        assert_eq!(Verdict::Pass, compare("#.#\n...\n", "#.#  \n...\n\n"));
+        assert_eq!(Verdict::Fail(2), compare("#.#\n...\n", "#..\n#..\n"));
+    }
+
+    #[test]
+    fn selftest_runs_until_halt() {
+        let program = [
+            0xA0, 0x00, // I = 0x000
+            0xD0, 0x05, // draw "0"
+            0x12, 0x04, // jump to itself
+        ];
+        let m = run_until_halt(&program, 1000, None).unwrap().unwrap();
+        let text = screen_text(&m);
+        let rows: Vec<&str> = text.lines().collect();
+        assert_eq!(crate::GFX_HEIGHT, rows.len());
+        assert_eq!(GFX_WIDTH, rows[0].len());
+        assert!(rows[0].starts_with("####."));
+        assert!(rows[1].starts_with("#..#."));
+
+        assert!(run_until_halt(&[0x12, 0x02, 0x12, 0x00], 1000, None)
+            .unwrap()
+            .is_none());
+        assert!(run_until_halt(&[0; crate::MAX_PROGRAM_SIZE + 1], 1000, None).is_err());
+    }
+}
@@ -0,0 +1,176 @@
+// Just enough HTTP/1.1 for the playground: a request line, headers and a
+// body of Content-Length bytes, one request per connection, answered with
+// a response that closes it. Anything bigger than the limits is refused
+// before it's read.
+
+use std::io::{self, BufRead, Read, Write};
+
+// the request line and the headers
+const MAX_HEAD: u64 = 16 * 1024;
+
+#[derive(Debug, PartialEq)]
+pub struct Request {
+    pub method: String,
+    pub path: String,
+    // what follows `?`, empty when there's none
+    pub query: String,
+    // names in lowercase
+    pub headers: Vec<(String, String)>,
+    pub body: Vec<u8>,
+}
+
+// why a request couldn't be read: the status to answer with, and why
+#[derive(Debug, PartialEq)]
+pub struct Refused(pub &'static str, pub String);
+
+impl Request {
+    // a request with a body of at most `max_body` bytes
+    pub fn read(r: &mut impl BufRead, max_body: usize) -> Result<Request, Refused> {
+        let bad = |what: &str| Refused("400 Bad Request", what.to_string());
+        let mut head = r.by_ref().take(MAX_HEAD);
+        let mut line = String::new();
+        let mut lines = Vec::new();
+        loop {
+            line.clear();
+            match head.read_line(&mut line) {
+                Ok(0) => return Err(bad("the request ends early")),
+                Ok(_) if !line.ends_with('\n') => {
+                    return Err(Refused(
+                        "431 Request Header Fields Too Large",
+                        String::from("the headers are too long"),
+                    ))
+                }
+                Ok(_) => {}
+                Err(e) => return Err(bad(&e.to_string())),
+            }
+            let trimmed = line.trim_end();
+            if trimmed.is_empty() {
+                break;
+            }
+            lines.push(trimmed.to_string());
+        }
+
+        let mut request_line = lines
+            .first()
+            .ok_or_else(|| bad("no request line"))?
+            .split(' ');
+        let (method, target) = match (request_line.next(), request_line.next()) {
+            (Some(method), Some(target)) if !method.is_empty() => (method, target),
+            _ => return Err(bad("invalid request line")),
+        };
+        let (path, query) = target.split_once('?').unwrap_or((target, ""));
+        let mut request = Request {
+            method: method.to_string(),
+            path: path.to_string(),
+            query: query.to_string(),
+            headers: Vec::new(),
+            body: Vec::new(),
+        };
+        for header in &lines[1..] {
+            let (name, value) = header
+                .split_once(':')
+                .ok_or_else(|| bad("invalid header"))?;
+            request
+                .headers
+                .push((name.trim().to_lowercase(), value.trim().to_string()));
+        }
+
+        let len = match request.header("content-length") {
+            Some(len) => len.parse().map_err(|_| bad("invalid Content-Length"))?,
+            None => 0,
+        };
+        if len > max_body {
+            return Err(Refused(
+                "413 Payload Too Large",
+                format!("{} bytes at most", max_body),
+            ));
+        }
+        request.body = vec![0; len];
+        r.read_exact(&mut request.body)
+            .map_err(|e| bad(&e.to_string()))?;
+        Ok(request)
+    }
+
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(n, _)| n.eq_ignore_ascii_case(name))
+            .map(|(_, v)| v.as_str())
+    }
+
+    // a `name=value` of the query, values aren't decoded
+    pub fn param(&self, name: &str) -> Option<&str> {
+        self.query
+            .split('&')
+            .filter_map(|p| p.split_once('='))
+            .find(|(n, _)| *n == name)
+            .map(|(_, v)| v)
+    }
+}
+
+pub fn respond(
+    w: &mut impl Write,
+    status: &str,
+    content_type: &str,
+    body: &[u8],
+) -> io::Result<()> {
+    write!(
+        w,
+        "HTTP/1.1 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+        status,
+        content_type,
+        body.len()
+    )?;
+    w.write_all(body)?;
+    w.flush()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn http_request() {
+        let mut input: &[u8] =
+            b"POST /run?cycles=1000&quirks=schip HTTP/1.1\r\nHost: localhost\r\nContent-Length: 4\r\n\r\n\x12\x00\xAB\xCD";
+        let request = Request::read(&mut input, 100).unwrap();
+        assert_eq!("POST", request.method);
+        assert_eq!("/run", request.path);
+        assert_eq!(Some("1000"), request.param("cycles"));
+        assert_eq!(Some("schip"), request.param("quirks"));
+        assert_eq!(None, request.param("seed"));
+        assert_eq!(Some("localhost"), request.header("HOST"));
+        assert_eq!(vec![0x12, 0x00, 0xAB, 0xCD], request.body);
+
+        let request = Request::read(&mut &b"GET / HTTP/1.1\r\n\r\n"[..], 0).unwrap();
+        assert_eq!(
+            ("GET", "/", ""),
+            (&request.method[..], &request.path[..], &request.query[..])
+        );
+
+        let refused = |input: &[u8]| Request::read(&mut &input[..], 4).unwrap_err().0;
+        assert_eq!(
+            "413 Payload Too Large",
+            refused(b"POST /run HTTP/1.1\r\nContent-Length: 5\r\n\r\n12345")
+        );
+        assert_eq!("400 Bad Request", refused(b"GET / HTTP/1.1\r\n"));
+        assert_eq!("400 Bad Request", refused(b"GET\r\n\r\n"));
+        assert_eq!(
+            "400 Bad Request",
+            refused(b"POST / HTTP/1.1\r\nContent-Length: x\r\n\r\n")
+        );
+        let long = format!("GET / HTTP/1.1\r\nCookie: {}\r\n\r\n", "a".repeat(20_000));
+        assert_eq!(
+            "431 Request Header Fields Too Large",
+            refused(long.as_bytes())
+        );
+
+        let mut out = Vec::new();
+        respond(&mut out, "200 OK", "text/plain", b"hi").unwrap();
+        assert_eq!(
+            "HTTP/1.1 200 OK\r\nContent-Type: text/plain\r\nContent-Length: 2\r\n\
+             Connection: close\r\n\r\nhi",
+            String::from_utf8(out).unwrap()
+        );
+    }
+}
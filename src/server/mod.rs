@@ -0,0 +1,645 @@
+// Web playground, built with the `server` feature: an HTTP server playing
+// ROMs uploaded from a browser, to host the emulator for others.
+//
+//     chip8 serve [--bind ADDR] [--port N] [--sessions N] [--limits LIST]
+//
+//     GET  /                   the player (player.html): pick a ROM and play it
+//     GET  /play?quirks=LIST   WebSocket, a session playing one ROM
+//     POST /run?cycles=N&quirks=LIST
+//                              run the ROM in the body headless, answer how
+//                              it ended and the screen in Unicode blocks
+//
+// A play session reads the ROM from the first binary message, then keypad
+// messages `down 5` / `up 5`, and sends each frame that changed as a binary
+// message: width, height, then a byte per pixel (its planes, 0 to 3). Text
+// messages say `sound on` / `sound off`, and `halted <why>` before closing.
+// A player may send `annotations` and the lines of an annotation file (see
+// annotations.rs), before the ROM or after; the session then sends `values`
+// and a `name: value` line per annotation each time one changes.
+//
+// The ROMs can't be trusted: each machine runs with the execution limits
+// of --limits (see limits.rs), on a thread of its own, and --sessions caps
+// the connections served at once; the ones past it get a 503. A session
+// whose ROM doesn't come within REQUEST_TIMEOUT is closed, so idle
+// connections can't hold the slots.
+
+use crate::annotations::Annotations;
+use crate::clock::{self, Clock};
+use crate::limits::Limits;
+use crate::quirks::Quirks;
+use crate::scheduler::Scheduler;
+use crate::{headless, Machine, CPU_IPS, MAX_PROGRAM_SIZE};
+use http::{Refused, Request};
+use std::io::{self, BufReader, Write};
+use std::net::{Shutdown, TcpListener, TcpStream};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{mpsc, Arc};
+use std::thread;
+use std::time::{Duration, Instant};
+use websocket::Message;
+
+mod http;
+mod websocket;
+
+const PLAYER: &str = include_str!("player.html");
+const DEFAULT_PORT: u16 = 8080;
+const DEFAULT_SESSIONS: usize = 8;
+// 10 minutes of play at CPU_IPS, and time enough to run 10M instructions
+const DEFAULT_LIMITS: &str = "cycles=10000000,timeout=600";
+// instructions run for a POST /run without `cycles`
+const DEFAULT_RUN_CYCLES: u64 = 1_000_000;
+// how long a client may take to send its request, or a player its ROM
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+// frame rate of the play sessions
+const FRAME_PERIOD: Duration = Duration::from_nanos(1_000_000_000 / 60);
+// the largest message a player sends, a ROM (or annotations)
+const MAX_MESSAGE: usize = MAX_PROGRAM_SIZE;
+
+struct Options {
+    bind: String,
+    port: u16,
+    sessions: usize,
+    limits: Limits,
+}
+
+fn parse_args(args: &[String]) -> Result<Options, String> {
+    let mut opts = Options {
+        bind: String::from("127.0.0.1"),
+        port: DEFAULT_PORT,
+        sessions: DEFAULT_SESSIONS,
+        limits: Limits::parse(DEFAULT_LIMITS)?,
+    };
+    let mut it = args.iter();
+    while let Some(arg) = it.next() {
+        let mut value = |name: &str| it.next().ok_or(format!("missing value for {}", name));
+        match arg.as_str() {
+            "--bind" => opts.bind = value(arg)?.clone(),
+            "--port" => {
+                let v = value(arg)?;
+                opts.port = v.parse().map_err(|_| format!("invalid port `{}`", v))?;
+            }
+            "--sessions" => {
+                let v = value(arg)?;
+                opts.sessions = v
+                    .parse()
+                    .ok()
+                    .filter(|n| *n > 0)
+                    .ok_or(format!("invalid number of sessions `{}`", v))?;
+            }
+            "--limits" => opts.limits = Limits::parse(value(arg)?)?,
+            s => {
+                return Err(format!(
+                    "unexpected argument `{}`\nusage: chip8 serve [--bind ADDR] [--port N] \
+                     [--sessions N] [--limits LIST]",
+                    s
+                ))
+            }
+        }
+    }
+    Ok(opts)
+}
+
+pub fn run(args: &[String]) -> io::Result<()> {
+    let opts = parse_args(args).map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+    let listener = TcpListener::bind((opts.bind.as_str(), opts.port))?;
+    println!("playground on http://{}", listener.local_addr()?);
+    let sandbox = Sandbox {
+        limits: opts.limits,
+        clock: clock::system(),
+        rom_timeout: REQUEST_TIMEOUT,
+    };
+    serve(listener, opts.sessions, sandbox)
+}
+
+// what the machines of the sessions run with: the limits, on a clock, and
+// how long a player has to send its ROM
+#[derive(Clone)]
+struct Sandbox {
+    limits: Limits,
+    clock: Arc<dyn Clock>,
+    rom_timeout: Duration,
+}
+
+// a connection counted against --sessions while it lives
+struct Session(Arc<AtomicUsize>);
+
+impl Drop for Session {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+fn serve(listener: TcpListener, sessions: usize, sandbox: Sandbox) -> io::Result<()> {
+    let open = Arc::new(AtomicUsize::new(0));
+    for stream in listener.incoming() {
+        let mut stream = match stream {
+            Ok(stream) => stream,
+            Err(e) => {
+                println!("cannot accept a connection: {}", e);
+                continue;
+            }
+        };
+        if open.fetch_add(1, Ordering::SeqCst) >= sessions {
+            open.fetch_sub(1, Ordering::SeqCst);
+            let _ = http::respond(
+                &mut stream,
+                "503 Service Unavailable",
+                "text/plain",
+                b"too many sessions, try again later\n",
+            );
+            continue;
+        }
+        let session = Session(open.clone());
+        let sandbox = sandbox.clone();
+        thread::spawn(move || {
+            let _session = session;
+            if let Err(e) = handle(stream, &sandbox) {
+                println!("connection: {}", e);
+            }
+        });
+    }
+    Ok(())
+}
+
+fn handle(mut stream: TcpStream, sandbox: &Sandbox) -> io::Result<()> {
+    stream.set_read_timeout(Some(REQUEST_TIMEOUT))?;
+    let request = match Request::read(&mut BufReader::new(&stream), MAX_PROGRAM_SIZE) {
+        Ok(request) => request,
+        Err(Refused(status, why)) => {
+            return http::respond(
+                &mut stream,
+                status,
+                "text/plain",
+                format!("{}\n", why).as_bytes(),
+            )
+        }
+    };
+    let quirks = match Quirks::parse(request.param("quirks").unwrap_or("")) {
+        Ok(quirks) => quirks,
+        Err(e) => {
+            return http::respond(&mut stream, "400 Bad Request", "text/plain", e.as_bytes());
+        }
+    };
+    match (request.method.as_str(), request.path.as_str()) {
+        ("GET", "/") => http::respond(
+            &mut stream,
+            "200 OK",
+            "text/html; charset=utf-8",
+            PLAYER.as_bytes(),
+        ),
+        ("POST", "/run") => {
+            let (status, text) = match run_rom(&request, quirks, sandbox) {
+                Ok(text) => ("200 OK", text),
+                Err(e) => ("400 Bad Request", format!("{}\n", e)),
+            };
+            http::respond(
+                &mut stream,
+                status,
+                "text/plain; charset=utf-8",
+                text.as_bytes(),
+            )
+        }
+        ("GET", "/play") => match request.header("sec-websocket-key") {
+            Some(key) => {
+                write!(
+                    stream,
+                    "HTTP/1.1 101 Switching Protocols\r\nUpgrade: websocket\r\n\
+                     Connection: Upgrade\r\nSec-WebSocket-Accept: {}\r\n\r\n",
+                    websocket::accept_key(key)
+                )?;
+                stream.set_read_timeout(None)?;
+                play(stream, quirks, sandbox)
+            }
+            None => http::respond(
+                &mut stream,
+                "426 Upgrade Required",
+                "text/plain",
+                b"a WebSocket is needed\n",
+            ),
+        },
+        _ => http::respond(&mut stream, "404 Not Found", "text/plain", b"not found\n"),
+    }
+}
+
+// a machine with `program` loaded, run with the quirks in the sandbox
+fn machine(program: Vec<u8>, quirks: Quirks, sandbox: &Sandbox) -> Result<Machine, String> {
+    let mut m = Machine::new();
+    m.set_quirks(quirks);
+    m.set_limits(sandbox.limits);
+    m.set_clock(sandbox.clock.clone());
+    m.init();
+    m.load_program(program).map_err(|e| e.to_string())?;
+    Ok(m)
+}
+
+// POST /run: how the ROM ended and its screen
+fn run_rom(request: &Request, quirks: Quirks, sandbox: &Sandbox) -> Result<String, String> {
+    let cycles = match request.param("cycles") {
+        Some(v) => v.parse().map_err(|_| format!("invalid cycles `{}`", v))?,
+        None => DEFAULT_RUN_CYCLES,
+    };
+    let m = machine(request.body.clone(), quirks, sandbox)?;
+    let (summary, m) = headless::run_machine(m, Some(cycles));
+    Ok(format!(
+        "{}\n{}",
+        summary,
+        headless::blocks(m.framebuffer())
+    ))
+}
+
+// the frame message: width, height and the planes of each pixel
+fn frame_message(m: &Machine) -> Vec<u8> {
+    let fb = m.framebuffer();
+    let mut message = vec![fb.width() as u8, fb.height() as u8];
+    message.extend(fb.pixels().iter().map(|p| p & 0x3));
+    message
+}
+
+// the keypad key of a `down 5` / `up 5` message
+fn key_message(text: &str) -> Option<(u8, bool)> {
+    let (action, key) = text.split_once(' ')?;
+    let key = u8::from_str_radix(key, 16).ok().filter(|k| *k < 16)?;
+    match action {
+        "down" => Some((key, true)),
+        "up" => Some((key, false)),
+        _ => None,
+    }
+}
+
+// the annotations of an `annotations` message
+fn annotations_message(text: &str) -> Option<Result<Annotations, String>> {
+    text.strip_prefix("annotations\n").map(Annotations::parse)
+}
+
+// GET /play once upgraded: the messages are read on a thread of their own
+// while the machine runs on this one
+fn play(stream: TcpStream, quirks: Quirks, sandbox: &Sandbox) -> io::Result<()> {
+    let (sender, messages) = mpsc::channel();
+    let mut reader = stream.try_clone()?;
+    thread::spawn(move || loop {
+        let message = websocket::read_message(&mut reader, MAX_MESSAGE);
+        let end = !matches!(
+            message,
+            Ok(Message::Text(_)) | Ok(Message::Binary(_)) | Ok(Message::Ping(_))
+        );
+        if sender.send(message).is_err() || end {
+            break;
+        }
+    });
+
+    let mut out = stream;
+    let result = session(&mut out, &messages, quirks, sandbox);
+    let _ = match &result {
+        Ok(Some(why)) => websocket::write_message(
+            &mut out,
+            websocket::TEXT,
+            format!("halted {}", why).as_bytes(),
+        ),
+        _ => Ok(()),
+    };
+    let _ = websocket::write_message(&mut out, websocket::CLOSE, &[]);
+    // the reader stops on the closed socket
+    let _ = out.shutdown(Shutdown::Both);
+    result.map(|_| ())
+}
+
+// the session until the player leaves (none) or the machine halts (why)
+fn session(
+    out: &mut TcpStream,
+    messages: &mpsc::Receiver<io::Result<Message>>,
+    quirks: Quirks,
+    sandbox: &Sandbox,
+) -> io::Result<Option<String>> {
+    // the ROM comes first, in time (on the wall clock: no machine runs yet)
+    let deadline = Instant::now() + sandbox.rom_timeout;
+    let mut annotations = None;
+    let program = loop {
+        let left = deadline.saturating_duration_since(Instant::now());
+        match messages.recv_timeout(left) {
+            Ok(Ok(Message::Binary(program))) => break program,
+            Ok(Ok(Message::Ping(payload))) => {
+                websocket::write_message(out, websocket::PONG, &payload)?
+            }
+            Ok(Ok(Message::Text(text))) => match annotations_message(&text) {
+                Some(Ok(a)) => annotations = Some(a),
+                Some(Err(e)) => return Ok(Some(format!("invalid annotations: {}", e))),
+                None => {}
+            },
+            Err(mpsc::RecvTimeoutError::Timeout) => {
+                return Ok(Some(String::from("no ROM sent in time")))
+            }
+            Ok(Ok(Message::Close)) | Err(mpsc::RecvTimeoutError::Disconnected) => return Ok(None),
+            Ok(Err(e)) => return Err(e),
+        }
+    };
+    let mut m = match machine(program, quirks, sandbox) {
+        Ok(m) => m,
+        Err(e) => return Ok(Some(e)),
+    };
+
+    let clock = &sandbox.clock;
+    let mut scheduler = Scheduler::new(CPU_IPS, FRAME_PERIOD, clock.now());
+    let mut sound = false;
+    // the values last sent
+    let mut values = String::new();
+    loop {
+        loop {
+            match messages.try_recv() {
+                Ok(Ok(Message::Text(text))) => {
+                    if let Some((key, pressed)) = key_message(&text) {
+                        m.set_key_state(key, pressed);
+                    }
+                    match annotations_message(&text) {
+                        Some(Ok(a)) => annotations = Some(a),
+                        Some(Err(e)) => return Ok(Some(format!("invalid annotations: {}", e))),
+                        None => {}
+                    }
+                }
+                Ok(Ok(Message::Ping(payload))) => {
+                    websocket::write_message(out, websocket::PONG, &payload)?
+                }
+                Ok(Ok(Message::Binary(_))) => {}
+                Ok(Ok(Message::Close)) | Err(mpsc::TryRecvError::Disconnected) => return Ok(None),
+                Ok(Err(e)) => return Err(e),
+                Err(mpsc::TryRecvError::Empty) => break,
+            }
+        }
+
+        let n = scheduler.batch(clock.now());
+        if let Err(e) = m.run_for(n as u64) {
+            websocket::write_message(out, websocket::BINARY, &frame_message(&m))?;
+            return Ok(Some(e.to_string()));
+        }
+        if m.framebuffer_mut().take_dirty() {
+            websocket::write_message(out, websocket::BINARY, &frame_message(&m))?;
+        }
+        if (m.get_timer(crate::Timer::Sound) > 0) != sound {
+            sound = !sound;
+            let text: &[u8] = if sound { b"sound on" } else { b"sound off" };
+            websocket::write_message(out, websocket::TEXT, text)?;
+        }
+        if let Some(a) = &annotations {
+            let now = a.summary(&m);
+            if now != values {
+                let message = format!("values\n{}", now);
+                websocket::write_message(out, websocket::TEXT, message.as_bytes())?;
+                values = now;
+            }
+        }
+        clock.sleep(scheduler.sleep_time(clock.now()));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clock::VirtualClock;
+    use std::io::Read;
+
+    fn args(list: &[&str]) -> Vec<String> {
+        list.iter().map(|s| s.to_string()).collect()
+    }
+
+    #[test]
+    fn server_parse_args() {
+        let opts = parse_args(&[]).unwrap();
+        assert_eq!(
+            ("127.0.0.1", DEFAULT_PORT, DEFAULT_SESSIONS),
+            (opts.bind.as_str(), opts.port, opts.sessions)
+        );
+        assert_eq!(Some(10_000_000), opts.limits.cycles);
+        let opts = parse_args(&args(&[
+            "--port",
+            "9000",
+            "--sessions",
+            "2",
+            "--limits",
+            "timeout=60",
+        ]))
+        .unwrap();
+        assert_eq!((9000, 2), (opts.port, opts.sessions));
+        assert_eq!(None, opts.limits.cycles);
+        assert!(parse_args(&args(&["--port", "http"])).is_err());
+        assert!(parse_args(&args(&["--sessions", "0"])).is_err());
+        assert!(parse_args(&args(&["pong.ch8"])).is_err());
+    }
+
+    #[test]
+    fn server_messages() {
+        assert_eq!(Some((0x5, true)), key_message("down 5"));
+        assert_eq!(Some((0xF, false)), key_message("up f"));
+        assert_eq!(None, key_message("up 10"));
+        assert_eq!(None, key_message("press 5"));
+        assert!(annotations_message("annotations\nlives: V0")
+            .unwrap()
+            .is_ok());
+        assert!(annotations_message("annotations\nlives: V")
+            .unwrap()
+            .is_err());
+        assert!(annotations_message("down 5").is_none());
+
+        let mut m = machine(
+            vec![0xA0, 0x00, 0xD0, 0x05],
+            Quirks::default(),
+            &Sandbox {
+                limits: Limits::default(),
+                clock: clock::system(),
+                rom_timeout: REQUEST_TIMEOUT,
+            },
+        )
+        .unwrap();
+        m.run_for(2).unwrap();
+        let frame = frame_message(&m);
+        assert_eq!([64, 32], frame[..2]);
+        assert_eq!(2 + 64 * 32, frame.len());
+        assert_eq!([1, 1, 1, 1, 0], frame[2..7]);
+    }
+
+    // a request to a server on its own port, the whole response
+    fn request(port: u16, text: &[u8]) -> String {
+        let mut stream = TcpStream::connect(("127.0.0.1", port)).unwrap();
+        stream.write_all(text).unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).unwrap();
+        response
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore)]
+    fn server_requests() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let sandbox = Sandbox {
+            limits: Limits::parse("cycles=5000").unwrap(),
+            clock: clock::system(),
+            rom_timeout: REQUEST_TIMEOUT,
+        };
+        thread::spawn(move || serve(listener, 4, sandbox));
+
+        let page = request(port, b"GET / HTTP/1.1\r\n\r\n");
+        assert!(page.starts_with("HTTP/1.1 200 OK\r\n"), "{}", page);
+        assert!(page.contains("<canvas"));
+
+        // I = "0" glyph; draw it at (0, 0); spin
+        let run = request(
+            port,
+            b"POST /run HTTP/1.1\r\nContent-Length: 6\r\n\r\n\xA0\x00\xD0\x05\x12\x04",
+        );
+        assert!(
+            run.contains(
+                "\r\n\r\nhalted at 0x204 after 17 cycles\n\u{2588}\u{2580}\u{2580}\u{2588} "
+            ),
+            "{}",
+            run
+        );
+        // the server's limits apply
+        let run = request(
+            port,
+            b"POST /run?cycles=9000 HTTP/1.1\r\nContent-Length: 4\r\n\r\n\x70\x01\x12\x00",
+        );
+        assert!(
+            run.contains("limit of 5000 cycles reached at 0x200"),
+            "{}",
+            run
+        );
+
+        let run = request(port, b"POST /run?quirks=nope HTTP/1.1\r\n\r\n");
+        assert!(run.starts_with("HTTP/1.1 400 Bad Request\r\n"), "{}", run);
+        let play = request(port, b"GET /play HTTP/1.1\r\n\r\n");
+        assert!(play.starts_with("HTTP/1.1 426 "), "{}", play);
+        let missing = request(port, b"GET /rom.ch8 HTTP/1.1\r\n\r\n");
+        assert!(missing.starts_with("HTTP/1.1 404 "), "{}", missing);
+    }
+
+    // a WebSocket to /play, and the response's head
+    fn upgrade(port: u16) -> (TcpStream, String) {
+        let mut stream = TcpStream::connect(("127.0.0.1", port)).unwrap();
+        stream
+            .write_all(
+                b"GET /play HTTP/1.1\r\nUpgrade: websocket\r\nConnection: Upgrade\r\n\
+                  Sec-WebSocket-Key: dGhlIHNhbXBsZSBub25jZQ==\r\n\r\n",
+            )
+            .unwrap();
+        let mut head = Vec::new();
+        while !head.ends_with(b"\r\n\r\n") {
+            let mut b = [0];
+            stream.read_exact(&mut b).unwrap();
+            head.push(b[0]);
+        }
+        (stream, String::from_utf8(head).unwrap())
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore)]
+    fn server_play_session() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        // the session's frames come on virtual time, without waiting
+        let clock = VirtualClock::new();
+        let sandbox = Sandbox {
+            limits: Limits::default(),
+            clock: Arc::new(clock.clone()),
+            rom_timeout: REQUEST_TIMEOUT,
+        };
+        thread::spawn(move || serve(listener, 4, sandbox));
+
+        let (mut stream, head) = upgrade(port);
+        assert!(head.starts_with("HTTP/1.1 101 "), "{}", head);
+        assert!(head.contains("Sec-WebSocket-Accept: s3pPLMBiTxaQ9kYGzzhZRbK+xOo=\r\n"));
+
+        // a masked binary message with the ROM: wait for key into V0, draw
+        // its digit, then stop on an invalid opcode
+        let rom = [0xF0, 0x0A, 0xF0, 0x29, 0xD1, 0x15, 0xFF, 0xFF];
+        let mut frame = vec![0x82, 0x80 | rom.len() as u8, 0, 0, 0, 0];
+        frame.extend_from_slice(&rom);
+        stream.write_all(&frame).unwrap();
+        stream.write_all(&[0x81, 0x86, 0, 0, 0, 0]).unwrap();
+        stream.write_all(b"down 1").unwrap();
+
+        let mut messages = Vec::new();
+        loop {
+            match websocket::read_message(&mut stream, 1 << 16).unwrap() {
+                Message::Close => break,
+                message => messages.push(message),
+            }
+        }
+        let last = messages.last().unwrap();
+        assert_eq!(
+            &Message::Text(String::from("halted invalid opcode FFFF at 0x206")),
+            last
+        );
+        match &messages[messages.len() - 2] {
+            // "1": ..*. on top
+            Message::Binary(frame) => assert_eq!([0, 0, 1, 0], frame[2..6]),
+            m => panic!("{:?}", m),
+        }
+        assert!(clock.elapsed() > Duration::ZERO);
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore)]
+    fn server_play_annotations() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let sandbox = Sandbox {
+            limits: Limits::default(),
+            clock: Arc::new(VirtualClock::new()),
+            rom_timeout: REQUEST_TIMEOUT,
+        };
+        thread::spawn(move || serve(listener, 4, sandbox));
+
+        let (mut stream, _) = upgrade(port);
+        // masked text then binary messages: the annotations, then a ROM
+        // setting V0 to 5 and waiting for a key
+        let text = b"annotations\nlives: V0";
+        let mut frame = vec![0x81, 0x80 | text.len() as u8, 0, 0, 0, 0];
+        frame.extend_from_slice(text);
+        let rom = [0x60, 0x05, 0xF1, 0x0A];
+        frame.extend_from_slice(&[0x82, 0x80 | rom.len() as u8, 0, 0, 0, 0]);
+        frame.extend_from_slice(&rom);
+        stream.write_all(&frame).unwrap();
+
+        // the values as they were before the ROM ran, then as it set them
+        let mut values = Vec::new();
+        while values.last().map(String::as_str) != Some("values\nlives: 5") {
+            match websocket::read_message(&mut stream, 1 << 16).unwrap() {
+                Message::Text(text) if text.starts_with("values\n") => values.push(text),
+                Message::Close => panic!("closed after {:?}", values),
+                _ => {}
+            }
+        }
+        assert!(values.len() <= 2, "{:?}", values);
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore)]
+    fn server_play_without_rom() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let sandbox = Sandbox {
+            limits: Limits::default(),
+            clock: clock::system(),
+            rom_timeout: Duration::from_millis(50),
+        };
+        // a single session, which the silent player gives back
+        thread::spawn(move || serve(listener, 1, sandbox));
+
+        let (mut stream, _) = upgrade(port);
+        assert_eq!(
+            Message::Text(String::from("halted no ROM sent in time")),
+            websocket::read_message(&mut stream, 1 << 16).unwrap()
+        );
+        assert_eq!(
+            Message::Close,
+            websocket::read_message(&mut stream, 1 << 16).unwrap()
+        );
+        let page = (0..100)
+            .map(|_| {
+                thread::sleep(Duration::from_millis(10));
+                request(port, b"GET / HTTP/1.1\r\n\r\n")
+            })
+            .find(|page| !page.starts_with("HTTP/1.1 503 "))
+            .unwrap();
+        assert!(page.starts_with("HTTP/1.1 200 OK\r\n"), "{}", page);
+    }
+}
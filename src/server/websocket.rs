@@ -0,0 +1,272 @@
+// The part of WebSocket (RFC 6455) the player needs: the handshake's
+// accept key, and reading and writing messages. Clients mask their frames,
+// the server doesn't; fragmented messages are put back together, pings are
+// handed to the caller to answer.
+
+use std::io::{self, Read, Write};
+
+// appended to the client's key before hashing it
+const GUID: &str = "258EAFA5-E914-47DA-95CA-C5AB0DC85B11";
+
+const CONTINUATION: u8 = 0x0;
+pub const TEXT: u8 = 0x1;
+pub const BINARY: u8 = 0x2;
+pub const CLOSE: u8 = 0x8;
+const PING: u8 = 0x9;
+pub const PONG: u8 = 0xA;
+
+#[derive(Debug, PartialEq)]
+pub enum Message {
+    Text(String),
+    Binary(Vec<u8>),
+    Ping(Vec<u8>),
+    Close,
+}
+
+// the Sec-WebSocket-Accept answering a Sec-WebSocket-Key
+pub fn accept_key(key: &str) -> String {
+    base64(&sha1(format!("{}{}", key.trim(), GUID).as_bytes()))
+}
+
+fn sha1(data: &[u8]) -> [u8; 20] {
+    let mut h: [u32; 5] = [
+        0x6745_2301,
+        0xEFCD_AB89,
+        0x98BA_DCFE,
+        0x1032_5476,
+        0xC3D2_E1F0,
+    ];
+    let mut message = data.to_vec();
+    message.push(0x80);
+    while message.len() % 64 != 56 {
+        message.push(0);
+    }
+    message.extend_from_slice(&(data.len() as u64 * 8).to_be_bytes());
+
+    for block in message.chunks_exact(64) {
+        let mut w = [0u32; 80];
+        for (i, word) in block.chunks_exact(4).enumerate() {
+            w[i] = u32::from_be_bytes([word[0], word[1], word[2], word[3]]);
+        }
+        for i in 16..80 {
+            w[i] = (w[i - 3] ^ w[i - 8] ^ w[i - 14] ^ w[i - 16]).rotate_left(1);
+        }
+        let [mut a, mut b, mut c, mut d, mut e] = h;
+        for (i, wi) in w.iter().enumerate() {
+            let (f, k) = match i {
+                0..=19 => ((b & c) | (!b & d), 0x5A82_7999),
+                20..=39 => (b ^ c ^ d, 0x6ED9_EBA1),
+                40..=59 => ((b & c) | (b & d) | (c & d), 0x8F1B_BCDC),
+                _ => (b ^ c ^ d, 0xCA62_C1D6),
+            };
+            let t = a
+                .rotate_left(5)
+                .wrapping_add(f)
+                .wrapping_add(e)
+                .wrapping_add(k)
+                .wrapping_add(*wi);
+            e = d;
+            d = c;
+            c = b.rotate_left(30);
+            b = a;
+            a = t;
+        }
+        for (h, v) in h.iter_mut().zip([a, b, c, d, e]) {
+            *h = h.wrapping_add(v);
+        }
+    }
+
+    let mut digest = [0; 20];
+    for (bytes, v) in digest.chunks_exact_mut(4).zip(h) {
+        bytes.copy_from_slice(&v.to_be_bytes());
+    }
+    digest
+}
+
+fn base64(data: &[u8]) -> String {
+    const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    let mut text = String::new();
+    for chunk in data.chunks(3) {
+        let n = chunk
+            .iter()
+            .enumerate()
+            .fold(0u32, |n, (i, b)| n | u32::from(*b) << (16 - 8 * i));
+        for i in 0..4 {
+            if i <= chunk.len() {
+                text.push(char::from(ALPHABET[(n >> (18 - 6 * i) & 0x3F) as usize]));
+            } else {
+                text.push('=');
+            }
+        }
+    }
+    text
+}
+
+fn invalid(what: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, what)
+}
+
+// a frame: fin, opcode and the unmasked payload
+fn read_frame(r: &mut impl Read, max: usize) -> io::Result<(bool, u8, Vec<u8>)> {
+    let mut head = [0; 2];
+    r.read_exact(&mut head)?;
+    let (fin, opcode, masked) = (head[0] & 0x80 != 0, head[0] & 0x0F, head[1] & 0x80 != 0);
+    let len = match head[1] & 0x7F {
+        126 => {
+            let mut len = [0; 2];
+            r.read_exact(&mut len)?;
+            u64::from(u16::from_be_bytes(len))
+        }
+        127 => {
+            let mut len = [0; 8];
+            r.read_exact(&mut len)?;
+            u64::from_be_bytes(len)
+        }
+        len => u64::from(len),
+    };
+    if len > max as u64 {
+        return Err(invalid("websocket message too large"));
+    }
+    let mut mask = [0; 4];
+    if masked {
+        r.read_exact(&mut mask)?;
+    }
+    let mut payload = vec![0; len as usize];
+    r.read_exact(&mut payload)?;
+    for (i, b) in payload.iter_mut().enumerate() {
+        *b ^= mask[i % 4];
+    }
+    Ok((fin, opcode, payload))
+}
+
+// the next message, of at most `max` bytes
+pub fn read_message(r: &mut impl Read, max: usize) -> io::Result<Message> {
+    let mut message: Option<(u8, Vec<u8>)> = None;
+    loop {
+        let (fin, opcode, payload) = read_frame(r, max)?;
+        let (opcode, data) = match (opcode, message.take()) {
+            (CLOSE, _) => return Ok(Message::Close),
+            // control frames can come between the fragments of a message
+            (PING, pending) => {
+                message = pending;
+                if message.is_none() {
+                    return Ok(Message::Ping(payload));
+                }
+                continue;
+            }
+            (PONG, pending) => {
+                message = pending;
+                continue;
+            }
+            (CONTINUATION, Some((opcode, mut data))) => {
+                if data.len() + payload.len() > max {
+                    return Err(invalid("websocket message too large"));
+                }
+                data.extend_from_slice(&payload);
+                (opcode, data)
+            }
+            (TEXT, None) | (BINARY, None) => (opcode, payload),
+            _ => return Err(invalid("unexpected websocket frame")),
+        };
+        if !fin {
+            message = Some((opcode, data));
+            continue;
+        }
+        return match opcode {
+            TEXT => String::from_utf8(data)
+                .map(Message::Text)
+                .map_err(|_| invalid("websocket text isn't UTF-8")),
+            _ => Ok(Message::Binary(data)),
+        };
+    }
+}
+
+// a whole message in one frame, unmasked as servers send them
+pub fn write_message(w: &mut impl Write, opcode: u8, payload: &[u8]) -> io::Result<()> {
+    let mut head = vec![0x80 | opcode];
+    match payload.len() {
+        len if len < 126 => head.push(len as u8),
+        len if len <= usize::from(u16::MAX) => {
+            head.push(126);
+            head.extend_from_slice(&(len as u16).to_be_bytes());
+        }
+        len => {
+            head.push(127);
+            head.extend_from_slice(&(len as u64).to_be_bytes());
+        }
+    }
+    w.write_all(&head)?;
+    w.write_all(payload)?;
+    w.flush()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // a frame as a browser sends it, masked
+    fn client_frame(fin: bool, opcode: u8, payload: &[u8]) -> Vec<u8> {
+        let mask = [0x37, 0xFA, 0x21, 0x3D];
+        let mut frame = vec![u8::from(fin) << 7 | opcode];
+        if payload.len() < 126 {
+            frame.push(0x80 | payload.len() as u8);
+        } else {
+            frame.push(0x80 | 126);
+            frame.extend_from_slice(&(payload.len() as u16).to_be_bytes());
+        }
+        frame.extend_from_slice(&mask);
+        frame.extend(payload.iter().enumerate().map(|(i, b)| b ^ mask[i % 4]));
+        frame
+    }
+
+    #[test]
+    fn websocket_handshake() {
+        assert_eq!("2jmj7l5rSw0yVb/vlWAYkK/YBwk=", base64(&sha1(b"")));
+        assert_eq!("Zm9vYmFy", base64(b"foobar"));
+        assert_eq!("Zm9vYg==", base64(b"foob"));
+        // the example of RFC 6455
+        assert_eq!(
+            "s3pPLMBiTxaQ9kYGzzhZRbK+xOo=",
+            accept_key("dGhlIHNhbXBsZSBub25jZQ==")
+        );
+    }
+
+    #[test]
+    fn websocket_messages() {
+        let mut input = client_frame(true, TEXT, b"down 5");
+        input.extend(client_frame(false, BINARY, &[0x12; 100]));
+        input.extend(client_frame(true, PING, b"?"));
+        input.extend(client_frame(true, CONTINUATION, &[0x00; 200]));
+        input.extend(client_frame(true, PING, b"!"));
+        input.extend(client_frame(true, CLOSE, &[]));
+        let mut r = &input[..];
+        assert_eq!(
+            Message::Text(String::from("down 5")),
+            read_message(&mut r, 4096).unwrap()
+        );
+        match read_message(&mut r, 4096).unwrap() {
+            Message::Binary(data) => {
+                assert_eq!(300, data.len());
+                assert_eq!([0x12, 0x00], [data[99], data[100]]);
+            }
+            m => panic!("{:?}", m),
+        }
+        assert_eq!(
+            Message::Ping(b"!".to_vec()),
+            read_message(&mut r, 4096).unwrap()
+        );
+        assert_eq!(Message::Close, read_message(&mut r, 4096).unwrap());
+        assert!(read_message(&mut r, 4096).is_err());
+
+        let input = client_frame(true, BINARY, &[0; 200]);
+        assert!(read_message(&mut &input[..], 100).is_err());
+
+        let mut out = Vec::new();
+        write_message(&mut out, TEXT, b"sound on").unwrap();
+        assert_eq!(b"\x81\x08sound on", &out[..]);
+        out.clear();
+        write_message(&mut out, BINARY, &[1; 300]).unwrap();
+        assert_eq!([0x82, 126, 0x01, 0x2C], out[..4]);
+        assert_eq!(304, out.len());
+    }
+}
@@ -0,0 +1,696 @@
+// Machine snapshots and their compact storage.
+//
+// A snapshot is a flat byte image of the machine state. Consecutive
+// snapshots are nearly identical, so the history keeps only the newest one
+// in full and every older one as the run-length encoded XOR against its
+// successor: a few dozen bytes per frame instead of several kilobytes.
+//
+// A save state is a compressed snapshot, kept per ROM hash in
+// `$XDG_DATA_HOME/chip8/states/HASH.state`, after a header: the magic
+// bytes, the version of the snapshot layout, then the configuration the
+// program ran under (the instructions per second, and the quirks by name).
+// The version goes up when the layout changes, and the states of other
+// versions are refused. A state is loaded with its quirks, and the speed it
+// was saved at is returned to run at: the same program under other quirks
+// or at another pace drifts away from where it was.
+
+use crate::audio::PATTERN_SIZE;
+use crate::framebuffer::{FrameBuffer, MAX_HEIGHT, MAX_WIDTH};
+use crate::quirks::Quirks;
+use crate::rng::XorShiftRng;
+use crate::utils::{data_dir, fnv1a, FNV_OFFSET};
+use crate::{Machine, MEMORY_SIZE, PROGRAM_START_ADDRESS, RPL_FLAGS, STACK_DEPTH};
+use std::collections::{HashMap, VecDeque};
+use std::convert::TryFrom;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+const STATE_MAGIC: &[u8; 4] = b"C8ST";
+// version of the snapshot layout
+const STATE_VERSION: u8 = 4;
+
+// what a run depends on besides the machine state
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct StateConfig {
+    pub quirks: Quirks,
+    pub cpu_hz: u32,
+}
+
+impl StateConfig {
+    // how `other` differs, in a few words, none when it's the same
+    pub fn changes(&self, other: &StateConfig) -> Option<String> {
+        let name = |q: &Quirks| match q.to_string() {
+            s if s.is_empty() => String::from("none"),
+            s => s,
+        };
+        let mut changes = Vec::new();
+        if other.quirks != self.quirks {
+            changes.push(format!(
+                "quirks {} (not {})",
+                name(&other.quirks),
+                name(&self.quirks)
+            ));
+        }
+        if other.cpu_hz != self.cpu_hz {
+            changes.push(format!(
+                "{} instructions per second (not {})",
+                other.cpu_hz, self.cpu_hz
+            ));
+        }
+        Some(changes.join(", ")).filter(|c| !c.is_empty())
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub enum SnapshotError {
+    // the data ended before the whole state was read
+    Truncated,
+    // a field holds a value the machine can't take
+    Corrupt(&'static str),
+}
+
+impl fmt::Display for SnapshotError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SnapshotError::Truncated => write!(f, "snapshot is truncated"),
+            SnapshotError::Corrupt(what) => write!(f, "snapshot is corrupt: {}", what),
+        }
+    }
+}
+
+struct Reader<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    fn bytes(&mut self, n: usize) -> Result<&'a [u8], SnapshotError> {
+        if self.data.len() < n {
+            return Err(SnapshotError::Truncated);
+        }
+        let (head, tail) = self.data.split_at(n);
+        self.data = tail;
+        Ok(head)
+    }
+
+    fn u8(&mut self) -> Result<u8, SnapshotError> {
+        Ok(self.bytes(1)?[0])
+    }
+
+    fn u16(&mut self) -> Result<u16, SnapshotError> {
+        let b = self.bytes(2)?;
+        Ok(u16::from_le_bytes([b[0], b[1]]))
+    }
+
+    fn u32(&mut self) -> Result<u32, SnapshotError> {
+        let b = self.bytes(4)?;
+        Ok(u32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+    }
+
+    fn u64(&mut self) -> Result<u64, SnapshotError> {
+        let mut b = [0; 8];
+        b.copy_from_slice(self.bytes(8)?);
+        Ok(u64::from_le_bytes(b))
+    }
+}
+
+impl Machine {
+    pub fn snapshot(&self) -> Vec<u8> {
+        let mut out = Vec::with_capacity(MEMORY_SIZE + MAX_WIDTH * MAX_HEIGHT + 128);
+        out.extend_from_slice(&self.memory);
+        out.extend_from_slice(&self.registers);
+        out.extend_from_slice(&self.index_register.to_le_bytes());
+        out.extend_from_slice(&(self.pc as u16).to_le_bytes());
+        out.extend_from_slice(&(self.gfx.width() as u16).to_le_bytes());
+        out.extend_from_slice(&(self.gfx.height() as u16).to_le_bytes());
+        out.push(self.gfx.planes());
+        out.extend_from_slice(self.gfx.pixels());
+        out.push(self.delay_timer);
+        out.push(self.sound_timer);
+        out.extend_from_slice(&self.audio_pattern);
+        out.push(self.audio_pitch);
+        out.extend_from_slice(&self.rpl_flags);
+        out.extend_from_slice(&(self.stack.len() as u16).to_le_bytes());
+        for a in &self.stack {
+            out.extend_from_slice(&(*a as u16).to_le_bytes());
+        }
+        out.extend_from_slice(&(self.program_size as u16).to_le_bytes());
+        for k in 0..16 {
+            out.push(self.keys.get(&k).copied().unwrap_or(0));
+        }
+        out.push(u8::from(self.draw_flag));
+        out.extend_from_slice(&self.rng.state().to_le_bytes());
+        out
+    }
+
+    // hash of the emulated state (what a snapshot holds, minus the keypad),
+    // for loop detection, rewind deduplication, netplay desync checks and
+    // tests. Equal states hash the same on any platform and in any run of a
+    // crate version, but the hash may change with the version: compare it
+    // with hashes from the same build, don't store it
+    pub fn state_hash(&self) -> u64 {
+        let mut h = fnv1a(FNV_OFFSET, &self.memory);
+        h = fnv1a(h, &self.registers);
+        h = fnv1a(h, &self.index_register.to_le_bytes());
+        h = fnv1a(h, &(self.pc as u16).to_le_bytes());
+        h = fnv1a(h, &(self.gfx.width() as u16).to_le_bytes());
+        h = fnv1a(h, &[self.gfx.planes()]);
+        h = fnv1a(h, self.gfx.pixels());
+        h = fnv1a(h, &self.rng.state().to_le_bytes());
+        h = fnv1a(h, &[self.delay_timer, self.sound_timer]);
+        h = fnv1a(h, &self.audio_pattern);
+        h = fnv1a(h, &[self.audio_pitch]);
+        h = fnv1a(h, &self.rpl_flags);
+        for a in &self.stack {
+            h = fnv1a(h, &(*a as u16).to_le_bytes());
+        }
+        h
+    }
+
+    pub fn restore(&mut self, data: &[u8]) -> Result<(), SnapshotError> {
+        let mut r = Reader { data };
+
+        let mut memory = [0; MEMORY_SIZE];
+        memory.copy_from_slice(r.bytes(MEMORY_SIZE)?);
+        let mut registers = [0; 16];
+        registers.copy_from_slice(r.bytes(16)?);
+        let index_register = r.u16()?;
+        let pc = usize::from(r.u16()?);
+        let width = usize::from(r.u16()?);
+        let height = usize::from(r.u16()?);
+        let planes = r.u8()?;
+        let pixels = r.bytes(width.saturating_mul(height))?;
+        let gfx = FrameBuffer::from_parts(width, height, planes, pixels)
+            .ok_or(SnapshotError::Corrupt("framebuffer"))?;
+        let delay_timer = r.u8()?;
+        let sound_timer = r.u8()?;
+        let mut audio_pattern = [0; PATTERN_SIZE];
+        audio_pattern.copy_from_slice(r.bytes(PATTERN_SIZE)?);
+        let audio_pitch = r.u8()?;
+        let mut rpl_flags = [0; RPL_FLAGS];
+        rpl_flags.copy_from_slice(r.bytes(RPL_FLAGS)?);
+        let depth = usize::from(r.u16()?);
+        if depth > STACK_DEPTH {
+            return Err(SnapshotError::Corrupt("stack too deep"));
+        }
+        let mut stack = Vec::with_capacity(STACK_DEPTH);
+        for _ in 0..depth {
+            let address = usize::from(r.u16()?);
+            if address >= MEMORY_SIZE {
+                return Err(SnapshotError::Corrupt("address out of memory"));
+            }
+            stack.push(address);
+        }
+        let program_size = usize::from(r.u16()?);
+        let mut keys = HashMap::new();
+        for k in 0..16 {
+            let state = r.u8()?;
+            if state > 0 {
+                keys.insert(k, state);
+            }
+        }
+        let draw_flag = r.u8()? != 0;
+        let rng = XorShiftRng::from_state(r.u64()?).ok_or(SnapshotError::Corrupt("rng state"))?;
+        if !r.data.is_empty() {
+            return Err(SnapshotError::Corrupt("trailing data"));
+        }
+        if pc >= MEMORY_SIZE || PROGRAM_START_ADDRESS + program_size > MEMORY_SIZE {
+            return Err(SnapshotError::Corrupt("address out of memory"));
+        }
+
+        self.memory = memory;
+        self.registers = registers;
+        self.index_register = index_register;
+        self.pc = pc;
+        self.gfx = gfx;
+        self.delay_timer = delay_timer;
+        self.sound_timer = sound_timer;
+        self.audio_pattern = audio_pattern;
+        self.audio_pitch = audio_pitch;
+        self.rpl_flags = rpl_flags;
+        self.stack = stack;
+        self.program_size = program_size;
+        self.keys = keys;
+        self.draw_flag = draw_flag;
+        self.rng = rng;
+        Ok(())
+    }
+
+    // the save state file of the ROM with that hash, none without a home
+    // directory
+    pub fn state_path(program_hash: u64) -> Option<PathBuf> {
+        data_dir().map(|dir| {
+            dir.join("chip8")
+                .join("states")
+                .join(format!("{:016x}.state", program_hash))
+        })
+    }
+
+    // `cpu_hz` the speed the program runs at
+    pub fn save_state(&self, path: &Path, cpu_hz: u32) -> io::Result<()> {
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        let mut data = STATE_MAGIC.to_vec();
+        data.push(STATE_VERSION);
+        data.extend_from_slice(&cpu_hz.to_le_bytes());
+        let quirks = self.quirks.to_string();
+        data.push(quirks.len() as u8);
+        data.extend_from_slice(quirks.as_bytes());
+        data.extend(compress(&self.snapshot()));
+        fs::write(path, data)
+    }
+
+    // the state restored with its quirks, returns what it was saved under
+    pub fn load_state(&mut self, path: &Path) -> io::Result<StateConfig> {
+        let invalid = |e: String| io::Error::new(io::ErrorKind::InvalidData, e);
+        let data = fs::read(path)?;
+        let data = match data.strip_prefix(STATE_MAGIC) {
+            Some([STATE_VERSION, data @ ..]) => data,
+            Some([version, ..]) => {
+                return Err(invalid(format!(
+                    "save state version {}, this version of chip8 reads version {}",
+                    version, STATE_VERSION
+                )))
+            }
+            _ => return Err(invalid(String::from("not a save state"))),
+        };
+        let mut r = Reader { data };
+        let cpu_hz = r.u32().map_err(|e| invalid(e.to_string()))?;
+        let len = r.u8().map_err(|e| invalid(e.to_string()))?;
+        let quirks = r
+            .bytes(usize::from(len))
+            .map_err(|e| invalid(e.to_string()))?;
+        let quirks = std::str::from_utf8(quirks)
+            .map_err(|e| e.to_string())
+            .and_then(Quirks::parse)
+            .map_err(|e| invalid(format!("invalid quirks: {}", e)))?;
+        let snapshot = decompress(r.data).map_err(|e| invalid(e.to_string()))?;
+        // the font moves with the quirks, the snapshot's memory goes over it
+        let before = self.quirks;
+        self.set_quirks(quirks);
+        if let Err(e) = self.restore(&snapshot) {
+            self.set_quirks(before);
+            return Err(invalid(e.to_string()));
+        }
+        Ok(StateConfig { quirks, cpu_hz })
+    }
+}
+
+// PackBits style run-length encoding: a control byte c < 128 is followed by
+// c + 1 literal bytes, 128 <= c < 255 repeats the next byte c - 125 times
+// (3..=129) and 255 is a long run, a little endian u16 count then the byte
+const MIN_RUN: usize = 3;
+const MAX_SHORT_RUN: usize = 129;
+const MAX_RUN: usize = u16::MAX as usize;
+const MAX_LITERAL: usize = 128;
+const LONG_RUN: u8 = 255;
+
+pub fn compress(data: &[u8]) -> Vec<u8> {
+    let mut out = Vec::new();
+    let mut literal_start = 0;
+    let mut i = 0;
+
+    let flush = |out: &mut Vec<u8>, literal: &[u8]| {
+        for chunk in literal.chunks(MAX_LITERAL) {
+            out.push((chunk.len() - 1) as u8);
+            out.extend_from_slice(chunk);
+        }
+    };
+
+    while i < data.len() {
+        let mut run = 1;
+        while i + run < data.len() && run < MAX_RUN && data[i + run] == data[i] {
+            run += 1;
+        }
+        if run >= MIN_RUN {
+            flush(&mut out, &data[literal_start..i]);
+            if run > MAX_SHORT_RUN {
+                out.push(LONG_RUN);
+                out.extend_from_slice(&(run as u16).to_le_bytes());
+            } else {
+                out.push((run + 125) as u8);
+            }
+            out.push(data[i]);
+            literal_start = i + run;
+        }
+        i += run;
+    }
+    flush(&mut out, &data[literal_start..]);
+    out
+}
+
+pub fn decompress(data: &[u8]) -> Result<Vec<u8>, SnapshotError> {
+    let mut out = Vec::new();
+    let mut r = Reader { data };
+    while !r.data.is_empty() {
+        let c = r.u8()?;
+        if usize::from(c) < MAX_LITERAL {
+            out.extend_from_slice(r.bytes(usize::from(c) + 1)?);
+        } else {
+            let run = if c == LONG_RUN {
+                usize::from(r.u16()?)
+            } else {
+                usize::from(c) - 125
+            };
+            let b = r.u8()?;
+            out.resize(out.len() + run, b);
+        }
+    }
+    Ok(out)
+}
+
+// encode `target` relative to `base`: its length followed by the compressed
+// XOR of the two (the shorter one is padded with zeroes)
+pub fn delta_encode(base: &[u8], target: &[u8]) -> Vec<u8> {
+    let xor: Vec<u8> = target
+        .iter()
+        .enumerate()
+        .map(|(i, b)| b ^ base.get(i).copied().unwrap_or(0))
+        .collect();
+    let mut out = (target.len() as u32).to_le_bytes().to_vec();
+    out.extend(compress(&xor));
+    out
+}
+
+pub fn delta_decode(base: &[u8], delta: &[u8]) -> Result<Vec<u8>, SnapshotError> {
+    let mut r = Reader { data: delta };
+    let len = r.bytes(4)?;
+    let len = usize::try_from(u32::from_le_bytes([len[0], len[1], len[2], len[3]]))
+        .map_err(|_| SnapshotError::Corrupt("length"))?;
+    let xor = decompress(r.data)?;
+    if xor.len() != len {
+        return Err(SnapshotError::Corrupt("delta length mismatch"));
+    }
+    Ok(xor
+        .iter()
+        .enumerate()
+        .map(|(i, b)| b ^ base.get(i).copied().unwrap_or(0))
+        .collect())
+}
+
+// Bounded history of snapshots, newest kept in full and older ones as
+// reverse deltas, so dropping the oldest entry never needs re-encoding.
+pub struct History {
+    capacity: usize,
+    newest: Option<Vec<u8>>,
+    // state hash of the newest snapshot, when it was recorded
+    newest_hash: Option<u64>,
+    // deltas[i] turns snapshot i + 1 into snapshot i (oldest first)
+    deltas: VecDeque<Vec<u8>>,
+}
+
+impl History {
+    pub fn new(capacity: usize) -> History {
+        History {
+            capacity: capacity.max(1),
+            newest: None,
+            newest_hash: None,
+            deltas: VecDeque::new(),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.deltas.len() + usize::from(self.newest.is_some())
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.newest.is_none()
+    }
+
+    // bytes used by the stored snapshots
+    pub fn size(&self) -> usize {
+        self.newest.as_ref().map_or(0, Vec::len) + self.deltas.iter().map(Vec::len).sum::<usize>()
+    }
+
+    pub fn push(&mut self, snapshot: Vec<u8>) {
+        if let Some(prev) = self.newest.take() {
+            self.deltas.push_back(delta_encode(&snapshot, &prev));
+            if self.deltas.len() >= self.capacity {
+                self.deltas.pop_front();
+            }
+        }
+        self.newest = Some(snapshot);
+        self.newest_hash = None;
+    }
+
+    // push the state of `m` unless it's the newest already (waiting for a
+    // key, paused by the program): rewinding skips over the still frames
+    pub fn record(&mut self, m: &Machine) {
+        let hash = m.state_hash();
+        if self.newest_hash == Some(hash) {
+            return;
+        }
+        self.push(m.snapshot());
+        self.newest_hash = Some(hash);
+    }
+
+    // remove and return the newest snapshot
+    pub fn pop(&mut self) -> Option<Vec<u8>> {
+        self.newest_hash = None;
+        let newest = self.newest.take()?;
+        if let Some(delta) = self.deltas.pop_back() {
+            // deltas are produced by push, they always decode
+            self.newest = Some(delta_decode(&newest, &delta).expect("corrupt history"));
+        }
+        Some(newest)
+    }
+
+    pub fn clear(&mut self) {
+        self.newest = None;
+        self.newest_hash = None;
+        self.deltas.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn running_machine() -> Machine {
+        let mut m = Machine::new();
+        m.init();
+        m.load_program(vec![
+            0x60, 0x05, // V0 = 5
+            0x22, 0x06, // call 0x206
+            0x12, 0x02, // jump 0x202
+            0xF0, 0x29, // I = sprite(V0)
+            0xD0, 0x05, // draw
+            0x00, 0xEE, // return
+        ])
+        .unwrap();
+        m
+    }
+
+    #[test]
+    fn snapshot_roundtrip() {
+        let mut m = running_machine();
+        for _ in 0..4 {
+            m.exec_single().unwrap();
+        }
+        m.keys.insert(3, 1);
+        let snap = m.snapshot();
+
+        let mut r = Machine::new();
+        r.restore(&snap).unwrap();
+        assert_eq!(snap, r.snapshot());
+        assert_eq!(m.pc, r.pc);
+        assert_eq!(m.stack, r.stack);
+        assert_eq!(m.gfx.pixels(), r.gfx.pixels());
+    }
+
+    #[test]
+    fn snapshot_keeps_random_sequence() {
+        let mut m = Machine::new();
+        m.seed_rng(42);
+        m.init();
+        // V0 = rand(); jump 0x200
+        m.load_program_hex("C0FF 1200").unwrap();
+        for _ in 0..10 {
+            m.exec_single().unwrap();
+        }
+        let snap = m.snapshot();
+
+        let mut r = Machine::new();
+        r.restore(&snap).unwrap();
+        for _ in 0..20 {
+            m.exec_single().unwrap();
+            r.exec_single().unwrap();
+            assert_eq!(m.registers[0], r.registers[0]);
+        }
+        assert_eq!(m.state_hash(), r.state_hash());
+
+        let mut zero_rng = snap.clone();
+        let n = zero_rng.len();
+        zero_rng[n - 8..].copy_from_slice(&[0; 8]);
+        assert_eq!(
+            Err(SnapshotError::Corrupt("rng state")),
+            r.restore(&zero_rng)
+        );
+    }
+
+    #[test]
+    fn state_hash_follows_state() {
+        let mut a = running_machine();
+        let mut b = running_machine();
+        assert_eq!(a.state_hash(), b.state_hash());
+
+        a.exec_single().unwrap();
+        assert_ne!(a.state_hash(), b.state_hash());
+        b.exec_single().unwrap();
+        assert_eq!(a.state_hash(), b.state_hash());
+
+        b.registers[3] = 1;
+        assert_ne!(a.state_hash(), b.state_hash());
+    }
+
+    #[test]
+    fn snapshot_restore_errors() {
+        let m = running_machine();
+        let snap = m.snapshot();
+        let mut r = Machine::new();
+        assert_eq!(Err(SnapshotError::Truncated), r.restore(&snap[..100]));
+
+        let mut longer = snap.clone();
+        longer.push(0);
+        assert!(matches!(r.restore(&longer), Err(SnapshotError::Corrupt(_))));
+    }
+
+    #[test]
+    fn snapshot_restore_too_deep_stack() {
+        // a state the interpreter can't get to: more calls open than it has
+        let mut m = running_machine();
+        m.stack = vec![0x200; STACK_DEPTH + 4];
+        let mut r = running_machine();
+        assert_eq!(
+            Err(SnapshotError::Corrupt("stack too deep")),
+            r.restore(&m.snapshot())
+        );
+
+        // a full one restores, and the next call overflows
+        m.stack = vec![0x200; STACK_DEPTH];
+        m.memory[m.pc..m.pc + 2].copy_from_slice(&[0x22, 0x00]);
+        r.restore(&m.snapshot()).unwrap();
+        assert_eq!(
+            Err(crate::Chip8Error::StackOverflow { pc: m.pc }),
+            r.exec_single()
+        );
+    }
+
+    #[test]
+    fn compress_roundtrip() {
+        let cases: Vec<Vec<u8>> = vec![
+            vec![],
+            vec![7],
+            vec![1, 1],
+            vec![0; 1000],
+            vec![9; 129],
+            vec![9; 130],
+            vec![3; 70_000],
+            (0..=255).collect(),
+            vec![1, 2, 2, 2, 3, 4, 4, 4, 4, 5],
+        ];
+        for c in cases {
+            assert_eq!(c, decompress(&compress(&c)).unwrap());
+        }
+        assert_eq!(4, compress(&[0; 1000]).len());
+    }
+
+    #[test]
+    fn delta_roundtrip() {
+        let a = vec![1, 2, 3, 4, 5, 6];
+        let b = vec![1, 2, 9, 4, 5];
+        assert_eq!(b, delta_decode(&a, &delta_encode(&a, &b)).unwrap());
+        assert_eq!(a, delta_decode(&b, &delta_encode(&b, &a)).unwrap());
+    }
+
+    #[test]
+    fn history_keeps_newest_frames() {
+        let mut m = running_machine();
+        let mut h = History::new(10);
+        let mut snaps = Vec::new();
+        for _ in 0..25 {
+            m.exec_single().unwrap();
+            snaps.push(m.snapshot());
+            h.push(m.snapshot());
+        }
+        assert_eq!(10, h.len());
+        // one full snapshot plus small deltas
+        assert!(h.size() < snaps[0].len() + 10 * 64);
+
+        for expected in snaps.iter().rev().take(10) {
+            assert_eq!(Some(expected), h.pop().as_ref());
+        }
+        assert!(h.is_empty());
+        assert_eq!(None, h.pop());
+    }
+
+    #[test]
+    fn history_skips_repeated_states() {
+        let mut m = running_machine();
+        let mut h = History::new(10);
+        h.record(&m);
+        h.record(&m);
+        assert_eq!(1, h.len());
+        m.exec_single().unwrap();
+        h.record(&m);
+        assert_eq!(2, h.len());
+
+        // after a pop the newest is recorded again
+        let snap = h.pop().unwrap();
+        h.record(&m);
+        assert_eq!(2, h.len());
+        assert_eq!(Some(snap), h.pop());
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore)]
+    fn save_state_file() {
+        let dir = std::env::temp_dir().join(format!("chip8-states-{}", std::process::id()));
+        let path = dir.join("states").join("0123456789abcdef.state");
+        let mut m = running_machine();
+        for _ in 0..4 {
+            m.exec_single().unwrap();
+        }
+        m.set_quirks(Quirks::parse("vip").unwrap());
+        m.save_state(&path, 600).unwrap();
+
+        // loaded with the quirks and speed it was saved under
+        let mut r = running_machine();
+        let saved = r.load_state(&path).unwrap();
+        assert_eq!(m.state_hash(), r.state_hash());
+        assert_eq!(m.quirks(), r.quirks());
+        assert_eq!(600, saved.cpu_hz);
+        let current = StateConfig {
+            quirks: Quirks::default(),
+            cpu_hz: 700,
+        };
+        assert_eq!(
+            Some(String::from(
+                "quirks shift-vy,load-store-increment,vf-reset,clip-sprites,display-wait,\
+                 key-release,vip-font,memory-4k (not none), 600 instructions per second (not 700)"
+            )),
+            current.changes(&saved)
+        );
+        assert_eq!(None, saved.changes(&saved));
+
+        fs::write(&path, [1, 2, 3]).unwrap();
+        let e = r.load_state(&path).unwrap_err();
+        assert_eq!("not a save state", e.to_string());
+        // a newer layout
+        let mut data = b"C8ST\x05".to_vec();
+        data.extend(compress(&m.snapshot()));
+        fs::write(&path, data).unwrap();
+        let e = r.load_state(&path).unwrap_err();
+        assert!(e.to_string().starts_with("save state version 5,"), "{}", e);
+        // unknown quirks, or a snapshot that doesn't fit the machine
+        fs::write(&path, b"C8ST\x04\xBC\x02\x00\x00\x03abc").unwrap();
+        let e = r.load_state(&path).unwrap_err();
+        assert_eq!("invalid quirks: unknown quirk `abc`", e.to_string());
+        fs::write(&path, b"C8ST\x04\xBC\x02\x00\x00\x00\x00\x00").unwrap();
+        assert!(r.load_state(&path).is_err());
+        assert_eq!(m.quirks(), r.quirks());
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,439 @@
+// Soak test: run many headless sessions with random keypad input against
+// one or more ROMs, in parallel, and report every session that panics or
+// leaves the machine in an invalid state.
+
+use crate::agent::{Agent, Observation, RandomAgent};
+use crate::exit;
+use crate::framebuffer::PLANE_MASK;
+use crate::gameover::GameOverDetector;
+use crate::scheduler::TimerClock;
+use crate::{Machine, CPU_IPS, STACK_DEPTH};
+use rand::Rng;
+use std::any::Any;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::panic;
+use std::path::Path;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+const DEFAULT_SESSIONS: usize = 200;
+const DEFAULT_CYCLES: usize = 20_000;
+// probability (per cycle) of toggling a random key
+const KEY_TOGGLE_CHANCE: f64 = 0.01;
+const WATCHDOG_PERIOD: Duration = Duration::from_millis(100);
+const HANG_TIMEOUT: Duration = Duration::from_secs(5);
+
+struct Options {
+    sessions: usize,
+    cycles: usize,
+    threads: usize,
+    seed: u64,
+    roms: Vec<String>,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum Outcome {
+    // ran for all the requested cycles
+    Completed,
+    // the program stopped on an error: an invalid opcode, running off its
+    // end, a stack underflow...
+    Halted(usize),
+    // the program reached a game over
+    GameOver(usize),
+    // the interpreter panicked or reached an invalid state
+    Failed(usize, String),
+}
+
+pub struct Report {
+    pub rom: String,
+    pub seed: u64,
+    pub outcome: Outcome,
+}
+
+fn parse_args(args: &[String]) -> Result<Options, String> {
+    let mut opts = Options {
+        sessions: DEFAULT_SESSIONS,
+        cycles: DEFAULT_CYCLES,
+        threads: thread::available_parallelism().map_or(1, |n| n.get()),
+        seed: rand::thread_rng().gen(),
+        roms: Vec::new(),
+    };
+
+    let mut it = args.iter();
+    while let Some(arg) = it.next() {
+        let mut value = |name: &str| -> Result<u64, String> {
+            let v = it.next().ok_or(format!("missing value for {}", name))?;
+            v.parse::<u64>()
+                .map_err(|e| format!("invalid value `{}` for {}: {}", v, name, e))
+        };
+        match arg.as_str() {
+            "--sessions" => opts.sessions = value(arg)? as usize,
+            "--cycles" => opts.cycles = value(arg)? as usize,
+            "--threads" => opts.threads = (value(arg)? as usize).max(1),
+            "--seed" => opts.seed = value(arg)?,
+            s if s.starts_with("--") => return Err(format!("unknown option `{}`", s)),
+            rom => opts.roms.push(rom.to_string()),
+        }
+    }
+
+    if opts.roms.is_empty() {
+        opts.roms = default_roms();
+    }
+    if opts.roms.is_empty() {
+        return Err(String::from("no ROM given and none found in ./data"));
+    }
+    Ok(opts)
+}
+
+fn default_roms() -> Vec<String> {
+    let mut roms: Vec<String> = match fs::read_dir("./data") {
+        Ok(entries) => entries
+            .filter_map(|e| e.ok())
+            .map(|e| e.path())
+            .filter(|p| p.extension().is_some_and(|ext| ext == "rom"))
+            .map(|p| p.to_string_lossy().into_owned())
+            .collect(),
+        Err(_) => Vec::new(),
+    };
+    roms.sort();
+    roms
+}
+
+fn check_invariants(m: &Machine) -> Result<(), String> {
+    if m.pc + 1 >= m.memory.len() {
+        return Err(format!("pc out of memory: {:#06X}", m.pc));
+    }
+    if usize::from(m.index_register) >= m.memory.len() {
+        return Err(format!("I out of memory: {:#06X}", m.index_register));
+    }
+    if m.stack.len() > STACK_DEPTH {
+        return Err(format!("stack overflow: depth {}", m.stack.len()));
+    }
+    if m.gfx.pixels().iter().any(|p| *p & !PLANE_MASK != 0) {
+        return Err(String::from("gfx contains a pixel on a missing plane"));
+    }
+    Ok(())
+}
+
+// run a program with random input, `heartbeat` is bumped every cycle
+pub fn run_session(program: &[u8], cycles: usize, seed: u64, heartbeat: &AtomicU64) -> Outcome {
+    let mut agent = RandomAgent::new(seed, KEY_TOGGLE_CHANCE);
+    let mut m = Machine::new();
+    m.seed_rng(seed);
+    m.init();
+    if m.load_program(program.to_vec()).is_err() {
+        return Outcome::Halted(0);
+    }
+    let mut game_over = GameOverDetector::new(None);
+    let mut clock = TimerClock::new(CPU_IPS);
+
+    let mut cycle = 0;
+    let result = panic::catch_unwind(panic::AssertUnwindSafe(|| {
+        while cycle < cycles {
+            let keys = agent.act(&Observation::new(cycle as u64, &m, None));
+            m.set_keypad(keys);
+            if m.exec_single().is_err() {
+                return Outcome::Halted(cycle);
+            }
+            for _ in 0..clock.advance(1) {
+                m.tick_timers();
+            }
+            if let Err(e) = check_invariants(&m) {
+                return Outcome::Failed(cycle, e);
+            }
+            if game_over.update(&m).is_some() {
+                return Outcome::GameOver(cycle);
+            }
+            heartbeat.fetch_add(1, Ordering::Relaxed);
+            cycle += 1;
+        }
+        Outcome::Completed
+    }));
+
+    match result {
+        Ok(outcome) => outcome,
+        Err(payload) => Outcome::Failed(cycle, panic_message(payload)),
+    }
+}
+
+fn panic_message(payload: Box<dyn Any + Send>) -> String {
+    let msg = if let Some(s) = payload.downcast_ref::<&str>() {
+        s.to_string()
+    } else if let Some(s) = payload.downcast_ref::<String>() {
+        s.clone()
+    } else {
+        String::from("unknown panic")
+    };
+    format!("panic: {}", msg)
+}
+
+// a job whose heartbeat stopped: a hang in the interpreter
+#[derive(Debug, PartialEq)]
+pub struct Hang {
+    pub job: usize,
+    pub timeout: Duration,
+}
+
+impl fmt::Display for Hang {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "job {}: no progress for {:?}", self.job, self.timeout)
+    }
+}
+
+// what the workers share with the watchdog
+struct Pool {
+    next: AtomicUsize,
+    finished: AtomicUsize,
+    outcomes: Mutex<Vec<(usize, Outcome)>>,
+    // per worker, the job it runs (usize::MAX once out of jobs) and its
+    // heartbeat
+    workers: Vec<(AtomicUsize, AtomicU64)>,
+}
+
+// run `jobs` jobs on `threads` threads, returning their outcomes in job
+// order. A job that stops bumping its heartbeat for HANG_TIMEOUT is a hang
+// in the interpreter: it's returned as such, the stuck thread left behind
+// since it can't be stopped (the caller is expected to exit).
+pub fn run_parallel<F>(jobs: usize, threads: usize, job: F) -> Result<Vec<Outcome>, Hang>
+where
+    F: Fn(usize, &AtomicU64) -> Outcome + Send + Sync + 'static,
+{
+    watch(jobs, threads, HANG_TIMEOUT, job)
+}
+
+fn watch<F>(jobs: usize, threads: usize, timeout: Duration, job: F) -> Result<Vec<Outcome>, Hang>
+where
+    F: Fn(usize, &AtomicU64) -> Outcome + Send + Sync + 'static,
+{
+    // panics are collected as failures, keep the default hook quiet
+    let default_hook = panic::take_hook();
+    panic::set_hook(Box::new(|_| {}));
+
+    let pool = Arc::new(Pool {
+        next: AtomicUsize::new(0),
+        finished: AtomicUsize::new(0),
+        outcomes: Mutex::new(Vec::new()),
+        workers: (0..threads.max(1))
+            .map(|_| (AtomicUsize::new(usize::MAX), AtomicU64::new(0)))
+            .collect(),
+    });
+    let job = Arc::new(job);
+    for w in 0..pool.workers.len() {
+        let (pool, job) = (pool.clone(), job.clone());
+        thread::spawn(move || {
+            let (current, heartbeat) = &pool.workers[w];
+            loop {
+                let i = pool.next.fetch_add(1, Ordering::Relaxed);
+                if i >= jobs {
+                    // idle from now on, not hung
+                    current.store(usize::MAX, Ordering::Relaxed);
+                    break;
+                }
+                current.store(i, Ordering::Relaxed);
+                heartbeat.fetch_add(1, Ordering::Relaxed);
+                let outcome = panic::catch_unwind(panic::AssertUnwindSafe(|| job(i, heartbeat)))
+                    .unwrap_or_else(|payload| Outcome::Failed(0, panic_message(payload)));
+                pool.outcomes.lock().unwrap().push((i, outcome));
+            }
+            pool.finished.fetch_add(1, Ordering::SeqCst);
+        });
+    }
+
+    // watchdog
+    let mut last: Vec<(u64, Instant)> = pool
+        .workers
+        .iter()
+        .map(|(_, h)| (h.load(Ordering::Relaxed), Instant::now()))
+        .collect();
+    let mut hang = None;
+    while hang.is_none() && pool.finished.load(Ordering::SeqCst) < pool.workers.len() {
+        thread::sleep(WATCHDOG_PERIOD.min(timeout));
+        for ((current, heartbeat), (beat, since)) in pool.workers.iter().zip(last.iter_mut()) {
+            let now = heartbeat.load(Ordering::Relaxed);
+            let job = current.load(Ordering::Relaxed);
+            if now != *beat {
+                *beat = now;
+                *since = Instant::now();
+            } else if since.elapsed() > timeout && job < jobs {
+                hang = Some(Hang { job, timeout });
+                break;
+            }
+        }
+    }
+
+    panic::set_hook(default_hook);
+    if let Some(hang) = hang {
+        return Err(hang);
+    }
+
+    let mut outcomes = std::mem::take(&mut *pool.outcomes.lock().unwrap());
+    outcomes.sort_by_key(|(i, _)| *i);
+    Ok(outcomes.into_iter().map(|(_, o)| o).collect())
+}
+
+pub fn run(args: &[String]) -> io::Result<()> {
+    let opts = parse_args(args).map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+
+    let mut programs = Vec::new();
+    for rom in &opts.roms {
+        let bytes = fs::read(rom).map_err(|e| exit::rom_load(rom, e))?;
+        programs.push((rom.clone(), bytes));
+    }
+
+    println!(
+        "soak: {} sessions x {} cycles on {} ROM(s), {} threads, seed {}",
+        opts.sessions,
+        opts.cycles,
+        programs.len(),
+        opts.threads,
+        opts.seed
+    );
+
+    let programs = Arc::new(programs);
+    let shared = programs.clone();
+    let (cycles, seed) = (opts.cycles, opts.seed);
+    let outcomes = run_parallel(opts.sessions, opts.threads, move |i, heartbeat| {
+        let (_, program) = &shared[i % shared.len()];
+        run_session(program, cycles, seed.wrapping_add(i as u64), heartbeat)
+    })
+    .map_err(|hang| exit::check_failed(format!("HANG {}", hang)))?;
+
+    let reports: Vec<Report> = outcomes
+        .into_iter()
+        .enumerate()
+        .map(|(i, outcome)| Report {
+            rom: programs[i % programs.len()].0.clone(),
+            seed: opts.seed.wrapping_add(i as u64),
+            outcome,
+        })
+        .collect();
+    summarize("soak", &reports)
+}
+
+// print the failed sessions and the outcome counts, failing if any failed
+pub fn summarize(label: &str, reports: &[Report]) -> io::Result<()> {
+    let count = |f: fn(&Outcome) -> bool| reports.iter().filter(|r| f(&r.outcome)).count();
+    let completed = count(|o| *o == Outcome::Completed);
+    let halted = count(|o| matches!(o, Outcome::Halted(_)));
+    let game_overs = count(|o| matches!(o, Outcome::GameOver(_)));
+    let mut failures: Vec<&Report> = reports
+        .iter()
+        .filter(|r| matches!(r.outcome, Outcome::Failed(_, _)))
+        .collect();
+    failures.sort_by_key(|r| r.seed);
+
+    for r in &failures {
+        if let Outcome::Failed(cycle, reason) = &r.outcome {
+            let name = Path::new(&r.rom)
+                .file_name()
+                .map_or(r.rom.clone(), |n| n.to_string_lossy().into_owned());
+            println!("FAIL {} seed {} cycle {}: {}", name, r.seed, cycle, reason);
+        }
+    }
+    println!(
+        "{}: {} completed, {} halted, {} game over, {} failed",
+        label,
+        completed,
+        halted,
+        game_overs,
+        failures.len()
+    );
+
+    if failures.is_empty() {
+        Ok(())
+    } else {
+        Err(exit::check_failed(format!(
+            "{} {} session(s) failed",
+            failures.len(),
+            label
+        )))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn soak_session_completes() {
+        // V0 += 1; jump back to 0x200
+        let program = vec![0x70, 0x01, 0x12, 0x00];
+        assert_eq!(
+            Outcome::Completed,
+            run_session(&program, 1000, 0, &AtomicU64::new(0))
+        );
+    }
+
+    #[test]
+    fn soak_session_game_over() {
+        // V0 += 1; jump to itself
+        let program = vec![0x70, 0x01, 0x12, 0x02];
+        assert!(matches!(
+            run_session(&program, 1000, 0, &AtomicU64::new(0)),
+            Outcome::GameOver(_)
+        ));
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore)] // threads and wall clock timeouts
+    fn soak_run_parallel_keeps_job_order() {
+        let outcomes = run_parallel(20, 4, |i, _| {
+            if i % 2 == 0 {
+                Outcome::Halted(i)
+            } else {
+                panic!("odd job")
+            }
+        })
+        .unwrap();
+        assert_eq!(20, outcomes.len());
+        assert_eq!(Outcome::Halted(4), outcomes[4]);
+        assert_eq!(
+            Outcome::Failed(0, String::from("panic: odd job")),
+            outcomes[5]
+        );
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore)] // threads and wall clock timeouts
+    fn soak_run_parallel_uneven_jobs() {
+        let timeout = Duration::from_millis(200);
+        // the worker done with the short job isn't taken for hung while the
+        // other one runs on, alive
+        let outcomes = watch(2, 2, timeout, |i, heartbeat| {
+            if i == 1 {
+                for _ in 0..60 {
+                    thread::sleep(Duration::from_millis(10));
+                    heartbeat.fetch_add(1, Ordering::Relaxed);
+                }
+            }
+            Outcome::Completed
+        })
+        .unwrap();
+        assert_eq!(vec![Outcome::Completed, Outcome::Completed], outcomes);
+
+        // one that stops beating is
+        assert_eq!(
+            Err(Hang { job: 1, timeout }),
+            watch(2, 2, timeout, |i, _| {
+                if i == 1 {
+                    thread::sleep(Duration::from_millis(1000));
+                }
+                Outcome::Completed
+            })
+        );
+    }
+
+    #[test]
+    fn soak_session_halts_on_stack_underflow() {
+        // return with an empty stack: an error now, not a panic
+        let program = vec![0x00, 0xEE];
+        assert_eq!(
+            Outcome::Halted(0),
+            run_session(&program, 10, 0, &AtomicU64::new(0))
+        );
+    }
+}
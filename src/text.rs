@@ -0,0 +1,221 @@
+// Text without a font library: the printable ASCII characters in 8x8
+// pixels (the public domain font8x8 by Daniel Hepper, from the IBM PC's),
+// drawn into RGBA buffers for the window's toasts and panel and into
+// Unicode half blocks for terminals. Other characters are drawn as `?`.
+
+pub const GLYPH_SIZE: usize = 8;
+
+// ' ' to '~', a byte per row, the lowest bit the leftmost pixel
+const GLYPHS: [[u8; 8]; 95] = [
+    [0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00], // ' '
+    [0x18, 0x3C, 0x3C, 0x18, 0x18, 0x00, 0x18, 0x00], // !
+    [0x36, 0x36, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00], // "
+    [0x36, 0x36, 0x7F, 0x36, 0x7F, 0x36, 0x36, 0x00], // #
+    [0x0C, 0x3E, 0x03, 0x1E, 0x30, 0x1F, 0x0C, 0x00], // $
+    [0x00, 0x63, 0x33, 0x18, 0x0C, 0x66, 0x63, 0x00], // %
+    [0x1C, 0x36, 0x1C, 0x6E, 0x3B, 0x33, 0x6E, 0x00], // &
+    [0x06, 0x06, 0x03, 0x00, 0x00, 0x00, 0x00, 0x00], // '
+    [0x18, 0x0C, 0x06, 0x06, 0x06, 0x0C, 0x18, 0x00], // (
+    [0x06, 0x0C, 0x18, 0x18, 0x18, 0x0C, 0x06, 0x00], // )
+    [0x00, 0x66, 0x3C, 0xFF, 0x3C, 0x66, 0x00, 0x00], // *
+    [0x00, 0x0C, 0x0C, 0x3F, 0x0C, 0x0C, 0x00, 0x00], // +
+    [0x00, 0x00, 0x00, 0x00, 0x00, 0x0C, 0x0C, 0x06], // ,
+    [0x00, 0x00, 0x00, 0x3F, 0x00, 0x00, 0x00, 0x00], // -
+    [0x00, 0x00, 0x00, 0x00, 0x00, 0x0C, 0x0C, 0x00], // .
+    [0x60, 0x30, 0x18, 0x0C, 0x06, 0x03, 0x01, 0x00], // /
+    [0x3E, 0x63, 0x73, 0x7B, 0x6F, 0x67, 0x3E, 0x00], // 0
+    [0x0C, 0x0E, 0x0C, 0x0C, 0x0C, 0x0C, 0x3F, 0x00], // 1
+    [0x1E, 0x33, 0x30, 0x1C, 0x06, 0x33, 0x3F, 0x00], // 2
+    [0x1E, 0x33, 0x30, 0x1C, 0x30, 0x33, 0x1E, 0x00], // 3
+    [0x38, 0x3C, 0x36, 0x33, 0x7F, 0x30, 0x78, 0x00], // 4
+    [0x3F, 0x03, 0x1F, 0x30, 0x30, 0x33, 0x1E, 0x00], // 5
+    [0x1C, 0x06, 0x03, 0x1F, 0x33, 0x33, 0x1E, 0x00], // 6
+    [0x3F, 0x33, 0x30, 0x18, 0x0C, 0x0C, 0x0C, 0x00], // 7
+    [0x1E, 0x33, 0x33, 0x1E, 0x33, 0x33, 0x1E, 0x00], // 8
+    [0x1E, 0x33, 0x33, 0x3E, 0x30, 0x18, 0x0E, 0x00], // 9
+    [0x00, 0x0C, 0x0C, 0x00, 0x00, 0x0C, 0x0C, 0x00], // :
+    [0x00, 0x0C, 0x0C, 0x00, 0x00, 0x0C, 0x0C, 0x06], // ;
+    [0x18, 0x0C, 0x06, 0x03, 0x06, 0x0C, 0x18, 0x00], // <
+    [0x00, 0x00, 0x3F, 0x00, 0x00, 0x3F, 0x00, 0x00], // =
+    [0x06, 0x0C, 0x18, 0x30, 0x18, 0x0C, 0x06, 0x00], // >
+    [0x1E, 0x33, 0x30, 0x18, 0x0C, 0x00, 0x0C, 0x00], // ?
+    [0x3E, 0x63, 0x7B, 0x7B, 0x7B, 0x03, 0x1E, 0x00], // @
+    [0x0C, 0x1E, 0x33, 0x33, 0x3F, 0x33, 0x33, 0x00], // A
+    [0x3F, 0x66, 0x66, 0x3E, 0x66, 0x66, 0x3F, 0x00], // B
+    [0x3C, 0x66, 0x03, 0x03, 0x03, 0x66, 0x3C, 0x00], // C
+    [0x1F, 0x36, 0x66, 0x66, 0x66, 0x36, 0x1F, 0x00], // D
+    [0x7F, 0x46, 0x16, 0x1E, 0x16, 0x46, 0x7F, 0x00], // E
+    [0x7F, 0x46, 0x16, 0x1E, 0x16, 0x06, 0x0F, 0x00], // F
+    [0x3C, 0x66, 0x03, 0x03, 0x73, 0x66, 0x7C, 0x00], // G
+    [0x33, 0x33, 0x33, 0x3F, 0x33, 0x33, 0x33, 0x00], // H
+    [0x1E, 0x0C, 0x0C, 0x0C, 0x0C, 0x0C, 0x1E, 0x00], // I
+    [0x78, 0x30, 0x30, 0x30, 0x33, 0x33, 0x1E, 0x00], // J
+    [0x67, 0x66, 0x36, 0x1E, 0x36, 0x66, 0x67, 0x00], // K
+    [0x0F, 0x06, 0x06, 0x06, 0x46, 0x66, 0x7F, 0x00], // L
+    [0x63, 0x77, 0x7F, 0x7F, 0x6B, 0x63, 0x63, 0x00], // M
+    [0x63, 0x67, 0x6F, 0x7B, 0x73, 0x63, 0x63, 0x00], // N
+    [0x1C, 0x36, 0x63, 0x63, 0x63, 0x36, 0x1C, 0x00], // O
+    [0x3F, 0x66, 0x66, 0x3E, 0x06, 0x06, 0x0F, 0x00], // P
+    [0x1E, 0x33, 0x33, 0x33, 0x3B, 0x1E, 0x38, 0x00], // Q
+    [0x3F, 0x66, 0x66, 0x3E, 0x36, 0x66, 0x67, 0x00], // R
+    [0x1E, 0x33, 0x07, 0x0E, 0x38, 0x33, 0x1E, 0x00], // S
+    [0x3F, 0x2D, 0x0C, 0x0C, 0x0C, 0x0C, 0x1E, 0x00], // T
+    [0x33, 0x33, 0x33, 0x33, 0x33, 0x33, 0x3F, 0x00], // U
+    [0x33, 0x33, 0x33, 0x33, 0x33, 0x1E, 0x0C, 0x00], // V
+    [0x63, 0x63, 0x63, 0x6B, 0x7F, 0x77, 0x63, 0x00], // W
+    [0x63, 0x63, 0x36, 0x1C, 0x1C, 0x36, 0x63, 0x00], // X
+    [0x33, 0x33, 0x33, 0x1E, 0x0C, 0x0C, 0x1E, 0x00], // Y
+    [0x7F, 0x63, 0x31, 0x18, 0x4C, 0x66, 0x7F, 0x00], // Z
+    [0x1E, 0x06, 0x06, 0x06, 0x06, 0x06, 0x1E, 0x00], // [
+    [0x03, 0x06, 0x0C, 0x18, 0x30, 0x60, 0x40, 0x00], // \
+    [0x1E, 0x18, 0x18, 0x18, 0x18, 0x18, 0x1E, 0x00], // ]
+    [0x08, 0x1C, 0x36, 0x63, 0x00, 0x00, 0x00, 0x00], // ^
+    [0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0xFF], // _
+    [0x0C, 0x0C, 0x18, 0x00, 0x00, 0x00, 0x00, 0x00], // `
+    [0x00, 0x00, 0x1E, 0x30, 0x3E, 0x33, 0x6E, 0x00], // a
+    [0x07, 0x06, 0x06, 0x3E, 0x66, 0x66, 0x3B, 0x00], // b
+    [0x00, 0x00, 0x1E, 0x33, 0x03, 0x33, 0x1E, 0x00], // c
+    [0x38, 0x30, 0x30, 0x3E, 0x33, 0x33, 0x6E, 0x00], // d
+    [0x00, 0x00, 0x1E, 0x33, 0x3F, 0x03, 0x1E, 0x00], // e
+    [0x1C, 0x36, 0x06, 0x0F, 0x06, 0x06, 0x0F, 0x00], // f
+    [0x00, 0x00, 0x6E, 0x33, 0x33, 0x3E, 0x30, 0x1F], // g
+    [0x07, 0x06, 0x36, 0x6E, 0x66, 0x66, 0x67, 0x00], // h
+    [0x0C, 0x00, 0x0E, 0x0C, 0x0C, 0x0C, 0x1E, 0x00], // i
+    [0x30, 0x00, 0x30, 0x30, 0x30, 0x33, 0x33, 0x1E], // j
+    [0x07, 0x06, 0x66, 0x36, 0x1E, 0x36, 0x67, 0x00], // k
+    [0x0E, 0x0C, 0x0C, 0x0C, 0x0C, 0x0C, 0x1E, 0x00], // l
+    [0x00, 0x00, 0x33, 0x7F, 0x7F, 0x6B, 0x63, 0x00], // m
+    [0x00, 0x00, 0x1F, 0x33, 0x33, 0x33, 0x33, 0x00], // n
+    [0x00, 0x00, 0x1E, 0x33, 0x33, 0x33, 0x1E, 0x00], // o
+    [0x00, 0x00, 0x3B, 0x66, 0x66, 0x3E, 0x06, 0x0F], // p
+    [0x00, 0x00, 0x6E, 0x33, 0x33, 0x3E, 0x30, 0x78], // q
+    [0x00, 0x00, 0x3B, 0x6E, 0x66, 0x06, 0x0F, 0x00], // r
+    [0x00, 0x00, 0x3E, 0x03, 0x1E, 0x30, 0x1F, 0x00], // s
+    [0x08, 0x0C, 0x3E, 0x0C, 0x0C, 0x2C, 0x18, 0x00], // t
+    [0x00, 0x00, 0x33, 0x33, 0x33, 0x33, 0x6E, 0x00], // u
+    [0x00, 0x00, 0x33, 0x33, 0x33, 0x1E, 0x0C, 0x00], // v
+    [0x00, 0x00, 0x63, 0x6B, 0x7F, 0x7F, 0x36, 0x00], // w
+    [0x00, 0x00, 0x63, 0x36, 0x1C, 0x36, 0x63, 0x00], // x
+    [0x00, 0x00, 0x33, 0x33, 0x33, 0x3E, 0x30, 0x1F], // y
+    [0x00, 0x00, 0x3F, 0x19, 0x0C, 0x26, 0x3F, 0x00], // z
+    [0x38, 0x0C, 0x0C, 0x07, 0x0C, 0x0C, 0x38, 0x00], // {
+    [0x18, 0x18, 0x18, 0x00, 0x18, 0x18, 0x18, 0x00], // |
+    [0x07, 0x0C, 0x0C, 0x38, 0x0C, 0x0C, 0x07, 0x00], // }
+    [0x6E, 0x3B, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00], // ~
+];
+
+fn glyph(c: char) -> &'static [u8; 8] {
+    let index = (c as usize).wrapping_sub(' ' as usize);
+    GLYPHS
+        .get(index)
+        .unwrap_or(&GLYPHS[usize::from(b'?' - b' ')])
+}
+
+// pixels across a line of text
+pub fn width(text: &str) -> usize {
+    text.chars().count() * GLYPH_SIZE
+}
+
+// whether the pixel at (x, y) of a line of text is lit
+pub fn lit(text: &str, x: usize, y: usize) -> bool {
+    match text.chars().nth(x / GLYPH_SIZE) {
+        Some(c) if y < GLYPH_SIZE => glyph(c)[y] & (1 << (x % GLYPH_SIZE)) != 0,
+        _ => false,
+    }
+}
+
+// draw a line of text at (x, y) into RGBA pixels `width` wide, the lit
+// pixels only, cut at the edges
+pub fn draw(rgba: &mut [u8], width: usize, x: usize, y: usize, text: &str, colour: [u8; 4]) {
+    let height = rgba.len() / 4 / width.max(1);
+    for (i, c) in text.chars().enumerate() {
+        for (row, bits) in glyph(c).iter().enumerate() {
+            for col in 0..GLYPH_SIZE {
+                let (px, py) = (x + i * GLYPH_SIZE + col, y + row);
+                if bits & (1 << col) != 0 && px < width && py < height {
+                    let at = (py * width + px) * 4;
+                    rgba[at..at + 4].copy_from_slice(&colour);
+                }
+            }
+        }
+    }
+}
+
+// lines of text on a background a pixel wider all around, and its size
+pub fn image(text: &str, colour: [u8; 4], background: [u8; 4]) -> (Vec<u8>, usize, usize) {
+    let lines: Vec<&str> = text.lines().collect();
+    let w = lines.iter().map(|l| width(l)).max().unwrap_or(0) + 2;
+    let h = lines.len().max(1) * GLYPH_SIZE + 2;
+    let mut rgba = background.repeat(w * h);
+    for (i, line) in lines.iter().enumerate() {
+        draw(&mut rgba, w, 1, 1 + i * GLYPH_SIZE, line, colour);
+    }
+    (rgba, w, h)
+}
+
+// pixels in Unicode half blocks, two rows per line
+pub fn half_blocks(width: usize, height: usize, lit: impl Fn(usize, usize) -> bool) -> String {
+    let lit = |x: usize, y: usize| y < height && lit(x, y);
+    let mut text = String::new();
+    for y in (0..height).step_by(2) {
+        text.extend((0..width).map(|x| match (lit(x, y), lit(x, y + 1)) {
+            (false, false) => ' ',
+            (true, false) => '▀',
+            (false, true) => '▄',
+            (true, true) => '█',
+        }));
+        text.push('\n');
+    }
+    text
+}
+
+// a line of text in half blocks, four lines of them
+pub fn blocks(text: &str) -> String {
+    half_blocks(width(text), GLYPH_SIZE, |x, y| lit(text, x, y))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn text_glyphs() {
+        assert_eq!(16, width("Hi"));
+        assert_eq!(glyph('?'), glyph('é'));
+        assert_eq!(glyph('?'), glyph('\n'));
+        // the top of "T" is 6 pixels wide, its stem the middle two
+        let top: Vec<bool> = (0..8).map(|x| lit("T", x, 0)).collect();
+        assert_eq!(vec![true, true, true, true, true, true, false, false], top);
+        assert!(lit("T", 2, 3) && lit("T", 3, 3) && !lit("T", 0, 3));
+        assert!(!lit("T", 2, 8) && !lit("T", 9, 0));
+    }
+
+    #[test]
+    fn text_draw() {
+        let white = [255; 4];
+        let mut rgba = vec![0; 10 * 4 * 4];
+        // cut at the right and bottom edges
+        draw(&mut rgba, 10, 4, 1, "T", white);
+        let row = |y: usize| -> Vec<bool> { (0..10).map(|x| rgba[(y * 10 + x) * 4] > 0).collect() };
+        assert!(row(0).iter().all(|on| !on));
+        assert_eq!(
+            vec![false, false, false, false, true, true, true, true, true, true],
+            row(1)
+        );
+
+        let (image, w, h) = image("A", white, [1, 2, 3, 4]);
+        assert_eq!((10, 10, 400), (w, h, image.len()));
+        assert_eq!([1, 2, 3, 4], image[..4]);
+        // the top of "A" is its 3rd and 4th pixels, after the margin
+        assert_eq!(white, image[(w + 3) * 4..(w + 4) * 4]);
+        // as wide as the longest line, a glyph high per line
+        let (lines, w, h) = super::image("AB\nA", white, [0; 4]);
+        assert_eq!((18, 18), (w, h));
+        assert_eq!(white, lines[((GLYPH_SIZE + 1) * w + 3) * 4..][..4]);
+    }
+
+    #[test]
+    fn text_blocks() {
+        assert_eq!(" ▀██▀   \n  ██    \n  ██    \n ▀▀▀▀   \n", blocks("I"));
+    }
+}
@@ -0,0 +1,590 @@
+// Event timeline: what a program does over time, on four tracks: the
+// draws (DXYN and CLS), the sound (the buzzer starting and stopping), the
+// inputs (keys of a replayed recording, and FX0A waiting for one) and the
+// timers set (FX15 and FX18). A headless run exports it, and a terminal
+// viewer shows it with no other tool:
+//
+//     chip8 timeline record [--cycles N] [--replay FILE] ROM OUT
+//     chip8 timeline view [--width N] FILE
+//
+// The file is text, an event per line at the cycle it happened on:
+//
+//     chip8 timeline 1
+//     # 700 cycles per second
+//     120 draw V4,V5 x 10 y 12, 6 rows
+//     188 timer delay 20
+//     1520 input down 5
+//
+// The viewer draws a row per track, a column per span of cycles, with how
+// many events each span has (1 to 9, `#` for more). Commands are read a
+// line at a time, like the debugger's: `+` and `-` zoom in and out, `<` and
+// `>` scroll, `go CYCLE` moves there, `hide TRACK` and `show TRACK` filter
+// the tracks, `list` prints the events shown and `q` quits. No raw-mode TUI
+// (ratatui, crossterm): that would be a new dependency, or terminal code
+// per platform, for a tool; a line of commands works in any terminal and
+// from scripts, and the screen is cleared with the ANSI escapes only.
+
+use crate::exit;
+use crate::idle::Wait;
+use crate::replay::{Recording, Replay};
+use crate::scheduler::TimerClock;
+use crate::{Machine, CPU_IPS};
+use std::fs;
+use std::io::{self, BufRead, Write};
+use std::path::Path;
+
+const HEADER: &str = "chip8 timeline 1";
+// ten seconds
+const DEFAULT_CYCLES: u64 = CPU_IPS as u64 * 10;
+const DEFAULT_WIDTH: usize = 72;
+// the width of the track names
+const LABEL: usize = 7;
+// most events printed by `list`
+const LIST_MAX: usize = 40;
+const HOME: &str = "\x1b[H";
+const CLEAR: &str = "\x1b[2J";
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Track {
+    Draw,
+    Sound,
+    Input,
+    Timer,
+}
+
+pub const TRACKS: [Track; 4] = [Track::Draw, Track::Sound, Track::Input, Track::Timer];
+
+impl Track {
+    pub fn name(self) -> &'static str {
+        match self {
+            Track::Draw => "draw",
+            Track::Sound => "sound",
+            Track::Input => "input",
+            Track::Timer => "timer",
+        }
+    }
+
+    pub fn parse(name: &str) -> Result<Track, String> {
+        TRACKS
+            .iter()
+            .copied()
+            .find(|t| t.name() == name)
+            .ok_or_else(|| format!("unknown track `{}` (draw, sound, input or timer)", name))
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Event {
+    pub cycle: u64,
+    pub track: Track,
+    pub detail: String,
+}
+
+// watches a machine and records its events
+#[derive(Default)]
+pub struct Recorder {
+    cycle: u64,
+    playing: bool,
+    waiting: bool,
+    pub events: Vec<Event>,
+}
+
+impl Recorder {
+    pub fn new() -> Recorder {
+        Recorder::default()
+    }
+
+    fn push(&mut self, track: Track, detail: String) {
+        self.events.push(Event {
+            cycle: self.cycle,
+            track,
+            detail,
+        });
+    }
+
+    // a key of a replayed recording
+    pub fn key(&mut self, key: u8, pressed: bool) {
+        let state = if pressed { "down" } else { "up" };
+        self.push(Track::Input, format!("{} {:X}", state, key));
+    }
+
+    // call once per instruction, before running it
+    pub fn update(&mut self, m: &Machine) {
+        let word = match m.memory.get(m.pc..m.pc + 2) {
+            Some(&[hi, lo]) => u16::from(hi) << 8 | u16::from(lo),
+            _ => 0,
+        };
+        let (x, y) = (usize::from(word >> 8 & 0xF), usize::from(word >> 4 & 0xF));
+        match word & 0xF000 {
+            0x0000 if word == 0x00E0 => self.push(Track::Draw, String::from("clear")),
+            0xD000 => self.push(
+                Track::Draw,
+                format!(
+                    "V{:X},V{:X} x {} y {}, {} rows",
+                    x,
+                    y,
+                    m.registers[x],
+                    m.registers[y],
+                    word & 0xF
+                ),
+            ),
+            0xF000 if word & 0xFF == 0x15 => {
+                self.push(Track::Timer, format!("delay {}", m.registers[x]))
+            }
+            0xF000 if word & 0xFF == 0x18 => {
+                self.push(Track::Timer, format!("sound {}", m.registers[x]))
+            }
+            _ => {}
+        }
+        let playing = m.sound_timer > 0;
+        if playing != self.playing {
+            self.playing = playing;
+            self.push(
+                Track::Sound,
+                String::from(if playing { "on" } else { "off" }),
+            );
+        }
+        let waiting = m.waiting() == Some(Wait::Key);
+        if waiting && !self.waiting {
+            self.push(Track::Input, String::from("waiting for a key"));
+        }
+        self.waiting = waiting;
+        self.cycle += 1;
+    }
+
+    pub fn cycles(&self) -> u64 {
+        self.cycle
+    }
+}
+
+pub fn to_text(events: &[Event], cpu_hz: u32) -> String {
+    let mut text = format!("{}\n# {} cycles per second\n", HEADER, cpu_hz);
+    for e in events {
+        text.push_str(&format!("{} {} {}\n", e.cycle, e.track.name(), e.detail));
+    }
+    text
+}
+
+pub fn parse(text: &str) -> Result<Vec<Event>, String> {
+    let mut lines = text
+        .lines()
+        .map(str::trim)
+        .enumerate()
+        .filter(|(_, l)| !l.is_empty() && !l.starts_with('#'));
+    if lines.next().map(|(_, l)| l) != Some(HEADER) {
+        return Err(format!("not a timeline (no `{}` line)", HEADER));
+    }
+    let mut events: Vec<Event> = Vec::new();
+    for (n, line) in lines {
+        let invalid = || format!("line {}: invalid `{}`", n + 1, line);
+        let mut parts = line.splitn(3, ' ');
+        let cycle = parts
+            .next()
+            .and_then(|c| c.parse().ok())
+            .ok_or_else(invalid)?;
+        let track = Track::parse(parts.next().unwrap_or("")).map_err(|_| invalid())?;
+        if events.last().is_some_and(|e| e.cycle > cycle) {
+            return Err(format!("line {}: events out of order", n + 1));
+        }
+        events.push(Event {
+            cycle,
+            track,
+            detail: parts.next().unwrap_or("").to_string(),
+        });
+    }
+    Ok(events)
+}
+
+// the part of the timeline shown
+#[derive(Debug, Clone, PartialEq)]
+pub struct View {
+    // the first cycle shown, and the cycles per column
+    pub start: u64,
+    pub scale: u64,
+    pub width: usize,
+    pub hidden: Vec<Track>,
+    // the last cycle with an event, as far as the view goes
+    pub last: u64,
+}
+
+impl View {
+    // the whole of `events` in `width` columns
+    pub fn new(events: &[Event], width: usize) -> View {
+        let last = events.last().map_or(0, |e| e.cycle);
+        let width = width.max(1);
+        View {
+            start: 0,
+            scale: (last / width as u64 + 1).max(1),
+            width,
+            hidden: Vec::new(),
+            last,
+        }
+    }
+
+    // the cycles across the view, the widest that fits in a u64
+    fn span(&self) -> u64 {
+        self.scale.saturating_mul(self.width as u64)
+    }
+
+    pub fn end(&self) -> u64 {
+        self.start.saturating_add(self.span())
+    }
+
+    pub fn shown<'a>(&'a self, events: &'a [Event]) -> impl Iterator<Item = &'a Event> {
+        events.iter().filter(move |e| {
+            (self.start..self.end()).contains(&e.cycle) && !self.hidden.contains(&e.track)
+        })
+    }
+
+    // a viewer command, true to quit
+    pub fn command(&mut self, line: &str) -> Result<bool, String> {
+        let words: Vec<&str> = line.split_whitespace().collect();
+        let half = self.span() / 2;
+        match words[..] {
+            [] => {}
+            ["q"] | ["quit"] => return Ok(true),
+            // around the middle
+            ["+"] if self.scale > 1 => {
+                self.start = self.start.saturating_add(half / 2);
+                self.scale /= 2;
+            }
+            ["+"] => {}
+            // as far as scale * width fits
+            ["-"] => {
+                self.start = self.start.saturating_sub(half);
+                self.scale = self
+                    .scale
+                    .saturating_mul(2)
+                    .min(u64::MAX / self.width as u64);
+            }
+            ["<"] => self.start = self.start.saturating_sub(half),
+            // not past the last event
+            [">"] => self.start = self.start.saturating_add(half).min(self.last),
+            ["go", cycle] => {
+                let cycle: u64 = cycle
+                    .parse()
+                    .map_err(|_| format!("invalid cycle `{}`", cycle))?;
+                self.start = cycle.min(self.last).saturating_sub(half);
+            }
+            ["hide", track] => {
+                let track = Track::parse(track)?;
+                if !self.hidden.contains(&track) {
+                    self.hidden.push(track);
+                }
+            }
+            ["show", track] => {
+                let track = Track::parse(track)?;
+                self.hidden.retain(|t| *t != track);
+            }
+            _ => return Err(format!("unknown command `{}`", line.trim())),
+        }
+        Ok(false)
+    }
+
+    pub fn render(&self, events: &[Event]) -> String {
+        let mut text = format!(
+            "cycles {} to {}, {} per column\n",
+            self.start,
+            self.end() - 1,
+            self.scale
+        );
+        for track in TRACKS.iter().filter(|t| !self.hidden.contains(t)) {
+            let mut counts = vec![0; self.width];
+            for e in self.shown(events).filter(|e| e.track == *track) {
+                counts[((e.cycle - self.start) / self.scale) as usize] += 1;
+            }
+            let row: String = counts
+                .iter()
+                .map(|n| match n {
+                    0 => '.',
+                    1..=9 => char::from(b'0' + *n as u8),
+                    _ => '#',
+                })
+                .collect();
+            text.push_str(&format!("{:<w$}{}\n", track.name(), row, w = LABEL));
+        }
+        // a mark every 20 columns, with its cycle
+        let mut axis = " ".repeat(LABEL);
+        for column in (0..self.width).step_by(20) {
+            let label = format!("|{}", self.start.saturating_add(column as u64 * self.scale));
+            axis = format!("{:<w$}{}", axis, label, w = LABEL + column);
+        }
+        axis.truncate(LABEL + self.width);
+        text.push_str(&axis);
+        text.push('\n');
+        text
+    }
+}
+
+fn record(args: &[String]) -> io::Result<()> {
+    let invalid = |e: String| io::Error::new(io::ErrorKind::InvalidInput, e);
+    let mut cycles = DEFAULT_CYCLES;
+    let mut replay = None;
+    let mut files = Vec::new();
+    let mut it = args.iter();
+    while let Some(arg) = it.next() {
+        let mut value = |name: &str| {
+            it.next()
+                .ok_or_else(|| invalid(format!("missing value for {}", name)))
+        };
+        match arg.as_str() {
+            "--cycles" => {
+                let v = value(arg)?;
+                cycles = v
+                    .parse()
+                    .map_err(|_| invalid(format!("invalid value `{}` for --cycles", v)))?;
+            }
+            "--replay" => {
+                let path = value(arg)?;
+                let recording = Recording::load(Path::new(path)).map_err(|e| {
+                    io::Error::new(
+                        e.kind(),
+                        format!("cannot load the inputs `{}`: {}", path, e),
+                    )
+                })?;
+                replay = Some(Replay::new(recording));
+            }
+            s if s.starts_with("--") => return Err(invalid(format!("unknown option `{}`", s))),
+            s => files.push(s.to_string()),
+        }
+    }
+    let (rom, out) = match files.as_slice() {
+        [rom, out] => (rom, out),
+        _ => {
+            return Err(invalid(String::from(
+                "usage: chip8 timeline record [--cycles N] [--replay FILE] ROM OUT",
+            )))
+        }
+    };
+
+    let mut m = Machine::new();
+    let mut cpu_hz = CPU_IPS;
+    if let Some(recording) = replay.as_ref().map(Replay::recording) {
+        m.set_quirks(recording.quirks);
+        cpu_hz = recording.cpu_hz;
+    }
+    m.init();
+    m.seed_rng(replay.as_ref().map_or(0, |r| r.recording().seed));
+    m.load_program_file(rom)
+        .map_err(|e| exit::rom_load(rom, e))?;
+    if replay
+        .as_ref()
+        .is_some_and(|r| r.recording().program_hash != m.program_hash())
+    {
+        return Err(invalid(format!(
+            "{} isn't the ROM the inputs were recorded with",
+            rom
+        )));
+    }
+
+    let mut recorder = Recorder::new();
+    let mut clock = TimerClock::new(cpu_hz);
+    let mut halted = None;
+    while recorder.cycles() < cycles {
+        if let Some(replay) = replay.as_mut() {
+            for e in replay.due(recorder.cycles()) {
+                m.set_key_state(e.key, e.pressed);
+                recorder.key(e.key, e.pressed);
+            }
+        }
+        recorder.update(&m);
+        if m.exec_single().is_err() {
+            halted = m.invalid_opcode();
+            break;
+        }
+        for _ in 0..clock.advance(1) {
+            m.tick_timers();
+        }
+    }
+
+    fs::write(out, to_text(&recorder.events, cpu_hz))?;
+    println!(
+        "{}: {} events in {} cycles",
+        out,
+        recorder.events.len(),
+        recorder.cycles()
+    );
+    // what was recorded up to it is still written
+    match halted {
+        Some((pc, opcode)) => Err(exit::invalid_opcode(pc, opcode)),
+        None => Ok(()),
+    }
+}
+
+fn view(args: &[String]) -> io::Result<()> {
+    let invalid = |e: String| io::Error::new(io::ErrorKind::InvalidInput, e);
+    let mut width = DEFAULT_WIDTH;
+    let mut file = None;
+    let mut it = args.iter();
+    while let Some(arg) = it.next() {
+        match arg.as_str() {
+            "--width" => {
+                let v = it
+                    .next()
+                    .ok_or_else(|| invalid(String::from("missing value for --width")))?;
+                width = v
+                    .parse()
+                    .ok()
+                    .filter(|w| *w >= 20)
+                    .ok_or_else(|| invalid(format!("invalid width `{}` (20 or more)", v)))?;
+            }
+            s if s.starts_with("--") => return Err(invalid(format!("unknown option `{}`", s))),
+            s if file.is_none() => file = Some(s.to_string()),
+            s => return Err(invalid(format!("unexpected argument `{}`", s))),
+        }
+    }
+    let file =
+        file.ok_or_else(|| invalid(String::from("usage: chip8 timeline view [--width N] FILE")))?;
+    let events = parse(&fs::read_to_string(&file)?)
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, format!("{}: {}", file, e)))?;
+
+    let mut view = View::new(&events, width);
+    let mut message = String::new();
+    let stdin = io::stdin();
+    let mut lines = stdin.lock().lines();
+    loop {
+        print!("{}{}{}", CLEAR, HOME, view.render(&events));
+        print!("{}\n+ - < > go CYCLE hide/show TRACK list q: ", message);
+        io::stdout().flush()?;
+        let line = match lines.next() {
+            Some(line) => line?,
+            None => return Ok(()),
+        };
+        message.clear();
+        if line.trim() == "list" {
+            for e in view.shown(&events).take(LIST_MAX) {
+                message.push_str(&format!(
+                    "{:>8} {:<6}{}\n",
+                    e.cycle,
+                    e.track.name(),
+                    e.detail
+                ));
+            }
+            continue;
+        }
+        match view.command(&line) {
+            Ok(true) => return Ok(()),
+            Ok(false) => {}
+            Err(e) => message = format!("{}\n", e),
+        }
+    }
+}
+
+pub fn run(args: &[String]) -> io::Result<()> {
+    match args.first().map(String::as_str) {
+        Some("record") => record(&args[1..]),
+        Some("view") => view(&args[1..]),
+        _ => Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "usage: chip8 timeline record|view ...",
+        )),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn timeline_records() {
+        let mut m = Machine::new();
+        m.init();
+        m.load_program(vec![
+            0x00, 0xE0, // 200 CLS
+            0x60, 0x05, // 202 LD V0, 5
+            0xF0, 0x18, // 204 LD ST, V0
+            0xD0, 0x03, // 206 DRW V0, V0, 3
+            0xF1, 0x0A, // 208 LD V1, K
+        ])
+        .unwrap();
+        let mut recorder = Recorder::new();
+        for _ in 0..4 {
+            recorder.update(&m);
+            m.exec_single().unwrap();
+        }
+        recorder.update(&m);
+        recorder.key(0xA, true);
+        let text = to_text(&recorder.events, CPU_IPS);
+        assert_eq!(
+            "chip8 timeline 1\n# 700 cycles per second\n0 draw clear\n2 timer sound 5\n\
+             3 draw V0,V0 x 5 y 5, 3 rows\n3 sound on\n4 input waiting for a key\n\
+             5 input down A\n",
+            text
+        );
+        assert_eq!(recorder.events, parse(&text).unwrap());
+        assert!(parse("0 draw clear\n").is_err());
+        assert!(parse("chip8 timeline 1\n5 draw a\n4 draw b\n").is_err());
+        assert!(parse("chip8 timeline 1\n5 music a\n").is_err());
+    }
+
+    #[test]
+    fn timeline_view() {
+        let event = |cycle, track| Event {
+            cycle,
+            track,
+            detail: String::new(),
+        };
+        let events = [
+            event(0, Track::Draw),
+            event(1, Track::Draw),
+            event(25, Track::Timer),
+            event(399, Track::Input),
+        ];
+        let mut view = View::new(&events, 40);
+        assert_eq!(10, view.scale);
+        assert_eq!(
+            "cycles 0 to 399, 10 per column\n\
+             draw   2.......................................\n\
+             sound  ........................................\n\
+             input  .......................................1\n\
+             timer  ..1.....................................\n       \
+             |0                  |200\n",
+            view.render(&events)
+        );
+
+        view.command("hide sound").unwrap();
+        view.command("+").unwrap();
+        assert_eq!((100, 5), (view.start, view.scale));
+        view.command("go 20").unwrap();
+        assert_eq!(0, view.start);
+        assert_eq!(3, view.shown(&events).count());
+        assert!(!view.render(&events).contains("sound"));
+        view.command("show sound").unwrap();
+        view.command("-").unwrap();
+        assert_eq!((0, 10), (view.start, view.scale));
+        view.command(">").unwrap();
+        assert_eq!(200, view.start);
+        assert!(view.command("hide music").is_err());
+        assert!(view.command("jump").is_err());
+        assert_eq!(Ok(true), view.command("q"));
+    }
+
+    #[test]
+    fn timeline_view_bounds() {
+        let events = [Event {
+            cycle: 399,
+            track: Track::Draw,
+            detail: String::new(),
+        }];
+        let mut view = View::new(&events, 40);
+        // not past the last event
+        view.command(&format!("go {}", u64::MAX)).unwrap();
+        assert_eq!(399 - 200, view.start);
+        for _ in 0..10 {
+            view.command(">").unwrap();
+        }
+        assert_eq!(399, view.start);
+        assert!(view.render(&events).starts_with("cycles 399 to 798, "));
+
+        // zoomed out as far as it goes
+        for _ in 0..100 {
+            view.command("-").unwrap();
+        }
+        assert_eq!(u64::MAX / 40, view.scale);
+        assert_eq!(0, view.start);
+        assert_eq!(u64::MAX - u64::MAX % 40, view.end());
+        assert!(view.render(&events).contains("draw   1......"));
+        view.command("go 399").unwrap();
+        view.render(&events);
+    }
+}
@@ -0,0 +1,364 @@
+// Instruction trace: a line per executed instruction, disassembled, with
+// I and the registers before and after it (`--trace FILE`).
+//
+//     0x200: 6005  LD V0, 0x05     I=000 V=00 00 .. 00 -> I=000 V=05 00 .. 00
+//
+// The caller brackets each `exec_single` with `before` and `after`.
+//
+// Traces of long runs are cut down with a filter (`--trace-filter SPEC`),
+// a comma separated list of what to keep:
+//
+//     0x200-0x2FF    instructions in the range (or at a single address)
+//     draw, jump...  instructions of a class (see `Class`)
+//     V3             instructions reading or writing V3
+//
+// An instruction is traced when it matches one of the ranges, one of the
+// classes and one of the registers; those left out match everything.
+
+use crate::disasm::{self, Syntax};
+use crate::watch::parse_number;
+use crate::{parse_opcode, Machine, OpCode, MEMORY_SIZE};
+use std::io::{self, Write};
+
+// width of the instruction column
+const INSTRUCTION_WIDTH: usize = 28;
+
+// the instruction at pc, e.g. `0x202: 7101  ADD V1, 0x01`
+pub fn instruction(m: &Machine) -> String {
+    match word(m) {
+        Some(w) => listing(m.pc, w),
+        None => format!("{:#05X}: out of memory", m.pc),
+    }
+}
+
+// the word at `address`, disassembled
+pub fn listing(address: usize, word: u16) -> String {
+    let text = disasm::decode(word).and_then(|f| f.format(word, Syntax::CowGod));
+    format!(
+        "{:#05X}: {:04X}  {}",
+        address,
+        word,
+        text.as_deref().unwrap_or("???")
+    )
+}
+
+// the word at pc, none past the end of memory
+fn word(m: &Machine) -> Option<u16> {
+    match m.memory.get(m.pc..m.pc + 2) {
+        Some([hi, lo]) => Some(u16::from(*hi) << 8 | u16::from(*lo)),
+        _ => None,
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Class {
+    // CLS, DRW, the scrolls, the resolution and plane changes
+    Draw,
+    // JP and JP V0
+    Jump,
+    // CALL and RET
+    Call,
+    // the conditional skips, the key ones included
+    Skip,
+    // SKP, SKNP and waiting for a key
+    Key,
+    // reading and setting the timers
+    Timer,
+    // reading and writing memory from the registers (BCD, save, load...)
+    Memory,
+    // setting registers from values, registers and random numbers
+    Alu,
+}
+
+impl Class {
+    const NAMES: [(&'static str, Class); 8] = [
+        ("draw", Class::Draw),
+        ("jump", Class::Jump),
+        ("call", Class::Call),
+        ("skip", Class::Skip),
+        ("key", Class::Key),
+        ("timer", Class::Timer),
+        ("memory", Class::Memory),
+        ("alu", Class::Alu),
+    ];
+
+    pub fn parse(name: &str) -> Option<Class> {
+        Class::NAMES
+            .iter()
+            .find(|(n, _)| *n == name)
+            .map(|(_, c)| *c)
+    }
+
+    pub fn contains(self, op: &OpCode) -> bool {
+        use OpCode::*;
+        match self {
+            Class::Draw => matches!(
+                *op,
+                Clear
+                    | Draw(..)
+                    | ScrollDown(_)
+                    | ScrollUp(_)
+                    | ScrollRight
+                    | ScrollLeft
+                    | LowRes
+                    | HighRes
+                    | SelectPlanes(_)
+            ),
+            Class::Jump => matches!(*op, JumpTo(_) | Flow(_)),
+            Class::Call => matches!(*op, Call(_) | Return),
+            Class::Skip => matches!(
+                *op,
+                SkipEq(..)
+                    | SkipNotEq(..)
+                    | SkipEqXY(..)
+                    | SkipNotEqXY(..)
+                    | KeyPressedX(_)
+                    | KeyNotPressedX(_)
+            ),
+            Class::Key => matches!(*op, KeyPressedX(_) | KeyNotPressedX(_) | KeyPressX(_)),
+            Class::Timer => matches!(*op, TimerX(_) | SetDelayTimer(_) | SetSoundTimer(_)),
+            Class::Memory => matches!(
+                *op,
+                SaveRange(..) | LoadRange(..) | Bcd(_) | DumpX(_) | LoadX(_) | LoadPattern
+            ),
+            Class::Alu => matches!(
+                *op,
+                SetX(..)
+                    | AddX(..)
+                    | AssignXY(..)
+                    | OrXY(..)
+                    | AndXY(..)
+                    | XorXY(..)
+                    | AddXY(..)
+                    | SubXY(..)
+                    | ShiftRightX1(..)
+                    | SubYX(..)
+                    | ShiftLeftX1(..)
+                    | RandX(..)
+            ),
+        }
+    }
+}
+
+// the registers an instruction reads or writes, bit N for VN
+pub fn registers(op: &OpCode) -> u16 {
+    use OpCode::*;
+    const VF: u16 = 1 << 0xF;
+    let bit = |r: usize| 1u16 << r;
+    // VX to VY, either way round
+    let span = |x: usize, y: usize| (x.min(y)..=x.max(y)).fold(0, |bits, r| bits | bit(r));
+    match *op {
+        SkipEq(x, _) | SkipNotEq(x, _) | SetX(x, _) | AddX(x, _) | RandX(x, _) => bit(x),
+        KeyPressedX(x) | KeyNotPressedX(x) | TimerX(x) | KeyPressX(x) | SetDelayTimer(x)
+        | SetSoundTimer(x) | MemAdd(x) | SpriteX(x) | BigSpriteX(x) | Bcd(x) | SetPitch(x) => {
+            bit(x)
+        }
+        SkipEqXY(x, y)
+        | SkipNotEqXY(x, y)
+        | AssignXY(x, y)
+        | OrXY(x, y)
+        | AndXY(x, y)
+        | XorXY(x, y) => bit(x) | bit(y),
+        // with the carry or the collision in VF
+        AddXY(x, y)
+        | SubXY(x, y)
+        | ShiftRightX1(x, y)
+        | SubYX(x, y)
+        | ShiftLeftX1(x, y)
+        | Draw(x, y, _) => bit(x) | bit(y) | VF,
+        SaveRange(x, y) | LoadRange(x, y) => span(x, y),
+        DumpX(x) | LoadX(x) | SaveFlags(x) | LoadFlags(x) => span(0, x),
+        Flow(_) => bit(0),
+        _ => 0,
+    }
+}
+
+// what gets traced, everything by default
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Filter {
+    // inclusive address ranges
+    ranges: Vec<(usize, usize)>,
+    classes: Vec<Class>,
+    registers: u16,
+}
+
+impl Filter {
+    pub fn parse(spec: &str) -> Result<Filter, String> {
+        let address = |s: &str| match parse_number(s) {
+            Some(a) if usize::from(a) < MEMORY_SIZE => Ok(usize::from(a)),
+            _ => Err(format!("invalid address `{}`", s)),
+        };
+        let mut filter = Filter::default();
+        for item in spec.split(',').map(str::trim) {
+            if let Some(class) = Class::parse(item) {
+                filter.classes.push(class);
+            } else if let Some(r) = item
+                .strip_prefix(['V', 'v'])
+                .filter(|r| r.len() == 1)
+                .and_then(|r| u8::from_str_radix(r, 16).ok())
+            {
+                filter.registers |= 1 << r;
+            } else if let Some((start, end)) = item.split_once('-') {
+                let (start, end) = (address(start)?, address(end)?);
+                if start > end {
+                    return Err(format!("empty range `{}`", item));
+                }
+                filter.ranges.push((start, end));
+            } else if item.starts_with(|c: char| c.is_ascii_digit()) {
+                let a = address(item)?;
+                filter.ranges.push((a, a));
+            } else {
+                let names: Vec<&str> = Class::NAMES.iter().map(|(n, _)| *n).collect();
+                return Err(format!(
+                    "unknown trace filter `{}` (an address range, a register or one of: {})",
+                    item,
+                    names.join(", ")
+                ));
+            }
+        }
+        Ok(filter)
+    }
+
+    // whether the instruction at pc is traced
+    pub fn matches(&self, m: &Machine) -> bool {
+        let op = parse_opcode(word(m));
+        (self.ranges.is_empty() || self.ranges.iter().any(|(s, e)| (*s..=*e).contains(&m.pc)))
+            && (self.classes.is_empty() || self.classes.iter().any(|c| c.contains(&op)))
+            && (self.registers == 0 || registers(&op) & self.registers != 0)
+    }
+}
+
+fn state(m: &Machine) -> String {
+    let registers: Vec<String> = m.registers.iter().map(|v| format!("{:02X}", v)).collect();
+    format!("I={:03X} V={}", m.index_register, registers.join(" "))
+}
+
+pub struct Tracer<W: Write> {
+    out: W,
+    // the line of the instruction being executed
+    pending: Option<String>,
+    filter: Filter,
+}
+
+impl<W: Write> Tracer<W> {
+    pub fn new(out: W) -> Tracer<W> {
+        Tracer::with_filter(out, Filter::default())
+    }
+
+    pub fn with_filter(out: W, filter: Filter) -> Tracer<W> {
+        Tracer {
+            out,
+            pending: None,
+            filter,
+        }
+    }
+
+    // skipped when the filter leaves the instruction out
+    pub fn before(&mut self, m: &Machine) {
+        if !self.filter.matches(m) {
+            self.pending = None;
+            return;
+        }
+        self.pending = Some(format!(
+            "{:<width$} {}",
+            instruction(m),
+            state(m),
+            width = INSTRUCTION_WIDTH
+        ));
+    }
+
+    pub fn after(&mut self, m: &Machine) -> io::Result<()> {
+        match self.pending.take() {
+            Some(line) => writeln!(self.out, "{} -> {}", line, state(m)),
+            None => Ok(()),
+        }
+    }
+
+    pub fn into_inner(self) -> W {
+        self.out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn trace_lines() {
+        let mut m = Machine::new();
+        m.init();
+        // V0 = 5; I = 0x300; invalid
+        m.load_program(vec![0x60, 0x05, 0xA3, 0x00, 0xF0, 0xFF])
+            .unwrap();
+        let mut tracer = Tracer::new(Vec::new());
+        loop {
+            tracer.before(&m);
+            let alive = m.exec_single().is_ok();
+            tracer.after(&m).unwrap();
+            if !alive {
+                break;
+            }
+        }
+        // nothing pending
+        tracer.after(&m).unwrap();
+
+        let zeros = vec!["00"; 15].join(" ");
+        let text = String::from_utf8(tracer.into_inner()).unwrap();
+        let lines: Vec<&str> = text.lines().collect();
+        assert_eq!(3, lines.len(), "{}", text);
+        assert_eq!(
+            format!(
+                "0x200: 6005  LD V0, 0x05     I=000 V=00 {0} -> I=000 V=05 {0}",
+                zeros
+            ),
+            lines[0]
+        );
+        assert!(
+            lines[1].starts_with("0x202: A300  LD I, 0x300     I=000 V=05"),
+            "{}",
+            lines[1]
+        );
+        assert!(lines[1].ends_with(&format!("-> I=300 V=05 {}", zeros)));
+        // the instruction it halted on
+        assert!(lines[2].starts_with("0x204: F0FF  ???"), "{}", lines[2]);
+    }
+
+    #[test]
+    fn trace_filters() {
+        assert!(Filter::parse("0x2FF-0x200").is_err());
+        assert!(Filter::parse("0x200-").is_err());
+        assert!(Filter::parse("VG").is_err());
+        let e = Filter::parse("draw,jumps").unwrap_err();
+        assert!(e.starts_with("unknown trace filter `jumps`"), "{}", e);
+
+        // the addresses of the first 8 instructions traced: V3 = 1; I = 0x300;
+        // draw at V3, V4; V3 += V4; jump to the draw
+        let traced = |spec: &str| {
+            let mut m = Machine::new();
+            m.init();
+            m.load_program_hex("6301 A300 D341 8344 1204").unwrap();
+            let mut tracer = Tracer::with_filter(Vec::new(), Filter::parse(spec).unwrap());
+            for _ in 0..8 {
+                tracer.before(&m);
+                m.exec_single().unwrap();
+                tracer.after(&m).unwrap();
+            }
+            let text = String::from_utf8(tracer.into_inner()).unwrap();
+            text.lines()
+                .map(|l| String::from(&l[..5]))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            vec!["0x204", "0x208", "0x204", "0x208"],
+            traced("draw,jump")
+        );
+        assert_eq!(
+            vec!["0x202", "0x206", "0x208", "0x206", "0x208"],
+            traced("0x202,0x206-0x208")
+        );
+        // the draws and additions set VF
+        assert_eq!(vec!["0x204", "0x206", "0x204", "0x206"], traced("VF"));
+        assert_eq!(vec!["0x204", "0x204"], traced("0x204-0x205,V3"));
+        assert_eq!(8, traced("0x200-0xFFFF").len());
+    }
+}
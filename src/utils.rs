@@ -1,4 +1,13 @@
 use std::convert::TryFrom;
+use std::path::{Path, PathBuf};
+
+// `$XDG_DATA_HOME`, `~/.local/share` by default, none without a home directory
+pub fn data_dir() -> Option<PathBuf> {
+    match std::env::var_os("XDG_DATA_HOME") {
+        Some(dir) if !dir.is_empty() => Some(PathBuf::from(dir)),
+        _ => std::env::var_os("HOME").map(|home| Path::new(&home).join(".local/share")),
+    }
+}
 
 pub fn convert_to_bits(mut b: u8) -> [u8; 8] {
     let mut r: [u8; 8] = [0; 8];
@@ -26,6 +35,56 @@ pub fn convert_to_bcd(mut d: u16) -> [u8; 3] {
     r
 }
 
+// 64 bit FNV-1a, continuing from a previous hash value
+pub const FNV_OFFSET: u64 = 0xcbf2_9ce4_8422_2325;
+
+pub fn fnv1a(mut hash: u64, data: &[u8]) -> u64 {
+    for b in data {
+        hash ^= u64::from(*b);
+        hash = hash.wrapping_mul(0x0000_0100_0000_01b3);
+    }
+    hash
+}
+
+// the address and file of an overlay given as `ADDR:FILE`, e.g.
+// `0x600:levels.bin`
+pub fn parse_overlay(text: &str) -> Result<(usize, String), String> {
+    let invalid = || format!("invalid overlay `{}`, expected ADDR:FILE", text);
+    let (address, file) = text.split_once(':').ok_or_else(invalid)?;
+    let address = crate::watch::parse_number(address)
+        .map(usize::from)
+        .filter(|a| *a < crate::MEMORY_SIZE)
+        .ok_or_else(invalid)?;
+    if file.is_empty() {
+        return Err(invalid());
+    }
+    Ok((address, file.to_string()))
+}
+
+// bytes of a hex dump like "6005 6102", "0x60, 0x05" or "200: 60 05"
+// (address labels ending with `:` are skipped)
+pub fn parse_hex(text: &str) -> Result<Vec<u8>, String> {
+    let mut bytes = Vec::new();
+    let tokens = text
+        .split(|c: char| c.is_whitespace() || c == ',')
+        .filter(|t| !t.is_empty() && !t.ends_with(':'));
+    for token in tokens {
+        let digits = token
+            .strip_prefix("0x")
+            .or_else(|| token.strip_prefix("0X"))
+            .unwrap_or(token);
+        if digits.is_empty() || digits.len() % 2 != 0 || !digits.is_ascii() {
+            return Err(format!("invalid hex `{}`", token));
+        }
+        for i in (0..digits.len()).step_by(2) {
+            let b = u8::from_str_radix(&digits[i..i + 2], 16)
+                .map_err(|_| format!("invalid hex `{}`", token))?;
+            bytes.push(b);
+        }
+    }
+    Ok(bytes)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -53,4 +112,45 @@ mod tests {
         assert_eq!([1, 2, 7], convert_to_bcd(127));
         assert_eq!([2, 5, 5], convert_to_bcd(255));
     }
+
+    #[test]
+    fn parse_hex_tests() {
+        let program = vec![0x60, 0x05, 0x61, 0x02];
+        assert_eq!(Ok(program.clone()), parse_hex("6005 6102"));
+        assert_eq!(Ok(program.clone()), parse_hex("0x60, 0x05,\n0x61, 0x02"));
+        assert_eq!(Ok(program.clone()), parse_hex("200: 60 05\n202: 61 02\n"));
+        assert_eq!(Ok(program), parse_hex("60056102"));
+        assert_eq!(Ok(Vec::new()), parse_hex("  "));
+
+        assert!(parse_hex("600").is_err());
+        assert!(parse_hex("60G5").is_err());
+        assert!(parse_hex("0x").is_err());
+        assert!(parse_hex("6é").is_err());
+    }
+
+    #[test]
+    fn parse_overlay_tests() {
+        assert_eq!(
+            Ok((0x600, String::from("levels.bin"))),
+            parse_overlay("0x600:levels.bin")
+        );
+        assert_eq!(
+            Ok((1536, String::from(r"C:\roms\levels.bin"))),
+            parse_overlay(r"1536:C:\roms\levels.bin")
+        );
+        assert!(parse_overlay("levels.bin").is_err());
+        assert!(parse_overlay("0x600:").is_err());
+        assert!(parse_overlay("0x1000g:levels.bin").is_err());
+    }
+
+    #[test]
+    fn fnv1a_tests() {
+        assert_eq!(0xcbf29ce484222325, fnv1a(FNV_OFFSET, b""));
+        assert_eq!(0xaf63dc4c8601ec8c, fnv1a(FNV_OFFSET, b"a"));
+        assert_eq!(0x85944171f73967e8, fnv1a(FNV_OFFSET, b"foobar"));
+        assert_eq!(
+            fnv1a(FNV_OFFSET, b"foobar"),
+            fnv1a(fnv1a(FNV_OFFSET, b"foo"), b"bar")
+        );
+    }
 }
@@ -0,0 +1,67 @@
+// Watched locations of the machine state, shared by the features reading
+// game values out of a running program (achievements, annotations).
+//
+// `[ADDR]` is the byte at a memory address and `VX` a register; numbers
+// are decimal or 0x prefixed hexadecimal.
+
+use crate::Machine;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Operand {
+    Memory(usize),
+    Register(usize),
+}
+
+impl Operand {
+    pub fn read(&self, m: &Machine) -> u16 {
+        match *self {
+            Operand::Memory(a) => u16::from(m.memory[a]),
+            Operand::Register(r) => u16::from(m.registers[r]),
+        }
+    }
+
+    // the largest value `read` can return; memory and registers are bytes
+    pub fn max(&self) -> u16 {
+        u16::from(u8::MAX)
+    }
+}
+
+pub fn parse_number(s: &str) -> Option<u16> {
+    match s.strip_prefix("0x").or_else(|| s.strip_prefix("0X")) {
+        Some(hex) => u16::from_str_radix(hex, 16).ok(),
+        None => s.parse().ok(),
+    }
+}
+
+pub fn parse_operand(s: &str) -> Result<Operand, String> {
+    if let Some(addr) = s.strip_prefix('[').and_then(|s| s.strip_suffix(']')) {
+        return match parse_number(addr.trim()) {
+            Some(a) if usize::from(a) < crate::MEMORY_SIZE => Ok(Operand::Memory(usize::from(a))),
+            _ => Err(format!("invalid address `{}`", addr)),
+        };
+    }
+    if let Some(r) = s.strip_prefix('V').or_else(|| s.strip_prefix('v')) {
+        if let Ok(r) = usize::from_str_radix(r, 16) {
+            if r < 16 {
+                return Ok(Operand::Register(r));
+            }
+        }
+    }
+    Err(format!("invalid operand `{}`", s))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn watch_parse_operand() {
+        assert_eq!(Ok(Operand::Memory(0x2F0)), parse_operand("[0x2F0]"));
+        assert_eq!(Ok(Operand::Memory(100)), parse_operand("[100]"));
+        assert_eq!(Ok(Operand::Register(0xA)), parse_operand("VA"));
+        assert_eq!(Ok(Operand::Register(3)), parse_operand("v3"));
+        assert!(parse_operand("[0x10000]").is_err());
+        assert!(parse_operand("V10").is_err());
+        assert!(parse_operand("0x200").is_err());
+    }
+}
@@ -0,0 +1,42 @@
+// The interpreter hot path doesn't allocate: once a program is loaded,
+// running it (instructions, drawing, timers, keys) leaves the heap alone.
+// One test in its own binary, so no other test allocates meanwhile.
+
+use chip8_core::alloc_audit::{allocations, CountingAllocator};
+use chip8_core::{Machine, CPU_IPS, TIMER_HZ};
+
+#[global_allocator]
+static ALLOCATOR: CountingAllocator = CountingAllocator;
+
+const FRAMES: u32 = 600;
+
+#[test]
+#[cfg_attr(miri, ignore)]
+fn allocations_hot_path() {
+    for rom in ["data/test_opcode.rom", "data/pong.rom", "data/tetris.rom"] {
+        let mut m = Machine::new();
+        m.init();
+        m.seed_rng(1);
+        m.load_program_file(rom).unwrap();
+
+        // a frame's worth to settle: buffers reach their size
+        let frame = CPU_IPS / TIMER_HZ;
+        for _ in 0..frame {
+            m.exec_single().unwrap();
+        }
+
+        let before = allocations();
+        for i in 0..FRAMES {
+            // some keys, for the programs that wait on one
+            m.set_key_state((i % 16) as u8, i % 3 == 0);
+            for _ in 0..frame {
+                if m.exec_single().is_err() {
+                    break;
+                }
+            }
+            m.tick_timers();
+            m.framebuffer_mut().take_dirty();
+        }
+        assert_eq!(0, allocations() - before, "{} allocates", rom);
+    }
+}
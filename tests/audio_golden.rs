@@ -0,0 +1,124 @@
+// The sound of deterministic runs, rendered to PCM through the library API
+// and compared with golden checksums: a change to the audio (the recorder,
+// the voice, the filters, the timers) that changes a sample fails here. When
+// a change is meant, the new checksums are in the failure messages.
+
+use chip8_core::audio::{self, Event, Filter};
+use chip8_core::{demo, utils, Machine};
+use std::convert::TryFrom;
+
+// about 4 seconds
+const CYCLES: u64 = 3_000;
+
+fn machine(program: Vec<u8>) -> Machine {
+    let mut m = Machine::new();
+    m.init();
+    m.seed_rng(0);
+    m.load_program(program).unwrap();
+    m
+}
+
+fn events(program: Vec<u8>, cycles: u64) -> Vec<(u64, Event)> {
+    let (recorder, invalid) = audio::record(&mut machine(program), cycles);
+    assert_eq!(None, invalid);
+    recorder.events
+}
+
+fn checksum(samples: &[i16]) -> u64 {
+    let bytes: Vec<u8> = samples.iter().flat_map(|s| s.to_le_bytes()).collect();
+    utils::fnv1a(utils::FNV_OFFSET, &bytes)
+}
+
+// the samples of both filters, checked against their checksums
+fn check(name: &str, events: &[(u64, Event)], cycles: u64, expected: [u64; 2]) {
+    let filters = [(Filter::Box, expected[0]), (Filter::Nearest, expected[1])];
+    for &(filter, expected) in filters.iter() {
+        let samples = audio::render(events, cycles, filter);
+        assert!(samples.iter().any(|s| *s != 0), "{}: silent", name);
+        assert_eq!(
+            expected,
+            checksum(&samples),
+            "{} ({:?}): got {:#018x}",
+            name,
+            filter,
+            checksum(&samples)
+        );
+    }
+}
+
+// beeps of the default square wave, then of a pattern at two pitches
+fn xo_chip_program() -> Vec<u8> {
+    let mut program = vec![
+        0x60, 0x0A, // 200 LD V0, 10
+        0xF0, 0x18, // 202 LD ST, V0
+        0x61, 0x14, // 204 LD V1, 20
+        0xF1, 0x15, // 206 LD DT, V1
+        0xF1, 0x07, // 208 LD V1, DT
+        0x31, 0x00, // 20A SE V1, 0
+        0x12, 0x08, // 20C JP 208
+        0xA2, 0x2C, // 20E LD I, 22C
+        0xF0, 0x02, // 210 LD PATTERN, [I]
+        0x62, 0x70, // 212 LD V2, 112
+        0xF2, 0x3A, // 214 LD PITCH, V2
+        0xF0, 0x18, // 216 LD ST, V0
+        0xF0, 0x15, // 218 LD DT, V0
+        0xF1, 0x07, // 21A LD V1, DT
+        0x31, 0x00, // 21C SE V1, 0
+        0x12, 0x1A, // 21E JP 21A
+        0x62, 0x30, // 220 LD V2, 48
+        0xF2, 0x3A, // 222 LD PITCH, V2
+        0xF0, 0x18, // 224 LD ST, V0
+        0x00, 0xE0, // 226 CLS
+        0x12, 0x28, // 228 JP 228
+        0x00, 0x00, // 22A
+    ];
+    // 22C: a pattern with a little of everything
+    program.extend((0..16).map(|i| u8::try_from(i * 37 % 256).unwrap() ^ 0x5A));
+    program
+}
+
+#[test]
+#[cfg_attr(miri, ignore)]
+fn audio_golden_xo_chip() {
+    let events = events(xo_chip_program(), CYCLES);
+    // a beep, the pattern and pitch, a beep, the pitch, a beep
+    let kinds: Vec<&str> = events
+        .iter()
+        .map(|(_, e)| match e {
+            Event::On => "on",
+            Event::Off => "off",
+            Event::Pattern(_) => "pattern",
+            Event::Pitch(_) => "pitch",
+        })
+        .collect();
+    assert_eq!(
+        ["on", "off", "pattern", "pitch", "on", "off", "pitch", "on", "off"],
+        kinds[..]
+    );
+    check(
+        "xo-chip",
+        &events,
+        CYCLES,
+        [0x5103_3895_eec5_d174, 0xf1a3_e578_cb15_1a5d],
+    );
+}
+
+#[test]
+#[cfg_attr(miri, ignore)]
+fn audio_golden_roms() {
+    // the demo and Pong serving and missing on their own
+    let cycles = CYCLES * 10;
+    check(
+        "demo",
+        &events(demo::program(), cycles),
+        cycles,
+        [0xd3e3_cc40_39b8_9e4a, 0x3b0d_9415_c3ce_4ded],
+    );
+    let pong = std::fs::read("data/pong.rom").unwrap();
+    check(
+        "pong",
+        &events(pong, cycles),
+        cycles,
+        [0x67be_784d_71c2_252f, 0xd981_90f5_56bf_8398],
+    );
+}
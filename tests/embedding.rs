@@ -0,0 +1,38 @@
+// The interpreter driven through the library API only, as a frontend other
+// than the SDL window would.
+
+use chip8_core::{Chip8Error, Machine, Timer, GFX_HEIGHT, GFX_WIDTH};
+
+#[test]
+fn embedding_run_draw_and_keypad() {
+    let mut m = Machine::new();
+    m.init();
+    // wait for a key, draw its font sprite at (0, 0), beep, halt
+    m.load_program_hex("F00A F029 6100 D115 F018").unwrap();
+
+    m.exec_single().unwrap();
+    assert!(!m.framebuffer_mut().take_dirty());
+    m.set_key_state(0xA, true);
+    assert!(m.is_key_pressed(0xA));
+    // the program ends on the zeros after it
+    let halt = loop {
+        if let Err(e) = m.exec_single() {
+            break e;
+        }
+    };
+    assert_eq!(
+        Chip8Error::InvalidOpcode {
+            pc: 0x20A,
+            opcode: 0
+        },
+        halt
+    );
+
+    let gfx = m.framebuffer();
+    assert_eq!((GFX_WIDTH, GFX_HEIGHT), (gfx.width(), gfx.height()));
+    // the top row of "A" is 0xF0
+    let row: Vec<u8> = (0..8).map(|x| gfx.pixel(x, 0)).collect();
+    assert_eq!(vec![1, 1, 1, 1, 0, 0, 0, 0], row);
+    assert_eq!(0xA, m.get_timer(Timer::Sound));
+    assert!(m.framebuffer_mut().take_dirty());
+}
@@ -0,0 +1,62 @@
+// The test ROMs in `data` with a golden image next to them (`NAME.expected`,
+// as written by `chip8 selftest --bless`), run headless for a fixed number
+// of cycles through the library API: a regression in an opcode changes the
+// screen they draw. Drop Timendus or corax89 test ROMs in `data` with their
+// `.expected` to have them checked too.
+
+use chip8_core::Machine;
+use std::fs;
+use std::path::Path;
+
+// the test ROMs draw their results and spin long before that
+const CYCLES: u64 = 200_000;
+
+fn run(rom: &Path, cycles: u64) -> Machine {
+    let mut m = Machine::new();
+    m.init();
+    m.load_program_file(rom.to_str().unwrap()).unwrap();
+    // they end on a jump to themselves, an error is a regression too
+    m.run_for(cycles)
+        .unwrap_or_else(|e| panic!("{}: {}", rom.display(), e));
+    m
+}
+
+fn screen_text(m: &Machine) -> String {
+    let gfx = m.framebuffer();
+    let mut text = String::new();
+    for y in 0..gfx.height() {
+        text.extend((0..gfx.width()).map(|x| if gfx.pixel(x, y) > 0 { '#' } else { '.' }));
+        text.push('\n');
+    }
+    text
+}
+
+#[test]
+#[cfg_attr(miri, ignore)]
+fn rom_suite_golden_images() {
+    let mut checked = 0;
+    for entry in fs::read_dir("data").unwrap() {
+        let rom = entry.unwrap().path();
+        let expected = match fs::read_to_string(rom.with_extension("expected")) {
+            Ok(text) if rom.extension().is_some_and(|e| e == "rom" || e == "ch8") => text,
+            _ => continue,
+        };
+        let m = run(&rom, CYCLES);
+        assert_eq!(expected, screen_text(&m), "{}", rom.display());
+        checked += 1;
+    }
+    assert!(checked > 0, "no test ROM with a golden image in data");
+}
+
+#[test]
+#[cfg_attr(miri, ignore)]
+fn rom_suite_runs_repeat() {
+    // the timers keep their pace across calls: the same cycles in two
+    // calls end on the same screen
+    let rom = Path::new("data/test_opcode.rom");
+    let whole = run(rom, CYCLES);
+    let mut halves = run(rom, CYCLES / 2 + 1);
+    halves.run_for(CYCLES / 2 - 1).unwrap();
+    assert_eq!(whole.framebuffer_hash(), halves.framebuffer_hash());
+    assert_ne!(Machine::new().framebuffer_hash(), whole.framebuffer_hash());
+}